@@ -0,0 +1,51 @@
+// Resets a scratch tree from a baseline and then changes it, either by cloning the baseline
+// afresh every time or with `clone_from`, which reuses the nodes the scratch tree already has.
+// Run with `cargo bench --bench clone`.
+
+#[macro_use]
+extern crate criterion;
+extern crate veb_rs;
+
+mod common;
+
+use criterion::Criterion;
+use veb_rs::VEBTree;
+
+const UNIVERSE: u64 = 1 << 24;
+const VALUES: usize = 100_000;
+const CHANGES: usize = 2_000;
+
+fn values(mut state: u64, count: usize) -> Vec<u64> {
+    (0..count).map(|_| common::xorshift(&mut state) % UNIVERSE).collect()
+}
+
+fn reset(c: &mut Criterion) {
+    let baseline =
+        VEBTree::<u64>::from_iter_with_universe(UNIVERSE, values(1, VALUES)).unwrap();
+    let changes = values(2, CHANGES);
+    let mut group = c.benchmark_group("reset_scratch");
+    group.bench_function("clone", |b| {
+        let mut scratch = baseline.clone();
+        b.iter(|| {
+            scratch = baseline.clone();
+            for &x in &changes {
+                scratch.insert(x);
+            }
+            scratch.len()
+        })
+    });
+    group.bench_function("clone_from", |b| {
+        let mut scratch = baseline.clone();
+        b.iter(|| {
+            scratch.clone_from(&baseline);
+            for &x in &changes {
+                scratch.insert(x);
+            }
+            scratch.len()
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, reset);
+criterion_main!(benches);
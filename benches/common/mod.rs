@@ -0,0 +1,10 @@
+// Shared by the benchmarks, and included into the crate's own tests with a `#[path]`
+// attribute, so this only uses `core`.
+
+// xorshift; good enough to scatter values across clusters
+pub fn xorshift(state: &mut u64) -> u64 {
+    *state ^= *state << 13;
+    *state ^= *state >> 7;
+    *state ^= *state << 17;
+    *state
+}
@@ -0,0 +1,49 @@
+// Compares the recursive VEBTree against the arena-backed FlatVEBTree on a mixed workload of
+// inserts and successor queries over a large universe.
+// Run with `cargo bench --bench layout`.
+
+#[macro_use]
+extern crate criterion;
+extern crate veb_rs;
+
+mod common;
+
+use criterion::{black_box, Criterion};
+use veb_rs::{FlatVEBTree, VEBTree};
+
+const UNIVERSE: u64 = 1 << 32;
+const OPS: usize = 20_000;
+
+fn values() -> Vec<u64> {
+    let mut state = 0x2545_f491_4f6c_dd1d_u64;
+    (0..OPS).map(|_| common::xorshift(&mut state) % UNIVERSE).collect()
+}
+
+fn mixed(c: &mut Criterion) {
+    let values = values();
+    let mut group = c.benchmark_group("insert_and_find_next");
+    group.bench_function("VEBTree", |b| {
+        b.iter(|| {
+            let mut tree = VEBTree::<u64>::new(UNIVERSE).unwrap();
+            for &x in &values {
+                tree.insert(x);
+                black_box(tree.find_next(x / 2));
+            }
+            tree
+        })
+    });
+    group.bench_function("FlatVEBTree", |b| {
+        b.iter(|| {
+            let mut tree = FlatVEBTree::<u64>::new(UNIVERSE).unwrap();
+            for &x in &values {
+                tree.insert(x);
+                black_box(tree.find_next(x / 2));
+            }
+            tree
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, mixed);
+criterion_main!(benches);
@@ -0,0 +1,74 @@
+// Read-only queries against a tree over a 2^32 universe holding 100,000 scattered values,
+// one at a time and in sorted batches.
+// Run with `cargo bench --bench queries`.
+
+#[macro_use]
+extern crate criterion;
+extern crate veb_rs;
+
+mod common;
+
+use criterion::{black_box, Criterion};
+use veb_rs::VEBTree;
+
+const UNIVERSE: u64 = 1 << 32;
+const VALUES: usize = 100_000;
+const QUERIES: usize = 10_000;
+
+fn values(mut state: u64, count: usize) -> Vec<u64> {
+    (0..count).map(|_| common::xorshift(&mut state) % UNIVERSE).collect()
+}
+
+fn queries(c: &mut Criterion) {
+    let tree = VEBTree::<u64>::from_iter_with_universe(UNIVERSE, values(1, VALUES)).unwrap();
+    let stored: Vec<u64> = tree.iter().step_by(VALUES / QUERIES).collect();
+    let probes = values(2, QUERIES);
+    let mut group = c.benchmark_group("queries");
+    group.bench_function("has (stored)", |b| {
+        b.iter(|| stored.iter().filter(|&&x| tree.has(black_box(x))).count())
+    });
+    group.bench_function("has (random)", |b| {
+        b.iter(|| probes.iter().filter(|&&x| tree.has(black_box(x))).count())
+    });
+    group.bench_function("find_next", |b| {
+        b.iter(|| probes.iter().filter_map(|&x| tree.find_next(black_box(x))).sum::<u64>())
+    });
+    group.bench_function("find_prev", |b| {
+        b.iter(|| probes.iter().filter_map(|&x| tree.find_prev(black_box(x))).sum::<u64>())
+    });
+    group.bench_function("insert", |b| {
+        b.iter(|| {
+            let mut tree = tree.clone();
+            for &x in &probes {
+                tree.insert(x);
+            }
+            tree
+        })
+    });
+    group.finish();
+}
+
+// Sorted queries in runs of nearby values, as the batched methods expect.
+fn sorted_batches(c: &mut Criterion) {
+    let tree = VEBTree::<u64>::from_iter_with_universe(UNIVERSE, values(1, VALUES)).unwrap();
+    let mut batch: Vec<u64> = values(3, QUERIES / 100)
+        .into_iter()
+        .flat_map(|centre| (0..100).map(move |i| (centre + i * 1000) % UNIVERSE))
+        .collect();
+    batch.sort();
+    let mut group = c.benchmark_group("sorted_batch");
+    group.bench_function("has", |b| {
+        b.iter(|| batch.iter().map(|&x| tree.has(black_box(x))).collect::<Vec<_>>())
+    });
+    group.bench_function("has_sorted", |b| b.iter(|| tree.has_sorted(black_box(&batch))));
+    group.bench_function("find_next", |b| {
+        b.iter(|| batch.iter().map(|&x| tree.find_next(black_box(x))).collect::<Vec<_>>())
+    });
+    group.bench_function("find_next_sorted", |b| {
+        b.iter(|| tree.find_next_sorted(black_box(&batch)))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, queries, sorted_batches);
+criterion_main!(benches);
@@ -0,0 +1,9 @@
+// Runs random operations on a VEBTree and a BTreeSet side by side; see src/fuzzing.rs for how
+// the input is decoded. Run with `cargo fuzz run differential`. A crash can be replayed with
+// `veb_rs::fuzzing::run`, which is how the cases in its tests were added.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| veb_rs::fuzzing::run(data));
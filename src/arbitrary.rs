@@ -0,0 +1,125 @@
+//! Proptest support, enabled by the `proptest` feature.
+//!
+//! Trees are generated by picking a universe and then a random subset of it. Small universes
+//! are favoured, since that's where cluster boundaries are easiest to hit, and both the
+//! universe and the values shrink towards small, readable counterexamples.
+
+#[cfg(test)]
+use alloc::collections::BTreeSet;
+#[cfg(test)]
+use alloc::vec::Vec;
+use core::cmp;
+
+use proptest::collection::vec;
+use proptest::prelude::*;
+
+use {VEBTree, VebInt};
+
+// The largest universe generated, which is big enough for three levels of clusters.
+const MAX_UNIVERSE: u64 = 1 << 20;
+
+// Universes which fit in T, usually small ones.
+fn universe<T: VebInt>() -> impl Strategy<Value = u64> {
+    let max = cmp::min(T::MAX_VALUE, MAX_UNIVERSE - 1) + 1;
+    prop_oneof![
+        3 => 2..=cmp::min(max, 300),
+        1 => 2..=max,
+    ]
+}
+
+/// Generates a tree with a universe of up to 2^20 (or less, if `T` is too narrow) holding up
+/// to 100 values.
+impl<T: VebInt + 'static> Arbitrary for VEBTree<T> {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        universe::<T>()
+            .prop_flat_map(|universe| (Just(universe), vec(0..universe, 0..100)))
+            .prop_map(|(universe, values)| {
+                VEBTree::from_iter_with_universe(universe, values.into_iter().map(T::from_u64))
+                    .unwrap()
+            })
+            .boxed()
+    }
+}
+
+#[cfg(test)]
+#[derive(Debug, Clone)]
+enum Op {
+    Insert(u32),
+    Delete(u32),
+}
+
+// A universe and a sequence of operations on values inside of it.
+#[cfg(test)]
+fn ops() -> impl Strategy<Value = (u64, Vec<Op>)> {
+    universe::<u32>().prop_flat_map(|universe| {
+        let value = (0..universe).prop_map(|x| x as u32);
+        let op = prop_oneof![value.clone().prop_map(Op::Insert), value.prop_map(Op::Delete)];
+        (Just(universe), vec(op, 0..100))
+    })
+}
+
+// Values worth querying: everything in small universes, and otherwise the ends of the
+// universe and the neighbours of every stored value.
+#[cfg(test)]
+fn probes(universe: u64, model: &BTreeSet<u32>) -> Vec<u32> {
+    if universe <= 300 {
+        return (0..universe as u32).collect();
+    }
+    let mut probes = vec![0, 1, universe as u32 - 1];
+    for &x in model {
+        probes.extend_from_slice(&[x.saturating_sub(1), x, x.saturating_add(1)]);
+    }
+    probes
+}
+
+#[cfg(test)]
+proptest! {
+    #[test]
+    fn matches_btree_set((universe, ops) in ops()) {
+        let mut tree = VEBTree::<u32>::new(universe).unwrap();
+        let mut model = BTreeSet::new();
+        for op in ops {
+            match op {
+                Op::Insert(x) => prop_assert_eq!(tree.insert(x), model.insert(x)),
+                Op::Delete(x) => prop_assert_eq!(tree.delete(x), model.remove(&x)),
+            }
+            prop_assert_eq!(tree.validate(), Ok(()));
+            prop_assert_eq!(tree.minimum(), model.iter().next().cloned());
+            prop_assert_eq!(tree.maximum(), model.iter().next_back().cloned());
+        }
+        for x in probes(universe, &model) {
+            prop_assert_eq!(tree.has(x), model.contains(&x), "has({})", x);
+            let next = model.range(x + 1..).next().cloned();
+            prop_assert_eq!(tree.find_next(x), next, "find_next({})", x);
+        }
+    }
+
+    #[test]
+    fn find_next_matches_btree_set(tree in any::<VEBTree<u32>>()) {
+        let model = BTreeSet::from(&tree);
+        prop_assert_eq!(tree.minimum(), model.iter().next().cloned());
+        prop_assert_eq!(tree.maximum(), model.iter().next_back().cloned());
+        for x in probes(tree.universe(), &model) {
+            let next = model.range(x + 1..).next().cloned();
+            prop_assert_eq!(tree.find_next(x), next, "find_next({})", x);
+        }
+    }
+
+    #[test]
+    fn insert_then_delete_is_a_no_op(tree in any::<VEBTree<u32>>(), x in any::<u64>()) {
+        let x = (x % tree.universe()) as u32;
+        let mut changed = tree.clone();
+        if tree.has(x) {
+            prop_assert!(changed.delete(x));
+            prop_assert!(changed.insert(x));
+        } else {
+            prop_assert!(changed.insert(x));
+            prop_assert!(changed.delete(x));
+        }
+        prop_assert_eq!(changed.validate(), Ok(()));
+        prop_assert_eq!(&changed, &tree);
+    }
+}
@@ -0,0 +1,232 @@
+//! Conversions to and from plain bitmaps, and to and from Roaring bitmaps with the `roaring`
+//! feature.
+//!
+//! A bitmap is a slice of 64-bit words, where bit i of word w stands for the value
+//! `64 * w + i`. Leaves cover at most 64 aligned values, so each one is built from, or written
+//! to, part of a single word.
+
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::cmp;
+#[cfg(feature = "roaring")]
+use core::convert::TryFrom;
+
+#[cfg(feature = "roaring")]
+use roaring::RoaringBitmap;
+
+use {below, cluster_count, Error, VEBTree, VebInt};
+
+// The `len` bits starting at bit `start`, which don't cross a word boundary.
+fn leaf_bits(words: &[u64], start: u64, len: u64) -> u64 {
+    let word = words.get((start / 64) as usize).cloned().unwrap_or(0);
+    (word >> (start % 64)) & below(len)
+}
+
+// Returns true if any of the `len` bits starting at bit `start` is set. Either both are
+// multiples of 64, or the bits are within one word.
+fn any_set(words: &[u64], start: u64, len: u64) -> bool {
+    if len < 64 {
+        return leaf_bits(words, start, len) != 0;
+    }
+    let first = (start / 64) as usize;
+    if first >= words.len() {
+        return false;
+    }
+    let last = cmp::min(words.len() as u64, (start + len) / 64) as usize;
+    words[first..last].iter().any(|&word| word != 0)
+}
+
+impl<T: VebInt> VEBTree<T> {
+    /// Generates a new van Emde Boas tree holding every value whose bit is set in `words`,
+    /// where bit i of word w stands for the value `64 * w + i`. Missing words at the end are
+    /// treated as zero. Will return an error if the universe is invalid (see `new`), or if a
+    /// bit is set for a value outside of the range [0, U).
+    /// The tree is built bottom-up, a leaf at a time, and clusters whose words are all zero
+    /// are skipped, so this takes O(W + N) time, where W is the number of words and N is the
+    /// number of values.
+    pub fn from_bitmap_words(max_elem: u64, words: &[u64]) -> Result<Self, Error> {
+        let mut tree = VEBTree::new(max_elem)?;
+        // the first word which may only have bits below the universe set
+        let last = max_elem / 64;
+        let beyond = words.iter().enumerate().skip(last as usize).any(|(w, &word)| {
+            if w as u64 == last { word & !below(max_elem % 64) != 0 } else { word != 0 }
+        });
+        if beyond {
+            return Err(Error::ValueOutOfRange);
+        }
+        tree.build_words(words, 0);
+        Ok(tree)
+    }
+
+    /// Returns the values in the tree as a bitmap, in the same format as `from_bitmap_words`
+    /// takes. The bitmap ends at the word holding the maximum, so it's empty for an empty
+    /// tree.
+    /// Each leaf is copied into the bitmap as a whole, so this takes O(M + N) time, where M is
+    /// the maximum and N is the number of allocated nodes.
+    pub fn to_bitmap_words(&self) -> Vec<u64> {
+        let mut words = vec![0; self.maximum().map_or(0, |max| (max.to_u64() / 64) as usize + 1)];
+        self.write_words(&mut words, 0);
+        words
+    }
+
+    // Fills an empty tree from the bits of `words` starting at bit `start`.
+    fn build_words(&mut self, words: &[u64], start: u64) {
+        if self.children.bits().is_some() {
+            self.set_bits(leaf_bits(words, start, self.universe));
+            return;
+        }
+        let sqrt = self.sqrt_universe;
+        let mut indices = Vec::new();
+        for idx in 0..cluster_count(self.universe) {
+            let cluster_start = start + idx * sqrt;
+            if cluster_start / 64 >= words.len() as u64 {
+                break;
+            } else if !any_set(words, cluster_start, sqrt) {
+                continue;
+            }
+            let mut subtree = self.empty_like(sqrt);
+            subtree.build_words(words, cluster_start);
+            self.len += subtree.len;
+            self.ensure_children();
+            self.children.set(idx, subtree);
+            indices.push(T::from_u64(idx));
+        }
+        if indices.is_empty() {
+            return;
+        }
+        let mut summary = self.empty_like(cluster_count(self.universe));
+        summary.build_sorted(&indices);
+        self.summary = Some(Arc::new(summary));
+        // the minimum was built into its cluster along with everything else
+        self.adopt_clusters();
+    }
+
+    // Sets the bits for every value in the tree, offset by `start`.
+    fn write_words(&self, words: &mut [u64], start: u64) {
+        if self.is_empty() {
+            return;
+        }
+        if let Some(bits) = self.children.bits() {
+            words[(start / 64) as usize] |= bits << (start % 64);
+            return;
+        }
+        let min = start + self.min.to_u64();
+        words[(min / 64) as usize] |= 1 << (min % 64);
+        for (idx, subtree) in self.children.iter() {
+            subtree.write_words(words, start + idx * self.sqrt_universe);
+        }
+    }
+}
+
+/// Builds a tree with the smallest power-of-two universe which holds the bitmap's maximum, or
+/// a universe of 2 if the bitmap is empty. The bitmap is already sorted, so the tree is built
+/// bottom-up as in `from_sorted_slice`.
+#[cfg(feature = "roaring")]
+impl<'a> From<&'a RoaringBitmap> for VEBTree<u32> {
+    fn from(bitmap: &'a RoaringBitmap) -> Self {
+        let universe = bitmap.max().map_or(2, |max| {
+            cmp::max(2, (u64::from(max) + 1).next_power_of_two())
+        });
+        let values: Vec<u32> = bitmap.iter().collect();
+        VEBTree::from_sorted_slice(universe, &values).unwrap()
+    }
+}
+
+/// Copies the values in a tree into a Roaring bitmap, a run of consecutive values at a time.
+/// Fails with `Error::ValueOutOfRange` if the tree holds a value which doesn't fit in a `u32`.
+#[cfg(feature = "roaring")]
+impl<'a, T: VebInt> TryFrom<&'a VEBTree<T>> for RoaringBitmap {
+    type Error = Error;
+
+    fn try_from(tree: &'a VEBTree<T>) -> Result<Self, Error> {
+        if tree.maximum().is_some_and(|max| max.to_u64() > u64::from(u32::MAX)) {
+            return Err(Error::ValueOutOfRange);
+        }
+        let mut bitmap = RoaringBitmap::new();
+        for run in tree.runs() {
+            bitmap.insert_range(run.start().to_u64() as u32..=run.end().to_u64() as u32);
+        }
+        Ok(bitmap)
+    }
+}
+
+#[cfg(test)]
+fn check_round_trip(universe: u64, values: &[u64]) {
+    let tree = VEBTree::<u64>::from_slice(universe, values).unwrap();
+    let words = tree.to_bitmap_words();
+    for &x in values {
+        assert!(words[(x / 64) as usize] & 1 << (x % 64) != 0);
+    }
+    assert_eq!(words.iter().map(|w| w.count_ones() as usize).sum::<usize>(), tree.len());
+    let rebuilt = VEBTree::<u64>::from_bitmap_words(universe, &words).unwrap();
+    assert_eq!(rebuilt.validate(), Ok(()));
+    assert_eq!(rebuilt, tree);
+    // trailing zero words don't change anything
+    let mut padded = words.clone();
+    padded.resize(words.len() + 3, 0);
+    if (padded.len() as u64 - 1) * 64 < universe {
+        assert_eq!(VEBTree::<u64>::from_bitmap_words(universe, &padded).unwrap(), tree);
+    }
+}
+
+#[test]
+fn round_trip() {
+    // empty, a leaf at the root, and universes which aren't powers of two
+    check_round_trip(1000, &[]);
+    check_round_trip(2, &[1]);
+    check_round_trip(64, &[0, 5, 63]);
+    check_round_trip(65, &[0, 64]);
+    check_round_trip(1000, &[0, 63, 64, 65, 127, 128, 999]);
+    // dense: every value, and long runs
+    let all: Vec<u64> = (0..5000).collect();
+    check_round_trip(5000, &all);
+    let runs: Vec<u64> = (0..1 << 16).filter(|x| x / 1000 % 2 == 0).collect();
+    check_round_trip(1 << 16, &runs);
+    // sparse
+    for &universe in &[300, 1 << 16, 1 << 20] {
+        for seed in 0..5 {
+            let tree = ::random_tree::<u64>(universe, 200 * seed as usize, seed);
+            check_round_trip(universe, &tree.to_sorted_vec());
+        }
+    }
+}
+
+#[test]
+fn bits_beyond_universe() {
+    assert_eq!(VEBTree::<u32>::from_bitmap_words(100, &[0, 1 << 36]).unwrap_err(),
+               Error::ValueOutOfRange);
+    assert_eq!(VEBTree::<u32>::from_bitmap_words(100, &[0, 0, 1]).unwrap_err(),
+               Error::ValueOutOfRange);
+    assert_eq!(VEBTree::<u32>::from_bitmap_words(64, &[!0, 1]).unwrap_err(),
+               Error::ValueOutOfRange);
+    assert_eq!(VEBTree::<u32>::from_bitmap_words(1, &[]).unwrap_err(), Error::UniverseTooSmall);
+    // the last value in the universe is fine, and so are zero words past it
+    let tree = VEBTree::<u32>::from_bitmap_words(100, &[0, 1 << 35, 0, 0]).unwrap();
+    assert_eq!(tree.to_sorted_vec(), [99]);
+    let tree = VEBTree::<u32>::from_bitmap_words(64, &[!0, 0]).unwrap();
+    assert_eq!(tree.len(), 64);
+}
+
+#[cfg(feature = "roaring")]
+#[test]
+fn roaring_round_trip() {
+    let empty = RoaringBitmap::new();
+    let tree = VEBTree::<u32>::from(&empty);
+    assert!(tree.is_empty() && tree.universe() == 2);
+    assert_eq!(RoaringBitmap::try_from(&tree).unwrap(), empty);
+
+    let mut bitmap: RoaringBitmap = (0..70_000).filter(|x| x % 3 != 0).collect();
+    bitmap.insert_range(1_000_000..1_200_000);
+    bitmap.insert(u32::MAX);
+    let tree = VEBTree::<u32>::from(&bitmap);
+    assert_eq!(tree.universe(), 1 << 32);
+    assert_eq!(tree.validate(), Ok(()));
+    assert!(tree.iter().eq(bitmap.iter()));
+    assert_eq!(RoaringBitmap::try_from(&tree).unwrap(), bitmap);
+
+    let small: RoaringBitmap = [3, 200].iter().cloned().collect();
+    assert_eq!(VEBTree::<u32>::from(&small).universe(), 256);
+
+    let wide = VEBTree::<u64>::from_slice(1 << 40, &[5, 1 << 33]).unwrap();
+    assert_eq!(RoaringBitmap::try_from(&wide).unwrap_err(), Error::ValueOutOfRange);
+}
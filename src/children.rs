@@ -0,0 +1,411 @@
+//! Storage for the clusters of a `VEBTree`.
+//!
+//! Dense storage keeps a slot for every cluster, so finding one is a single index. Sparse
+//! storage only keeps the clusters which have been allocated, in a `BTreeMap`, which costs
+//! O(log(C)) per lookup (where C is the number of allocated clusters) but no memory at all for
+//! the clusters which were never used.
+//!
+//! A node whose universe fits in a `u64` has no clusters at all: it's a leaf, which keeps a
+//! bit for each value instead.
+//!
+//! Clusters are reference counted, so cloning a tree only copies the cluster slots of its
+//! root. A cluster is copied the first time it's modified through a tree which shares it, so
+//! clones never see each other's changes.
+//!
+//! Alongside the clusters, dense and sparse storage keep running counts of the values in
+//! them (see `counts`), for counting the values before a given cluster without visiting the
+//! ones in between. Every change to a cluster goes through this storage: clusters are
+//! borrowed for modification as a `ClusterMut`, which updates the counts when it's dropped.
+
+use alloc::collections::btree_map::Entry;
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::mem::size_of;
+use core::ops::{Deref, DerefMut};
+
+use counts::Counts;
+use {cluster_count, VEBTree, VebInt, LEAF_UNIVERSE};
+
+#[derive(Debug)]
+pub enum Children<T: VebInt> {
+    // dense counts have an entry for each slot
+    Dense(Vec<Option<Arc<VEBTree<T>>>>, Counts),
+    Sparse(BTreeMap<u64, Arc<VEBTree<T>>>, Counts),
+    // sparse only records how the tree was made, for is_sparse
+    Leaf { bits: u64, sparse: bool },
+}
+
+impl<T: VebInt> Children<T> {
+    pub fn new(universe: u64, sparse: bool) -> Self {
+        if universe <= LEAF_UNIVERSE {
+            Children::Leaf { bits: 0, sparse }
+        } else if sparse {
+            Children::Sparse(BTreeMap::new(), Counts::new(cluster_count(universe), true))
+        } else {
+            Children::Dense(vec![], Counts::new(cluster_count(universe), false))
+        }
+    }
+
+    pub fn is_sparse(&self) -> bool {
+        match *self {
+            Children::Dense(..) => false,
+            Children::Sparse(..) => true,
+            Children::Leaf { sparse, .. } => sparse,
+        }
+    }
+
+    // The values stored in a leaf, or None if this isn't one.
+    pub fn bits(&self) -> Option<u64> {
+        match *self {
+            Children::Leaf { bits, .. } => Some(bits),
+            _ => None,
+        }
+    }
+
+    pub fn bits_mut(&mut self) -> Option<&mut u64> {
+        match *self {
+            Children::Leaf { ref mut bits, .. } => Some(bits),
+            _ => None,
+        }
+    }
+
+    // Returns true if no cluster has been allocated.
+    pub fn is_unallocated(&self) -> bool {
+        match *self {
+            Children::Dense(ref slots, _) => slots.is_empty(),
+            Children::Sparse(ref clusters, _) => clusters.is_empty(),
+            Children::Leaf { .. } => true,
+        }
+    }
+
+    // The counts of the values in the clusters, or None for a leaf.
+    pub fn counts(&self) -> Option<&Counts> {
+        match *self {
+            Children::Dense(_, ref counts) | Children::Sparse(_, ref counts) => Some(counts),
+            Children::Leaf { .. } => None,
+        }
+    }
+
+    // Makes room for every cluster. Only dense storage needs to do anything.
+    // Takes O(C) time if it does, where C is the number of clusters.
+    pub fn reserve(&mut self, clusters: usize) {
+        if let Children::Dense(ref mut slots, ref mut counts) = *self {
+            if slots.len() < clusters {
+                slots.reserve_exact(clusters - slots.len());
+                slots.resize(clusters, None);
+                let lens = slots.iter().enumerate().filter_map(|(idx, slot)| {
+                    slot.as_ref().map(|subtree| (idx as u64, subtree.len))
+                });
+                counts.rebuild(clusters, lens);
+            }
+        }
+    }
+
+    // Returns a cluster if it's allocated, even if it's empty.
+    pub fn get(&self, idx: u64) -> Option<&VEBTree<T>> {
+        match *self {
+            Children::Dense(ref slots, _) => {
+                slots.get(idx as usize).and_then(Option::as_deref)
+            }
+            Children::Sparse(ref clusters, _) => clusters.get(&idx).map(|subtree| &**subtree),
+            Children::Leaf { .. } => None,
+        }
+    }
+
+    // Copies the cluster first if it's shared with another tree.
+    pub fn get_mut<'a>(&'a mut self, idx: u64) -> Option<ClusterMut<'a, T>> {
+        let (subtree, counts) = match *self {
+            Children::Dense(ref mut slots, ref mut counts) => {
+                (slots.get_mut(idx as usize).and_then(Option::as_mut), counts)
+            }
+            Children::Sparse(ref mut clusters, ref mut counts) => {
+                (clusters.get_mut(&idx), counts)
+            }
+            Children::Leaf { .. } => return None,
+        };
+        subtree.map(move |subtree| ClusterMut::new(Arc::make_mut(subtree), counts, idx))
+    }
+
+    // Returns a cluster, allocating an empty one with the given universe if needed. Dense
+    // storage must already have room for it.
+    pub fn get_or_alloc<'a>(&'a mut self, idx: u64, universe: u64) -> ClusterMut<'a, T> {
+        let sparse = self.is_sparse();
+        let alloc = || Arc::new(VEBTree::with_storage(universe, sparse).unwrap());
+        let (subtree, counts) = match *self {
+            Children::Dense(ref mut slots, ref mut counts) => {
+                (slots[idx as usize].get_or_insert_with(alloc), counts)
+            }
+            Children::Sparse(ref mut clusters, ref mut counts) => {
+                (clusters.entry(idx).or_insert_with(alloc), counts)
+            }
+            Children::Leaf { .. } => unreachable!("leaves have no clusters"),
+        };
+        ClusterMut::new(Arc::make_mut(subtree), counts, idx)
+    }
+
+    // Stores a cluster. Dense storage must already have room for it.
+    pub fn set(&mut self, idx: u64, subtree: VEBTree<T>) {
+        self.set_shared(idx, Arc::new(subtree));
+    }
+
+    // Stores a cluster which may be shared with another tree.
+    pub fn set_shared(&mut self, idx: u64, subtree: Arc<VEBTree<T>>) {
+        let len = subtree.len;
+        let (old, counts) = match *self {
+            Children::Dense(ref mut slots, ref mut counts) => {
+                (slots[idx as usize].replace(subtree), counts)
+            }
+            Children::Sparse(ref mut clusters, ref mut counts) => {
+                (clusters.insert(idx, subtree), counts)
+            }
+            Children::Leaf { .. } => unreachable!("leaves have no clusters"),
+        };
+        counts.update(idx, old.map_or(0, |old| old.len), len);
+    }
+
+    pub fn remove(&mut self, idx: u64) -> Option<Arc<VEBTree<T>>> {
+        let (removed, counts) = match *self {
+            Children::Dense(ref mut slots, ref mut counts) => {
+                (slots.get_mut(idx as usize).and_then(Option::take), counts)
+            }
+            Children::Sparse(ref mut clusters, ref mut counts) => {
+                (clusters.remove(&idx), counts)
+            }
+            Children::Leaf { .. } => return None,
+        };
+        if let Some(ref removed) = removed {
+            counts.update(idx, removed.len, 0);
+        }
+        removed
+    }
+
+    // Drops every cluster, keeping the kind of storage.
+    pub fn release(&mut self) {
+        match *self {
+            Children::Dense(ref mut slots, ref mut counts) => {
+                *slots = vec![];
+                counts.release();
+            }
+            Children::Sparse(ref mut clusters, ref mut counts) => {
+                clusters.clear();
+                counts.release();
+            }
+            Children::Leaf { .. } => {}
+        }
+    }
+
+    // Drops the clusters at or after `used`, along with any empty ones kept by `clear`, and
+    // releases the spare capacity. Every non-empty cluster must be before `used`.
+    pub fn shrink(&mut self, used: u64) {
+        match *self {
+            Children::Dense(ref mut slots, ref mut counts) => {
+                slots.truncate(used as usize);
+                slots.shrink_to_fit();
+                counts.shrink(used as usize);
+                for slot in slots.iter_mut() {
+                    if slot.as_ref().is_some_and(|subtree| subtree.is_empty()) {
+                        *slot = None;
+                    }
+                }
+            }
+            Children::Sparse(ref mut clusters, _) => {
+                clusters.split_off(&used);
+                clusters.retain(|_, subtree| !subtree.is_empty());
+            }
+            Children::Leaf { .. } => {}
+        }
+    }
+
+    // The number of cluster slots, which is one past the highest allocated cluster for sparse
+    // storage.
+    pub fn slots(&self) -> usize {
+        match *self {
+            Children::Dense(ref slots, _) => slots.len(),
+            Children::Sparse(ref clusters, _) => {
+                clusters.keys().next_back().map_or(0, |&last| last as usize + 1)
+            }
+            Children::Leaf { .. } => 0,
+        }
+    }
+
+    // An estimate of the heap memory used by the storage itself, including the counts but
+    // not the memory owned by the clusters.
+    pub fn heap_bytes(&self) -> usize {
+        match *self {
+            Children::Dense(ref slots, ref counts) => {
+                slots.capacity() * size_of::<Option<Arc<VEBTree<T>>>>() + counts.heap_bytes()
+            }
+            Children::Sparse(ref clusters, ref counts) => {
+                clusters.len() * (size_of::<u64>() + size_of::<Arc<VEBTree<T>>>()) +
+                counts.heap_bytes()
+            }
+            Children::Leaf { .. } => 0,
+        }
+    }
+
+    // Every allocated cluster along with its index, in ascending order.
+    pub fn iter<'a>(&'a self) -> impl Iterator<Item = (u64, &'a VEBTree<T>)> + 'a {
+        let (dense, sparse) = match *self {
+            Children::Dense(ref slots, _) => (Some(slots), None),
+            Children::Sparse(ref clusters, _) => (None, Some(clusters)),
+            Children::Leaf { .. } => (None, None),
+        };
+        let dense = dense.into_iter().flat_map(|slots| {
+            slots.iter().enumerate().filter_map(|(idx, slot)| {
+                slot.as_deref().map(|subtree| (idx as u64, subtree))
+            })
+        });
+        let sparse = sparse.into_iter().flat_map(|clusters| {
+            clusters.iter().map(|(&idx, subtree)| (idx, &**subtree))
+        });
+        dense.chain(sparse)
+    }
+
+    // Every allocated cluster which isn't shared with another tree. This bypasses the counts,
+    // so the clusters' lengths mustn't change.
+    pub fn owned_mut<'a>(&'a mut self) -> impl Iterator<Item = &'a mut VEBTree<T>> + 'a {
+        let (dense, sparse) = match *self {
+            Children::Dense(ref mut slots, _) => (Some(slots), None),
+            Children::Sparse(ref mut clusters, _) => (None, Some(clusters)),
+            Children::Leaf { .. } => (None, None),
+        };
+        let dense = dense.into_iter()
+            .flat_map(|slots| slots.iter_mut().filter_map(Option::as_mut));
+        let sparse = sparse.into_iter().flat_map(|clusters| clusters.values_mut());
+        dense.chain(sparse).filter_map(Arc::get_mut)
+    }
+
+    // Empties every cluster, keeping the memory of the ones this tree owns outright. Shared
+    // clusters are dropped instead, since emptying them would mean copying them first.
+    pub fn clear(&mut self) {
+        match *self {
+            Children::Dense(ref mut slots, ref mut counts) => {
+                for slot in slots.iter_mut() {
+                    match slot.as_mut().map(Arc::get_mut) {
+                        Some(Some(subtree)) => subtree.clear(),
+                        Some(None) => *slot = None,
+                        None => {}
+                    }
+                }
+                counts.clear();
+            }
+            Children::Sparse(ref mut clusters, ref mut counts) => {
+                clusters.retain(|_, subtree| match Arc::get_mut(subtree) {
+                    Some(subtree) => {
+                        subtree.clear();
+                        true
+                    }
+                    None => false,
+                });
+                counts.clear();
+            }
+            Children::Leaf { .. } => {}
+        }
+    }
+}
+
+// A cluster borrowed for modification, which brings its parent's counts up to date once it's
+// dropped.
+pub struct ClusterMut<'a, T: VebInt + 'a> {
+    cluster: &'a mut VEBTree<T>,
+    counts: &'a mut Counts,
+    idx: u64,
+    // the cluster's length when it was borrowed
+    len: usize,
+}
+
+impl<'a, T: VebInt> ClusterMut<'a, T> {
+    fn new(cluster: &'a mut VEBTree<T>, counts: &'a mut Counts, idx: u64) -> Self {
+        let len = cluster.len;
+        ClusterMut { cluster, counts, idx, len }
+    }
+}
+
+impl<'a, T: VebInt> Deref for ClusterMut<'a, T> {
+    type Target = VEBTree<T>;
+
+    fn deref(&self) -> &VEBTree<T> {
+        self.cluster
+    }
+}
+
+impl<'a, T: VebInt> DerefMut for ClusterMut<'a, T> {
+    fn deref_mut(&mut self) -> &mut VEBTree<T> {
+        self.cluster
+    }
+}
+
+impl<'a, T: VebInt> Drop for ClusterMut<'a, T> {
+    fn drop(&mut self) {
+        self.counts.update(self.idx, self.len, self.cluster.len);
+    }
+}
+
+impl<T: VebInt> Clone for Children<T> {
+    fn clone(&self) -> Self {
+        match *self {
+            Children::Dense(ref slots, ref counts) => {
+                Children::Dense(slots.clone(), counts.clone())
+            }
+            Children::Sparse(ref clusters, ref counts) => {
+                Children::Sparse(clusters.clone(), counts.clone())
+            }
+            Children::Leaf { bits, sparse } => Children::Leaf { bits, sparse },
+        }
+    }
+
+    // Keeps the slots, and copies into the clusters which aren't shared, when both use the
+    // same kind of storage.
+    fn clone_from(&mut self, source: &Self) {
+        match (&mut *self, source) {
+            (&mut Children::Dense(ref mut slots, ref mut counts),
+             Children::Dense(from, from_counts)) => {
+                slots.truncate(from.len());
+                for (slot, from) in slots.iter_mut().zip(from) {
+                    clone_slot_from(slot, from);
+                }
+                let len = slots.len();
+                slots.extend_from_slice(&from[len..]);
+                counts.clone_from(from_counts);
+                return;
+            }
+            (&mut Children::Sparse(ref mut clusters, ref mut counts),
+             Children::Sparse(from, from_counts)) => {
+                counts.clone_from(from_counts);
+                clusters.retain(|idx, _| from.contains_key(idx));
+                for (&idx, from) in from {
+                    match clusters.entry(idx) {
+                        Entry::Occupied(entry) => clone_cluster_from(entry.into_mut(), from),
+                        Entry::Vacant(entry) => {
+                            entry.insert(from.clone());
+                        }
+                    }
+                }
+                return;
+            }
+            _ => {}
+        }
+        *self = source.clone();
+    }
+}
+
+// Makes a cluster a copy of another, copying into it in place if nothing else shares it, and
+// otherwise sharing the other one.
+pub fn clone_cluster_from<T: VebInt>(cluster: &mut Arc<VEBTree<T>>, source: &Arc<VEBTree<T>>) {
+    if Arc::ptr_eq(cluster, source) {
+        return;
+    }
+    match Arc::get_mut(cluster) {
+        Some(cluster) => cluster.clone_from(source),
+        None => *cluster = source.clone(),
+    }
+}
+
+pub fn clone_slot_from<T: VebInt>(slot: &mut Option<Arc<VEBTree<T>>>,
+                                  source: &Option<Arc<VEBTree<T>>>) {
+    match (slot.as_mut(), source.as_ref()) {
+        (Some(cluster), Some(source)) => clone_cluster_from(cluster, source),
+        _ => *slot = source.clone(),
+    }
+}
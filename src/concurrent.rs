@@ -0,0 +1,378 @@
+//! A van Emde Boas tree which can be shared between threads, enabled by the `std` feature.
+//!
+//! The tree is sharded at the top level: every top-level cluster is a `VEBTree` behind its own
+//! lock, and the summary of which clusters hold values is a `VEBTree` behind another. A
+//! cluster's entry in the summary is only changed while holding that cluster's write lock,
+//! so whenever no write is in progress, the summary holds exactly the non-empty clusters.
+//! Locks are always taken cluster first, then summary, and a query never holds two cluster
+//! locks at once.
+
+use alloc::vec::Vec;
+use core::cmp;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::RwLock;
+
+#[cfg(test)]
+use alloc::collections::BTreeSet;
+#[cfg(test)]
+use alloc::sync::Arc;
+
+use {cluster_count, Error, VEBTree, VebInt};
+
+/// A set of integers in the range [0, U) which can be read and modified through a shared
+/// reference from many threads at once.
+/// `insert`, `delete` and `has` lock only the top-level cluster holding their value, so
+/// operations on values in different clusters run in parallel, and each of them is atomic.
+/// Queries which may look at several clusters (`minimum`, `maximum`, `find_next` and
+/// `find_prev`) lock one cluster at a time, so while other threads are modifying the tree
+/// they return a value which was in the tree at some point during the call, but may miss a
+/// value inserted or deleted in the meantime.
+/// Every top-level cluster's lock is allocated up front, which takes O(sqrt(U)) memory.
+#[derive(Debug)]
+pub struct ConcurrentVEBTree<T: VebInt> {
+    clusters: Vec<RwLock<Option<VEBTree<T>>>>,
+    summary: RwLock<VEBTree<T>>,
+    len: AtomicUsize,
+    universe: u64,
+    // the number of values covered by each cluster; always a power of two
+    sqrt_universe: u64,
+}
+
+impl<T: VebInt> ConcurrentVEBTree<T> {
+    /// Generates a new, empty tree. Will return an error under the same conditions as
+    /// `VEBTree::new`.
+    pub fn new(max_elem: u64) -> Result<Self, Error> {
+        // the same split as the root of a VEBTree
+        let sqrt_universe = VEBTree::<T>::new(max_elem)?.sqrt_universe;
+        let count = ((max_elem - 1) / sqrt_universe + 1) as usize;
+        Ok(ConcurrentVEBTree {
+            clusters: (0..count).map(|_| RwLock::new(None)).collect(),
+            summary: RwLock::new(VEBTree::new(cluster_count(max_elem))?),
+            len: AtomicUsize::new(0),
+            universe: max_elem,
+            sqrt_universe,
+        })
+    }
+
+    /// Returns the size of the universe, the argument to the constructor.
+    /// Takes constant time.
+    pub fn universe(&self) -> u64 {
+        self.universe
+    }
+
+    /// Returns the number of values stored in the tree. While other threads are modifying
+    /// the tree this is only a snapshot.
+    /// Takes constant time.
+    pub fn len(&self) -> usize {
+        self.len.load(Ordering::SeqCst)
+    }
+
+    /// Returns true if the tree is empty.
+    /// Takes constant time.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // The cluster holding x, and x's position in it.
+    fn split(&self, x: T) -> (usize, T) {
+        let x = x.to_u64();
+        ((x / self.sqrt_universe) as usize, T::from_u64(x % self.sqrt_universe))
+    }
+
+    fn index(&self, idx: u64, low: T) -> T {
+        T::from_u64(idx * self.sqrt_universe + low.to_u64())
+    }
+
+    /// Returns true if the tree contains the value.
+    /// Takes O(log(log(U))) time, where U is the argument to the constructor.
+    pub fn has(&self, x: T) -> bool {
+        if x.to_u64() >= self.universe {
+            return false;
+        }
+        let (idx, low) = self.split(x);
+        self.clusters[idx].read().unwrap().as_ref().is_some_and(|cluster| cluster.has(low))
+    }
+
+    /// Inserts a value, returning true if it was not already present. Panics if the value is
+    /// outside of the range [0, U).
+    /// Takes O(log(U)) time, where U is the argument to the constructor.
+    pub fn insert(&self, x: T) -> bool {
+        self.try_insert(x).expect("value out of range")
+    }
+
+    /// Inserts a value, returning an error and leaving the tree untouched if the value is
+    /// outside of the range [0, U). Otherwise, returns true if the value was not already
+    /// present.
+    /// Takes O(log(U)) time, where U is the argument to the constructor.
+    pub fn try_insert(&self, x: T) -> Result<bool, Error> {
+        if x.to_u64() >= self.universe {
+            return Err(Error::ValueOutOfRange);
+        }
+        let (idx, low) = self.split(x);
+        let mut cluster = self.clusters[idx].write().unwrap();
+        // with a universe of 2 or 3 every cluster holds one value, but a tree needs at least 2
+        let universe = cmp::max(2, self.sqrt_universe);
+        let cluster = cluster.get_or_insert_with(|| VEBTree::new(universe).unwrap());
+        if !cluster.insert(low) {
+            return Ok(false);
+        }
+        if cluster.len() == 1 {
+            self.summary.write().unwrap().insert(T::from_u64(idx as u64));
+        }
+        self.len.fetch_add(1, Ordering::SeqCst);
+        Ok(true)
+    }
+
+    /// Removes a value, returning true if it was present. A cluster's memory is freed as soon
+    /// as its last value is removed.
+    /// Takes O(log(U)) time, where U is the argument to the constructor.
+    pub fn delete(&self, x: T) -> bool {
+        if x.to_u64() >= self.universe {
+            return false;
+        }
+        let (idx, low) = self.split(x);
+        let mut slot = self.clusters[idx].write().unwrap();
+        let emptied = match *slot {
+            Some(ref mut cluster) => {
+                if !cluster.delete(low) {
+                    return false;
+                }
+                cluster.is_empty()
+            }
+            None => return false,
+        };
+        if emptied {
+            *slot = None;
+            self.summary.write().unwrap().delete(T::from_u64(idx as u64));
+        }
+        self.len.fetch_sub(1, Ordering::SeqCst);
+        true
+    }
+
+    // The lowest value in the first non-empty cluster at or after `idx`. A cluster the summary
+    // lists may have been emptied since, in which case the search moves on past it.
+    fn first_from(&self, mut idx: Option<T>) -> Option<T> {
+        while let Some(i) = idx {
+            let i = i.to_u64();
+            if let Some(min) = self.clusters[i as usize].read().unwrap().as_ref()
+                .and_then(VEBTree::minimum) {
+                return Some(self.index(i, min));
+            }
+            idx = self.summary.read().unwrap().find_next(T::from_u64(i));
+        }
+        None
+    }
+
+    // The mirror image of first_from.
+    fn last_from(&self, mut idx: Option<T>) -> Option<T> {
+        while let Some(i) = idx {
+            let i = i.to_u64();
+            if let Some(max) = self.clusters[i as usize].read().unwrap().as_ref()
+                .and_then(VEBTree::maximum) {
+                return Some(self.index(i, max));
+            }
+            idx = self.summary.read().unwrap().find_prev(T::from_u64(i));
+        }
+        None
+    }
+
+    /// Returns the lowest value in the tree, or None if it's empty. Only best-effort while
+    /// other threads are modifying the tree.
+    /// Takes O(log(log(U))) time, where U is the argument to the constructor.
+    pub fn minimum(&self) -> Option<T> {
+        let first = self.summary.read().unwrap().minimum();
+        self.first_from(first)
+    }
+
+    /// Returns the highest value in the tree, or None if it's empty. Only best-effort while
+    /// other threads are modifying the tree.
+    /// Takes O(log(log(U))) time, where U is the argument to the constructor.
+    pub fn maximum(&self) -> Option<T> {
+        let last = self.summary.read().unwrap().maximum();
+        self.last_from(last)
+    }
+
+    /// Finds the lowest value in the tree which is greater than x, or None if there isn't
+    /// one. Only best-effort while other threads are modifying the tree.
+    /// Takes O(log(log(U))) time, where U is the argument to the constructor.
+    pub fn find_next(&self, x: T) -> Option<T> {
+        if x.to_u64() >= self.universe {
+            return None;
+        }
+        let (idx, low) = self.split(x);
+        let next = self.clusters[idx].read().unwrap().as_ref()
+            .and_then(|cluster| cluster.find_next(low));
+        match next {
+            Some(next) => Some(self.index(idx as u64, next)),
+            None => {
+                let idx = self.summary.read().unwrap().find_next(T::from_u64(idx as u64));
+                self.first_from(idx)
+            }
+        }
+    }
+
+    /// Finds the highest value in the tree which is less than x, or None if there isn't one.
+    /// Only best-effort while other threads are modifying the tree.
+    /// Takes O(log(log(U))) time, where U is the argument to the constructor.
+    pub fn find_prev(&self, x: T) -> Option<T> {
+        if x.to_u64() >= self.universe {
+            return self.maximum();
+        }
+        let (idx, low) = self.split(x);
+        let prev = self.clusters[idx].read().unwrap().as_ref()
+            .and_then(|cluster| cluster.find_prev(low));
+        match prev {
+            Some(prev) => Some(self.index(idx as u64, prev)),
+            None => {
+                let idx = self.summary.read().unwrap().find_prev(T::from_u64(idx as u64));
+                self.last_from(idx)
+            }
+        }
+    }
+
+    /// Copies the values into a `VEBTree`. The clusters are locked one at a time, so this is
+    /// only a consistent snapshot if no other thread is modifying the tree.
+    /// Takes O(N) time, where N is the number of values.
+    pub fn to_tree(&self) -> VEBTree<T> {
+        let mut tree = VEBTree::new(self.universe).unwrap();
+        let mut idx = self.summary.read().unwrap().minimum();
+        while let Some(i) = idx {
+            if let Some(ref cluster) = *self.clusters[i.to_u64() as usize].read().unwrap() {
+                for low in cluster {
+                    tree.insert(self.index(i.to_u64(), low));
+                }
+            }
+            idx = self.summary.read().unwrap().find_next(i);
+        }
+        tree
+    }
+}
+
+impl<T: VebInt> From<VEBTree<T>> for ConcurrentVEBTree<T> {
+    fn from(tree: VEBTree<T>) -> Self {
+        let concurrent = ConcurrentVEBTree::new(tree.universe()).unwrap();
+        for x in &tree {
+            concurrent.insert(x);
+        }
+        concurrent
+    }
+}
+
+#[test]
+fn single_thread() {
+    fn assert_send_sync<S: Send + Sync>() {}
+    assert_send_sync::<ConcurrentVEBTree<u64>>();
+
+    for &universe in &[2, 3, 100, 1 << 16, 1 << 32] {
+        let tree = ConcurrentVEBTree::<u64>::new(universe).unwrap();
+        assert_eq!((tree.minimum(), tree.maximum()), (None, None));
+        assert_eq!((tree.find_next(0), tree.find_prev(universe - 1)), (None, None));
+        let values: Vec<u64> = [0, 1, universe / 2, universe - 1].iter().cloned()
+            .collect::<BTreeSet<_>>().into_iter().collect();
+        for &x in &values {
+            assert!(tree.insert(x) && !tree.insert(x) && tree.has(x));
+        }
+        assert_eq!(tree.try_insert(universe), Err(Error::ValueOutOfRange));
+        assert!(!tree.has(universe) && !tree.delete(universe));
+        assert_eq!(tree.len(), values.len());
+        assert_eq!(tree.minimum(), Some(0));
+        assert_eq!(tree.maximum(), Some(universe - 1));
+        for pair in values.windows(2) {
+            assert_eq!(tree.find_next(pair[0]), Some(pair[1]));
+            assert_eq!(tree.find_prev(pair[1]), Some(pair[0]));
+        }
+        assert_eq!(tree.find_prev(universe), Some(universe - 1));
+        assert_eq!(tree.to_tree().to_sorted_vec(), values);
+        for &x in &values {
+            assert!(tree.delete(x) && !tree.delete(x) && !tree.has(x));
+        }
+        assert!(tree.is_empty() && tree.minimum().is_none());
+        assert!(tree.clusters.iter().all(|cluster| cluster.read().unwrap().is_none()));
+    }
+}
+
+// Each thread inserts and deletes its own values, checking them against its own model, while
+// the other threads do the same in the same clusters. The models are merged at the end.
+#[test]
+fn threads_with_models() {
+    const THREADS: u64 = 8;
+    let universe = 1 << 20;
+    let tree = Arc::new(ConcurrentVEBTree::<u32>::new(universe).unwrap());
+    let handles: Vec<_> = (0..THREADS).map(|id| {
+        let tree = tree.clone();
+        std::thread::spawn(move || {
+            let mut model = BTreeSet::new();
+            let mut state = id + 1;
+            for _ in 0..20_000 {
+                // most of the values are in a few clusters, to make the threads contend
+                let r = ::xorshift(&mut state);
+                let x = if r % 4 == 3 { r % universe } else { r % 4096 };
+                let x = (x - x % THREADS + id) as u32;
+                match r / 7 % 4 {
+                    0 | 1 => assert_eq!(tree.insert(x), model.insert(x)),
+                    2 => assert_eq!(tree.delete(x), model.remove(&x)),
+                    _ => {
+                        assert_eq!(tree.has(x), model.contains(&x));
+                        // another thread's values may come and go, but never show up out of
+                        // order
+                        if let Some(next) = tree.find_next(x) {
+                            assert!(next > x);
+                        }
+                        if let Some(prev) = tree.find_prev(x) {
+                            assert!(prev < x);
+                        }
+                    }
+                }
+            }
+            model
+        })
+    }).collect();
+    let mut expected = BTreeSet::new();
+    for handle in handles {
+        expected.extend(handle.join().unwrap());
+    }
+    let tree = tree.to_tree();
+    assert_eq!(tree.validate(), Ok(()));
+    assert_eq!(BTreeSet::from(&tree), expected);
+}
+
+// Values in the first and last clusters stay put while other threads fill and empty the
+// clusters around them, so the minimum and maximum never change even as the summary does.
+#[test]
+fn summary_churn() {
+    let universe = 1 << 16;
+    let tree = Arc::new(ConcurrentVEBTree::<u32>::new(universe).unwrap());
+    tree.insert(5);
+    tree.insert(universe as u32 - 5);
+    let writers: Vec<_> = (0..4).map(|id| {
+        let tree = tree.clone();
+        std::thread::spawn(move || {
+            let mut state = id + 100;
+            for _ in 0..20_000 {
+                // a value of this thread's own, in any cluster including the first and last
+                let x = ((::xorshift(&mut state) % universe) & !3 | id) as u32;
+                if x != 5 && x != universe as u32 - 5 {
+                    assert!(tree.insert(x) && tree.delete(x));
+                }
+            }
+        })
+    }).collect();
+    let readers: Vec<_> = (0..4).map(|id| {
+        let tree = tree.clone();
+        std::thread::spawn(move || {
+            let mut state = id + 200;
+            for _ in 0..20_000 {
+                assert_eq!(tree.minimum(), Some(5));
+                assert_eq!(tree.maximum(), Some(universe as u32 - 5));
+                let x = (::xorshift(&mut state) % (universe - 10)) as u32 + 5;
+                assert!(tree.find_next(x).is_some_and(|y| y > x));
+                assert!(tree.find_prev(x + 1).is_some_and(|y| y <= x));
+            }
+        })
+    }).collect();
+    for handle in writers.into_iter().chain(readers) {
+        handle.join().unwrap();
+    }
+    assert_eq!(tree.len(), 2);
+    assert_eq!(tree.to_tree().to_sorted_vec(), [5, universe as u32 - 5]);
+}
@@ -0,0 +1,237 @@
+//! Running counts of the values in each cluster of a `VEBTree`.
+//!
+//! The counts are kept as a Fenwick tree over the cluster indices: entry i (counting from 1)
+//! holds the total length of the clusters in (i - lowbit(i), i], where lowbit(i) is the lowest
+//! set bit of i. Changing one cluster's length updates O(log(C)) entries, where C is the number
+//! of clusters, and so does finding the number of values in the clusters before a given one,
+//! or the cluster holding the k-th value. Lengths are added with wrapping arithmetic, so a
+//! change can be applied as a single difference whichever way it goes.
+//!
+//! Entry i only covers clusters up to i, so a dense Fenwick tree which is cut short still
+//! gives the right counts for the clusters it covers; that lets it grow and shrink along with
+//! the cluster slots.
+
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+use core::mem::size_of;
+
+#[derive(Debug)]
+pub enum Counts {
+    // an entry for each cluster slot
+    Dense(Vec<usize>),
+    // only the entries which aren't zero, keyed by their position counting from 1, out of
+    // `clusters` in all
+    Sparse { entries: BTreeMap<u64, usize>, clusters: u64 },
+}
+
+// The lowest set bit of i.
+fn lowbit(i: u64) -> u64 {
+    i & i.wrapping_neg()
+}
+
+impl Counts {
+    pub fn new(clusters: u64, sparse: bool) -> Self {
+        if sparse {
+            Counts::Sparse { entries: BTreeMap::new(), clusters }
+        } else {
+            Counts::Dense(vec![])
+        }
+    }
+
+    // The number of clusters covered.
+    fn size(&self) -> u64 {
+        match *self {
+            Counts::Dense(ref entries) => entries.len() as u64,
+            Counts::Sparse { clusters, .. } => clusters,
+        }
+    }
+
+    fn entry(&self, i: u64) -> usize {
+        match *self {
+            Counts::Dense(ref entries) => entries[i as usize - 1],
+            Counts::Sparse { ref entries, .. } => entries.get(&i).cloned().unwrap_or(0),
+        }
+    }
+
+    // Records that the length of cluster idx changed from `old` to `new`.
+    pub fn update(&mut self, idx: u64, old: usize, new: usize) {
+        let delta = new.wrapping_sub(old);
+        if delta == 0 {
+            return;
+        }
+        let size = self.size();
+        let mut i = idx + 1;
+        while i <= size {
+            match *self {
+                Counts::Dense(ref mut entries) => {
+                    let entry = &mut entries[i as usize - 1];
+                    *entry = entry.wrapping_add(delta);
+                }
+                Counts::Sparse { ref mut entries, .. } => {
+                    let entry = entries.entry(i).or_insert(0);
+                    *entry = entry.wrapping_add(delta);
+                    if *entry == 0 {
+                        entries.remove(&i);
+                    }
+                }
+            }
+            i += lowbit(i);
+        }
+    }
+
+    // The number of values in the clusters before cluster idx.
+    pub fn before(&self, idx: u64) -> usize {
+        let mut i = idx.min(self.size());
+        let mut count = 0usize;
+        while i > 0 {
+            count = count.wrapping_add(self.entry(i));
+            i &= i - 1;
+        }
+        count
+    }
+
+    // The number of values counted in cluster idx.
+    pub fn of(&self, idx: u64) -> usize {
+        self.before(idx + 1).wrapping_sub(self.before(idx))
+    }
+
+    // Finds the cluster holding the k-th value in the clusters (counting from 0), returning
+    // its index and the number of values in the clusters before it. k must be less than the
+    // total length of the clusters.
+    pub fn find(&self, mut k: usize) -> (u64, usize) {
+        let size = self.size();
+        let mut pos = 0;
+        let mut step = if size == 0 { 0 } else { 1 << (63 - size.leading_zeros()) };
+        while step > 0 {
+            if pos + step <= size {
+                let entry = self.entry(pos + step);
+                if entry <= k {
+                    pos += step;
+                    k -= entry;
+                }
+            }
+            step >>= 1;
+        }
+        (pos, self.before(pos))
+    }
+
+    // Forgets every count, keeping the memory of a dense tree.
+    pub fn clear(&mut self) {
+        match *self {
+            Counts::Dense(ref mut entries) => {
+                for entry in entries.iter_mut() {
+                    *entry = 0;
+                }
+            }
+            Counts::Sparse { ref mut entries, .. } => entries.clear(),
+        }
+    }
+
+    // Drops every count, along with the memory of a dense tree.
+    pub fn release(&mut self) {
+        match *self {
+            Counts::Dense(ref mut entries) => *entries = vec![],
+            Counts::Sparse { ref mut entries, .. } => entries.clear(),
+        }
+    }
+
+    // Rebuilds the counts from the length of every cluster, covering `slots` clusters if this
+    // is dense. Takes O(C) time, plus O(log(C)) for each non-empty cluster if it's sparse.
+    pub fn rebuild<I: Iterator<Item = (u64, usize)>>(&mut self, slots: usize, lens: I) {
+        match *self {
+            Counts::Dense(ref mut entries) => {
+                if entries.len() < slots {
+                    entries.reserve_exact(slots - entries.len());
+                }
+                entries.clear();
+                entries.resize(slots, 0);
+                for (idx, len) in lens {
+                    entries[idx as usize] = len;
+                }
+                for i in 1..=slots {
+                    let parent = i + (i & i.wrapping_neg());
+                    if parent <= slots {
+                        entries[parent - 1] += entries[i - 1];
+                    }
+                }
+                return;
+            }
+            Counts::Sparse { ref mut entries, .. } => entries.clear(),
+        }
+        for (idx, len) in lens {
+            self.update(idx, 0, len);
+        }
+    }
+
+    // Covers only the first `slots` clusters, which must hold every value, and releases the
+    // spare capacity. Only dense counts need to do anything.
+    pub fn shrink(&mut self, slots: usize) {
+        if let Counts::Dense(ref mut entries) = *self {
+            entries.truncate(slots);
+            entries.shrink_to_fit();
+        }
+    }
+
+    // An estimate of the heap memory used by the counts.
+    pub fn heap_bytes(&self) -> usize {
+        match *self {
+            Counts::Dense(ref entries) => entries.capacity() * size_of::<usize>(),
+            Counts::Sparse { ref entries, .. } => {
+                entries.len() * (size_of::<u64>() + size_of::<usize>())
+            }
+        }
+    }
+}
+
+impl Clone for Counts {
+    fn clone(&self) -> Self {
+        match *self {
+            Counts::Dense(ref entries) => Counts::Dense(entries.clone()),
+            Counts::Sparse { ref entries, clusters } => {
+                Counts::Sparse { entries: entries.clone(), clusters }
+            }
+        }
+    }
+
+    // Reuses the memory of dense counts.
+    fn clone_from(&mut self, source: &Self) {
+        match (&mut *self, source) {
+            (&mut Counts::Dense(ref mut entries), Counts::Dense(from)) => {
+                entries.clone_from(from)
+            }
+            _ => *self = source.clone(),
+        }
+    }
+}
+
+#[test]
+fn matches_lengths() {
+    use alloc::vec::Vec;
+
+    let lens: Vec<usize> = (0..100u64).map(|i| ((i * 37) % 11) as usize).collect();
+    for &sparse in &[false, true] {
+        let mut counts = Counts::new(100, sparse);
+        counts.rebuild(100, lens.iter().enumerate().map(|(idx, &len)| (idx as u64, len)));
+        let mut lens = lens.clone();
+        for step in 0..200 {
+            let idx = (step * 13) % 100;
+            let new = (step * 7) % 5;
+            counts.update(idx as u64, lens[idx], new);
+            lens[idx] = new;
+            let total: usize = lens.iter().sum();
+            for idx in 0..=100 {
+                assert_eq!(counts.before(idx as u64), lens[..idx].iter().sum::<usize>());
+            }
+            for k in 0..total {
+                let (idx, before) = counts.find(k);
+                assert!(before <= k && k < before + lens[idx as usize]);
+            }
+        }
+        // a dense tree cut short at the last non-empty cluster still counts correctly
+        let last = lens.iter().rposition(|&len| len > 0).unwrap() + 1;
+        counts.shrink(last);
+        for idx in 0..=100 {
+            assert_eq!(counts.before(idx as u64), lens[..idx.min(last)].iter().sum::<usize>());
+        }
+    }
+}
@@ -0,0 +1,412 @@
+//! Cursors, for stepping back and forth through a tree from a position found by a search.
+//!
+//! Like the cursors on `BTreeMap`, a cursor points either at a value or at a "ghost" position
+//! past both ends of the tree, which moving forwards leaves for the minimum and moving
+//! backwards leaves for the maximum.
+//!
+//! Batches of sorted queries are answered by moving a single cursor forwards, in
+//! `VEBTree::has_sorted` and `VEBTree::find_next_sorted`.
+
+#[cfg(test)]
+use alloc::collections::BTreeSet;
+use alloc::vec::Vec;
+#[cfg(test)]
+use core::cmp;
+
+use {VEBTree, VebInt};
+#[cfg(test)]
+use {random_tree, xorshift};
+
+/// A read-only cursor over a `VEBTree`, created by `VEBTree::cursor_at`.
+///
+/// The cursor keeps the path from the root down to the node holding its value. A step only
+/// climbs as far as the lowest node on that path which has a value beyond the current one,
+/// so stepping between values in the same cluster doesn't start from the root.
+#[derive(Debug, Clone)]
+pub struct Cursor<'a, T: VebInt + 'a> {
+    tree: &'a VEBTree<T>,
+    // each node along with the value its range starts at; empty at the ghost position
+    path: Vec<(&'a VEBTree<T>, u64)>,
+    current: Option<T>,
+}
+
+/// A cursor over a `VEBTree` which can remove values, created by `VEBTree::cursor_at_mut`.
+///
+/// Removing a value can free the nodes on the path to it, so unlike `Cursor` this doesn't
+/// keep a path, and every step takes O(log(log(U))) time.
+#[derive(Debug)]
+pub struct CursorMut<'a, T: VebInt + 'a> {
+    tree: &'a mut VEBTree<T>,
+    current: Option<T>,
+}
+
+impl<T: VebInt> VEBTree<T> {
+    /// Returns a cursor pointing at the smallest value in the tree which is greater than or
+    /// equal to x, or at the ghost position if there isn't one.
+    /// Takes O(log(log(U))) time, where U is the argument to the constructor.
+    pub fn cursor_at<'a>(&'a self, x: T) -> Cursor<'a, T> {
+        let mut cursor = Cursor {
+            tree: self,
+            path: vec![],
+            current: self.find_next_geq(x),
+        };
+        if cursor.current.is_some() {
+            cursor.path.push((self, 0));
+            cursor.descend();
+        }
+        cursor
+    }
+
+    /// Returns a cursor which can remove values, pointing at the smallest value in the tree
+    /// which is greater than or equal to x, or at the ghost position if there isn't one.
+    /// Takes O(log(log(U))) time, where U is the argument to the constructor.
+    pub fn cursor_at_mut<'a>(&'a mut self, x: T) -> CursorMut<'a, T> {
+        let current = self.find_next_geq(x);
+        CursorMut { tree: self, current }
+    }
+
+    /// Returns whether each of a batch of values is in the tree, the same as calling `has` on
+    /// each of them.
+    /// Meant for sorted batches: a single cursor moves forwards through the tree, so a query
+    /// close to the one before it only climbs as far up the tree as it has to. A query smaller
+    /// than the one before it is still answered correctly, but starts again from the root.
+    pub fn has_sorted(&self, queries: &[T]) -> Vec<bool> {
+        self.sorted_queries(queries, |cursor, x| {
+            cursor.seek(x.to_u64());
+            cursor.current == Some(x)
+        })
+    }
+
+    /// Returns the result of `find_next` for each of a batch of values, walking the tree
+    /// with a single cursor as `has_sorted` does. Queries which aren't in ascending order are
+    /// answered correctly, but start again from the root.
+    pub fn find_next_sorted(&self, queries: &[T]) -> Vec<Option<T>> {
+        self.sorted_queries(queries, |cursor, x| {
+            match x.to_u64().checked_add(1) {
+                Some(next) => {
+                    cursor.seek(next);
+                    cursor.current
+                }
+                None => None,
+            }
+        })
+    }
+
+    // Answers each query with a cursor which is only moved back to the start when a query
+    // is smaller than the one before it.
+    fn sorted_queries<'a, R, F>(&'a self, queries: &[T], mut answer: F) -> Vec<R>
+        where F: FnMut(&mut Cursor<'a, T>, T) -> R
+    {
+        let start = T::from_u64(0);
+        let mut cursor = self.cursor_at(start);
+        let mut last = start;
+        queries.iter().map(|&x| {
+            if x < last {
+                cursor = self.cursor_at(start);
+            }
+            last = x;
+            answer(&mut cursor, x)
+        }).collect()
+    }
+}
+
+impl<'a, T: VebInt> Cursor<'a, T> {
+    /// Returns the value the cursor points at, or None at the ghost position.
+    pub fn current(&self) -> Option<T> {
+        self.current
+    }
+
+    /// Moves to the next value, or from the maximum to the ghost position, or from the ghost
+    /// position to the minimum.
+    /// Takes O(log(log(U))) time, and constant time within a leaf.
+    pub fn move_next(&mut self) {
+        let x = match self.current {
+            Some(x) => x.to_u64(),
+            None => return self.restart(self.tree.minimum()),
+        };
+        while let Some(&(node, base)) = self.path.last() {
+            if x < base + node.max.to_u64() {
+                let next = node.find_next(T::from_u64(x - base)).unwrap();
+                self.current = Some(T::from_u64(base + next.to_u64()));
+                return self.descend();
+            }
+            self.path.pop();
+        }
+        self.current = None;
+    }
+
+    /// Moves to the previous value, or from the minimum to the ghost position, or from the
+    /// ghost position to the maximum.
+    /// Takes O(log(log(U))) time, and constant time within a leaf.
+    pub fn move_prev(&mut self) {
+        let x = match self.current {
+            Some(x) => x.to_u64(),
+            None => return self.restart(self.tree.maximum()),
+        };
+        while let Some(&(node, base)) = self.path.last() {
+            if x > base + node.min.to_u64() {
+                let prev = node.find_prev(T::from_u64(x - base)).unwrap();
+                self.current = Some(T::from_u64(base + prev.to_u64()));
+                return self.descend();
+            }
+            self.path.pop();
+        }
+        self.current = None;
+    }
+
+    // Moves forwards to the smallest value greater than or equal to x, climbing only as far as
+    // the lowest node on the path which holds such a value. Does nothing if the current value
+    // is already at least x, or at the ghost position.
+    fn seek(&mut self, x: u64) {
+        if self.current.is_none_or(|current| current.to_u64() >= x) {
+            return;
+        }
+        while let Some(&(node, base)) = self.path.last() {
+            if x <= base + node.max.to_u64() {
+                let next = node.find_next_geq(T::from_u64(x - base)).unwrap();
+                self.current = Some(T::from_u64(base + next.to_u64()));
+                return self.descend();
+            }
+            self.path.pop();
+        }
+        self.current = None;
+    }
+
+    // Moves from the ghost position to one end of the tree.
+    fn restart(&mut self, x: Option<T>) {
+        self.current = x;
+        if x.is_some() {
+            self.path.push((self.tree, 0));
+            self.descend();
+        }
+    }
+
+    // Extends the path from its last node down to the node holding the current value: either
+    // a leaf, or a node whose minimum it is.
+    fn descend(&mut self) {
+        let x = self.current.unwrap().to_u64();
+        loop {
+            let (node, base) = *self.path.last().unwrap();
+            let low = T::from_u64(x - base);
+            if node.children.bits().is_some() || low == node.min {
+                return;
+            }
+            let idx = node.high(low);
+            let shift = node.sqrt_universe.trailing_zeros();
+            let subtree = node.children.get(idx.to_u64()).unwrap();
+            self.path.push((subtree, base + (idx.to_u64() << shift)));
+        }
+    }
+}
+
+impl<'a, T: VebInt> CursorMut<'a, T> {
+    /// Returns the value the cursor points at, or None at the ghost position.
+    pub fn current(&self) -> Option<T> {
+        self.current
+    }
+
+    /// Moves to the next value, or from the maximum to the ghost position, or from the ghost
+    /// position to the minimum.
+    /// Takes O(log(log(U))) time, where U is the argument to the constructor.
+    pub fn move_next(&mut self) {
+        self.current = match self.current {
+            Some(x) => self.tree.find_next(x),
+            None => self.tree.minimum(),
+        };
+    }
+
+    /// Moves to the previous value, or from the minimum to the ghost position, or from the
+    /// ghost position to the maximum.
+    /// Takes O(log(log(U))) time, where U is the argument to the constructor.
+    pub fn move_prev(&mut self) {
+        self.current = match self.current {
+            Some(x) => self.tree.find_prev(x),
+            None => self.tree.maximum(),
+        };
+    }
+
+    /// Removes the value the cursor points at and moves to the next one, returning the value
+    /// which was removed. Does nothing and returns None at the ghost position.
+    /// Takes O(log(U)) time, where U is the argument to the constructor.
+    pub fn remove_current(&mut self) -> Option<T> {
+        let x = self.current?;
+        self.current = self.tree.find_next(x);
+        self.tree.delete(x);
+        Some(x)
+    }
+}
+
+#[test]
+fn empty() {
+    let mut tree = VEBTree::<u32>::new(1000).unwrap();
+    let mut cursor = tree.cursor_at(0);
+    assert_eq!(cursor.current(), None);
+    cursor.move_next();
+    assert_eq!(cursor.current(), None);
+    cursor.move_prev();
+    assert_eq!(cursor.current(), None);
+    let mut cursor = tree.cursor_at_mut(5);
+    assert_eq!(cursor.remove_current(), None);
+    cursor.move_prev();
+    assert_eq!(cursor.current(), None);
+}
+
+#[test]
+fn steps_past_both_ends() {
+    let tree = VEBTree::<u32>::from_slice(1000, &[3, 70, 999]).unwrap();
+    let mut cursor = tree.cursor_at(4);
+    assert_eq!(cursor.current(), Some(70));
+    cursor.move_next();
+    assert_eq!(cursor.current(), Some(999));
+    cursor.move_next();
+    assert_eq!(cursor.current(), None);
+    cursor.move_next();
+    assert_eq!(cursor.current(), Some(3));
+    cursor.move_prev();
+    assert_eq!(cursor.current(), None);
+    cursor.move_prev();
+    assert_eq!(cursor.current(), Some(999));
+    cursor.move_prev();
+    assert_eq!(cursor.current(), Some(70));
+
+    // values past the end of the tree, or the universe, give the ghost
+    assert_eq!(tree.cursor_at(1000).current(), None);
+    let mut cursor = tree.cursor_at(u32::MAX);
+    assert_eq!(cursor.current(), None);
+    cursor.move_prev();
+    assert_eq!(cursor.current(), Some(999));
+    assert_eq!(tree.cursor_at(70).current(), Some(70));
+}
+
+// Random walks back and forth, against a BTreeSet.
+#[test]
+fn matches_btree_set() {
+    for &(universe, count) in &[(2, 2), (64, 30), (5000, 300), (1 << 20, 3000)] {
+        let tree = random_tree::<u32>(universe, count, universe);
+        let model = BTreeSet::from(&tree);
+        let mut state = universe | 1;
+        for _ in 0..20 {
+            let start = (xorshift(&mut state) % universe) as u32;
+            let mut cursor = tree.cursor_at(start);
+            let mut expected = model.range(start..).next().cloned();
+            for _ in 0..50 {
+                assert_eq!(cursor.current(), expected);
+                if xorshift(&mut state) % 3 == 2 {
+                    cursor.move_prev();
+                    expected = match expected {
+                        Some(x) => model.range(..x).next_back().cloned(),
+                        None => model.iter().next_back().cloned(),
+                    };
+                } else {
+                    cursor.move_next();
+                    expected = match expected {
+                        Some(x) => model.range(x..).nth(1).cloned(),
+                        None => model.iter().next().cloned(),
+                    };
+                }
+            }
+        }
+
+        // a full walk in each direction visits every value
+        let mut cursor = tree.cursor_at(0);
+        let mut forwards = Vec::new();
+        while let Some(x) = cursor.current() {
+            forwards.push(x);
+            cursor.move_next();
+        }
+        assert_eq!(forwards, tree.to_sorted_vec());
+        cursor.move_prev();
+        let mut backwards = Vec::new();
+        while let Some(x) = cursor.current() {
+            backwards.push(x);
+            cursor.move_prev();
+        }
+        backwards.reverse();
+        assert_eq!(backwards, forwards);
+    }
+}
+
+// Batches of queries, sorted and not, against the single queries.
+#[test]
+fn sorted_queries() {
+    let tree = VEBTree::<u32>::from_slice(1000, &[3, 70, 71, 999]).unwrap();
+    let queries = [0, 3, 3, 4, 70, 71, 72, 998, 999, 1000, u32::MAX];
+    assert_eq!(tree.has_sorted(&queries),
+               [false, true, true, false, true, true, false, false, true, false, false]);
+    assert_eq!(tree.find_next_sorted(&queries),
+               [Some(3), Some(70), Some(70), Some(70), Some(71), Some(999), Some(999), Some(999),
+                None, None, None]);
+    assert!(VEBTree::<u32>::new(16).unwrap().has_sorted(&[0, 5]).iter().all(|&b| !b));
+    assert!(tree.find_next_sorted(&[]).is_empty());
+
+    for &(universe, count) in &[(2, 1), (64, 30), (5000, 300), (1 << 20, 3000), (1 << 32, 5000)] {
+        let tree = random_tree::<u32>(universe, count, universe);
+        let mut state = universe | 1;
+        // clustered runs of queries around random points, and uniform ones
+        let mut queries = Vec::new();
+        for _ in 0..200 {
+            let centre = xorshift(&mut state) % universe;
+            queries.extend((0..20).map(|i| cmp::min(centre + i * 3, universe + 1) as u32));
+        }
+        for _ in 0..1000 {
+            queries.push((xorshift(&mut state) % (universe + 2)) as u32);
+        }
+        queries.sort();
+        let check = |queries: &[u32]| {
+            let has: Vec<bool> = queries.iter().map(|&x| tree.has(x)).collect();
+            let next: Vec<Option<u32>> = queries.iter().map(|&x| tree.find_next(x)).collect();
+            assert_eq!(tree.has_sorted(queries), has);
+            assert_eq!(tree.find_next_sorted(queries), next);
+        };
+        check(&queries);
+        // every stored value, and its neighbours
+        let values: Vec<u32> = tree.iter()
+            .flat_map(|x| vec![x.saturating_sub(1), x, x + 1])
+            .collect();
+        check(&values);
+        // out of order, which still has to give the right answers
+        queries.reverse();
+        check(&queries);
+        queries.swap(0, 500);
+        check(&queries);
+    }
+}
+
+#[test]
+fn remove_current() {
+    // 1000 and 1001 are alone in their cluster, and 5000 is the maximum
+    let mut tree = VEBTree::<u32>::from_slice(65536, &[3, 5, 1000, 1001, 5000])
+        .unwrap();
+    {
+        let mut cursor = tree.cursor_at_mut(1000);
+        assert_eq!(cursor.remove_current(), Some(1000));
+        assert_eq!(cursor.current(), Some(1001));
+        // the cluster and its place in the summary go here
+        assert_eq!(cursor.remove_current(), Some(1001));
+        assert_eq!(cursor.current(), Some(5000));
+        cursor.move_prev();
+        assert_eq!(cursor.current(), Some(5));
+        cursor.move_next();
+        assert_eq!(cursor.remove_current(), Some(5000));
+        assert_eq!(cursor.current(), None);
+        assert_eq!(cursor.remove_current(), None);
+        cursor.move_next();
+        assert_eq!(cursor.remove_current(), Some(3));
+        assert_eq!(cursor.current(), Some(5));
+    }
+    assert_eq!(tree.to_sorted_vec(), [5]);
+    assert_eq!(tree.validate(), Ok(()));
+
+    // removing everything through one cursor
+    let mut tree = random_tree::<u32>(1 << 16, 2000, 9);
+    let values = tree.to_sorted_vec();
+    {
+        let mut cursor = tree.cursor_at_mut(0);
+        for &x in &values {
+            assert_eq!(cursor.remove_current(), Some(x));
+        }
+        assert_eq!(cursor.current(), None);
+    }
+    assert!(tree.is_empty());
+    assert_eq!(tree.validate(), Ok(()));
+}
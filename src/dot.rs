@@ -0,0 +1,85 @@
+//! Graphviz output, for looking at the structure of a tree while debugging.
+//!
+//! Every allocated node becomes a record showing its universe and, unless it's empty, its
+//! minimum, maximum and number of values; leaves also show their bitmask. Edges to clusters
+//! are labelled with the cluster index, and the edge to a summary is dashed. Nodes are numbered
+//! in the order they're visited, clusters in ascending order after the summary, so the same
+//! tree always gives the same output.
+
+use alloc::string::String;
+use core::fmt::Write;
+
+use {VEBTree, VebInt};
+
+impl<T: VebInt> VEBTree<T> {
+    /// Returns the structure of the tree as a Graphviz digraph, which can be rendered with
+    /// `dot -Tsvg`. Empty clusters kept allocated by `clear` aren't drawn. Meant for debugging;
+    /// the exact output may change between releases.
+    /// Takes O(N) time, where N is the number of allocated subtrees.
+    pub fn to_dot(&self) -> String {
+        let mut out = String::from("digraph VEBTree {\n    node [shape=record];\n");
+        self.write_dot(&mut out, &mut 0);
+        out.push_str("}\n");
+        out
+    }
+
+    // Writes this node and everything below it, returning the id of this node.
+    fn write_dot(&self, out: &mut String, next_id: &mut usize) -> usize {
+        let id = *next_id;
+        *next_id += 1;
+        // writing to a String can't fail
+        write!(out, "    n{} [label=\"{{U={}", id, self.universe).unwrap();
+        if !self.is_empty() {
+            write!(out, "|min={:?}|max={:?}|len={}", self.min, self.max, self.len).unwrap();
+        }
+        if let Some(bits) = self.children.bits() {
+            write!(out, "|bits={:#x}", bits).unwrap();
+        }
+        out.push_str("}\"];\n");
+
+        if let Some(ref summary) = self.summary {
+            let child = summary.write_dot(out, next_id);
+            writeln!(out, "    n{} -> n{} [label=\"summary\", style=dashed];", id, child).unwrap();
+        }
+        for (idx, subtree) in self.children.iter().filter(|&(_, subtree)| !subtree.is_empty()) {
+            let child = subtree.write_dot(out, next_id);
+            writeln!(out, "    n{} -> n{} [label=\"{}\"];", id, child, idx).unwrap();
+        }
+        id
+    }
+}
+
+#[test]
+fn structure() {
+    let leaf = VEBTree::<u32>::from_slice(64, &[1, 5, 63]).unwrap();
+    assert_eq!(leaf.to_dot(),
+               "digraph VEBTree {\n    node [shape=record];\n    \
+                n0 [label=\"{U=64|min=1|max=63|len=3|bits=0x8000000000000022}\"];\n}\n");
+
+    let mut tree = VEBTree::<u32>::from_slice(4096, &[3, 70, 200, 4000]).unwrap();
+    let dot = tree.to_dot();
+    assert_eq!(dot, tree.clone().to_dot());
+    assert!(dot.starts_with("digraph VEBTree {\n"));
+    assert!(dot.ends_with("}\n"));
+    for line in &[
+        "n0 [label=\"{U=4096|min=3|max=4000|len=4}\"];",
+        // the summary holds clusters 1, 3 and 62
+        "n1 [label=\"{U=64|min=1|max=62|len=3|bits=0x400000000000000a}\"];",
+        "n0 -> n1 [label=\"summary\", style=dashed];",
+        "n2 [label=\"{U=64|min=6|max=6|len=1|bits=0x40}\"];",
+        "n0 -> n2 [label=\"1\"];",
+        "n0 -> n3 [label=\"3\"];",
+        "n4 [label=\"{U=64|min=32|max=32|len=1|bits=0x100000000}\"];",
+        "n0 -> n4 [label=\"62\"];",
+    ] {
+        assert!(dot.contains(line), "{} missing from\n{}", line, dot);
+    }
+    assert_eq!(dot.matches("->").count(), 4);
+
+    // clusters emptied by clear aren't drawn
+    tree.clear();
+    assert_eq!(tree.to_dot(),
+               "digraph VEBTree {\n    node [shape=record];\n    \
+                n0 [label=\"{U=4096}\"];\n    n1 [label=\"{U=64|bits=0x0}\"];\n    \
+                n0 -> n1 [label=\"summary\", style=dashed];\n}\n");
+}
@@ -0,0 +1,69 @@
+//! The error type returned by the fallible methods in this crate.
+
+use core::fmt;
+
+/// The reasons an operation can fail. More variants may be added in the future, so matches on
+/// this need a wildcard arm.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Error {
+    /// The universe must hold at least two values.
+    UniverseTooSmall,
+    /// The universe holds values which don't fit in the key type.
+    UniverseTooLarge,
+    /// A value is outside of the range [0, U).
+    ValueOutOfRange,
+    /// Values which had to be strictly increasing weren't, which includes duplicates.
+    NotStrictlyIncreasing,
+    /// Two trees in a set operation have different universes.
+    UniverseMismatch,
+    /// A universe had to be picked to fit the values, but there weren't any.
+    NoValues,
+    /// A snapshot ended before all of its data had been read.
+    TruncatedSnapshot,
+    /// A snapshot started with an encoding which isn't recognised.
+    UnknownSnapshotFormat,
+    /// A snapshot had more bytes after the end of its data.
+    TrailingBytes,
+    /// A number in a snapshot was encoded with more bytes than any `u64` needs.
+    VarintTooLong,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            Error::UniverseTooSmall => "universe size must be > 1",
+            Error::UniverseTooLarge => "universe too big",
+            Error::ValueOutOfRange => "value out of range",
+            Error::NotStrictlyIncreasing => "values not strictly increasing",
+            Error::UniverseMismatch => "universes differ",
+            Error::NoValues => "no values",
+            Error::TruncatedSnapshot => "truncated snapshot",
+            Error::UnknownSnapshotFormat => "unknown snapshot format",
+            Error::TrailingBytes => "trailing bytes in snapshot",
+            Error::VarintTooLong => "varint too long",
+        })
+    }
+}
+
+#[cfg(feature = "std")]
+impl ::std::error::Error for Error {}
+
+#[cfg(feature = "std")]
+#[test]
+fn composes_with_question_mark() {
+    use std::boxed::Box;
+    use std::error::Error as StdError;
+    use std::string::ToString;
+    use VEBTree;
+
+    fn build(universe: u64) -> Result<VEBTree<u8>, Box<dyn StdError>> {
+        let mut tree = VEBTree::new(universe)?;
+        tree.try_insert(200)?;
+        Ok(tree)
+    }
+    assert!(build(256).is_ok());
+    assert_eq!(build(1).unwrap_err().to_string(), "universe size must be > 1");
+    assert_eq!(build(300).unwrap_err().to_string(), "universe too big");
+    assert_eq!(build(100).unwrap_err().to_string(), "value out of range");
+}
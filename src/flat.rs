@@ -0,0 +1,736 @@
+//! A van Emde Boas tree whose nodes all live in a single arena.
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::convert::TryFrom;
+
+use {Error, SuccessorSet, VebInt};
+
+// The position of a node in the arena.
+type NodeId = u32;
+
+#[derive(Debug, Clone, Copy)]
+struct Node<T> {
+    // as in VEBTree: min > max if the node is empty, and the minimum isn't stored in any
+    // cluster
+    min: T,
+    max: T,
+    universe: u64,
+    // the number of values covered by each cluster; always a power of two
+    sqrt_universe: u64,
+    summary: Option<NodeId>,
+    // the position in the slot arena of this node's first cluster; the slots are allocated
+    // once a cluster is first needed
+    children: Option<usize>,
+}
+
+impl<T: VebInt> Node<T> {
+    fn is_empty(&self) -> bool {
+        self.min > self.max
+    }
+
+    fn high(&self, x: T) -> T {
+        T::from_u64(x.to_u64() >> self.sqrt_universe.trailing_zeros())
+    }
+
+    fn low(&self, x: T) -> T {
+        T::from_u64(x.to_u64() & (self.sqrt_universe - 1))
+    }
+
+    fn index(&self, i: T, j: T) -> T {
+        T::from_u64(i.to_u64() << self.sqrt_universe.trailing_zeros() | j.to_u64())
+    }
+}
+
+/// A van Emde Boas tree storing values of type `T` in the range [0, U), with the same
+/// behaviour as `VEBTree` but a flat memory layout.
+/// Every node is kept in one vector and every node's cluster slots in another, with nodes
+/// referring to each other by index, so the whole tree takes a handful of allocations instead
+/// of several per node. Clusters which become empty keep their node, which is reused if a
+/// value is inserted into that cluster again; `clear` releases everything.
+#[derive(Debug, Clone)]
+pub struct FlatVEBTree<T: VebInt> {
+    nodes: Vec<Node<T>>,
+    slots: Vec<Option<NodeId>>,
+    len: usize,
+}
+
+// the root is always the first node
+const ROOT: NodeId = 0;
+
+impl<T: VebInt> FlatVEBTree<T> {
+    /// Generates a new, empty tree. Will return an error under the same conditions as
+    /// `VEBTree::new`.
+    pub fn new(max_elem: u64) -> Result<Self, Error> {
+        if max_elem <= 1 {
+            Err(Error::UniverseTooSmall)
+        } else if max_elem - 1 > T::MAX_VALUE {
+            Err(Error::UniverseTooLarge)
+        } else {
+            let mut tree = FlatVEBTree {
+                nodes: vec![],
+                slots: vec![],
+                len: 0,
+            };
+            tree.alloc_node(max_elem);
+            Ok(tree)
+        }
+    }
+
+    /// Generates a new tree containing every value in a slice. Will return an error under the
+    /// same conditions as `VEBTree::from_slice`.
+    pub fn from_slice(max_elem: u64, values: &[T]) -> Result<Self, Error> {
+        let mut tree = FlatVEBTree::new(max_elem)?;
+        for &x in values {
+            if x.to_u64() >= max_elem {
+                return Err(Error::ValueOutOfRange);
+            }
+            tree.insert(x);
+        }
+        Ok(tree)
+    }
+
+    // Adds an empty node (and, recursively, its summary) to the arena.
+    fn alloc_node(&mut self, universe: u64) -> NodeId {
+        // the same geometry as VEBTree::new
+        let bits = 64 - (universe - 1).leading_zeros();
+        let id = NodeId::try_from(self.nodes.len()).expect("too many nodes for a FlatVEBTree");
+        self.nodes.push(Node {
+            min: T::from_u64(1),
+            max: T::from_u64(0),
+            universe,
+            sqrt_universe: 1 << (bits / 2),
+            summary: None,
+            children: None,
+        });
+        if universe > 2 {
+            let summary = self.alloc_node(1 << (bits - bits / 2));
+            self.nodes[id as usize].summary = Some(summary);
+        }
+        id
+    }
+
+    fn node(&self, id: NodeId) -> Node<T> {
+        self.nodes[id as usize]
+    }
+
+    fn node_mut(&mut self, id: NodeId) -> &mut Node<T> {
+        &mut self.nodes[id as usize]
+    }
+
+    // Returns the node for a non-empty cluster.
+    fn cluster(&self, node: &Node<T>, idx: T) -> Option<NodeId> {
+        node.children
+            .and_then(|first| self.slots[first + idx.to_u64() as usize])
+            .filter(|&child| !self.node(child).is_empty())
+    }
+
+    // Returns the node for a cluster, allocating it (and the node's slots) if needed. The
+    // node may be empty.
+    fn cluster_or_alloc(&mut self, id: NodeId, idx: T) -> NodeId {
+        let node = self.node(id);
+        let first = match node.children {
+            Some(first) => first,
+            None => {
+                let first = self.slots.len();
+                let clusters = self.node(node.summary.unwrap()).universe as usize;
+                self.slots.resize(first + clusters, None);
+                self.node_mut(id).children = Some(first);
+                first
+            }
+        };
+        let slot = first + idx.to_u64() as usize;
+        match self.slots[slot] {
+            Some(child) => child,
+            None => {
+                let child = self.alloc_node(node.sqrt_universe);
+                self.slots[slot] = Some(child);
+                child
+            }
+        }
+    }
+
+    /// Returns the lowest value stored in the tree, or None if it's empty.
+    pub fn minimum(&self) -> Option<T> {
+        let root = self.node(ROOT);
+        if root.is_empty() { None } else { Some(root.min) }
+    }
+
+    /// Returns the highest value stored in the tree, or None if it's empty.
+    pub fn maximum(&self) -> Option<T> {
+        let root = self.node(ROOT);
+        if root.is_empty() { None } else { Some(root.max) }
+    }
+
+    /// Returns the size of the universe, the argument to the constructor.
+    pub fn universe(&self) -> u64 {
+        self.node(ROOT).universe
+    }
+
+    /// Returns the number of values stored in the tree.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns true if the tree contains no values.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns true if the tree contains x. Values outside of the universe are never present.
+    /// Takes O(log(log(U))) time, where U is the argument to the constructor.
+    pub fn has(&self, mut x: T) -> bool {
+        if x.to_u64() >= self.universe() {
+            return false;
+        }
+        let mut node = self.node(ROOT);
+        loop {
+            if node.is_empty() {
+                return false;
+            } else if x == node.min || x == node.max {
+                return true;
+            } else if node.universe == 2 {
+                return false;
+            }
+            match self.cluster(&node, node.high(x)) {
+                Some(child) => {
+                    x = node.low(x);
+                    node = self.node(child);
+                }
+                None => return false,
+            }
+        }
+    }
+
+    /// Finds the next highest value in the tree, or None if it doesn't exist.
+    /// Takes O(log(log(U))) time, where U is the argument to the constructor.
+    pub fn find_next(&self, x: T) -> Option<T> {
+        if x.to_u64() >= self.universe() {
+            return None;
+        }
+        self.find_next_at(ROOT, x)
+    }
+
+    fn find_next_at(&self, id: NodeId, x: T) -> Option<T> {
+        let node = self.node(id);
+        if node.is_empty() {
+            None
+        } else if node.universe == 2 {
+            if x.to_u64() == 0 && node.max.to_u64() == 1 {
+                Some(node.max)
+            } else {
+                None
+            }
+        } else if x < node.min {
+            Some(node.min)
+        } else {
+            let idx = node.high(x);
+            if let Some(child) = self.cluster(&node, idx) {
+                if node.low(x) < self.node(child).max {
+                    let low = self.find_next_at(child, node.low(x)).unwrap();
+                    return Some(node.index(idx, low));
+                }
+            }
+            // Since universe > 2, we know summary exists.
+            self.find_next_at(node.summary.unwrap(), idx).map(|next_index| {
+                let child = self.cluster(&node, next_index).unwrap();
+                node.index(next_index, self.node(child).min)
+            })
+        }
+    }
+
+    /// Finds the next lowest value in the tree, or None if it doesn't exist.
+    /// Takes O(log(log(U))) time, where U is the argument to the constructor.
+    pub fn find_prev(&self, x: T) -> Option<T> {
+        if x.to_u64() >= self.universe() {
+            return self.maximum();
+        }
+        self.find_prev_at(ROOT, x)
+    }
+
+    fn find_prev_at(&self, id: NodeId, x: T) -> Option<T> {
+        // the mirror image of find_next_at
+        let node = self.node(id);
+        if node.is_empty() {
+            None
+        } else if node.universe == 2 {
+            if x.to_u64() == 1 && node.min.to_u64() == 0 {
+                Some(node.min)
+            } else {
+                None
+            }
+        } else if x > node.max {
+            Some(node.max)
+        } else {
+            let idx = node.high(x);
+            if let Some(child) = self.cluster(&node, idx) {
+                if node.low(x) > self.node(child).min {
+                    let low = self.find_prev_at(child, node.low(x)).unwrap();
+                    return Some(node.index(idx, low));
+                }
+            }
+            // the minimum isn't in any cluster, so it has to be checked separately
+            match self.find_prev_at(node.summary.unwrap(), idx) {
+                Some(prev_index) => {
+                    let child = self.cluster(&node, prev_index).unwrap();
+                    Some(node.index(prev_index, self.node(child).max))
+                }
+                None if x > node.min => Some(node.min),
+                None => None,
+            }
+        }
+    }
+
+    /// Returns an iterator over the values in the tree, in ascending order.
+    pub fn iter<'a>(&'a self) -> FlatIter<'a, T> {
+        FlatIter {
+            tree: self,
+            front: self.minimum(),
+            back: self.maximum(),
+            remaining: self.len,
+        }
+    }
+
+    /// Returns every value in the tree, in ascending order.
+    /// Takes O(N log(log(U))) time, where N is the number of values in the tree.
+    pub fn to_sorted_vec(&self) -> Vec<T> {
+        self.iter().collect()
+    }
+
+    /// Insert a value into the tree. Returns true if the value was not already present. Panics
+    /// if the value is outside of the range [0, U).
+    /// Takes O(log(log(U))) time, where U is the argument to the constructor.
+    pub fn insert(&mut self, x: T) -> bool {
+        assert!(x.to_u64() < self.universe(), "value out of range");
+        let inserted = self.insert_at(ROOT, x);
+        if inserted {
+            self.len += 1;
+        }
+        inserted
+    }
+
+    fn insert_at(&mut self, id: NodeId, mut x: T) -> bool {
+        let node = self.node(id);
+        if node.is_empty() {
+            let node = self.node_mut(id);
+            node.min = x;
+            node.max = x;
+            return true;
+        }
+        if x == node.min || x == node.max {
+            return false;
+        }
+        if x < node.min {
+            // the old minimum gets pushed down into the clusters instead
+            self.node_mut(id).min = x;
+            x = node.min;
+        }
+        let inserted = if node.universe == 2 {
+            true
+        } else {
+            let idx = node.high(x);
+            let child = self.cluster_or_alloc(id, idx);
+            if self.node(child).is_empty() {
+                self.insert_at(child, node.low(x));
+                self.insert_at(node.summary.unwrap(), idx);
+                true
+            } else {
+                self.insert_at(child, node.low(x))
+            }
+        };
+        if x > node.max {
+            self.node_mut(id).max = x;
+        }
+        inserted
+    }
+
+    /// Removes a value from the tree. Returns true if the value was present.
+    /// Takes O(log(log(U))) time, where U is the argument to the constructor.
+    pub fn delete(&mut self, x: T) -> bool {
+        if !self.has(x) {
+            return false;
+        }
+        self.delete_at(ROOT, x);
+        self.len -= 1;
+        true
+    }
+
+    // Removes a value which is known to be present.
+    fn delete_at(&mut self, id: NodeId, mut x: T) {
+        let node = self.node(id);
+        if node.min == node.max {
+            let node = self.node_mut(id);
+            node.min = T::from_u64(1);
+            node.max = T::from_u64(0);
+            return;
+        }
+        if node.universe == 2 {
+            let node = self.node_mut(id);
+            node.min = T::from_u64(1 - x.to_u64());
+            node.max = node.min;
+            return;
+        }
+        let summary = node.summary.unwrap();
+        if x == node.min {
+            // the new minimum is the smallest element in the clusters; pull it up out of its
+            // cluster
+            let first = self.node(summary).min;
+            let child = self.cluster(&node, first).unwrap();
+            x = node.index(first, self.node(child).min);
+            self.node_mut(id).min = x;
+        }
+        let idx = node.high(x);
+        let child = self.cluster(&node, idx).unwrap();
+        self.delete_at(child, node.low(x));
+        if self.node(child).is_empty() {
+            // the empty node stays in its slot, but the summary forgets it
+            self.delete_at(summary, idx);
+        }
+        if x == node.max {
+            let summary = self.node(summary);
+            self.node_mut(id).max = if summary.is_empty() {
+                // only 1 element in the tree
+                self.node(id).min
+            } else {
+                let last = self.cluster(&node, summary.max).unwrap();
+                node.index(summary.max, self.node(last).max)
+            };
+        }
+    }
+
+    /// Removes and returns the lowest value stored in the tree, or None if it's empty.
+    /// Takes O(log(log(U))) time, where U is the argument to the constructor.
+    pub fn pop_min(&mut self) -> Option<T> {
+        let min = self.minimum();
+        if let Some(min) = min {
+            self.delete_at(ROOT, min);
+            self.len -= 1;
+        }
+        min
+    }
+
+    /// Removes and returns the highest value stored in the tree, or None if it's empty.
+    /// Takes O(log(log(U))) time, where U is the argument to the constructor.
+    pub fn pop_max(&mut self) -> Option<T> {
+        let max = self.maximum();
+        if let Some(max) = max {
+            self.delete_at(ROOT, max);
+            self.len -= 1;
+        }
+        max
+    }
+
+    /// Removes every element from the tree and releases the memory used by its clusters.
+    pub fn clear(&mut self) {
+        *self = FlatVEBTree::new(self.universe()).unwrap();
+    }
+
+    /// Checks the internal structure of the tree, returning a description of the first problem
+    /// found and the path to the node it was found in, such as `root.clusters[3].summary`.
+    /// As with `VEBTree::validate`, this is meant for debugging and tests.
+    /// Takes O(N) time, where N is the number of nodes in the arena.
+    pub fn validate(&self) -> Result<(), String> {
+        let len = self.validate_at(ROOT, "root")?;
+        if len != self.len {
+            return Err(format!("len is {} but {} values are stored", self.len, len));
+        }
+        Ok(())
+    }
+
+    // Checks a node and everything below it, returning the number of values it holds.
+    fn validate_at(&self, id: NodeId, path: &str) -> Result<usize, String> {
+        let fail = |problem: &str| Err(format!("{}: {}", path, problem));
+        let node = self.node(id);
+        let summary = node.summary.map(|summary| self.node(summary));
+        if node.is_empty() {
+            return if summary.is_some_and(|summary| !summary.is_empty()) {
+                fail("the node is empty but its summary isn't")
+            } else {
+                Ok(0)
+            };
+        }
+        if node.max.to_u64() >= node.universe {
+            return fail(&format!("{:?} is outside of the universe {}", node.max, node.universe));
+        }
+        let summary = match (node.summary, summary) {
+            (Some(id), Some(summary)) => (id, summary),
+            // only a node over two values has no summary, and both of them are min and max
+            _ => return Ok(if node.min == node.max { 1 } else { 2 }),
+        };
+        self.validate_at(summary.0, &format!("{}.summary", path))?;
+        let mut len = 1;
+        let clusters = summary.1.universe;
+        for idx in (0..clusters).map(T::from_u64) {
+            let listed = self.has_at(summary.0, idx);
+            let child = node.children.and_then(|first| self.slots[first + idx.to_u64() as usize]);
+            let child = match child {
+                Some(child) => child,
+                None if listed => return fail(&format!("cluster {:?} is listed but has no node",
+                                                       idx)),
+                None => continue,
+            };
+            let cluster = self.node(child);
+            if cluster.universe != node.sqrt_universe {
+                return fail(&format!("cluster {:?} has a universe of {} rather than {}",
+                                     idx, cluster.universe, node.sqrt_universe));
+            } else if cluster.is_empty() == listed {
+                return fail(&format!("cluster {:?} is {} but {} in the summary", idx,
+                                     if listed { "empty" } else { "not empty" },
+                                     if listed { "listed" } else { "missing" }));
+            }
+            len += self.validate_at(child, &format!("{}.clusters[{:?}]", path, idx))?;
+            if cluster.is_empty() {
+                continue;
+            }
+            if node.index(idx, cluster.min) <= node.min {
+                return fail(&format!("cluster {:?} holds {:?}, which isn't above the minimum \
+                                      {:?}", idx, node.index(idx, cluster.min), node.min));
+            } else if node.index(idx, cluster.max) > node.max {
+                return fail(&format!("cluster {:?} holds {:?}, which is above the maximum \
+                                      {:?}", idx, node.index(idx, cluster.max), node.max));
+            }
+        }
+        // the maximum is stored in the clusters unless it is also the minimum
+        let max_stored = if summary.1.is_empty() {
+            None
+        } else {
+            let last = self.cluster(&node, summary.1.max).unwrap();
+            Some(node.index(summary.1.max, self.node(last).max))
+        };
+        if max_stored != Some(node.max).filter(|&max| max != node.min) {
+            return fail(&format!("the largest value in the clusters is {:?} but max is {:?}",
+                                 max_stored, node.max));
+        }
+        Ok(len)
+    }
+
+    // `has`, starting from any node.
+    fn has_at(&self, id: NodeId, mut x: T) -> bool {
+        let mut node = self.node(id);
+        loop {
+            if node.is_empty() {
+                return false;
+            } else if x == node.min || x == node.max {
+                return true;
+            } else if node.universe == 2 {
+                return false;
+            }
+            match self.cluster(&node, node.high(x)) {
+                Some(child) => {
+                    x = node.low(x);
+                    node = self.node(child);
+                }
+                None => return false,
+            }
+        }
+    }
+}
+
+/// An iterator over the values in a `FlatVEBTree`, created by `FlatVEBTree::iter`.
+#[derive(Debug, Clone)]
+pub struct FlatIter<'a, T: VebInt + 'a> {
+    tree: &'a FlatVEBTree<T>,
+    // the next values to yield from either end
+    front: Option<T>,
+    back: Option<T>,
+    // the ends meet once this reaches 0
+    remaining: usize,
+}
+
+impl<'a, T: VebInt> Iterator for FlatIter<'a, T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        let x = self.front;
+        self.front = x.and_then(|x| self.tree.find_next(x));
+        x
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<'a, T: VebInt> DoubleEndedIterator for FlatIter<'a, T> {
+    fn next_back(&mut self) -> Option<T> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        let x = self.back;
+        self.back = x.and_then(|x| self.tree.find_prev(x));
+        x
+    }
+}
+
+impl<'a, T: VebInt> ExactSizeIterator for FlatIter<'a, T> {}
+
+#[cfg(test)]
+use alloc::collections::BTreeSet;
+#[cfg(test)]
+use VEBTree;
+
+impl<T: VebInt> SuccessorSet<T> for FlatVEBTree<T> {
+    fn insert(&mut self, x: T) -> bool {
+        FlatVEBTree::insert(self, x)
+    }
+
+    fn delete(&mut self, x: T) -> bool {
+        FlatVEBTree::delete(self, x)
+    }
+
+    fn has(&self, x: T) -> bool {
+        FlatVEBTree::has(self, x)
+    }
+
+    fn minimum(&self) -> Option<T> {
+        FlatVEBTree::minimum(self)
+    }
+
+    fn maximum(&self) -> Option<T> {
+        FlatVEBTree::maximum(self)
+    }
+
+    fn find_next(&self, x: T) -> Option<T> {
+        FlatVEBTree::find_next(self, x)
+    }
+
+    fn is_empty(&self) -> bool {
+        FlatVEBTree::is_empty(self)
+    }
+
+    fn universe(&self) -> u64 {
+        FlatVEBTree::universe(self)
+    }
+}
+
+#[test]
+fn creation() {
+    assert!(FlatVEBTree::<u32>::new(50).is_ok());
+    assert_eq!(FlatVEBTree::<u32>::new(1).unwrap_err(), Error::UniverseTooSmall);
+    assert_eq!(FlatVEBTree::<u8>::new(257).unwrap_err(), Error::UniverseTooLarge);
+}
+
+#[test]
+fn insert_has_delete() {
+    let mut tree = FlatVEBTree::<u32>::new(256).unwrap();
+    assert!(tree.is_empty());
+    assert_eq!(tree.find_next(0), None);
+    for &x in &[100, 3, 17, 200, 255] {
+        assert!(tree.insert(x));
+    }
+    assert!(!tree.insert(17));
+    assert_eq!(tree.len(), 5);
+    assert_eq!(tree.minimum(), Some(3));
+    assert_eq!(tree.maximum(), Some(255));
+    assert!(tree.has(17) && !tree.has(18) && !tree.has(1000));
+    assert_eq!(tree.find_next(17), Some(100));
+    assert_eq!(tree.find_next(0), Some(3));
+    assert_eq!(tree.find_next(255), None);
+
+    assert!(tree.delete(3));
+    assert!(!tree.delete(3));
+    assert!(!tree.delete(4));
+    assert_eq!(tree.minimum(), Some(17));
+    assert!(tree.delete(255));
+    assert_eq!(tree.maximum(), Some(200));
+    assert_eq!(tree.len(), 3);
+
+    tree.clear();
+    assert!(tree.is_empty());
+    assert_eq!(tree.minimum(), None);
+    assert!(!tree.has(17));
+    assert_eq!(tree.universe(), 256);
+}
+
+#[test]
+fn reuses_emptied_clusters() {
+    let mut tree = FlatVEBTree::<u32>::new(1 << 16).unwrap();
+    tree.insert(1);
+    tree.insert(60000);
+    let nodes = tree.nodes.len();
+    tree.delete(60000);
+    assert!(!tree.has(60000));
+    assert_eq!(tree.find_next(1), None);
+    tree.insert(60001);
+    assert_eq!(tree.nodes.len(), nodes);
+    assert_eq!(tree.find_next(1), Some(60001));
+}
+
+// Runs the same random operations against a FlatVEBTree, a VEBTree and a BTreeSet.
+#[test]
+fn matches_recursive_layout() {
+    for &universe in &[2, 3, 16, 1000, 1 << 16, 1 << 20] {
+        let mut flat = FlatVEBTree::<u32>::new(universe).unwrap();
+        let mut tree = VEBTree::<u32>::new(universe).unwrap();
+        let mut set = BTreeSet::new();
+        let mut state = universe | 1;
+        for _ in 0..3000 {
+            let op = ::xorshift(&mut state) % 3;
+            let x = (::xorshift(&mut state) % universe) as u32;
+            match op {
+                0 | 1 => {
+                    assert_eq!(flat.insert(x), set.insert(x));
+                    tree.insert(x);
+                }
+                _ => {
+                    assert_eq!(flat.delete(x), set.remove(&x));
+                    tree.delete(x);
+                }
+            }
+            assert_eq!(flat.len(), set.len());
+            assert_eq!(flat.minimum(), tree.minimum());
+            assert_eq!(flat.maximum(), tree.maximum());
+            let y = (::xorshift(&mut state) % universe) as u32;
+            assert_eq!(flat.has(y), set.contains(&y));
+            assert_eq!(flat.find_next(y), tree.find_next(y));
+        }
+        let mut next = flat.minimum();
+        for &x in &set {
+            assert_eq!(next, Some(x));
+            next = flat.find_next(x);
+        }
+        assert_eq!(next, None);
+    }
+}
+
+#[test]
+fn large_universe() {
+    let mut tree = FlatVEBTree::<u64>::new(1 << 40).unwrap();
+    tree.insert(5);
+    tree.insert((1 << 40) - 1);
+    tree.insert(1 << 30);
+    assert_eq!(tree.find_next(5), Some(1 << 30));
+    assert_eq!(tree.find_next(1 << 30), Some((1 << 40) - 1));
+    assert!(tree.delete(1 << 30));
+    assert_eq!(tree.find_next(5), Some((1 << 40) - 1));
+}
+
+#[test]
+fn out_of_range_queries() {
+    for &universe in &[2u64, 3, 16, 1000] {
+        let mut tree = FlatVEBTree::<u32>::new(universe).unwrap();
+        tree.insert(0);
+        tree.insert(1);
+        for &x in &[universe, universe + 1, 5000, u64::from(u32::MAX)] {
+            let x = x as u32;
+            assert!(!tree.has(x));
+            assert_eq!(tree.find_next(x), None);
+            assert!(!tree.delete(x));
+            assert_eq!(tree.len(), 2);
+        }
+    }
+}
+
+#[test]
+#[should_panic(expected = "value out of range")]
+fn insert_out_of_range() {
+    let mut tree = FlatVEBTree::<u32>::new(1000).unwrap();
+    tree.insert(1000);
+}
+
@@ -0,0 +1,193 @@
+//! Differential testing against a `BTreeSet`, shared by the fuzz target in `fuzz/` and the
+//! replay tests below. Enabled by the `fuzz` feature; not part of the public API.
+//!
+//! The input is decoded into a `Program`: a universe, the kind of storage and a sequence of
+//! operations. The first byte picks the size of the universe (its low six bits) and whether
+//! the tree is sparse (its high bit), and the next four bytes pick the universe within that
+//! size. Every operation after that takes five bytes, an opcode and a little-endian argument,
+//! and arguments can land a little past the end of the universe so out-of-range values get
+//! exercised too. Leftover bytes are ignored.
+
+use alloc::collections::BTreeSet;
+use alloc::vec::Vec;
+
+use VEBTree;
+
+// Universes stay below 2^32 + 2, so dense storage never needs more than 2^16 cluster slots.
+const MAX_UNIVERSE_BITS: u32 = 32;
+
+/// One call on the tree.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Op {
+    /// `try_insert`, which must fail for values outside of the universe.
+    Insert(u64),
+    /// `delete`.
+    Delete(u64),
+    /// `has`.
+    Has(u64),
+    /// `find_next`.
+    FindNext(u64),
+    /// `find_prev`.
+    FindPrev(u64),
+    /// `minimum`.
+    Minimum,
+    /// `maximum`.
+    Maximum,
+}
+
+/// A decoded fuzz input.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Program {
+    /// The universe passed to the constructor.
+    pub universe: u64,
+    /// Whether the tree is made with `new_sparse`.
+    pub sparse: bool,
+    /// The calls to make, in order.
+    pub ops: Vec<Op>,
+}
+
+fn read_u32(bytes: &[u8]) -> u32 {
+    bytes.iter().rev().fold(0, |acc, &b| (acc << 8) | u32::from(b))
+}
+
+impl Program {
+    /// Decodes a fuzz input, or returns None if it's too short to name a universe.
+    pub fn decode(data: &[u8]) -> Option<Self> {
+        if data.len() < 5 {
+            return None;
+        }
+        let bits = u32::from(data[0] & 0x3f) % (MAX_UNIVERSE_BITS + 1);
+        let universe = 2 + u64::from(read_u32(&data[1..5])) % (1 << bits);
+        // arguments go up to an eighth past the end of the universe
+        let limit = universe + universe / 8 + 2;
+        let ops = data[5..]
+            .chunks(5)
+            .filter(|chunk| chunk.len() == 5)
+            .map(|chunk| {
+                let x = u64::from(read_u32(&chunk[1..])) % limit;
+                match chunk[0] % 7 {
+                    0 | 1 => Op::Insert(x),
+                    2 => Op::Delete(x),
+                    3 => Op::Has(x),
+                    4 => Op::FindNext(x),
+                    5 => Op::FindPrev(x),
+                    _ => if x & 1 == 0 { Op::Minimum } else { Op::Maximum },
+                }
+            })
+            .collect();
+        Some(Program {
+            universe,
+            sparse: data[0] & 0x80 != 0,
+            ops,
+        })
+    }
+
+    /// Runs every operation on a `VEBTree` and a `BTreeSet`, panicking as soon as they
+    /// disagree or the tree's invariants are broken.
+    pub fn check(&self) {
+        let mut tree = if self.sparse {
+            VEBTree::new_sparse(self.universe)
+        } else {
+            VEBTree::new(self.universe)
+        }.unwrap();
+        let mut model = BTreeSet::new();
+        for (i, &op) in self.ops.iter().enumerate() {
+            match op {
+                Op::Insert(x) => {
+                    let expected = if x < self.universe { Ok(model.insert(x)) } else {
+                        Err(::Error::ValueOutOfRange)
+                    };
+                    assert_eq!(tree.try_insert(x), expected, "op {}: {:?}", i, op);
+                }
+                Op::Delete(x) => {
+                    assert_eq!(tree.delete(x), model.remove(&x), "op {}: {:?}", i, op);
+                }
+                Op::Has(x) => {
+                    assert_eq!(tree.has(x), model.contains(&x), "op {}: {:?}", i, op);
+                }
+                Op::FindNext(x) => {
+                    let expected = model.range(x + 1..).next().cloned();
+                    assert_eq!(tree.find_next(x), expected, "op {}: {:?}", i, op);
+                }
+                Op::FindPrev(x) => {
+                    let expected = model.range(..x).next_back().cloned();
+                    assert_eq!(tree.find_prev(x), expected, "op {}: {:?}", i, op);
+                }
+                Op::Minimum => {
+                    let expected = model.iter().next().cloned();
+                    assert_eq!(tree.minimum(), expected, "op {}: {:?}", i, op);
+                }
+                Op::Maximum => {
+                    let expected = model.iter().next_back().cloned();
+                    assert_eq!(tree.maximum(), expected, "op {}: {:?}", i, op);
+                }
+            }
+            assert_eq!(tree.len(), model.len(), "op {}: {:?}", i, op);
+        }
+        assert_eq!(tree.validate(), Ok(()));
+        assert!(tree.iter().eq(model.iter().cloned()));
+    }
+}
+
+/// Decodes and checks a fuzz input; this is the body of the fuzz target.
+pub fn run(data: &[u8]) {
+    if let Some(program) = Program::decode(data) {
+        program.check();
+    }
+}
+
+#[test]
+fn decode() {
+    assert_eq!(Program::decode(&[0, 0, 0, 0]), None);
+    // bits = 4, so the universe is 2 + 0x0103 % 16 and arguments are taken mod 7; the
+    // trailing byte is ignored
+    let program = Program::decode(&[0x84, 3, 1, 0, 0, 1, 9, 0, 0, 0, 13, 2, 0, 0, 0, 7]).unwrap();
+    assert_eq!(program, Program {
+        universe: 5,
+        sparse: true,
+        ops: vec![Op::Insert(2), Op::Minimum],
+    });
+    // bits wrap around at 33, so 63 is 30
+    assert_eq!(Program::decode(&[63, 0xff, 0xff, 0xff, 0xff]).unwrap().universe, 1 << 30 | 1);
+}
+
+// The edge cases the fuzzer is aimed at, written out by hand.
+#[test]
+fn replay() {
+    let cases = [
+        // duplicate inserts into a leaf and into clusters
+        (2, vec![Op::Insert(1), Op::Insert(1), Op::Minimum]),
+        (4096, vec![Op::Insert(775), Op::Insert(775), Op::Delete(775), Op::Insert(775)]),
+        // deleting absent values, including the old minimum of an emptied tree
+        (4096, vec![Op::Delete(5), Op::Insert(5), Op::Delete(5), Op::Delete(5), Op::Minimum]),
+        // universes which aren't powers of two, and values just past their end
+        (102, vec![Op::Insert(101), Op::Insert(102), Op::FindNext(100), Op::FindPrev(103)]),
+        (4099, vec![Op::Insert(4098), Op::Insert(64), Op::Delete(64), Op::FindNext(0),
+                    Op::FindPrev(4098), Op::Insert(4099), Op::Maximum]),
+    ];
+    for &(universe, ref ops) in &cases {
+        for &sparse in &[false, true] {
+            Program { universe, sparse, ops: ops.clone() }.check();
+        }
+    }
+}
+
+// Random inputs, as a quick stand-in for running the fuzzer.
+#[test]
+fn random_inputs() {
+    let mut state = 0x2545_f491_4f6c_dd1d;
+    for _ in 0..300 {
+        let len = 5 + ::xorshift(&mut state) as usize % 500;
+        let mut data = Vec::with_capacity(len);
+        while data.len() < len {
+            let x = ::xorshift(&mut state);
+            // mostly small universes, so values collide
+            if data.is_empty() {
+                data.push((x as u8 % 20) | (x >> 8) as u8 & 0x80);
+            } else {
+                data.push(x as u8);
+            }
+        }
+        run(&data);
+    }
+}
@@ -10,30 +10,210 @@
 #![cfg_attr(feature = "dev", feature(plugin))]
 #![cfg_attr(feature = "dev", plugin(clippy))]
 
+#![no_std]
 
 //! A simple implementation of van Emde Boas trees.
+//!
+//! Only `core` and `alloc` are needed; the default `std` feature can be disabled to use the
+//! crate in `no_std` environments with a global allocator. The `std` feature also adds
+//! `ConcurrentVEBTree`, which can be shared between threads.
+//!
+//! The optional `serde` feature adds serialization, the `proptest` feature implements
+//! `proptest::arbitrary::Arbitrary` for `VEBTree` to generate trees in property tests, and
+//! the `rayon` feature adds `VEBTree::par_iter` for iterating over a tree in parallel. The
+//! `rand` feature adds `VEBTree::choose`, for picking a value uniformly at random, and the
+//! `roaring` feature adds conversions to and from `roaring::RoaringBitmap`. The `stats`
+//! feature counts the operations each tree performs and the work they do, for tuning;
+//! without it the counting compiles away entirely.
 
-use std::mem;
+#[macro_use]
+extern crate alloc;
+#[cfg(any(feature = "std", test))]
+extern crate std;
+#[cfg(feature = "serde")]
+extern crate serde;
+#[cfg(all(feature = "serde", test))]
+extern crate bincode;
+#[cfg(test)]
+extern crate serde_json;
+#[cfg(any(feature = "proptest", test))]
+extern crate proptest;
+#[cfg(feature = "rand")]
+extern crate rand;
+#[cfg(feature = "roaring")]
+extern crate roaring;
+#[cfg(feature = "rayon")]
+extern crate rayon;
 
-/// The van Emde Boas tree itself.
-#[derive(Debug, Clone)]
-pub struct VEBTree {
-    children: Vec<Option<VEBTree>>,
-    summary: Option<Box<VEBTree>>,
-    // special cases of min and max:
-    // if the tree is empty, min > max
+use alloc::string::String;
+use alloc::collections::BTreeSet;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use children::{clone_slot_from, Children};
+use stats::{Op, Probe};
+use core::convert::TryFrom;
+use core::hash::{Hash, Hasher};
+use core::ops::{Bound, RangeBounds, RangeInclusive};
+use core::{cmp, fmt, iter, mem};
+
+#[cfg(any(feature = "proptest", test))]
+mod arbitrary;
+mod bitmap;
+mod children;
+mod counts;
+#[cfg(feature = "std")]
+mod concurrent;
+mod cursor;
+mod dot;
+mod error;
+mod flat;
+#[cfg(any(feature = "fuzz", test))]
+#[doc(hidden)]
+pub mod fuzzing;
+mod map;
+mod multiset;
+mod naive;
+mod offset;
+#[cfg(feature = "rayon")]
+mod parallel;
+mod queue;
+#[cfg(feature = "rand")]
+mod random;
+#[cfg(test)]
+#[path = "../benches/common/mod.rs"]
+mod rng;
+#[cfg(feature = "serde")]
+mod serialize;
+mod snapshot;
+mod stats;
+
+#[cfg(feature = "std")]
+pub use concurrent::ConcurrentVEBTree;
+pub use cursor::{Cursor, CursorMut};
+pub use error::Error;
+pub use flat::{FlatIter, FlatVEBTree};
+pub use map::VEBMap;
+pub use multiset::VEBMultiSet;
+pub use naive::NaiveSet;
+pub use offset::{OffsetCursor, OffsetCursorMut, OffsetIter, OffsetVEBTree};
+#[cfg(feature = "rayon")]
+pub use parallel::ParIter;
+pub use queue::VEBQueue;
+#[cfg(feature = "stats")]
+pub use stats::Stats;
+
+#[cfg(test)]
+use rng::xorshift;
+
+mod sealed {
+    pub trait Sealed {}
+}
+
+/// An unsigned integer type which can be stored in a `VEBTree`. Narrower types use less
+/// memory per node, but limit how large the universe can be. This trait is sealed, and is
+/// implemented for `u8`, `u16`, `u32`, `u64` and `usize`.
+pub trait VebInt: sealed::Sealed + Copy + Ord + fmt::Debug {
+    /// The largest value of this type.
+    const MAX_VALUE: u64;
+
+    /// Widens the value to a u64, which is used for intermediate arithmetic.
+    fn to_u64(self) -> u64;
+
+    /// Narrows a u64 which is known to fit into this type.
+    fn from_u64(x: u64) -> Self;
+}
+
+macro_rules! impl_veb_int {
+    ( $( $t: ty ),* ) => {
+        $(
+            impl sealed::Sealed for $t {}
+
+            #[allow(trivial_numeric_casts)]
+            impl VebInt for $t {
+                const MAX_VALUE: u64 = <$t>::MAX as u64;
+
+                fn to_u64(self) -> u64 {
+                    self as u64
+                }
+
+                fn from_u64(x: u64) -> Self {
+                    x as $t
+                }
+            }
+        )*
+    }
+}
+
+impl_veb_int!(u8, u16, u32, u64, usize);
+
+/// The core operations shared by `VEBTree`, `FlatVEBTree` and `NaiveSet`, so code can be
+/// written once and run against any of them. The trait is object safe.
+pub trait SuccessorSet<T: VebInt> {
+    /// Inserts a value, returning true if it was not already present. Panics if the value is
+    /// outside of the range [0, U).
+    fn insert(&mut self, x: T) -> bool;
+
+    /// Removes a value, returning true if it was present.
+    fn delete(&mut self, x: T) -> bool;
+
+    /// Returns true if the set contains the value.
+    fn has(&self, x: T) -> bool;
+
+    /// Returns the lowest value in the set, or None if it's empty.
+    fn minimum(&self) -> Option<T>;
+
+    /// Returns the highest value in the set, or None if it's empty.
+    fn maximum(&self) -> Option<T>;
+
+    /// Finds the lowest value in the set which is greater than `x`, or None if there isn't one.
+    fn find_next(&self, x: T) -> Option<T>;
+
+    /// Returns true if the set is empty.
+    fn is_empty(&self) -> bool;
+
+    /// Returns the size of the range of values the set can hold.
+    fn universe(&self) -> u64;
+}
+
+/// The van Emde Boas tree itself, storing values of type `T` in the range [0, U).
+///
+/// Cloning a tree is cheap: the clones share their clusters and summaries, and a shared node
+/// is only copied when one of the clones modifies it, so changes through one clone are never
+/// seen through another. A clone takes O(S) time, where S is the number of cluster slots in
+/// the root, and each modification afterwards copies at most the nodes on its path.
+///
+/// Each node also keeps a running count of the values in its clusters, so `rank` and
+/// `select` add up whole clusters instead of visiting them. Keeping the counts up to date
+/// costs O(log(C)) at each level of a node with C clusters, which comes to O(log(U)) for an
+/// insert or delete, rather than the O(log(log(U))) of a plain van Emde Boas tree; queries
+/// which don't count values are unaffected.
+pub struct VEBTree<T: VebInt> {
+    children: Children<T>,
+    summary: Option<Arc<VEBTree<T>>>,
+    // min and max are only meaningful when len > 0, and every method checks is_empty before
+    // looking at them. An empty tree still sets min > max, so a missed check can't match a
+    // real value.
     // if the tree contains only one element, min == max == that element.
-    min: i64,
-    max: i64,
-    universe: i64,
-    sqrt_universe: i64,
+    min: T,
+    max: T,
+    // the number of values stored in this tree, including min and max
+    len: usize,
+    universe: u64,
+    // the number of values covered by each cluster; always a power of two
+    sqrt_universe: u64,
+    // set by new_prealloc: emptied clusters are kept rather than freed, so that inserting and
+    // deleting never allocate
+    prealloc: bool,
+    // every node has counters, but only those of the node an operation was called on change
+    #[cfg(feature = "stats")]
+    counters: stats::Counters,
 }
 
 // helper macros
 
 macro_rules! subtree {
     ( $self_: ident, $x: expr ) => {
-        $self_.children.get($x).expect("child idx out of bounds").as_ref()
+        $self_.children.get($x.to_u64()).filter(|subtree| !subtree.is_empty())
     }
 }
 
@@ -43,61 +223,314 @@ macro_rules! summary {
     }
 }
 
-macro_rules! summary_mut {
-    ( $self_: ident ) => {
-        $self_.summary.as_mut().expect("summary not present")
+// The number of clusters in a tree, and so the universe of its summary. Only meaningful if the
+// universe is larger than 2.
+fn cluster_count(universe: u64) -> u64 {
+    let bits = 64 - (universe - 1).leading_zeros();
+    1 << (bits - bits / 2)
+}
+
+// Nodes whose universe is at most this are leaves, which keep a bit for each value instead of
+// splitting them into clusters.
+const LEAF_UNIVERSE: u64 = 64;
+
+// The bit for x in a leaf, or no bits if x is past the end of any leaf.
+fn bit(x: u64) -> u64 {
+    if x < LEAF_UNIVERSE {
+        1 << x
+    } else {
+        0
+    }
+}
+
+// The bits for every value less than x.
+fn below(x: u64) -> u64 {
+    if x < LEAF_UNIVERSE {
+        (1 << x) - 1
+    } else {
+        !0
+    }
+}
+
+// The bits for every value greater than x.
+fn above(x: u64) -> u64 {
+    !below(x) & !bit(x)
+}
+
+fn lowest<T: VebInt>(bits: u64) -> Option<T> {
+    if bits == 0 {
+        None
+    } else {
+        Some(T::from_u64(u64::from(bits.trailing_zeros())))
+    }
+}
+
+fn highest<T: VebInt>(bits: u64) -> Option<T> {
+    if bits == 0 {
+        None
+    } else {
+        Some(T::from_u64(63 - u64::from(bits.leading_zeros())))
     }
 }
 
-impl VEBTree {
-    fn high(&self, x: i64) -> i64 {
-        ((x as f64) / (self.sqrt_universe as f64)).floor() as i64
+// the summary is allocated the first time a value goes into a cluster
+macro_rules! summary_mut {
+    ( $self_: ident ) => {{
+        let clusters = cluster_count($self_.universe);
+        let sparse = $self_.children.is_sparse();
+        Arc::make_mut($self_.summary.get_or_insert_with(|| {
+            Arc::new(VEBTree::with_storage(clusters, sparse).unwrap())
+        }))
+    }}
+}
+
+impl<T: VebInt> VEBTree<T> {
+    // sqrt_universe is always a power of two, so splitting values into a cluster index and a
+    // position within that cluster is just bit manipulation.
+    fn high(&self, x: T) -> T {
+        T::from_u64(x.to_u64() >> self.sqrt_universe.trailing_zeros())
+    }
+
+    fn low(&self, x: T) -> T {
+        T::from_u64(x.to_u64() & (self.sqrt_universe - 1))
+    }
+
+    // i is a cluster index, so shifting it back up can't carry past the bits of the universe
+    // and this can't overflow even for universes near u64::MAX.
+    fn index(&self, i: T, j: T) -> T {
+        T::from_u64(i.to_u64() << self.sqrt_universe.trailing_zeros() | j.to_u64())
+    }
+
+    // The summary is only allocated once a value goes into a cluster, so these treat a missing
+    // summary as an empty one.
+
+    fn first_cluster(&self) -> Option<T> {
+        self.summary.as_ref().and_then(|summary| summary.minimum())
+    }
+
+    fn last_cluster(&self) -> Option<T> {
+        self.summary.as_ref().and_then(|summary| summary.maximum())
+    }
+
+    fn next_cluster(&self, idx: T) -> Option<T> {
+        self.summary.as_ref().and_then(|summary| summary.find_next(idx))
+    }
+
+    fn prev_cluster(&self, idx: T) -> Option<T> {
+        self.summary.as_ref().and_then(|summary| summary.find_prev(idx))
+    }
+
+    /// Generates a new van Emde Boas tree which can hold the values in the range
+    /// [0, `max_elem`); `max_elem` itself is out of range. Will return an error if `max_elem`
+    /// is less than 2 or if values below it don't fit in `T`.
+    pub fn new(max_elem: u64) -> Result<Self, Error> {
+        VEBTree::with_storage(max_elem, false)
     }
 
-    fn low(&self, x: i64) -> i64 {
-        x % self.sqrt_universe
+    /// Generates a new van Emde Boas tree which only stores the clusters it uses, rather than
+    /// a slot for every cluster. This saves a lot of memory for sparse trees over huge
+    /// universes, at the cost of an extra O(log(C)) factor on every operation, where C is the
+    /// number of clusters in use at each level. Otherwise the tree behaves exactly like one
+    /// made by `new`, and will return an error under the same conditions.
+    pub fn new_sparse(max_elem: u64) -> Result<Self, Error> {
+        VEBTree::with_storage(max_elem, true)
     }
 
-    fn index(&self, i: i64, j: i64) -> i64 {
-        i * self.sqrt_universe + j
+    /// Returns true if the tree was made by `new_sparse`.
+    /// Takes constant time.
+    pub fn is_sparse(&self) -> bool {
+        self.children.is_sparse()
     }
 
-    /// Generates a new van Emde Boas tree. Will return an error if
-    /// the input is less than 1 or greater than the max value of an isize.
-    pub fn new(max_elem: i64) -> Result<Self, &'static str> {
+    fn with_storage(max_elem: u64, sparse: bool) -> Result<Self, Error> {
         if max_elem <= 1 {
-            Err("universe size must be > 1")
-        } else if max_elem > isize::max_value() as i64 {
-            Err("universe too big")
+            Err(Error::UniverseTooSmall)
+        } else if max_elem - 1 > T::MAX_VALUE {
+            Err(Error::UniverseTooLarge)
         } else {
-            // sqrt_universe: 2^(floor(log_2(universe) / 2))
-            let sqrt_universe = (((max_elem as f64).ln() / (2f64).ln()) / 2f64).exp2() as i64;
+            // Round the universe up to 2^bits. Each cluster covers the lower half of the bits
+            // (sqrt_universe: 2^floor(bits / 2)), and there is a cluster for every value of
+            // the upper half (2^ceil(bits / 2) clusters).
+            let bits = 64 - (max_elem - 1).leading_zeros();
             Ok(VEBTree {
                 universe: max_elem,
-                sqrt_universe: sqrt_universe,
-                min: max_elem,
-                max: -1,
-                summary: if max_elem == 2 {
-                    None
-                } else {
-                    Some(Box::new(VEBTree::new(sqrt_universe).unwrap()))
-                },
-                children: if max_elem == 2 {
-                    vec![]
-                } else {
-                    vec![None; sqrt_universe as usize]
-                },
+                sqrt_universe: 1 << (bits / 2),
+                min: T::from_u64(1),
+                max: T::from_u64(0),
+                len: 0,
+                // both allocated once a cluster is needed, so an empty tree owns no memory
+                summary: None,
+                children: Children::new(max_elem, sparse),
+                prealloc: false,
+                #[cfg(feature = "stats")]
+                counters: stats::Counters::default(),
             })
         }
     }
 
+    /// Generates a new van Emde Boas tree with every cluster and summary allocated up front,
+    /// at every level, for callers which can't allocate once they're running. In a tree made
+    /// this way, `insert`, `delete`, `pop_min`, `pop_max`, `remove_range`, `retain` and
+    /// `clear` never allocate or free memory: a cluster which empties out is kept for the next
+    /// value that lands in it. Otherwise the tree behaves exactly like one made by `new`, and
+    /// will return an error under the same conditions.
+    /// Methods which move clusters into other trees, such as `split_off`, can still take
+    /// clusters away, after which they're allocated again as needed. A clone shares every
+    /// node with the original, so the first change through either one copies the nodes on its
+    /// path, and `clear` allocates new nodes in place of the shared ones.
+    /// Takes O(U) time and memory, so this is only suitable for modest universes.
+    pub fn new_prealloc(max_elem: u64) -> Result<Self, Error> {
+        let mut tree = VEBTree::with_storage(max_elem, false)?;
+        tree.preallocate();
+        Ok(tree)
+    }
+
+    /// Returns true if the tree was made by `new_prealloc`.
+    /// Takes constant time.
+    pub fn is_preallocated(&self) -> bool {
+        self.prealloc
+    }
+
+    // Allocates every cluster and summary below an empty dense tree which isn't already
+    // allocated.
+    fn preallocate(&mut self) {
+        self.prealloc = true;
+        if self.children.bits().is_some() {
+            return;
+        }
+        let clusters = cluster_count(self.universe);
+        self.ensure_children();
+        for idx in 0..clusters {
+            if self.children.get(idx).is_none() {
+                let mut subtree = VEBTree::with_storage(self.sqrt_universe, false).unwrap();
+                subtree.preallocate();
+                self.children.set(idx, subtree);
+            }
+        }
+        if self.summary.is_none() {
+            let mut summary = VEBTree::with_storage(clusters, false).unwrap();
+            summary.preallocate();
+            self.summary = Some(Arc::new(summary));
+        }
+    }
+
+    /// Generates a new van Emde Boas tree containing every value from an iterator. Will
+    /// return an error if the universe is invalid (see `new`) or if any of the values are
+    /// outside of the range [0, U).
+    pub fn from_iter_with_universe<I>(max_elem: u64, iter: I) -> Result<Self, Error>
+        where I: IntoIterator<Item = T>
+    {
+        let mut tree = VEBTree::new(max_elem)?;
+        for x in iter {
+            tree.try_insert(x)?;
+        }
+        Ok(tree)
+    }
+
+    /// Generates a new van Emde Boas tree containing every value in a slice. Will return an
+    /// error under the same conditions as `from_iter_with_universe`.
+    pub fn from_slice(max_elem: u64, values: &[T]) -> Result<Self, Error> {
+        VEBTree::from_iter_with_universe(max_elem, values.iter().cloned())
+    }
+
+    /// Generates a new van Emde Boas tree from a strictly increasing slice of values. The tree
+    /// is built bottom-up, with each cluster and summary constructed once from its run of the
+    /// input, which is much faster than inserting the values one at a time. Will return an
+    /// error if the universe is invalid (see `new`), if any value is outside of the range
+    /// [0, U), or if the values aren't strictly increasing; duplicates are rejected rather
+    /// than removed.
+    /// Takes O(N + S) time, where N is the number of values and S is the number of cluster
+    /// slots reserved. Every node holding two or more values reserves a slot for each of its
+    /// clusters: sqrt(U) at the root, about U^(1/4) in each of its clusters which holds two or
+    /// more values, and so on down. For values spread over many clusters, S can be much larger
+    /// than sqrt(U), up to O(U) for a full tree.
+    pub fn from_sorted_slice(max_elem: u64, values: &[T]) -> Result<Self, Error> {
+        VEBTree::sorted_with_storage(max_elem, values, false)
+    }
+
+    // Builds a tree from strictly increasing values which were read from outside the
+    // program. Dense storage takes memory in proportion to the universe however few values
+    // there are, so it's only used if there's a value for every leaf's worth of the
+    // universe; otherwise a short input declaring a huge universe could exhaust memory.
+    fn from_decoded(max_elem: u64, values: &[T]) -> Result<Self, Error> {
+        let sparse = (values.len() as u64) < max_elem / LEAF_UNIVERSE;
+        VEBTree::sorted_with_storage(max_elem, values, sparse)
+    }
+
+    fn sorted_with_storage(max_elem: u64, values: &[T], sparse: bool) -> Result<Self, Error> {
+        let mut tree = VEBTree::with_storage(max_elem, sparse)?;
+        if values.windows(2).any(|w| w[0] >= w[1]) {
+            return Err(Error::NotStrictlyIncreasing);
+        }
+        if values.last().is_some_and(|x| x.to_u64() >= max_elem) {
+            return Err(Error::ValueOutOfRange);
+        }
+        tree.build_sorted(values);
+        Ok(tree)
+    }
+
+    /// Generates a new van Emde Boas tree containing every value in a `BTreeSet`. Will return
+    /// an error, without building anything, if the universe is invalid (see `new`) or if any
+    /// of the values are outside of the range [0, U).
+    /// The set is already sorted, so the tree is built bottom-up as in `from_sorted_slice`.
+    pub fn from_btree_set(max_elem: u64, set: &BTreeSet<T>) -> Result<Self, Error> {
+        let values: Vec<T> = set.iter().cloned().collect();
+        VEBTree::from_sorted_slice(max_elem, &values)
+    }
+
+    // Fills an empty tree from a strictly increasing run of values, all of which are in range.
+    fn build_sorted(&mut self, values: &[T]) {
+        let (&first, rest) = match values.split_first() {
+            Some(split) => split,
+            None => return,
+        };
+        if self.children.bits().is_some() {
+            self.set_bits(values.iter().fold(0, |bits, x| bits | bit(x.to_u64())));
+            return;
+        }
+        self.min = first;
+        self.max = *values.last().unwrap();
+        self.len = values.len();
+        if rest.is_empty() {
+            return;
+        }
+        let sqrt = self.sqrt_universe;
+        let mut indices = Vec::new();
+        let mut lows = Vec::new();
+        let mut rest = rest;
+        self.ensure_children();
+        while let Some(&x) = rest.first() {
+            let idx = self.high(x);
+            let run = rest.iter().take_while(|&&y| self.high(y) == idx).count();
+            lows.clear();
+            lows.extend(rest[..run].iter().map(|&y| self.low(y)));
+            rest = &rest[run..];
+            let mut subtree = VEBTree::with_storage(sqrt, self.is_sparse()).unwrap();
+            subtree.build_sorted(&lows);
+            self.children.set(idx.to_u64(), subtree);
+            indices.push(idx);
+        }
+        summary_mut!(self).build_sorted(&indices);
+    }
+
+    /// Generates a new van Emde Boas tree containing every value in a slice, with the smallest
+    /// power-of-two universe which can hold all of them. Will return an error if the slice is
+    /// empty.
+    pub fn from_max_value(values: &[T]) -> Result<Self, Error> {
+        let max = values.iter().max().ok_or(Error::NoValues)?.to_u64();
+        let universe = max.checked_add(1)
+            .and_then(u64::checked_next_power_of_two)
+            .ok_or(Error::UniverseTooLarge)?;
+        VEBTree::from_slice(cmp::max(universe, 2), values)
+    }
+
     // =========
     // observers
     // =========
 
     /// Returns the lowest value stored in the tree, or None if it's empty.
     /// Takes constant time.
-    pub fn minimum(&self) -> Option<i64> {
+    pub fn minimum(&self) -> Option<T> {
         if self.is_empty() {
             None
         } else {
@@ -107,7 +540,7 @@ impl VEBTree {
 
     /// Returns the highest value stored in the tree, or None if it's empty.
     /// Takes constant time.
-    pub fn maximum(&self) -> Option<i64> {
+    pub fn maximum(&self) -> Option<T> {
         if self.is_empty() {
             None
         } else {
@@ -115,60 +548,314 @@ impl VEBTree {
         }
     }
 
-    /// Returns the maximum value it's possible to store in the tree.
+    /// Returns the size of the universe, the argument to the constructor. The tree holds
+    /// values in the range [0, U), so the largest value it can store is one less than this.
     /// Takes constant time.
-    pub fn universe(&self) -> i64 {
+    pub fn universe(&self) -> u64 {
         self.universe
     }
 
+    /// Returns the number of values covered by each of the top-level clusters. This is always
+    /// a power of two; the universe is split into `ceil(U / cluster_size())` clusters.
+    pub fn cluster_size(&self) -> u64 {
+        self.sqrt_universe
+    }
+
+    /// Returns the number of values stored in the tree.
+    /// Takes constant time.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
     /// Returns true if the tree is empty.
     /// Takes constant time.
     pub fn is_empty(&self) -> bool {
-        self.min > self.max
+        self.len == 0
     }
 
     /// Returns true if this van Emde Boas tree contains the specified value.
     /// Takes O(log(log(U))) time, where U is the argument to the constructor.
-    pub fn has(&self, x: i64) -> bool {
-        if x == self.min || x == self.max {
+    pub fn has(&self, x: T) -> bool {
+        let mut probe = Probe::default();
+        let found = self.has_probed(x, &mut probe);
+        self.record(Op::Has(found), probe);
+        found
+    }
+
+    #[inline]
+    fn has_probed(&self, mut x: T, probe: &mut Probe) -> bool {
+        // walks down the clusters in a loop rather than recursing, since this is a hot path
+        let mut node = self;
+        loop {
+            probe.level();
+            if node.is_empty() {
+                return false;
+            } else if x == node.min || x == node.max {
+                return true;
+            } else if let Some(bits) = node.children.bits() {
+                return bits & bit(x.to_u64()) != 0;
+            } else if x.to_u64() >= node.universe {
+                return false;
+            }
+            match subtree!(node, node.high(x)) {
+                Some(subtree) => {
+                    x = node.low(x);
+                    node = subtree;
+                }
+                None => return false,
+            }
+        }
+    }
+
+    // The recursive version of has, which the loop above must agree with.
+    #[cfg(test)]
+    fn has_recursive(&self, x: T) -> bool {
+        if self.is_empty() {
+            false
+        } else if x == self.min || x == self.max {
             true
-        } else if self.universe == 2 || x > self.universe {
+        } else if let Some(bits) = self.children.bits() {
+            bits & bit(x.to_u64()) != 0
+        } else if x.to_u64() >= self.universe {
             false
         } else {
-            subtree!(self, self.high(x) as usize).map_or(false, |subtree| subtree.has(self.low(x)))
+            subtree!(self, self.high(x))
+                .map_or(false, |subtree| subtree.has_recursive(self.low(x)))
+        }
+    }
+
+    /// Returns an estimate of the number of bytes of heap memory owned by the tree. This
+    /// counts the cluster storage and every cluster and summary, but not the size of the
+    /// `VEBTree` value itself. Nodes shared with clones are counted in full by every tree
+    /// which shares them.
+    /// Takes O(N) time, where N is the number of allocated subtrees.
+    pub fn memory_footprint(&self) -> usize {
+        // each node is allocated along with the two reference counts of its Arc
+        let node = size_of::<VEBTree<T>>() + 2 * size_of::<usize>();
+        let slots = self.children.heap_bytes();
+        let children = self.children
+            .iter()
+            .map(|(_, subtree)| node + subtree.memory_footprint())
+            .sum::<usize>();
+        let summary = self.summary
+            .as_ref()
+            .map_or(0, |summary| node + summary.memory_footprint());
+        slots + children + summary
+    }
+
+    /// Returns an estimate of the total number of bytes used by the tree: the heap memory
+    /// counted by `memory_footprint`, including spare capacity in the cluster slots, plus the
+    /// `VEBTree` value itself.
+    /// Takes O(N) time, where N is the number of allocated subtrees.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use veb_rs::VEBTree;
+    ///
+    /// let mut tree = VEBTree::<u32>::new(1 << 20).unwrap();
+    /// let mut state = 1u32;
+    /// for _ in 0..1000 {
+    ///     state = state.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+    ///     tree.insert(state >> 12);
+    /// }
+    /// println!("{} values: {} nodes, {} levels, {} bytes",
+    ///          tree.len(), tree.node_count(), tree.depth(), tree.allocated_bytes());
+    /// assert!(tree.allocated_bytes() > tree.memory_footprint());
+    /// ```
+    pub fn allocated_bytes(&self) -> usize {
+        size_of::<VEBTree<T>>() + self.memory_footprint()
+    }
+
+    /// Returns the number of nodes allocated in the tree, counting the tree itself and every
+    /// cluster and summary at every level. Clusters which `clear` keeps allocated for reuse
+    /// are counted even though they're empty.
+    /// Takes O(N) time, where N is the number of allocated subtrees.
+    pub fn node_count(&self) -> usize {
+        let children = self.children
+            .iter()
+            .map(|(_, subtree)| subtree.node_count())
+            .sum::<usize>();
+        let summary = self.summary.as_ref().map_or(0, |summary| summary.node_count());
+        1 + children + summary
+    }
+
+    /// Returns the number of levels of allocated nodes, following both clusters and
+    /// summaries. A tree which hasn't allocated any clusters, such as an empty one, has a
+    /// depth of 1.
+    /// Takes O(N) time, where N is the number of allocated subtrees.
+    pub fn depth(&self) -> usize {
+        let below = self.children
+            .iter()
+            .map(|(_, subtree)| subtree.depth())
+            .chain(self.summary.as_ref().map(|summary| summary.depth()))
+            .max()
+            .unwrap_or(0);
+        1 + below
+    }
+
+    /// Returns the number of values stored in the tree which are in the range [lo, hi].
+    /// Counts the values up to each end with `rank`, so this takes O(log(U)) time, where U is
+    /// the argument to the constructor.
+    pub fn count_in_range(&self, lo: T, hi: T) -> usize {
+        if lo > hi {
+            0
+        } else {
+            self.rank(hi) - self.count_below(lo)
+        }
+    }
+
+    /// Returns the number of values stored in the tree which are less than or equal to `x`.
+    /// At each level, the values in the clusters before the one holding `x` are added up from
+    /// the node's running counts rather than visited, which takes O(log(C)) time for a node
+    /// with C clusters, so this takes O(log(U)) time in all, where U is the argument to the
+    /// constructor.
+    pub fn rank(&self, mut x: T) -> usize {
+        let mut node = self;
+        let mut count = 0;
+        loop {
+            if let Some(bits) = node.children.bits() {
+                return count + (bits & !above(x.to_u64())).count_ones() as usize;
+            } else if node.is_empty() || x < node.min {
+                return count;
+            } else if x >= node.max {
+                return count + node.len;
+            }
+            // the minimum is below x, but isn't stored in any cluster
+            let idx = node.high(x);
+            count += 1 + node.children.counts().unwrap().before(idx.to_u64());
+            match subtree!(node, idx) {
+                Some(subtree) => {
+                    x = node.low(x);
+                    node = subtree;
+                }
+                None => return count,
+            }
+        }
+    }
+
+    /// Returns the number of values stored in the tree which are strictly less than `x`.
+    /// Takes the same time as `rank`.
+    pub fn count_below(&self, x: T) -> usize {
+        if x.to_u64() == 0 {
+            0
+        } else {
+            self.rank(T::from_u64(x.to_u64() - 1))
+        }
+    }
+
+    /// Returns the number of values stored in the tree which are in the half-open range
+    /// [lo, hi). Takes the same time as `rank`.
+    pub fn count_range(&self, lo: T, hi: T) -> usize {
+        if lo >= hi {
+            0
+        } else {
+            self.count_below(hi) - self.count_below(lo)
+        }
+    }
+
+    /// Returns the k-th smallest value stored in the tree, counting from 0, or None if there
+    /// are k or fewer values. To jump k values ahead of a given value, use `nth_next`.
+    /// At each level, the cluster holding the k-th value is found from the node's running
+    /// counts without visiting the clusters before it, which takes O(log(C)) time for a node
+    /// with C clusters, so this takes O(log(U)) time in all, where U is the argument to the
+    /// constructor.
+    pub fn select(&self, mut k: usize) -> Option<T> {
+        if k >= self.len {
+            return None;
+        }
+        let mut node = self;
+        // the value the current node's range starts at
+        let mut base = 0;
+        loop {
+            if k == 0 {
+                return Some(T::from_u64(base + node.min.to_u64()));
+            } else if let Some(mut bits) = node.children.bits() {
+                for _ in 0..k {
+                    // clear the lowest bit
+                    bits &= bits - 1;
+                }
+                return lowest(bits).map(|low: T| T::from_u64(base + low.to_u64()));
+            }
+            // skip the minimum, which isn't stored in the clusters
+            let (idx, before) = node.children.counts().unwrap().find(k - 1);
+            k -= 1 + before;
+            base += idx * node.sqrt_universe;
+            node = subtree!(node, idx).unwrap();
         }
     }
 
-    fn find_in_subtree(&self, x: i64) -> Option<i64> {
-        // subtree not present - we need to look in a different cluster. Since universe
-        // > 2, we know summary exists.
-        summary!(self).find_next(self.high(x)).map_or(None, |next_index| {
-            Some(self.index(next_index, subtree!(self, next_index as usize).unwrap().min))
+    /// Returns the value k places after the next highest value above x, so `nth_next(x, 0)`
+    /// is the same as `find_next(x)`. Returns None if k or fewer values are greater than x.
+    /// Counts its way there with a `rank` and a `select` rather than stepping through values
+    /// one at a time, so it takes O(log(U)) time whatever k is, where U is the argument to the
+    /// constructor.
+    pub fn nth_next(&self, x: T, k: usize) -> Option<T> {
+        self.rank(x).checked_add(k).and_then(|i| self.select(i))
+    }
+
+    fn find_in_subtree(&self, x: T) -> Option<T> {
+        // subtree not present - we need to look in a different cluster
+        self.next_cluster(self.high(x)).map(|next_index| {
+            self.index(next_index, subtree!(self, next_index).unwrap().min)
         })
     }
 
     /// Finds the next highest value in this van Emde Boas tree, or None if it doesn't exit.
     /// Takes O(log(log(U))) time, where U is the argument to the constructor.
-    pub fn find_next(&self, x: i64) -> Option<i64> {
-        // base case
-        if self.is_empty() {
-            None
-        } else if self.universe == 2 {
-            if x == 0 && self.max == 1 {
-                Some(1)
-            } else {
-                None
+    pub fn find_next(&self, x: T) -> Option<T> {
+        let mut probe = Probe::default();
+        let next = self.find_next_probed(x, &mut probe);
+        self.record(Op::FindNext, probe);
+        next
+    }
+
+    #[inline]
+    fn find_next_probed(&self, mut x: T, probe: &mut Probe) -> Option<T> {
+        // walks down the clusters in a loop, keeping the value each node's range starts at;
+        // only a miss in a cluster needs a second search, in the summary
+        let mut node = self;
+        let mut base = 0;
+        let found = loop {
+            probe.level();
+            if let Some(bits) = node.children.bits() {
+                break lowest::<u64>(bits & above(x.to_u64()));
+            } else if node.is_empty() {
+                return None;
+            } else if x < node.min {
+                break Some(node.min.to_u64());
+            }
+            let idx = node.high(x);
+            let low = node.low(x);
+            match subtree!(node, idx) {
+                Some(subtree) if low < subtree.max => {
+                    base += idx.to_u64() << node.sqrt_universe.trailing_zeros();
+                    x = low;
+                    node = subtree;
+                }
+                _ => break node.find_in_subtree(x).map(T::to_u64),
             }
+        };
+        found.map(|y| T::from_u64(base + y))
+    }
+
+    // The recursive version of find_next, which the loop above must agree with.
+    #[cfg(test)]
+    fn find_next_recursive(&self, x: T) -> Option<T> {
+        if let Some(bits) = self.children.bits() {
+            lowest(bits & above(x.to_u64()))
+        } else if self.is_empty() {
+            None
         } else if x < self.min {
             Some(self.min)
         } else {
             let idx = self.high(x);
             let low = self.low(x);
             // look in subtrees
-            subtree!(self, idx as usize).map_or_else(|| self.find_in_subtree(x), |subtree| {
-                let max_low = subtree!(self, idx as usize).unwrap().max;
+            subtree!(self, idx).map_or_else(|| self.find_in_subtree(x), |subtree| {
+                let max_low = subtree.max;
                 if low < max_low {
-                    Some(self.index(idx, subtree.find_next(low).unwrap()))
+                    Some(self.index(idx, subtree.find_next_recursive(low).unwrap()))
                 } else {
                     self.find_in_subtree(x)
                 }
@@ -176,161 +863,5479 @@ impl VEBTree {
         }
     }
 
-    // ========
-    // mutators
-    // ========
-
-    fn empty_insert(&mut self, x: i64) {
-        self.min = x;
-        self.max = x;
+    /// Finds the next lowest value in this van Emde Boas tree, or None if it doesn't exist.
+    /// Takes O(log(log(U))) time, where U is the argument to the constructor.
+    pub fn find_prev(&self, mut x: T) -> Option<T> {
+        // the mirror image of find_next
+        let mut node = self;
+        let mut base = 0;
+        let found = loop {
+            if let Some(bits) = node.children.bits() {
+                break highest::<u64>(bits & below(x.to_u64()));
+            } else if node.is_empty() {
+                return None;
+            } else if x > node.max {
+                break Some(node.max.to_u64());
+            }
+            let idx = node.high(x);
+            let low = node.low(x);
+            match subtree!(node, idx) {
+                Some(subtree) if low > subtree.min => {
+                    base += idx.to_u64() << node.sqrt_universe.trailing_zeros();
+                    x = low;
+                    node = subtree;
+                    continue;
+                }
+                _ => {}
+            }
+            // the closest earlier cluster; the minimum isn't in any cluster, so it has to be
+            // checked separately
+            break match node.prev_cluster(idx) {
+                Some(prev_index) => {
+                    let prev = node.index(prev_index, subtree!(node, prev_index).unwrap().max);
+                    Some(prev.to_u64())
+                }
+                None if x > node.min => Some(node.min.to_u64()),
+                None => None,
+            };
+        };
+        found.map(|y| T::from_u64(base + y))
     }
 
-    /// Insert a value into this van Emde Boas tree.
-    /// Takes O(log(log(U))) time, where U is the argument to the constructor.
-    pub fn insert(&mut self, mut x: i64) {
-        if self.is_empty() {
-            self.empty_insert(x);
+    // The recursive version of find_prev, which the loop above must agree with.
+    #[cfg(test)]
+    fn find_prev_recursive(&self, x: T) -> Option<T> {
+        if let Some(bits) = self.children.bits() {
+            highest(bits & below(x.to_u64()))
+        } else if self.is_empty() {
+            None
+        } else if x > self.max {
+            Some(self.max)
         } else {
-            if self.min == self.max {
-                if x < self.min {
-                    self.min = x;
-                }
-                if x > self.max {
-                    self.max = x;
+            let idx = self.high(x);
+            let low = self.low(x);
+            // look in the cluster containing x first
+            if let Some(subtree) = subtree!(self, idx) {
+                if low > subtree.min {
+                    return Some(self.index(idx, subtree.find_prev_recursive(low).unwrap()));
                 }
             }
-            if x < self.min {
-                mem::swap(&mut self.min, &mut x);
-            }
-            if x > self.max {
-                self.max = x;
+            // then in the closest earlier cluster; the minimum isn't in any cluster, so it
+            // has to be checked separately
+            match self.prev_cluster(idx) {
+                Some(prev_index) => {
+                    Some(self.index(prev_index, subtree!(self, prev_index).unwrap().max))
+                }
+                None if x > self.min => Some(self.min),
+                None => None,
             }
+        }
+    }
+
+    /// Finds the smallest value in the tree which is greater than or equal to x, or None if
+    /// there isn't one. Unlike `find_next`, this returns x itself if it's in the tree.
+    /// Takes O(log(log(U))) time, where U is the argument to the constructor.
+    pub fn find_next_geq(&self, x: T) -> Option<T> {
+        if self.is_empty() || x > self.max {
+            None
+        } else if x <= self.min {
+            Some(self.min)
+        } else if let Some(bits) = self.children.bits() {
+            lowest(bits & !below(x.to_u64()))
+        } else {
             let idx = self.high(x);
             let low = self.low(x);
-            let sqrt = self.sqrt_universe;
-            let subtree = &mut self.children[idx as usize];
-            match *subtree {
-                Some(ref mut subtree) => subtree.insert(low),
-                None => {
-                    let mut new_tree = VEBTree::new(sqrt).unwrap();
-                    new_tree.empty_insert(low);
-                    mem::replace(subtree, Some(new_tree));
-                    summary_mut!(self).insert(idx);
+            match subtree!(self, idx) {
+                Some(subtree) if low <= subtree.max => {
+                    Some(self.index(idx, subtree.find_next_geq(low).unwrap()))
                 }
+                _ => self.find_in_subtree(x),
             }
         }
     }
 
-    /// Removes an element from this van Emde Boas tree.
+    /// Finds the largest value in the tree which is less than or equal to x, or None if there
+    /// isn't one. Unlike `find_prev`, this returns x itself if it's in the tree.
     /// Takes O(log(log(U))) time, where U is the argument to the constructor.
-    pub fn delete(&mut self, mut x: i64) {
-        if self.min == self.max && self.min == x {
-            self.min = self.universe;
-            self.max = -1;
+    pub fn find_prev_leq(&self, x: T) -> Option<T> {
+        if self.is_empty() || x < self.min {
+            None
+        } else if x >= self.max {
+            Some(self.max)
+        } else if let Some(bits) = self.children.bits() {
+            highest(bits & !above(x.to_u64()))
         } else {
-            if self.min == x {
-                // we need to calculate the new minimum
-                self.min = if summary!(self).is_empty() {
-                    self.max // return
-                } else {
-                    // we need to insert the old minimum
-                    x = subtree!(self, summary!(self).min as usize).unwrap().min;
-                    x
-                }
-            }
-            if self.max == x {
-                // we need to calculate the new maximum
-                self.max = if summary!(self).is_empty() {
-                    // only 1 element in the tree
-                    self.min
-                } else {
-                    subtree!(self, summary!(self).max as usize).unwrap().max
+            let idx = self.high(x);
+            let low = self.low(x);
+            match subtree!(self, idx) {
+                Some(subtree) if low >= subtree.min => {
+                    Some(self.index(idx, subtree.find_prev_leq(low).unwrap()))
                 }
+                // the minimum isn't in any cluster, but x >= min
+                _ => match self.prev_cluster(idx) {
+                    Some(prev_index) => {
+                        Some(self.index(prev_index, subtree!(self, prev_index).unwrap().max))
+                    }
+                    None => Some(self.min),
+                },
             }
-            if !summary!(self).is_empty() {
-                // recurse
-                let idx = self.high(x);
-                let low = self.low(x);
-                let mut subtree = &mut self.children[idx as usize];
-                subtree.as_mut().unwrap().delete(low);
-                // don't store empty trees, and remove from summary as well
-                if subtree.as_ref().unwrap().is_empty() {
-                    subtree.take();
-                    summary_mut!(self).delete(idx);
-                }
+        }
+    }
+
+    /// Returns the smallest value in the tree which is greater than or equal to x, or None if
+    /// there isn't one. The same as `find_next_geq`.
+    pub fn ceiling(&self, x: T) -> Option<T> {
+        self.find_next_geq(x)
+    }
+
+    /// Returns the largest value in the tree which is less than or equal to x, or None if
+    /// there isn't one. The same as `find_prev_leq`.
+    pub fn floor(&self, x: T) -> Option<T> {
+        self.find_prev_leq(x)
+    }
+
+    /// Returns the value in the tree closest to x, or None if the tree is empty. If x is
+    /// halfway between two values, the smaller one is returned.
+    /// Takes O(log(log(U))) time, where U is the argument to the constructor.
+    pub fn nearest(&self, x: T) -> Option<T> {
+        if self.is_empty() {
+            None
+        } else if x <= self.min {
+            Some(self.min)
+        } else if x >= self.max {
+            Some(self.max)
+        } else if self.has(x) {
+            Some(x)
+        } else {
+            // min < x < max, so there are values on both sides
+            let prev = self.find_prev(x).unwrap();
+            let next = self.find_next(x).unwrap();
+            if x.to_u64() - prev.to_u64() <= next.to_u64() - x.to_u64() {
+                Some(prev)
+            } else {
+                Some(next)
             }
         }
     }
 
-}
+    /// Finds the next highest value in the tree, wrapping around to the minimum if there's
+    /// nothing above x, as when looking for the next slot of a timer wheel. Returns None only
+    /// if the tree is empty. If the tree holds a single value and it's x, that value is
+    /// returned again, since it comes back around one full cycle later. x may be anywhere,
+    /// including past the end of the universe.
+    /// Takes O(log(log(U))) time, where U is the argument to the constructor.
+    pub fn find_next_cyclic(&self, x: T) -> Option<T> {
+        if self.is_empty() || x >= self.max {
+            self.minimum()
+        } else {
+            self.find_next(x)
+        }
+    }
 
-#[test]
-fn creation() {
-    assert!(VEBTree::new(50).is_ok());
-}
+    /// Finds the next lowest value in the tree, wrapping around to the maximum if there's
+    /// nothing below x; the mirror image of `find_next_cyclic`. Returns None only if the tree
+    /// is empty, and returns x itself if it's the only value in the tree.
+    /// Takes O(log(log(U))) time, where U is the argument to the constructor.
+    pub fn find_prev_cyclic(&self, x: T) -> Option<T> {
+        if self.is_empty() || x <= self.min {
+            self.maximum()
+        } else {
+            self.find_prev(x)
+        }
+    }
 
-#[test]
-fn creation_fail() {
-    assert!(VEBTree::new(1).is_err());
-}
+    /// Returns the k values in the tree closest to x, nearest first, or every value if there
+    /// are k or fewer. As in `nearest`, of two values the same distance from x the smaller one
+    /// comes first.
+    /// Walks outwards from x in both directions at once, so this takes O(k log(log(U))) time.
+    pub fn k_closest(&self, x: T, k: usize) -> Vec<T> {
+        let mut closest = Vec::with_capacity(cmp::min(k, self.len));
+        // x itself, if it's stored, is found on the way down
+        let mut lower = self.find_prev_leq(x);
+        let mut upper = self.find_next(x);
+        while closest.len() < k {
+            let take_lower = match (lower, upper) {
+                (Some(lo), Some(hi)) => x.to_u64() - lo.to_u64() <= hi.to_u64() - x.to_u64(),
+                (Some(_), None) => true,
+                (None, Some(_)) => false,
+                (None, None) => break,
+            };
+            if take_lower {
+                let lo = lower.unwrap();
+                closest.push(lo);
+                lower = self.find_prev(lo);
+            } else {
+                let hi = upper.unwrap();
+                closest.push(hi);
+                upper = self.find_next(hi);
+            }
+        }
+        closest
+    }
 
-#[test]
-fn insertion_and_has() {
-    let mut tree = VEBTree::new(50).unwrap();
-    assert!(!tree.has(25));
-    assert!(!tree.has(26));
-    tree.insert(25);
-    assert!(tree.has(25));
-    assert!(!tree.has(26));
-    tree.insert(26);
-    assert!(tree.has(25));
-    assert!(tree.has(26));
-}
+    /// Returns an iterator over every value in the tree, in ascending order. The iterator is
+    /// double-ended, so `.rev()` walks the values in descending order.
+    /// Each step takes O(log(log(U))) time, where U is the argument to the constructor.
+    pub fn iter<'a>(&'a self) -> Iter<'a, T> {
+        Iter {
+            front: Walk::new(self),
+            back: Walk::new(self),
+            remaining: self.len,
+        }
+    }
 
-#[test]
-fn is_empty() {
-    let mut tree = VEBTree::new(50).unwrap();
-    assert!(tree.is_empty());
-    tree.insert(25);
-    assert!(!tree.is_empty());
-    tree.delete(25);
-    assert!(tree.is_empty());
-}
+    /// Returns an iterator over the values in the tree which are greater than or equal to x,
+    /// in ascending order, so `iter_from(0)` is the same as `iter()`. This is the same
+    /// iterator as `iter` returns, so it's double-ended, and `.rev()` walks back from the
+    /// maximum down to the smallest value greater than or equal to x.
+    /// Starting the iterator takes O(log(log(U))) time, plus the time `count_below` takes to
+    /// work out its length; each step after that takes O(log(log(U))) time.
+    pub fn iter_from<'a>(&'a self, x: T) -> Iter<'a, T> {
+        let front = match self.find_prev(x) {
+            Some(prev) => Walk::after(self, prev),
+            None => Walk::new(self),
+        };
+        Iter {
+            front,
+            back: Walk::new(self),
+            remaining: self.len - self.count_below(x),
+        }
+    }
 
-#[test]
-fn find_next() {
-    let mut tree = VEBTree::new(50).unwrap();
-    println!("find next: empty: {:?}", tree);
-    assert!(tree.find_next(0).is_none());
-    assert!(tree.find_next(24).is_none());
-    assert!(tree.find_next(25).is_none());
-    tree.insert(25);
-    println!("find next: 25: {:?}", tree);
-    assert!(tree.find_next(0).is_some());
-    assert!(tree.find_next(24).is_some());
-    assert!(tree.find_next(25).is_none());
-}
+    /// Returns an iterator over the values in the tree which are in the range [lo, hi], in
+    /// ascending order. If lo > hi, the iterator is empty.
+    /// Each step takes O(log(log(U))) time, where U is the argument to the constructor.
+    pub fn iter_range<'a>(&'a self, lo: T, hi: T) -> impl Iterator<Item = T> + 'a {
+        let hi = cmp::min(hi, T::from_u64(self.universe - 1));
+        let first = if lo > hi {
+            None
+        } else if lo.to_u64() == 0 {
+            self.minimum()
+        } else {
+            self.find_next(T::from_u64(lo.to_u64() - 1))
+        };
+        iter::successors(first, move |&x| self.find_next(x)).take_while(move |&x| x <= hi)
+    }
 
-#[test]
-fn delete() {
-    let mut tree = VEBTree::new(50).unwrap();
-    println!("delete: empty: {:?}", tree);
-    assert!(!tree.has(25));
-    assert!(!tree.has(26));
-    tree.insert(25);
-    println!("delete: 25: {:?}", tree);
-    assert!(tree.has(25));
-    assert!(!tree.has(26));
-    tree.insert(26);
-    println!("delete: 25 and 26: {:?}", tree);
-    assert!(tree.has(25));
-    assert!(tree.has(26));
-    tree.delete(26);
-    println!("delete: 26 (1 deletion): {:?}", tree);
-    assert!(!tree.has(26));
-    assert!(tree.has(25));
-    tree.delete(25);
-    println!("delete: empty (2 deletions): {:?}", tree);
-    assert!(!tree.has(26));
-    assert!(!tree.has(25));
+    /// Returns an iterator over the values in the tree which are within `range`, in ascending
+    /// order. Bounds outside of the universe are fine; the iterator is just empty there.
+    /// Like `BTreeSet::range`, panics if the start of the range is greater than its end, or if
+    /// they're equal and both excluded.
+    /// Only visits the stored values within the range, each step taking O(log(log(U))) time.
+    pub fn range<'a, R: RangeBounds<T>>(&'a self, range: R) -> Range<'a, T> {
+        let (front, back) = match self.inclusive_bounds(&range) {
+            Some((lo, hi)) => (self.ceiling(T::from_u64(lo)), self.floor(T::from_u64(hi))),
+            None => (None, None),
+        };
+        Range {
+            tree: self,
+            front,
+            back,
+        }
+    }
+
+    // Converts a range to inclusive bounds within the universe, or None if it doesn't overlap
+    // the universe. Panics on reversed ranges, as BTreeSet::range does.
+    fn inclusive_bounds<R: RangeBounds<T>>(&self, range: &R) -> Option<(u64, u64)> {
+        match self.unclipped_bounds(range) {
+            (Some(lo), Some(hi)) if lo <= hi && lo < self.universe => {
+                Some((lo, cmp::min(hi, self.universe - 1)))
+            }
+            _ => None,
+        }
+    }
+
+    // Converts a range to inclusive bounds, which are None if they're past either end of u64.
+    // The upper bound may be outside of the universe.
+    fn unclipped_bounds<R: RangeBounds<T>>(&self, range: &R) -> (Option<u64>, Option<u64>) {
+        match (range.start_bound(), range.end_bound()) {
+            (Bound::Excluded(s), Bound::Excluded(e)) if s == e => {
+                panic!("range start and end are equal and excluded")
+            }
+            (Bound::Included(s), Bound::Included(e)) |
+            (Bound::Included(s), Bound::Excluded(e)) |
+            (Bound::Excluded(s), Bound::Included(e)) |
+            (Bound::Excluded(s), Bound::Excluded(e)) if s > e => {
+                panic!("range start is greater than range end")
+            }
+            _ => {}
+        }
+        let lo = match range.start_bound() {
+            Bound::Included(&x) => Some(x.to_u64()),
+            Bound::Excluded(&x) => x.to_u64().checked_add(1),
+            Bound::Unbounded => Some(0),
+        };
+        let hi = match range.end_bound() {
+            Bound::Included(&x) => Some(x.to_u64()),
+            Bound::Excluded(&x) => x.to_u64().checked_sub(1),
+            Bound::Unbounded => Some(self.universe - 1),
+        };
+        (lo, hi)
+    }
+
+    // Finds the smallest value in [x, U) which isn't in the tree. Clusters which are full are
+    // skipped without looking inside of them.
+    fn absent_geq(&self, x: u64) -> Option<u64> {
+        if x >= self.universe {
+            None
+        } else if self.is_empty() || x < self.min.to_u64() || x > self.max.to_u64() {
+            Some(x)
+        } else if self.len as u64 == self.universe {
+            None
+        } else if let Some(bits) = self.children.bits() {
+            lowest::<u64>(!bits & !below(x) & below(self.universe))
+        } else {
+            let (min_idx, min_low) = (self.high(self.min), self.low(self.min).to_u64());
+            let mut idx = self.high(T::from_u64(x)).to_u64();
+            let mut low = self.low(T::from_u64(x)).to_u64();
+            let clusters = cluster_count(self.universe);
+            while idx < clusters {
+                let subtree = subtree!(self, idx);
+                let absent = |low| subtree.map_or(Some(low), |subtree| subtree.absent_geq(low));
+                let mut found = absent(low);
+                // the minimum isn't stored in its cluster
+                if idx == min_idx.to_u64() && found == Some(min_low) {
+                    found = absent(min_low + 1).filter(|&low| low < self.sqrt_universe);
+                }
+                if let Some(low) = found {
+                    let value = idx << self.sqrt_universe.trailing_zeros() | low;
+                    return if value < self.universe { Some(value) } else { None };
+                }
+                idx += 1;
+                low = 0;
+            }
+            None
+        }
+    }
+
+    /// Finds the smallest value which is greater than or equal to x and not stored in the
+    /// tree, or None if every value in [x, U) is stored. Useful for handing out the lowest
+    /// free ID.
+    /// Full clusters are recognised from their size and skipped without looking inside of
+    /// them, so this takes O(log(log(U))) time plus O(1) for each full cluster passed over.
+    pub fn find_next_absent(&self, x: T) -> Option<T> {
+        self.absent_geq(x.to_u64()).map(T::from_u64)
+    }
+
+    /// Finds the smallest value which isn't stored in the tree, or None if the tree is full.
+    /// The same as `find_next_absent(0)`.
+    pub fn find_first_absent(&self) -> Option<T> {
+        self.find_next_absent(T::from_u64(0))
+    }
+
+    /// Returns an iterator over every value in [0, U) which is not stored in the tree, in
+    /// ascending order.
+    /// Runs of stored values are skipped a cluster at a time where possible, so each step
+    /// takes O(log(log(U))) time plus O(1) for each full cluster it passes over.
+    pub fn absent<'a>(&'a self) -> Absent<'a, T> {
+        self.absent_in(..)
+    }
+
+    /// Returns an iterator over every value in `range` which is not stored in the tree, in
+    /// ascending order. Values outside of [0, U) are never yielded. Panics on reversed ranges,
+    /// in the same way as `range`.
+    pub fn absent_in<'a, R: RangeBounds<T>>(&'a self, range: R) -> Absent<'a, T> {
+        let (next, end) = match self.inclusive_bounds(&range) {
+            Some((lo, hi)) => (Some(lo), hi),
+            None => (None, 0),
+        };
+        Absent {
+            tree: self,
+            next,
+            end,
+        }
+    }
+
+    /// Returns an iterator over the runs of consecutive values stored in the tree, in
+    /// ascending order. Each run is as long as possible, so {3, 4, 5, 9, 10} gives `3..=5` and
+    /// `9..=10`.
+    /// Full clusters are recognised from their size and skipped without looking inside of
+    /// them, so each step takes O(log(log(U))) time plus O(1) for each full cluster in the run.
+    pub fn runs<'a>(&'a self) -> Runs<'a, T> {
+        Runs {
+            tree: self,
+            next: self.minimum(),
+        }
+    }
+
+    /// Returns a tree with the same universe and kind of storage holding exactly the values in
+    /// [0, U) which aren't in this one.
+    /// The gaps between runs of stored values are filled as in `insert_range`, so clusters
+    /// which are full here are skipped and clusters which are empty here are filled whole,
+    /// taking O(R log(log(U)) + C) time, where R is the number of runs and C the number of
+    /// clusters in the result.
+    pub fn complement(&self) -> VEBTree<T> {
+        let mut result = self.empty_like(self.universe);
+        let mut start = 0;
+        for run in self.runs() {
+            if run.start().to_u64() > start {
+                result.fill_span(start, run.start().to_u64() - 1);
+            }
+            start = run.end().to_u64() + 1;
+        }
+        if start < self.universe {
+            result.fill_span(start, self.universe - 1);
+        }
+        result
+    }
+
+    /// Replaces the contents of the tree with its complement, so every value in [0, U) which
+    /// was stored is removed and every other value is inserted. See `complement`.
+    pub fn invert(&mut self) {
+        *self = self.complement();
+    }
+
+    /// Returns every value in the tree, in ascending order.
+    /// Takes O(N log(log(U))) time, where N is the number of values in the tree.
+    pub fn to_sorted_vec(&self) -> Vec<T> {
+        let mut values = Vec::with_capacity(self.len);
+        values.extend(self.iter_range(T::from_u64(0), T::from_u64(self.universe - 1)));
+        values
+    }
+
+    /// Consumes the tree, returning every value in it in ascending order.
+    /// Walks the values once, taking O(N log(log(U))) time, where N is the number of values.
+    pub fn into_sorted_vec(self) -> Vec<T> {
+        self.iter().collect()
+    }
+
+    // ========
+    // mutators
+    // ========
+
+    fn empty_insert(&mut self, x: T) {
+        self.min = x;
+        self.max = x;
+        self.len = 1;
+        if let Some(bits) = self.children.bits_mut() {
+            *bits = bit(x.to_u64());
+        }
+    }
+
+    /// Insert a value into this van Emde Boas tree. Returns true if the value was not
+    /// already present. Panics if the value is outside of the range [0, U); use `try_insert`
+    /// if that isn't known ahead of time. Every other method accepts any value, treating
+    /// those outside of the universe as absent.
+    /// Takes O(log(U)) time, where U is the argument to the constructor.
+    pub fn insert(&mut self, x: T) -> bool {
+        let mut probe = Probe::default();
+        let inserted = self.insert_probed(x, &mut probe);
+        self.record(Op::Insert, probe);
+        inserted
+    }
+
+    fn insert_probed(&mut self, mut x: T, probe: &mut Probe) -> bool {
+        assert!(x.to_u64() < self.universe, "value out of range");
+        probe.level();
+        if let Some(bits) = self.children.bits() {
+            self.set_bits(bits | bit(x.to_u64()));
+            return bits & bit(x.to_u64()) == 0;
+        }
+        if self.is_empty() {
+            self.empty_insert(x);
+            return true;
+        }
+        if x == self.min || x == self.max {
+            return false;
+        }
+        if x < self.min {
+            // the old minimum gets pushed down into the clusters instead
+            mem::swap(&mut self.min, &mut x);
+        }
+        let idx = self.high(x);
+        let low = self.low(x);
+        let sqrt = self.sqrt_universe;
+        self.ensure_children();
+        #[cfg(feature = "stats")]
+        {
+            if self.children.get(idx.to_u64()).is_none() {
+                probe.allocation();
+            }
+        }
+        // the cluster may have been kept around, empty, by clear
+        let mut subtree = self.children.get_or_alloc(idx.to_u64(), sqrt);
+        let inserted = if subtree.is_empty() {
+            subtree.empty_insert(low);
+            drop(subtree);
+            summary_mut!(self).insert(idx);
+            true
+        } else {
+            subtree.insert_probed(low, probe)
+        };
+        if x > self.max {
+            self.max = x;
+        }
+        if inserted {
+            self.len += 1;
+        }
+        inserted
+    }
+
+    /// Insert a value into this van Emde Boas tree, returning an error and leaving the tree
+    /// untouched if the value is outside of the range [0, U). Otherwise, returns true if the
+    /// value was not already present.
+    /// Takes O(log(U)) time, where U is the argument to the constructor.
+    pub fn try_insert(&mut self, x: T) -> Result<bool, Error> {
+        if x.to_u64() >= self.universe {
+            Err(Error::ValueOutOfRange)
+        } else {
+            Ok(self.insert(x))
+        }
+    }
+
+    /// Inserts every value from a strictly increasing slice, with the same result as inserting
+    /// them one at a time. Will return an error without inserting anything if any value is
+    /// outside of the range [0, U).
+    /// Values which share a cluster are inserted into it together, so each cluster is only
+    /// visited (and allocated) once.
+    pub fn insert_sorted(&mut self, values: &[T]) -> Result<(), Error> {
+        if values.iter().any(|x| x.to_u64() >= self.universe) {
+            return Err(Error::ValueOutOfRange);
+        }
+        debug_assert!(values.windows(2).all(|w| w[0] < w[1]), "values not strictly increasing");
+        self.insert_run(values);
+        Ok(())
+    }
+
+    // Inserts a sorted run of values, all of which are in range.
+    fn insert_run(&mut self, values: &[T]) {
+        let (&first, mut rest) = match values.split_first() {
+            Some(split) => split,
+            None => return,
+        };
+        if let Some(bits) = self.children.bits() {
+            self.set_bits(values.iter().fold(bits, |bits, x| bits | bit(x.to_u64())));
+            return;
+        }
+        // only the first value can become the new minimum; everything after it is larger, so
+        // it goes into the clusters
+        self.insert(first);
+        let sqrt = self.sqrt_universe;
+        let mut lows = Vec::new();
+        while let Some(&x) = rest.first() {
+            let idx = self.high(x);
+            let run = rest.iter().take_while(|&&y| self.high(y) == idx).count();
+            lows.clear();
+            lows.extend(rest[..run].iter().filter(|&&y| y > self.min).map(|&y| self.low(y)));
+            rest = &rest[run..];
+            let last = match lows.last() {
+                Some(&low) => self.index(idx, low),
+                None => continue,
+            };
+            self.ensure_children();
+            let mut subtree = self.children.get_or_alloc(idx.to_u64(), sqrt);
+            let before = subtree.len;
+            subtree.insert_run(&lows);
+            let added = subtree.len - before;
+            drop(subtree);
+            if before == 0 {
+                summary_mut!(self).insert(idx);
+            }
+            self.len += added;
+            if last > self.max {
+                self.max = last;
+            }
+        }
+    }
+
+    /// Releases memory which is no longer needed after values have been deleted, without
+    /// changing the contents of the tree. Cluster slots past the last non-empty cluster are
+    /// dropped, and are reallocated if a value is inserted into one of them. Empty clusters
+    /// and summaries are released at every level, so a tree emptied this way owns no memory.
+    /// Can be called at any time, and doesn't change the result of any query. Does nothing to
+    /// a tree made by `new_prealloc`, which keeps all of its memory.
+    /// Takes O(S) time, where S is the number of cluster slots allocated in the tree.
+    pub fn shrink_to_fit(&mut self) {
+        if self.children.bits().is_some() || self.prealloc {
+            return;
+        }
+        let used = match self.last_cluster() {
+            Some(last) => last.to_u64() + 1,
+            None => {
+                self.release_clusters();
+                return;
+            }
+        };
+        // this also drops the empty clusters kept by clear
+        self.children.shrink(used);
+        // shrinking a node shared with a clone would mean copying it first, which can only
+        // use more memory
+        for subtree in self.children.owned_mut() {
+            subtree.shrink_to_fit();
+        }
+        if let Some(summary) = self.summary.as_mut().and_then(Arc::get_mut) {
+            summary.shrink_to_fit();
+        }
+    }
+
+    /// Changes the universe of the tree to [0, `max_elem`), keeping every value. The tree is
+    /// rebuilt bottom-up with the layout of the new universe, as in `from_sorted_slice`, and
+    /// keeps its kind of storage. Shrinking is allowed as long as every value still fits; will
+    /// return an error, leaving the tree untouched, if the new universe is invalid (see `new`)
+    /// or if the maximum is outside of the range [0, `max_elem`).
+    /// Takes the same time as `from_sorted_slice` with the new universe, which for a dense
+    /// tree can be well above O(N + sqrt(U)), or O(U) for a tree made by `new_prealloc`.
+    pub fn grow_universe(&mut self, max_elem: u64) -> Result<(), Error> {
+        let mut tree = VEBTree::with_storage(max_elem, self.is_sparse())?;
+        if self.maximum().is_some_and(|max| max.to_u64() >= max_elem) {
+            return Err(Error::ValueOutOfRange);
+        }
+        if max_elem != self.universe && self.prealloc {
+            tree.preallocate();
+            tree.insert_run(&self.to_sorted_vec());
+            *self = tree;
+        } else if max_elem != self.universe {
+            tree.build_sorted(&self.to_sorted_vec());
+            *self = tree;
+        }
+        Ok(())
+    }
+
+    /// Returns an iterator which yields every value in the tree in ascending order, and leaves
+    /// the tree empty once it's dropped, whether or not it was run to completion. Like
+    /// `clear`, the memory used by the clusters is kept for reuse.
+    pub fn drain<'a>(&'a mut self) -> Drain<'a, T> {
+        Drain {
+            next: self.minimum(),
+            remaining: self.len,
+            tree: self,
+        }
+    }
+
+    /// Removes every element from this van Emde Boas tree. The universe is unchanged, so the
+    /// tree can be reused immediately.
+    /// The memory used by the clusters is kept for values inserted afterwards, so filling and
+    /// clearing the same tree doesn't allocate each time; use `shrink_to_fit` to release it.
+    /// Takes time proportional to the number of clusters which are allocated.
+    pub fn clear(&mut self) {
+        if self.is_empty() {
+            return;
+        }
+        self.set_empty();
+        self.children.clear();
+        match self.summary.as_mut().map(Arc::get_mut) {
+            Some(Some(summary)) => summary.clear(),
+            // a shared summary is dropped rather than copied, and the clusters can't be kept
+            // without one
+            Some(None) => {
+                self.children.release();
+                self.summary = None;
+            }
+            None => {}
+        }
+        if self.prealloc {
+            // replace the nodes which were shared with a clone, and so dropped
+            self.preallocate();
+        }
+    }
+
+    /// Removes an element from this van Emde Boas tree. Returns true if the value was
+    /// present; otherwise, including when it's outside of the range [0, U), the tree is left
+    /// untouched.
+    /// Takes O(log(U)) time, where U is the argument to the constructor.
+    pub fn delete(&mut self, x: T) -> bool {
+        let mut probe = Probe::default();
+        let deleted = !self.is_empty() && self.has_probed(x, &mut probe);
+        if deleted {
+            self.delete_probed(x, &mut probe);
+        }
+        self.record(Op::Delete, probe);
+        deleted
+    }
+
+    /// Removes and returns the lowest value stored in the tree, or None if it's empty.
+    /// Takes O(log(U)) time, where U is the argument to the constructor.
+    pub fn pop_min(&mut self) -> Option<T> {
+        let min = self.minimum();
+        if let Some(min) = min {
+            self.delete_present(min);
+        }
+        min
+    }
+
+    /// Removes and returns the highest value stored in the tree, or None if it's empty.
+    /// Takes O(log(U)) time, where U is the argument to the constructor.
+    pub fn pop_max(&mut self) -> Option<T> {
+        let max = self.maximum();
+        if let Some(max) = max {
+            self.delete_present(max);
+        }
+        max
+    }
+
+    /// Moves every value greater than or equal to `x` into a new tree with the same universe,
+    /// which is returned. Only the values less than `x` are left in this tree.
+    /// Clusters entirely above `x` are moved over whole, and the summary is split in the same
+    /// way, so only the cluster containing `x` is divided at each level. This takes
+    /// O((C + 1) log(U)) time, where C is the number of non-empty clusters moved.
+    pub fn split_off(&mut self, x: T) -> VEBTree<T> {
+        if self.is_empty() || x > self.max {
+            return self.empty_like(self.universe);
+        }
+        if x <= self.min {
+            let empty = self.empty_like(self.universe);
+            return mem::replace(self, empty);
+        }
+        let mut upper = self.empty_like(self.universe);
+        if let Some(bits) = self.children.bits() {
+            upper.set_bits(bits & !below(x.to_u64()));
+            self.set_bits(bits & below(x.to_u64()));
+            return upper;
+        }
+        let idx = self.high(x);
+        let low = self.low(x);
+        // the clusters above the one containing x move over whole, along with their part of
+        // the summary
+        let upper_summary = match idx.to_u64() + 1 {
+            next if next < cluster_count(self.universe) => {
+                summary_mut!(self).split_off(T::from_u64(next))
+            }
+            _ => self.empty_like(cluster_count(self.universe)),
+        };
+        if !upper_summary.is_empty() {
+            upper.ensure_children();
+        }
+        for moved in upper_summary.iter() {
+            let subtree = self.children.remove(moved.to_u64()).unwrap();
+            upper.len += subtree.len;
+            upper.children.set_shared(moved.to_u64(), subtree);
+        }
+        upper.summary = Some(Arc::new(upper_summary));
+        // the cluster containing x is split in two
+        let split = match self.children.get_mut(idx.to_u64()) {
+            Some(mut subtree) if !subtree.is_empty() => {
+                let split = subtree.split_off(low);
+                Some((split, subtree.is_empty()))
+            }
+            _ => None,
+        };
+        if let Some((split, emptied)) = split {
+            if emptied {
+                self.drop_cluster(idx);
+            }
+            if !split.is_empty() {
+                upper.len += split.len;
+                upper.ensure_children();
+                upper.children.set(idx.to_u64(), split);
+                summary_mut!(upper).insert(idx);
+            }
+        }
+        self.len -= upper.len;
+        upper.adopt_clusters();
+        self.max = match self.last_cluster() {
+            Some(last) => self.index(last, subtree!(self, last).unwrap().max),
+            None => {
+                self.release_clusters();
+                self.min
+            }
+        };
+        upper
+    }
+
+    /// Inserts every value in the range, returning how many weren't already present. Panics
+    /// if the range holds any values outside of [0, U), as `insert` does, and like
+    /// `BTreeSet::range`, if the start of the range is greater than its end, or if they're
+    /// equal and both excluded.
+    /// Clusters entirely inside of the range are filled directly, and leaves a word at a time,
+    /// so this takes O((C + 1) log(U)) time, where C is the number of clusters the range
+    /// covers at every level.
+    pub fn insert_range<R: RangeBounds<T>>(&mut self, range: R) -> usize {
+        match self.unclipped_bounds(&range) {
+            (Some(lo), Some(hi)) if lo <= hi => {
+                assert!(hi < self.universe, "value out of range");
+                self.fill_span(lo, hi)
+            }
+            _ => 0,
+        }
+    }
+
+    // Inserts every value in [lo, hi], where lo <= hi < U, returning how many were added.
+    fn fill_span(&mut self, mut lo: u64, hi: u64) -> usize {
+        if let Some(bits) = self.children.bits() {
+            let span = !below(lo) & (below(hi) | bit(hi));
+            self.set_bits(bits | span);
+            return (span & !bits).count_ones() as usize;
+        }
+        let mut added = 0;
+        if self.is_empty() {
+            self.min = T::from_u64(lo);
+            self.max = T::from_u64(lo);
+            added = 1;
+            lo += 1;
+        } else if lo < self.min.to_u64() {
+            // the old minimum gets pushed down into the clusters instead
+            let old = mem::replace(&mut self.min, T::from_u64(lo)).to_u64();
+            self.fill_clusters(old, old);
+            added = 1;
+            lo += 1;
+        } else if lo == self.min.to_u64() {
+            lo += 1;
+        }
+        if lo <= hi {
+            added += self.fill_clusters(lo, hi);
+            if hi > self.max.to_u64() {
+                self.max = T::from_u64(hi);
+            }
+        }
+        self.len += added;
+        added
+    }
+
+    // Inserts [lo, hi] into the clusters, where min < lo <= hi < U, returning how many values
+    // were added. Leaves min, max and len alone.
+    fn fill_clusters(&mut self, lo: u64, hi: u64) -> usize {
+        let (lo_idx, hi_idx) = (self.high(T::from_u64(lo)), self.high(T::from_u64(hi)));
+        let (lo_low, hi_low) = (self.low(T::from_u64(lo)), self.low(T::from_u64(hi)));
+        let last_low = self.sqrt_universe - 1;
+        self.ensure_children();
+        let added = if lo_idx == hi_idx {
+            self.fill_cluster(lo_idx.to_u64(), lo_low.to_u64(), hi_low.to_u64())
+        } else {
+            let mut added = self.fill_cluster(lo_idx.to_u64(), lo_low.to_u64(), last_low);
+            for idx in lo_idx.to_u64() + 1..hi_idx.to_u64() {
+                added += self.fill_cluster(idx, 0, last_low);
+            }
+            added + self.fill_cluster(hi_idx.to_u64(), 0, hi_low.to_u64())
+        };
+        // every cluster in between now has something in it
+        summary_mut!(self).fill_span(lo_idx.to_u64(), hi_idx.to_u64());
+        added
+    }
+
+    fn fill_cluster(&mut self, idx: u64, lo: u64, hi: u64) -> usize {
+        let sqrt = self.sqrt_universe;
+        self.children.get_or_alloc(idx, sqrt).fill_span(lo, hi)
+    }
+
+    /// Removes every value in the range, returning how many were removed. Bounds outside of
+    /// the universe are fine, and like `BTreeSet::range`, panics if the start of the range is
+    /// greater than its end, or if they're equal and both excluded.
+    /// Clusters entirely inside of the range are dropped whole without visiting their values,
+    /// and only the clusters holding the ends of the range are searched, so this takes
+    /// O((C + 1) log(U)) time, where C is the number of non-empty clusters dropped.
+    pub fn remove_range<R: RangeBounds<T>>(&mut self, range: R) -> usize {
+        match self.inclusive_bounds(&range) {
+            Some((lo, hi)) => self.remove_span(lo, hi),
+            None => 0,
+        }
+    }
+
+    // Removes every value in [lo, hi], where lo <= hi < U, returning how many were removed.
+    fn remove_span(&mut self, lo: u64, hi: u64) -> usize {
+        if self.is_empty() || hi < self.min.to_u64() || lo > self.max.to_u64() {
+            return 0;
+        }
+        if let Some(bits) = self.children.bits() {
+            let span = !below(lo) & (below(hi) | bit(hi));
+            self.set_bits(bits & !span);
+            return (bits & span).count_ones() as usize;
+        }
+        let before = self.len;
+        let remove_min = lo <= self.min.to_u64();
+        let (lo_idx, hi_idx) = (self.high(T::from_u64(lo)), self.high(T::from_u64(hi)));
+        // the clusters strictly between the two ends are dropped whole, summary and all
+        if hi_idx.to_u64() > lo_idx.to_u64() + 1 {
+            let (first, last) = (lo_idx.to_u64() + 1, hi_idx.to_u64() - 1);
+            if let Some(ref summary) = self.summary {
+                for idx in summary.range(T::from_u64(first)..=T::from_u64(last)) {
+                    self.len -= if self.prealloc {
+                        let mut subtree = self.children.get_mut(idx.to_u64()).unwrap();
+                        let len = subtree.len;
+                        subtree.clear();
+                        len
+                    } else {
+                        self.children.remove(idx.to_u64()).unwrap().len
+                    };
+                }
+            }
+            if let Some(summary) = self.summary.as_mut() {
+                Arc::make_mut(summary).remove_span(first, last);
+            }
+        }
+        // then the clusters holding each end, which may be the same one
+        let lo_low = self.low(T::from_u64(lo)).to_u64();
+        let hi_low = self.low(T::from_u64(hi)).to_u64();
+        if lo_idx == hi_idx {
+            self.remove_span_in_cluster(lo_idx, lo_low, hi_low);
+        } else {
+            self.remove_span_in_cluster(lo_idx, lo_low, self.sqrt_universe - 1);
+            self.remove_span_in_cluster(hi_idx, 0, hi_low);
+        }
+        if self.first_cluster().is_none() {
+            self.release_clusters();
+        }
+        if remove_min {
+            self.len -= 1;
+            // every remaining value is in the clusters
+            self.adopt_clusters();
+        } else {
+            self.max = match self.last_cluster() {
+                Some(last) => self.index(last, subtree!(self, last).unwrap().max),
+                None => self.min,
+            };
+        }
+        before - self.len
+    }
+
+    // Removes [lo, hi] from a cluster, dropping it if that empties it. Leaves min and max
+    // alone.
+    fn remove_span_in_cluster(&mut self, idx: T, lo: u64, hi: u64) {
+        let emptied = match self.children.get_mut(idx.to_u64()) {
+            Some(mut subtree) if !subtree.is_empty() => {
+                self.len -= subtree.remove_span(lo, hi);
+                subtree.is_empty()
+            }
+            _ => false,
+        };
+        if emptied {
+            self.drop_cluster(idx);
+        }
+    }
+
+    /// Removes every value for which `f` returns false, visiting the values in ascending
+    /// order.
+    /// Filters each cluster in place without allocating, taking O(N log(U)) time, where N is
+    /// the number of values in the tree.
+    pub fn retain<F: FnMut(T) -> bool>(&mut self, mut f: F) {
+        self.retain_from(0, &mut f);
+    }
+
+    // Filters the tree in place; base is the value of the first element covered by this tree.
+    fn retain_from<F: FnMut(T) -> bool>(&mut self, base: u64, f: &mut F) {
+        if self.is_empty() {
+            return;
+        }
+        if let Some(bits) = self.children.bits() {
+            let mut kept = 0;
+            let mut rest = bits;
+            while let Some(x) = lowest::<u64>(rest) {
+                rest &= rest - 1;
+                if f(T::from_u64(base + x)) {
+                    kept |= bit(x);
+                }
+            }
+            self.set_bits(kept);
+            return;
+        }
+        let keep_min = f(T::from_u64(base + self.min.to_u64()));
+        // the clusters are filtered in ascending order, so f sees every value in order
+        self.len = keep_min as usize;
+        let mut next = self.first_cluster();
+        while let Some(idx) = next {
+            next = self.next_cluster(idx);
+            let cluster_base = base + (idx.to_u64() << self.sqrt_universe.trailing_zeros());
+            let emptied = {
+                let mut subtree = self.children.get_mut(idx.to_u64()).unwrap();
+                subtree.retain_from(cluster_base, f);
+                self.len += subtree.len;
+                subtree.is_empty()
+            };
+            if emptied {
+                self.drop_cluster(idx);
+            }
+        }
+        if self.first_cluster().is_none() {
+            self.release_clusters();
+        }
+        if keep_min {
+            self.max = match self.last_cluster() {
+                Some(last) => self.index(last, subtree!(self, last).unwrap().max),
+                None => self.min,
+            };
+        } else {
+            // every remaining value is in the clusters
+            self.adopt_clusters();
+        }
+    }
+
+    /// Checks the internal structure of the tree, returning a description of the first problem
+    /// found and the path to the node it was found in, such as `root.clusters[3].summary`.
+    /// A tree only ever fails this because of a bug in this crate, so it's meant for debugging
+    /// and tests. Empty clusters kept allocated by `clear` are allowed, as long as they aren't
+    /// listed in the summary.
+    /// Takes O(N) time, where N is the number of allocated subtrees.
+    pub fn validate(&self) -> Result<(), String> {
+        self.validate_at("root")
+    }
+
+    fn validate_at(&self, path: &str) -> Result<(), String> {
+        let fail = |problem: &str| Err(format!("{}: {}", path, problem));
+        if let Some(bits) = self.children.bits() {
+            let (min, max) = (lowest(bits), highest(bits));
+            return if self.summary.is_some() {
+                fail("leaf has a summary")
+            } else if bits & !below(self.universe) != 0 {
+                fail(&format!("{:?} is outside of the universe {}", max.unwrap(), self.universe))
+            } else if bits.count_ones() as usize != self.len {
+                fail(&format!("len is {} but {} values are stored", self.len, bits.count_ones()))
+            } else if !self.is_empty() && (min != Some(self.min) || max != Some(self.max)) {
+                fail(&format!("min and max are {:?} and {:?} but the stored values range from \
+                              {:?} to {:?}", self.min, self.max, min.unwrap(), max.unwrap()))
+            } else {
+                Ok(())
+            };
+        }
+        let child_path = |idx: u64| format!("{}.clusters[{}]", path, idx);
+        if self.is_empty() {
+            for (idx, child) in self.children.iter() {
+                if !child.is_empty() {
+                    return fail(&format!("the tree is empty but cluster {} isn't", idx));
+                }
+                child.validate_at(&child_path(idx))?;
+            }
+            if self.summary.as_ref().is_some_and(|summary| !summary.is_empty()) {
+                return fail("the tree is empty but its summary isn't");
+            } else if self.children.counts().is_some_and(|counts| counts.before(!0) != 0) {
+                return fail("the tree is empty but its clusters are counted as holding values");
+            }
+            return Ok(());
+        }
+        if self.min > self.max {
+            return fail(&format!("min {:?} is greater than max {:?}", self.min, self.max));
+        }
+        if self.max.to_u64() >= self.universe {
+            return fail(&format!("{:?} is outside of the universe {}", self.max, self.universe));
+        }
+        let summary = match self.summary {
+            Some(ref summary) => summary,
+            // nothing has gone into a cluster yet
+            None if self.len != 1 || self.min != self.max => {
+                return fail(&format!("{} values are stored without a summary", self.len));
+            }
+            None if !self.children.is_unallocated() => {
+                return fail("clusters are allocated without a summary");
+            }
+            None => return Ok(()),
+        };
+        if summary.universe != cluster_count(self.universe) {
+            return fail(&format!("the summary's universe is {} rather than {}",
+                                 summary.universe, cluster_count(self.universe)));
+        }
+        summary.validate_at(&format!("{}.summary", path))?;
+        if self.children.slots() as u64 > summary.universe {
+            return fail(&format!("{} cluster slots are allocated but there are only {} clusters",
+                                 self.children.slots(), summary.universe));
+        }
+        let counts = self.children.counts().unwrap();
+        let mut len = 1;
+        for (idx, child) in self.children.iter() {
+            if counts.of(idx) != child.len {
+                return fail(&format!("cluster {} holds {} values but is counted as holding {}",
+                                     idx, child.len, counts.of(idx)));
+            }
+            let listed = summary.has(T::from_u64(idx));
+            if child.is_empty() && listed {
+                return fail(&format!("empty cluster {} is listed in the summary", idx));
+            } else if !child.is_empty() && !listed {
+                return fail(&format!("cluster {} is missing from the summary", idx));
+            } else if child.universe != self.sqrt_universe {
+                return fail(&format!("cluster {} has a universe of {} rather than {}",
+                                     idx, child.universe, self.sqrt_universe));
+            }
+            child.validate_at(&child_path(idx))?;
+            if child.is_empty() {
+                // clear keeps empty clusters allocated
+                continue;
+            }
+            let idx = T::from_u64(idx);
+            if self.index(idx, child.min) <= self.min {
+                return fail(&format!("cluster {:?} holds {:?}, which isn't above the minimum \
+                                      {:?}", idx, self.index(idx, child.min), self.min));
+            } else if self.index(idx, child.max) > self.max {
+                return fail(&format!("cluster {:?} holds {:?}, which is above the maximum \
+                                      {:?}", idx, self.index(idx, child.max), self.max));
+            }
+            len += child.len;
+        }
+        // every cluster in the summary has to be one of the children checked above
+        if let Some(idx) = summary.iter().find(|&idx| subtree!(self, idx).is_none()) {
+            return fail(&format!("the summary lists cluster {:?}, which is empty", idx));
+        }
+        // the maximum is stored in the clusters unless it is also the minimum
+        let max_stored = summary.maximum()
+            .map(|last| self.index(last, subtree!(self, last).unwrap().max));
+        let expected = Some(self.max).filter(|&max| max != self.min);
+        if max_stored != expected {
+            return fail(&format!("the largest value in the clusters is {:?} but max is {:?}",
+                                 max_stored, self.max));
+        }
+        if len != self.len {
+            return fail(&format!("len is {} but {} values are stored", self.len, len));
+        } else if counts.before(summary.universe) != len - 1 {
+            return fail(&format!("the clusters hold {} values but are counted as holding {}",
+                                 len - 1, counts.before(summary.universe)));
+        }
+        Ok(())
+    }
+
+    // Allocates the (initially empty) slots for every cluster, if that hasn't happened yet or
+    // shrink_to_fit has trimmed them.
+    fn ensure_children(&mut self) {
+        self.children.reserve(cluster_count(self.universe) as usize);
+    }
+
+    // Makes a new, empty tree with the same kind of cluster storage as this one.
+    fn empty_like(&self, universe: u64) -> VEBTree<T> {
+        VEBTree::with_storage(universe, self.is_sparse()).unwrap()
+    }
+
+    // Copies a tree, converting it to the given kind of cluster storage if needed. The copy
+    // only keeps the clusters it uses, even if this tree was preallocated.
+    fn clone_as(&self, sparse: bool) -> VEBTree<T> {
+        if self.is_sparse() == sparse && !self.prealloc {
+            return self.clone();
+        }
+        let mut tree = VEBTree::with_storage(self.universe, sparse).unwrap();
+        tree.build_sorted(&self.to_sorted_vec());
+        tree
+    }
+
+    // Gives the cluster slots and the summary back once every cluster is empty, until they're
+    // needed again.
+    fn release_clusters(&mut self) {
+        if !self.prealloc {
+            self.children.release();
+            self.summary = None;
+        }
+    }
+
+    // Takes a cluster which has just been emptied out of the summary, freeing it unless the
+    // tree keeps its clusters.
+    fn drop_cluster(&mut self, idx: T) {
+        if !self.prealloc {
+            self.children.remove(idx.to_u64());
+        }
+        summary_mut!(self).delete_present(idx);
+    }
+
+    // Adds a finished operation to the counters kept with the `stats` feature.
+    #[inline]
+    fn record(&self, op: Op, probe: Probe) {
+        #[cfg(feature = "stats")]
+        stats::record(&self.counters, op, probe);
+        #[cfg(not(feature = "stats"))]
+        let _ = (op, probe);
+    }
+
+    fn set_empty(&mut self) {
+        self.min = T::from_u64(1);
+        self.max = T::from_u64(0);
+        self.len = 0;
+        if let Some(bits) = self.children.bits_mut() {
+            *bits = 0;
+        }
+    }
+
+    // Replaces the values stored in a leaf, keeping min, max and len in step with them.
+    fn set_bits(&mut self, bits: u64) {
+        *self.children.bits_mut().expect("not a leaf") = bits;
+        match (lowest(bits), highest(bits)) {
+            (Some(min), Some(max)) => {
+                self.min = min;
+                self.max = max;
+                self.len = bits.count_ones() as usize;
+            }
+            _ => self.set_empty(),
+        }
+    }
+
+    // Removes an element which is known to be in the tree.
+    fn delete_present(&mut self, x: T) {
+        self.delete_probed(x, &mut Probe::default());
+    }
+
+    fn delete_probed(&mut self, mut x: T, probe: &mut Probe) {
+        probe.level();
+        if let Some(bits) = self.children.bits() {
+            self.set_bits(bits & !bit(x.to_u64()));
+        } else if self.min == self.max {
+            self.set_empty();
+        } else {
+            self.len -= 1;
+            if self.min == x {
+                // the new minimum is the smallest element in the clusters; pull it up out
+                // of its cluster
+                let first = summary!(self).min;
+                x = self.index(first, subtree!(self, first).unwrap().min);
+                self.min = x;
+            }
+            // recurse
+            let idx = self.high(x);
+            let low = self.low(x);
+            let emptied = {
+                let mut subtree = self.children.get_mut(idx.to_u64()).unwrap();
+                subtree.delete_probed(low, probe);
+                subtree.is_empty()
+            };
+            if emptied {
+                // don't store empty trees, and remove from summary as well
+                probe.prune();
+                self.drop_cluster(idx);
+                if self.first_cluster().is_none() {
+                    self.release_clusters();
+                }
+            }
+            if self.max == x {
+                // we need to calculate the new maximum
+                self.max = if self.first_cluster().is_none() {
+                    // only 1 element in the tree
+                    self.min
+                } else {
+                    let last = summary!(self).max;
+                    self.index(last, subtree!(self, last).unwrap().max)
+                }
+            }
+        }
+    }
+
+
+    // ==============
+    // set operations
+    // ==============
+
+    /// Returns a new tree containing every value in either this tree or `other`. Will return
+    /// an error if the two trees have different universes.
+    /// Merges the trees cluster by cluster, so clusters only present in one tree are copied
+    /// over wholesale instead of being rebuilt one element at a time.
+    pub fn union(&self, other: &VEBTree<T>) -> Result<VEBTree<T>, Error> {
+        if self.universe != other.universe {
+            Err(Error::UniverseMismatch)
+        } else {
+            let mut result = self.clone();
+            result.merge_from(other);
+            Ok(result)
+        }
+    }
+
+    /// Adds every value in `other` to this tree, in the same way as `union` but without
+    /// building a new tree. Will return an error, leaving this tree unchanged, if the two trees
+    /// have different universes.
+    pub fn union_with(&mut self, other: &VEBTree<T>) -> Result<(), Error> {
+        if self.universe != other.universe {
+            Err(Error::UniverseMismatch)
+        } else {
+            self.merge_from(other);
+            Ok(())
+        }
+    }
+
+    /// Moves every value in `other` into this tree, leaving `other` empty, like
+    /// `BTreeSet::append`. Will return an error, leaving both trees unchanged, if the two trees
+    /// have different universes.
+    /// Since `other` is emptied anyway, its clusters are moved over whole wherever this tree
+    /// has no cluster at the same index, and only clusters present in both trees are merged.
+    pub fn append(&mut self, other: &mut VEBTree<T>) -> Result<(), Error> {
+        if self.universe != other.universe {
+            return Err(Error::UniverseMismatch);
+        }
+        let empty = other.empty_like(other.universe);
+        let other = mem::replace(other, empty);
+        if self.is_sparse() == other.is_sparse() {
+            self.append_from(other);
+        } else {
+            // the clusters would have the wrong kind of storage, so they're copied instead
+            self.merge_from(&other);
+        }
+        Ok(())
+    }
+
+    // Moves every value in other (which must have the same universe and kind of storage) into
+    // this tree.
+    fn append_from(&mut self, mut other: VEBTree<T>) {
+        if let (Some(ours), Some(theirs)) = (self.children.bits(), other.children.bits()) {
+            self.set_bits(ours | theirs);
+            return;
+        }
+        if other.is_empty() {
+            return;
+        }
+        if self.is_empty() && self.prealloc == other.prealloc {
+            *self = other;
+            return;
+        }
+        // as in merge_from, other's minimum isn't in any of its clusters
+        self.insert(other.min);
+        let theirs = match other.summary.take() {
+            Some(summary) if !summary.is_empty() => summary,
+            _ => return,
+        };
+        self.ensure_children();
+        for idx in theirs.iter() {
+            let moved = other.children.remove(idx.to_u64()).unwrap();
+            let unmerged = match self.children.get_mut(idx.to_u64()) {
+                Some(mut ours) => {
+                    self.len -= ours.len;
+                    ours.append_from(Arc::try_unwrap(moved).unwrap_or_else(|shared| {
+                        (*shared).clone()
+                    }));
+                    self.len += ours.len;
+                    None
+                }
+                None => Some(moved),
+            };
+            if let Some(moved) = unmerged {
+                self.len += moved.len;
+                self.children.set_shared(idx.to_u64(), moved);
+            }
+        }
+        summary_mut!(self).append_from(Arc::try_unwrap(theirs).unwrap_or_else(|shared| {
+            (*shared).clone()
+        }));
+        if other.max > self.max {
+            self.max = other.max;
+        }
+    }
+
+    // Adds every value in other (which must have the same universe) to this tree.
+    fn merge_from(&mut self, other: &VEBTree<T>) {
+        if let (Some(ours), Some(theirs)) = (self.children.bits(), other.children.bits()) {
+            self.set_bits(ours | theirs);
+            return;
+        }
+        if other.is_empty() {
+            return;
+        }
+        if self.is_empty() && !self.prealloc {
+            *self = other.clone_as(self.is_sparse());
+            return;
+        }
+        // other's minimum isn't stored in any of its clusters, so it has to be inserted
+        // separately; this also leaves self.min <= everything in other's clusters.
+        self.insert(other.min);
+        let theirs = match other.summary {
+            Some(ref summary) if !summary.is_empty() => summary,
+            _ => return,
+        };
+        self.ensure_children();
+        let mut next = other.first_cluster();
+        while let Some(idx) = next {
+            let theirs = subtree!(other, idx).unwrap();
+            let sparse = self.is_sparse();
+            let merged = match self.children.get_mut(idx.to_u64()) {
+                Some(mut ours) => {
+                    self.len -= ours.len;
+                    ours.merge_from(theirs);
+                    self.len += ours.len;
+                    true
+                }
+                None => false,
+            };
+            if !merged {
+                self.len += theirs.len;
+                self.children.set(idx.to_u64(), theirs.clone_as(sparse));
+            }
+            next = other.next_cluster(idx);
+        }
+        summary_mut!(self).merge_from(theirs);
+        if other.max > self.max {
+            self.max = other.max;
+        }
+    }
+
+    /// Returns a new tree containing the values present in both this tree and `other`. Will
+    /// return an error if the two trees have different universes.
+    /// Only recurses into clusters which are present in both trees.
+    pub fn intersection(&self, other: &VEBTree<T>) -> Result<VEBTree<T>, Error> {
+        if self.universe != other.universe {
+            Err(Error::UniverseMismatch)
+        } else {
+            Ok(self.intersect(other))
+        }
+    }
+
+    // Intersects with another tree with the same universe.
+    fn intersect(&self, other: &VEBTree<T>) -> VEBTree<T> {
+        let mut result = self.empty_like(self.universe);
+        if let (Some(ours), Some(theirs)) = (self.children.bits(), other.children.bits()) {
+            result.set_bits(ours & theirs);
+            return result;
+        }
+        if self.is_empty() || other.is_empty() {
+            return result;
+        }
+        if let (Some(ours), Some(theirs)) = (self.summary.as_ref(), other.summary.as_ref()) {
+            // the clusters present in both trees; ones whose intersection turns out to be
+            // empty are dropped again below
+            let mut common = ours.intersect(theirs);
+            let mut next = common.minimum();
+            while let Some(idx) = next {
+                next = common.find_next(idx);
+                let ours = subtree!(self, idx).unwrap();
+                let theirs = subtree!(other, idx).unwrap();
+                let both = ours.intersect(theirs);
+                if both.is_empty() {
+                    common.delete_present(idx);
+                } else {
+                    result.len += both.len;
+                    result.ensure_children();
+                    result.children.set(idx.to_u64(), both);
+                }
+            }
+            result.summary = Some(Arc::new(common));
+            result.adopt_clusters();
+        }
+        // the minimums aren't stored in the clusters
+        for &x in &[self.min, other.min] {
+            if self.has(x) && other.has(x) {
+                result.insert(x);
+            }
+        }
+        result
+    }
+
+    /// Returns a new tree containing the values present in this tree but not in `other`. Will
+    /// return an error if the two trees have different universes.
+    /// Clusters which aren't present in `other` are copied over wholesale.
+    pub fn difference(&self, other: &VEBTree<T>) -> Result<VEBTree<T>, Error> {
+        if self.universe != other.universe {
+            Err(Error::UniverseMismatch)
+        } else {
+            Ok(self.subtract(other))
+        }
+    }
+
+    /// Returns a new tree containing the values present in exactly one of this tree and
+    /// `other`. Will return an error if the two trees have different universes.
+    pub fn symmetric_difference(&self, other: &VEBTree<T>) -> Result<VEBTree<T>, Error> {
+        if self.universe != other.universe {
+            Err(Error::UniverseMismatch)
+        } else {
+            let mut result = self.subtract(other);
+            result.merge_from(&other.subtract(self));
+            Ok(result)
+        }
+    }
+
+    // Removes the values in another tree with the same universe.
+    fn subtract(&self, other: &VEBTree<T>) -> VEBTree<T> {
+        if self.is_empty() || other.is_empty() {
+            return self.clone();
+        }
+        let mut result = self.empty_like(self.universe);
+        if let (Some(ours), Some(theirs)) = (self.children.bits(), other.children.bits()) {
+            result.set_bits(ours & !theirs);
+            return result;
+        }
+        if let Some(ref summary) = self.summary {
+            // the clusters left over; ones which turn out to be empty are dropped again below
+            let mut kept = (**summary).clone();
+            let mut next = kept.minimum();
+            while let Some(idx) = next {
+                next = kept.find_next(idx);
+                let ours = subtree!(self, idx).unwrap();
+                let left = match subtree!(other, idx) {
+                    Some(theirs) => ours.subtract(theirs),
+                    None => ours.clone(),
+                };
+                if left.is_empty() {
+                    kept.delete_present(idx);
+                } else {
+                    result.len += left.len;
+                    result.ensure_children();
+                    result.children.set(idx.to_u64(), left);
+                }
+            }
+            result.summary = Some(Arc::new(kept));
+            result.adopt_clusters();
+        }
+        // the minimums aren't stored in the clusters
+        if !other.has(self.min) {
+            result.insert(self.min);
+        }
+        result.delete(other.min);
+        result
+    }
+
+    /// Returns true if every value in this tree is also in `other`. Will return an error if
+    /// the two trees have different universes.
+    /// Stops at the first cluster which isn't contained in the matching cluster of `other`.
+    pub fn is_subset(&self, other: &VEBTree<T>) -> Result<bool, Error> {
+        if self.universe != other.universe {
+            Err(Error::UniverseMismatch)
+        } else {
+            Ok(self.subset_of(other))
+        }
+    }
+
+    /// Returns true if every value in `other` is also in this tree. Will return an error if
+    /// the two trees have different universes.
+    pub fn is_superset(&self, other: &VEBTree<T>) -> Result<bool, Error> {
+        other.is_subset(self)
+    }
+
+    /// Returns true if no value is in both this tree and `other`. Will return an error if the
+    /// two trees have different universes.
+    /// Only recurses into clusters which are present in both trees, and stops at the first
+    /// value found in both.
+    pub fn is_disjoint(&self, other: &VEBTree<T>) -> Result<bool, Error> {
+        if self.universe != other.universe {
+            Err(Error::UniverseMismatch)
+        } else {
+            Ok(self.disjoint_from(other))
+        }
+    }
+
+    // is_subset for two trees with the same universe.
+    fn subset_of(&self, other: &VEBTree<T>) -> bool {
+        if self.is_empty() {
+            return true;
+        }
+        if let (Some(ours), Some(theirs)) = (self.children.bits(), other.children.bits()) {
+            return ours & !theirs == 0;
+        }
+        if self.len > other.len || !other.has(self.min) || !other.has(self.max) {
+            return false;
+        }
+        // other's minimum isn't stored in its clusters, so the cluster it falls in (if any) is
+        // checked value by value instead
+        let special = self.high(other.min);
+        self.summary.as_ref().is_none_or(|summary| summary.iter().all(|idx| {
+            let ours = subtree!(self, idx).unwrap();
+            if idx == special {
+                ours.iter().all(|low| other.has(self.index(idx, low)))
+            } else {
+                subtree!(other, idx).map_or(false, |theirs| ours.subset_of(theirs))
+            }
+        }))
+    }
+
+    // is_disjoint for two trees with the same universe.
+    fn disjoint_from(&self, other: &VEBTree<T>) -> bool {
+        if let (Some(ours), Some(theirs)) = (self.children.bits(), other.children.bits()) {
+            return ours & theirs == 0;
+        }
+        if self.is_empty() || other.is_empty() {
+            return true;
+        }
+        // the minimums aren't stored in the clusters
+        if other.has(self.min) || self.has(other.min) {
+            return false;
+        }
+        self.summary.as_ref().is_none_or(|summary| summary.iter().all(|idx| {
+            subtree!(other, idx).map_or(true, |theirs| {
+                subtree!(self, idx).unwrap().disjoint_from(theirs)
+            })
+        }))
+    }
+
+    // Sets min and max for a tree whose values have all been placed into its clusters, with
+    // its summary and len already filled in. The minimum is moved back out of its cluster.
+    fn adopt_clusters(&mut self) {
+        if self.first_cluster().is_none() {
+            self.set_empty();
+            self.release_clusters();
+            return;
+        }
+        let last = summary!(self).max;
+        self.max = self.index(last, subtree!(self, last).unwrap().max);
+        let first = summary!(self).min;
+        let low = subtree!(self, first).unwrap().min;
+        self.min = self.index(first, low);
+        let emptied = {
+            let mut subtree = self.children.get_mut(first.to_u64()).unwrap();
+            subtree.delete_present(low);
+            subtree.is_empty()
+        };
+        if emptied {
+            self.drop_cluster(first);
+            if self.first_cluster().is_none() {
+                self.release_clusters();
+            }
+        }
+    }
+}
+
+/// An iterator over the values in a `VEBTree`, created by `VEBTree::iter`.
+/// Each end keeps its position at every level of the tree, so stepping only moves as far up
+/// the tree as it has to rather than starting again from the root.
+#[derive(Debug, Clone)]
+pub struct Iter<'a, T: VebInt + 'a> {
+    front: Walk<'a, T>,
+    back: Walk<'a, T>,
+    // the ends meet once this reaches 0
+    remaining: usize,
+}
+
+// A position in the tree: the path from the root down to the node currently being walked.
+#[derive(Debug, Clone)]
+struct Walk<'a, T: VebInt + 'a> {
+    stack: Vec<Frame<'a, T>>,
+}
+
+#[derive(Debug, Clone)]
+struct Frame<'a, T: VebInt + 'a> {
+    node: &'a VEBTree<T>,
+    // the value of the first element covered by node
+    base: u64,
+    state: FrameState<T>,
+}
+
+// How far the walk through a node has got. Going forwards, a node yields its minimum, then
+// its clusters in ascending order. Going backwards, it's the other way around. Leaves have no
+// clusters, so they just remember the last value they yielded.
+#[derive(Debug, Clone, Copy)]
+enum FrameState<T> {
+    Start,
+    Min,
+    Cluster(T),
+    Value(T),
+    Done,
+}
+
+impl<'a, T: VebInt> Walk<'a, T> {
+    fn new(tree: &'a VEBTree<T>) -> Self {
+        let mut stack = vec![];
+        if !tree.is_empty() {
+            stack.push(Frame {
+                node: tree,
+                base: 0,
+                state: FrameState::Start,
+            });
+        }
+        Walk { stack }
+    }
+
+    // Starts a forwards walk just after x, which must be in the tree, as if x had just been
+    // yielded.
+    fn after(tree: &'a VEBTree<T>, x: T) -> Self {
+        let mut walk = Walk::new(tree);
+        loop {
+            let idx = {
+                let top = walk.stack.last_mut().unwrap();
+                let low = T::from_u64(x.to_u64() - top.base);
+                if top.node.children.bits().is_some() {
+                    top.state = FrameState::Value(low);
+                    return walk;
+                } else if low == top.node.min {
+                    top.state = FrameState::Min;
+                    return walk;
+                }
+                top.node.high(low)
+            };
+            walk.enter(idx);
+        }
+    }
+
+    // Starts walking a (non-empty) cluster of the node on top of the stack.
+    fn enter(&mut self, idx: T) {
+        let (node, base) = {
+            let top = self.stack.last_mut().unwrap();
+            top.state = FrameState::Cluster(idx);
+            (top.node, top.base)
+        };
+        self.stack.push(Frame {
+            node: subtree!(node, idx).unwrap(),
+            base: base + (idx.to_u64() << node.sqrt_universe.trailing_zeros()),
+            state: FrameState::Start,
+        });
+    }
+
+    fn next(&mut self) -> Option<T> {
+        loop {
+            let (node, base, state) = {
+                let top = self.stack.last()?;
+                (top.node, top.base, top.state)
+            };
+            let value = |x: T| Some(T::from_u64(base + x.to_u64()));
+            match state {
+                FrameState::Start => {
+                    self.stack.last_mut().unwrap().state = match node.children.bits() {
+                        Some(_) => FrameState::Value(node.min),
+                        None => FrameState::Min,
+                    };
+                    return value(node.min);
+                }
+                FrameState::Value(last) => {
+                    let top = self.stack.last_mut().unwrap();
+                    match node.find_next(last) {
+                        Some(x) => {
+                            top.state = FrameState::Value(x);
+                            return value(x);
+                        }
+                        None => top.state = FrameState::Done,
+                    }
+                }
+                FrameState::Min => {
+                    match node.first_cluster() {
+                        Some(idx) => self.enter(idx),
+                        None => self.stack.last_mut().unwrap().state = FrameState::Done,
+                    }
+                }
+                FrameState::Cluster(idx) => {
+                    match node.next_cluster(idx) {
+                        Some(idx) => self.enter(idx),
+                        None => self.stack.last_mut().unwrap().state = FrameState::Done,
+                    }
+                }
+                FrameState::Done => {
+                    self.stack.pop();
+                }
+            }
+        }
+    }
+
+    fn next_back(&mut self) -> Option<T> {
+        loop {
+            let (node, base, state) = {
+                let top = self.stack.last()?;
+                (top.node, top.base, top.state)
+            };
+            let value = |x: T| Some(T::from_u64(base + x.to_u64()));
+            let next_cluster = match state {
+                FrameState::Start if node.children.bits().is_some() => {
+                    self.stack.last_mut().unwrap().state = FrameState::Value(node.max);
+                    return value(node.max);
+                }
+                FrameState::Value(last) => {
+                    if let Some(x) = node.find_prev(last) {
+                        self.stack.last_mut().unwrap().state = FrameState::Value(x);
+                        return value(x);
+                    }
+                    self.stack.pop();
+                    continue;
+                }
+                FrameState::Start => node.last_cluster(),
+                FrameState::Cluster(idx) => node.prev_cluster(idx),
+                FrameState::Min | FrameState::Done => {
+                    self.stack.pop();
+                    continue;
+                }
+            };
+            match next_cluster {
+                Some(idx) => self.enter(idx),
+                None => {
+                    // the minimum comes last
+                    self.stack.last_mut().unwrap().state = FrameState::Min;
+                    return value(node.min);
+                }
+            }
+        }
+    }
+}
+
+impl<'a, T: VebInt> Iterator for Iter<'a, T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        self.front.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<'a, T: VebInt> DoubleEndedIterator for Iter<'a, T> {
+    fn next_back(&mut self) -> Option<T> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        self.back.next_back()
+    }
+}
+
+impl<'a, T: VebInt> ExactSizeIterator for Iter<'a, T> {}
+
+impl<'a, T: VebInt> IntoIterator for &'a VEBTree<T> {
+    type Item = T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Iter<'a, T> {
+        self.iter()
+    }
+}
+
+/// An iterator over the values in part of a `VEBTree`, created by `VEBTree::range`.
+#[derive(Debug, Clone)]
+pub struct Range<'a, T: VebInt + 'a> {
+    tree: &'a VEBTree<T>,
+    // the next values to yield from either end; the range is finished once they cross
+    front: Option<T>,
+    back: Option<T>,
+}
+
+impl<'a, T: VebInt> Range<'a, T> {
+    fn finished(&self) -> bool {
+        match (self.front, self.back) {
+            (Some(front), Some(back)) => front > back,
+            _ => true,
+        }
+    }
+}
+
+impl<'a, T: VebInt> Iterator for Range<'a, T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        if self.finished() {
+            return None;
+        }
+        let x = self.front.unwrap();
+        self.front = self.tree.find_next(x);
+        Some(x)
+    }
+}
+
+impl<'a, T: VebInt> DoubleEndedIterator for Range<'a, T> {
+    fn next_back(&mut self) -> Option<T> {
+        if self.finished() {
+            return None;
+        }
+        let x = self.back.unwrap();
+        self.back = self.tree.find_prev(x);
+        Some(x)
+    }
+}
+
+/// An iterator over the values which are not in a `VEBTree`, created by `VEBTree::absent`
+/// and `VEBTree::absent_in`.
+#[derive(Debug, Clone)]
+pub struct Absent<'a, T: VebInt + 'a> {
+    tree: &'a VEBTree<T>,
+    // where to resume searching, and the last value which may be yielded
+    next: Option<u64>,
+    end: u64,
+}
+
+impl<'a, T: VebInt> Iterator for Absent<'a, T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        let x = self.tree.absent_geq(self.next?).filter(|&x| x <= self.end);
+        self.next = x.and_then(|x| x.checked_add(1));
+        x.map(T::from_u64)
+    }
+}
+
+/// An iterator over the runs of consecutive values in a `VEBTree`, created by
+/// `VEBTree::runs`.
+#[derive(Debug, Clone)]
+pub struct Runs<'a, T: VebInt + 'a> {
+    tree: &'a VEBTree<T>,
+    // the start of the next run
+    next: Option<T>,
+}
+
+impl<'a, T: VebInt> Iterator for Runs<'a, T> {
+    type Item = RangeInclusive<T>;
+
+    fn next(&mut self) -> Option<RangeInclusive<T>> {
+        let start = self.next?;
+        // start is stored, so the first gap is after it
+        let end = match self.tree.absent_geq(start.to_u64()) {
+            Some(gap) => T::from_u64(gap - 1),
+            None => T::from_u64(self.tree.universe - 1),
+        };
+        self.next = self.tree.find_next(end);
+        Some(start..=end)
+    }
+}
+
+/// An iterator which empties a `VEBTree`, created by `VEBTree::drain`.
+#[derive(Debug)]
+pub struct Drain<'a, T: VebInt + 'a> {
+    tree: &'a mut VEBTree<T>,
+    next: Option<T>,
+    remaining: usize,
+}
+
+impl<'a, T: VebInt> Iterator for Drain<'a, T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        // the values are only removed when the iterator is dropped, all at once
+        let x = self.next?;
+        self.next = self.tree.find_next(x);
+        self.remaining -= 1;
+        Some(x)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<'a, T: VebInt> ExactSizeIterator for Drain<'a, T> {}
+
+impl<'a, T: VebInt> Drop for Drain<'a, T> {
+    fn drop(&mut self) {
+        self.tree.clear();
+    }
+}
+
+/// An iterator which consumes a `VEBTree`, created by its `IntoIterator` implementation.
+/// Values are removed from the tree as they're yielded, so the memory used by emptied clusters
+/// is released as iteration goes on.
+#[derive(Debug, Clone)]
+pub struct IntoIter<T: VebInt> {
+    tree: VEBTree<T>,
+}
+
+impl<T: VebInt> Iterator for IntoIter<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.tree.pop_min()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.tree.len, Some(self.tree.len))
+    }
+}
+
+impl<T: VebInt> DoubleEndedIterator for IntoIter<T> {
+    fn next_back(&mut self) -> Option<T> {
+        self.tree.pop_max()
+    }
+}
+
+impl<T: VebInt> ExactSizeIterator for IntoIter<T> {}
+
+impl<T: VebInt> IntoIterator for VEBTree<T> {
+    type Item = T;
+    type IntoIter = IntoIter<T>;
+
+    fn into_iter(self) -> IntoIter<T> {
+        IntoIter { tree: self }
+    }
+}
+
+impl<T: VebInt> SuccessorSet<T> for VEBTree<T> {
+    fn insert(&mut self, x: T) -> bool {
+        VEBTree::insert(self, x)
+    }
+
+    fn delete(&mut self, x: T) -> bool {
+        VEBTree::delete(self, x)
+    }
+
+    fn has(&self, x: T) -> bool {
+        VEBTree::has(self, x)
+    }
+
+    fn minimum(&self) -> Option<T> {
+        VEBTree::minimum(self)
+    }
+
+    fn maximum(&self) -> Option<T> {
+        VEBTree::maximum(self)
+    }
+
+    fn find_next(&self, x: T) -> Option<T> {
+        VEBTree::find_next(self, x)
+    }
+
+    fn is_empty(&self) -> bool {
+        VEBTree::is_empty(self)
+    }
+
+    fn universe(&self) -> u64 {
+        VEBTree::universe(self)
+    }
+}
+
+/// `clone_from` reuses the memory the tree already has when the source has the same universe
+/// and kind of storage: the root's cluster slots are kept, and every node which isn't shared
+/// with another tree is copied into in place, wherever the source has a node in the same
+/// position. Nodes the source doesn't have are freed. Afterwards, the copied nodes belong to
+/// this tree alone, so modifying them doesn't copy them again either. With a different
+/// universe or kind of storage, this is the same as `clone`.
+impl<T: VebInt> Clone for VEBTree<T> {
+    fn clone(&self) -> Self {
+        VEBTree {
+            children: self.children.clone(),
+            summary: self.summary.clone(),
+            min: self.min,
+            max: self.max,
+            len: self.len,
+            universe: self.universe,
+            sqrt_universe: self.sqrt_universe,
+            prealloc: self.prealloc,
+            #[cfg(feature = "stats")]
+            counters: self.counters.clone(),
+        }
+    }
+
+    fn clone_from(&mut self, source: &Self) {
+        if self.universe != source.universe || self.is_sparse() != source.is_sparse() ||
+           self.prealloc != source.prealloc {
+            *self = source.clone();
+            return;
+        }
+        self.children.clone_from(&source.children);
+        clone_slot_from(&mut self.summary, &source.summary);
+        self.min = source.min;
+        self.max = source.max;
+        self.len = source.len;
+        #[cfg(feature = "stats")]
+        self.counters.clone_from(&source.counters);
+    }
+}
+
+/// Two trees are equal if they have the same universe and contain the same values, regardless
+/// of the order in which the values were inserted or deleted.
+impl<T: VebInt> PartialEq for VEBTree<T> {
+    fn eq(&self, other: &VEBTree<T>) -> bool {
+        if self.universe != other.universe || self.len != other.len ||
+           self.minimum() != other.minimum() || self.maximum() != other.maximum() {
+            return false;
+        }
+        let all = T::from_u64(self.universe - 1);
+        self.iter_range(T::from_u64(0), all).eq(other.iter_range(T::from_u64(0), all))
+    }
+}
+
+impl<T: VebInt> Eq for VEBTree<T> {}
+
+/// Hashes the universe, the number of values and then the values in ascending order, so trees
+/// which compare equal hash identically whatever their internal layout.
+impl<T: VebInt> Hash for VEBTree<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.universe.hash(state);
+        self.len.hash(state);
+        for x in self.iter() {
+            x.to_u64().hash(state);
+        }
+    }
+}
+
+// Debug output lists at most this many values before eliding the rest.
+const DEBUG_ELEMENTS: usize = 32;
+
+impl<T: VebInt> VEBTree<T> {
+    // Writes the values in braces, eliding any past the first `limit`.
+    fn write_elements(&self, f: &mut fmt::Formatter, limit: usize) -> fmt::Result {
+        f.write_str("{")?;
+        for (i, x) in self.iter().take(limit).enumerate() {
+            if i > 0 {
+                f.write_str(", ")?;
+            }
+            write!(f, "{:?}", x)?;
+        }
+        if self.len > limit {
+            write!(f, ", .. {} more", self.len - limit)?;
+        }
+        f.write_str("}")
+    }
+}
+
+/// Prints the universe, the number of values and the first 32 values, as in
+/// `VEBTree { universe: 1024, len: 3, elements: {3, 17, 200} }`. The alternate form, `{:#?}`,
+/// instead dumps every node of the tree's internal structure.
+impl<T: VebInt> fmt::Debug for VEBTree<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if f.alternate() {
+            return f.debug_struct("VEBTree")
+                .field("universe", &self.universe)
+                .field("sqrt_universe", &self.sqrt_universe)
+                .field("len", &self.len)
+                .field("min", &self.min)
+                .field("max", &self.max)
+                .field("prealloc", &self.prealloc)
+                .field("summary", &self.summary)
+                .field("children", &self.children)
+                .finish();
+        }
+        write!(f, "VEBTree {{ universe: {}, len: {}, elements: ", self.universe, self.len)?;
+        self.write_elements(f, DEBUG_ELEMENTS)?;
+        f.write_str(" }")
+    }
+}
+
+/// Prints every value in the tree in ascending order, in braces, as in `{3, 17, 200}`.
+impl<T: VebInt> fmt::Display for VEBTree<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.write_elements(f, usize::MAX)
+    }
+}
+
+impl<'a, T: VebInt> From<&'a VEBTree<T>> for BTreeSet<T> {
+    fn from(tree: &'a VEBTree<T>) -> BTreeSet<T> {
+        tree.iter_range(T::from_u64(0), T::from_u64(tree.universe - 1)).collect()
+    }
+}
+
+/// Builds a tree from a universe and a slice of values, in the same way as `from_slice`.
+impl<'a, T: VebInt> TryFrom<(u64, &'a [T])> for VEBTree<T> {
+    type Error = Error;
+
+    fn try_from((max_elem, values): (u64, &'a [T])) -> Result<Self, Error> {
+        VEBTree::from_slice(max_elem, values)
+    }
+}
+
+/// Inserts every value from the iterator. Panics if any value is outside of the range [0, U).
+impl<T: VebInt> Extend<T> for VEBTree<T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for x in iter {
+            self.try_insert(x).expect("value out of range");
+        }
+    }
+}
+
+/// Inserts every value from the iterator. Panics if any value is outside of the range [0, U).
+impl<'a, T: VebInt> Extend<&'a T> for VEBTree<T> {
+    fn extend<I: IntoIterator<Item = &'a T>>(&mut self, iter: I) {
+        self.extend(iter.into_iter().cloned());
+    }
+}
+
+/// Collects the values into a tree with the smallest power-of-two universe which can hold all
+/// of them, as `from_max_value` does. Collecting nothing gives an empty tree with a universe
+/// of 2. Panics if a value is too large for any universe to hold.
+impl<T: VebInt> iter::FromIterator<T> for VEBTree<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let values: Vec<T> = iter.into_iter().collect();
+        if values.is_empty() {
+            VEBTree::new(2).unwrap()
+        } else {
+            VEBTree::from_max_value(&values).expect("universe too big")
+        }
+    }
+}
+
+#[cfg(test)]
+fn random_tree<T: VebInt>(universe: u64, count: usize, seed: u64) -> VEBTree<T> {
+    let mut state = seed | 1;
+    let mut tree = VEBTree::new(universe).unwrap();
+    for _ in 0..count {
+        tree.insert(T::from_u64(xorshift(&mut state) % universe));
+    }
+    assert_eq!(tree.validate(), Ok(()));
+    tree
+}
+
+// Tests of the operations which every layout of the tree supports, instantiated once for each
+// of the listed tree types.
+#[cfg(test)]
+macro_rules! set_tests {
+    ( $( $name: ident: $tree: ident<$t: ty> ),* ) => {
+        $(
+            mod $name {
+                use super::*;
+                use std::println;
+
+                type K = $t;
+                type Tree = $tree<K>;
+
+                // Builds a random tree with this module's layout, holding the same values as
+                // the crate's `random_tree`.
+                fn random_tree(universe: u64, count: usize, seed: u64) -> Tree {
+                    let values = ::random_tree::<K>(universe, count, seed).to_sorted_vec();
+                    Tree::from_slice(universe, &values).unwrap()
+                }
+
+                #[test]
+                fn creation() {
+                    assert!(Tree::new(50).is_ok());
+                }
+
+                #[test]
+                fn creation_fail() {
+                    assert_eq!(Tree::new(0).unwrap_err(), Error::UniverseTooSmall);
+                    assert_eq!(Tree::new(1).unwrap_err(), Error::UniverseTooSmall);
+                    if K::MAX_VALUE < u64::MAX - 1 {
+                        assert_eq!(Tree::new(K::MAX_VALUE + 2).unwrap_err(),
+                                   Error::UniverseTooLarge);
+                    }
+                }
+
+                #[test]
+                fn insertion_and_has() {
+                    let mut tree = Tree::new(50).unwrap();
+                    assert!(!tree.has(25));
+                    assert!(!tree.has(26));
+                    tree.insert(25);
+                    assert!(tree.has(25));
+                    assert!(!tree.has(26));
+                    tree.insert(26);
+                    assert!(tree.has(25));
+                    assert!(tree.has(26));
+                }
+
+                #[test]
+                fn insert_returns_whether_new() {
+                    let mut tree = Tree::new(16).unwrap();
+                    assert!(tree.insert(9));
+                    assert!(!tree.insert(9));
+                    assert!(tree.insert(2));
+                    assert!(!tree.insert(2));
+                    assert!(tree.insert(5));
+                    assert!(!tree.insert(5));
+                    // re-inserting the current minimum and maximum
+                    assert!(!tree.insert(2));
+                    assert!(!tree.insert(9));
+                    assert_eq!(tree.minimum(), Some(2));
+                    assert_eq!(tree.maximum(), Some(9));
+                }
+
+                #[test]
+                fn is_empty() {
+                    let mut tree = Tree::new(50).unwrap();
+                    assert!(tree.is_empty());
+                    tree.insert(25);
+                    assert!(!tree.is_empty());
+                    tree.delete(25);
+                    assert!(tree.is_empty());
+                }
+
+                #[test]
+                fn find_next() {
+                    let mut tree = Tree::new(50).unwrap();
+                    println!("find next: empty: {:?}", tree);
+                    assert!(tree.find_next(0).is_none());
+                    assert!(tree.find_next(24).is_none());
+                    assert!(tree.find_next(25).is_none());
+                    tree.insert(25);
+                    println!("find next: 25: {:?}", tree);
+                    assert!(tree.find_next(0).is_some());
+                    assert!(tree.find_next(24).is_some());
+                    assert!(tree.find_next(25).is_none());
+                }
+
+                #[test]
+                fn delete() {
+                    let mut tree = Tree::new(50).unwrap();
+                    println!("delete: empty: {:?}", tree);
+                    assert!(!tree.has(25));
+                    assert!(!tree.has(26));
+                    tree.insert(25);
+                    println!("delete: 25: {:?}", tree);
+                    assert!(tree.has(25));
+                    assert!(!tree.has(26));
+                    tree.insert(26);
+                    println!("delete: 25 and 26: {:?}", tree);
+                    assert!(tree.has(25));
+                    assert!(tree.has(26));
+                    tree.delete(26);
+                    println!("delete: 26 (1 deletion): {:?}", tree);
+                    assert_eq!(tree.validate(), Ok(()));
+                    assert!(!tree.has(26));
+                    assert!(tree.has(25));
+                    tree.delete(25);
+                    println!("delete: empty (2 deletions): {:?}", tree);
+                    assert_eq!(tree.validate(), Ok(()));
+                    assert!(!tree.has(26));
+                    assert!(!tree.has(25));
+                }
+
+                #[test]
+                fn delete_absent() {
+                    let mut tree = Tree::new(50).unwrap();
+                    assert!(!tree.delete(25));
+                    assert!(tree.is_empty());
+
+                    tree.insert(10);
+                    tree.insert(40);
+                    // between the two stored values
+                    assert!(!tree.delete(25));
+                    // larger than the universe
+                    assert!(!tree.delete(100));
+                    assert!(tree.has(10));
+                    assert!(tree.has(40));
+                    assert!(!tree.has(25));
+                    assert_eq!(tree.minimum(), Some(10));
+                    assert_eq!(tree.maximum(), Some(40));
+
+                    assert!(tree.delete(40));
+                    assert!(!tree.delete(40));
+                    // single element: deleting a different value must not empty the tree
+                    assert!(!tree.delete(25));
+                    assert!(tree.has(10));
+                    assert!(!tree.is_empty());
+                    assert_eq!(tree.validate(), Ok(()));
+                }
+
+                #[test]
+                fn delete_absent_from_existing_cluster() {
+                    // clusters of 8 values each; 17 and 20 share a cluster with 19
+                    let mut tree = Tree::from_slice(50, &[3, 17, 20, 40]).unwrap();
+                    assert!(!tree.delete(19));
+                    assert!(!tree.delete(16));
+                    // the cluster holding only the maximum
+                    assert!(!tree.delete(41));
+                    assert_eq!(tree.validate(), Ok(()));
+                    assert_eq!(tree.to_sorted_vec(), vec![3, 17, 20, 40]);
+                    assert_eq!(tree.len(), 4);
+                    assert_eq!((tree.minimum(), tree.maximum()), (Some(3), Some(40)));
+
+                    let mut one = Tree::from_slice(50, &[17]).unwrap();
+                    assert!(!one.delete(19));
+                    assert!(!one.delete(49));
+                    assert!(one.has(17));
+                    assert_eq!(one.len(), 1);
+                }
+
+                #[test]
+                fn duplicate_insert_then_delete() {
+                    for &universe in &[50, 1000] {
+                        let mut tree = Tree::new(universe).unwrap();
+                        assert!(tree.insert(25));
+                        assert!(!tree.insert(25));
+                        assert_eq!(tree.validate(), Ok(()));
+                        assert!(tree.delete(25));
+                        assert_eq!(tree.validate(), Ok(()));
+                        assert!(tree.is_empty());
+                        assert!(tree.insert(30));
+                        assert!(!tree.has(25));
+                        assert_eq!(tree.to_sorted_vec(), vec![30]);
+
+                        // duplicates of a value stored in a cluster, rather than in min or max
+                        let mut tree = Tree::from_slice(universe, &[3, 25, 40]).unwrap();
+                        assert!(!tree.insert(25));
+                        assert_eq!(tree.validate(), Ok(()));
+                        assert!(tree.delete(25));
+                        assert_eq!(tree.validate(), Ok(()));
+                        assert!(!tree.has(25));
+                        assert_eq!(tree.len(), 2);
+                    }
+                }
+
+                #[test]
+                fn clear() {
+                    let mut tree = Tree::new(256).unwrap();
+                    let values = [3, 17, 18, 100, 255];
+                    for &x in &values {
+                        tree.insert(x);
+                    }
+                    tree.clear();
+                    assert_eq!(tree.validate(), Ok(()));
+                    assert!(tree.is_empty());
+                    assert_eq!(tree.universe(), 256);
+                    assert!(tree.minimum().is_none());
+                    assert!(tree.find_next(0).is_none());
+                    for &x in &values {
+                        assert!(!tree.has(x));
+                    }
+
+                    tree.insert(100);
+                    tree.insert(4);
+                    assert_eq!(tree.minimum(), Some(4));
+                    assert_eq!(tree.maximum(), Some(100));
+                    assert_eq!(tree.find_next(4), Some(100));
+                    assert!(tree.has(4));
+                    assert!(tree.has(100));
+                    assert!(!tree.has(3));
+                    assert!(!tree.has(255));
+                }
+
+                #[test]
+                fn pop_min_and_max() {
+                    let mut tree = Tree::new(16).unwrap();
+                    assert!(tree.pop_min().is_none());
+                    assert!(tree.pop_max().is_none());
+
+                    tree.insert(7);
+                    assert_eq!(tree.pop_min(), Some(7));
+                    assert!(tree.is_empty());
+                    tree.insert(7);
+                    assert_eq!(tree.pop_max(), Some(7));
+                    assert!(tree.is_empty());
+
+                    tree.insert(2);
+                    tree.insert(9);
+                    tree.insert(14);
+                    assert_eq!(tree.pop_max(), Some(14));
+                    assert_eq!(tree.pop_min(), Some(2));
+                    assert_eq!(tree.pop_max(), Some(9));
+                    assert!(tree.pop_min().is_none());
+                    assert!(tree.is_empty());
+                }
+
+                #[test]
+                fn pop_min_drains_in_order() {
+                    let mut tree = Tree::new(256).unwrap();
+                    let mut expected = vec![];
+                    for i in 0..100 {
+                        let x = (i * 97 + 13) % 256;
+                        tree.insert(x);
+                        expected.push(x);
+                    }
+                    expected.sort();
+                    let mut drained = vec![];
+                    while let Some(x) = tree.pop_min() {
+                        assert_eq!(tree.validate(), Ok(()));
+                        drained.push(x);
+                    }
+                    assert_eq!(drained, expected);
+                    assert!(tree.is_empty());
+                }
+
+                #[test]
+                fn empty_has_nothing() {
+                    for &universe in &[2, 3, 50, 1000] {
+                        let mut tree = Tree::new(universe).unwrap();
+                        let probes = [0, 1, universe - 1, universe, universe + 1,
+                                      u64::from(u32::MAX)];
+                        let check = |tree: &Tree| {
+                            assert!(tree.is_empty());
+                            assert_eq!(tree.minimum(), None);
+                            assert_eq!(tree.maximum(), None);
+                            for &x in &probes {
+                                let x = K::from_u64(x);
+                                assert!(!tree.has(x), "{} in an empty tree", x);
+                                assert_eq!(tree.find_next(x), None);
+                                assert_eq!(tree.find_prev(x), None);
+                            }
+                        };
+                        check(&tree);
+                        for &x in &[0, 1, universe - 1] {
+                            tree.insert(K::from_u64(x));
+                            tree.delete(K::from_u64(x));
+                            check(&tree);
+                        }
+                        tree.insert(K::from_u64(1));
+                        tree.pop_min();
+                        check(&tree);
+                        tree.insert(K::from_u64(universe - 1));
+                        tree.clear();
+                        check(&tree);
+                    }
+                }
+
+                #[test]
+                fn has_out_of_range() {
+                    let mut tree = Tree::new(16).unwrap();
+                    for &x in &[16, 116, K::MAX] {
+                        assert!(!tree.has(x));
+                    }
+                    tree.insert(3);
+                    tree.insert(15);
+                    tree.insert(9);
+                    for &x in &[16, 116, K::MAX] {
+                        assert!(!tree.has(x));
+                    }
+                }
+
+                #[test]
+                fn find_prev_edges() {
+                    // clusters of 16 values each
+                    let tree = Tree::from_slice(256, &[20, 31, 32, 47, 200]).unwrap();
+                    // below and exactly at the minimum
+                    assert_eq!(tree.find_prev(0), None);
+                    assert_eq!(tree.find_prev(19), None);
+                    assert_eq!(tree.find_prev(20), None);
+                    assert_eq!(tree.find_prev(21), Some(20));
+                    // across cluster boundaries
+                    assert_eq!(tree.find_prev(32), Some(31));
+                    assert_eq!(tree.find_prev(33), Some(32));
+                    assert_eq!(tree.find_prev(48), Some(47));
+                    assert_eq!(tree.find_prev(200), Some(47));
+                    // above the maximum
+                    assert_eq!(tree.find_prev(201), Some(200));
+                    assert_eq!(tree.find_prev(255), Some(200));
+
+                    let mut two = Tree::new(2).unwrap();
+                    assert_eq!(two.find_prev(1), None);
+                    two.insert(1);
+                    assert_eq!(two.find_prev(1), None);
+                    two.insert(0);
+                    assert_eq!(two.find_prev(1), Some(0));
+                    assert_eq!(two.find_prev(0), None);
+                }
+
+                // A value below the minimum pushes the old minimum down into the clusters,
+                // even when it was the only value; checked by chaining successors from the
+                // minimum and predecessors from the maximum after every insert.
+                #[test]
+                fn insert_below_minimum() {
+                    fn check(tree: &Tree, expected: &[u64]) {
+                        let expected: Vec<K> = expected.iter().map(|&x| K::from_u64(x)).collect();
+                        let forward: Vec<K> = iter::successors(tree.minimum(), |&x| {
+                            tree.find_next(x)
+                        }).collect();
+                        let mut backward: Vec<K> = iter::successors(tree.maximum(), |&x| {
+                            tree.find_prev(x)
+                        }).collect();
+                        backward.reverse();
+                        assert_eq!(forward, expected);
+                        assert_eq!(backward, expected);
+                        assert_eq!(tree.validate(), Ok(()));
+                    }
+
+                    for &(universe, scale) in &[(16, 1), (4096, 64), (1 << 20, 4096)] {
+                        let mut tree = Tree::new(universe).unwrap();
+                        tree.insert(K::from_u64(9 * scale));
+                        check(&tree, &[9 * scale]);
+                        tree.insert(K::from_u64(2 * scale));
+                        check(&tree, &[2 * scale, 9 * scale]);
+                        tree.insert(K::from_u64(scale));
+                        check(&tree, &[scale, 2 * scale, 9 * scale]);
+                        // then above the maximum, and below the minimum once more
+                        tree.insert(K::from_u64(10 * scale));
+                        check(&tree, &[scale, 2 * scale, 9 * scale, 10 * scale]);
+                        tree.insert(K::from_u64(0));
+                        check(&tree, &[0, scale, 2 * scale, 9 * scale, 10 * scale]);
+                    }
+                }
+
+                // The new minimum or maximum after a delete is put back together from its
+                // cluster's index and its position in the cluster.
+                #[test]
+                fn delete_min_and_max_across_clusters() {
+                    let mut tree = Tree::from_slice(16, &[2, 9]).unwrap();
+                    assert!(tree.delete(2));
+                    assert_eq!((tree.minimum(), tree.maximum()), (Some(9), Some(9)));
+                    assert!(tree.has(9) && !tree.has(1) && !tree.has(2));
+
+                    // clusters of 64 values, with the survivors in clusters 5, 9 and 40
+                    let values = [3, 5 * 64 + 7, 9 * 64 + 1, 9 * 64 + 60, 40 * 64 + 33];
+                    let mut tree = Tree::from_slice(4096, &values).unwrap();
+                    assert!(tree.delete(3));
+                    assert_eq!(tree.minimum(), Some(5 * 64 + 7));
+                    assert!(tree.delete(40 * 64 + 33));
+                    assert_eq!(tree.maximum(), Some(9 * 64 + 60));
+                    assert!(!tree.has(7) && !tree.has(33) && !tree.has(60));
+                    assert_eq!(tree.find_next(0), Some(5 * 64 + 7));
+                    assert_eq!(tree.find_next(5 * 64 + 7), Some(9 * 64 + 1));
+                    assert_eq!(tree.find_next(9 * 64 + 60), None);
+                    assert_eq!(tree.find_prev(4095), Some(9 * 64 + 60));
+                    assert_eq!(tree.validate(), Ok(()));
+                    // down to a single value, from either end
+                    assert!(tree.delete(5 * 64 + 7) && tree.delete(9 * 64 + 60));
+                    assert_eq!(tree.minimum(), Some(9 * 64 + 1));
+                    assert_eq!(tree.maximum(), Some(9 * 64 + 1));
+                    assert_eq!(tree.find_next(0), Some(9 * 64 + 1));
+                    assert_eq!(tree.validate(), Ok(()));
+                }
+
+                // Two values inserted and then deleted in either order, in tiny universes and
+                // in one leaf cluster of a bigger tree.
+                #[test]
+                fn insert_delete_pairs() {
+                    for &(universe, lo, hi) in &[(2, 0, 2), (3, 0, 3), (16, 0, 16), (128, 8, 16)] {
+                        for a in (lo..hi).map(K::from_u64) {
+                            for b in (lo..hi).map(K::from_u64).filter(|&b| b != a) {
+                                for &first in &[a, b] {
+                                    let second = if first == a { b } else { a };
+                                    let mut tree = Tree::new(universe).unwrap();
+                                    assert!(tree.insert(a) && tree.insert(b));
+                                    assert_eq!(tree.minimum(), Some(cmp::min(a, b)));
+                                    assert_eq!(tree.maximum(), Some(cmp::max(a, b)));
+                                    assert!(tree.delete(first) && !tree.has(first));
+                                    assert!(tree.has(second) && tree.len() == 1);
+                                    assert_eq!(tree.minimum(), Some(second));
+                                    assert_eq!(tree.maximum(), Some(second));
+                                    assert_eq!(tree.find_next(first),
+                                               Some(second).filter(|&x| x > first));
+                                    assert_eq!(tree.validate(), Ok(()));
+                                    assert!(tree.delete(second) && tree.is_empty());
+                                    assert_eq!(tree.find_next(K::from_u64(0)), None);
+                                    assert_eq!(tree.validate(), Ok(()));
+                                    assert!(tree.insert(second) && tree.insert(first));
+                                    assert_eq!(tree.len(), 2);
+                                }
+                            }
+                        }
+                    }
+                }
+
+                #[test]
+                fn iter_rev() {
+                    let tree = random_tree(1 << 12, 300, 29);
+                    let mut expected = tree.to_sorted_vec();
+                    assert_eq!(tree.iter().collect::<Vec<_>>(), expected);
+                    expected.reverse();
+                    let reversed: Vec<K> = tree.iter().rev().collect();
+                    assert!(reversed.windows(2).all(|w| w[0] > w[1]));
+                    assert_eq!(reversed, expected);
+                    assert_eq!(Tree::new(16).unwrap().iter().rev().next(), None);
+                }
+
+                #[test]
+                fn len_matches_btree_set() {
+                    let mut tree = Tree::new(1000).unwrap();
+                    let mut set = BTreeSet::new();
+                    let mut state = 12345;
+                    for _ in 0..5000 {
+                        // small range, so duplicate inserts and deletes of missing values
+                        // are common
+                        let x = K::from_u64(xorshift(&mut state) % 300);
+                        if xorshift(&mut state) % 2 == 0 {
+                            assert_eq!(tree.insert(x), set.insert(x));
+                        } else {
+                            assert_eq!(tree.delete(x), set.remove(&x));
+                        }
+                        assert_eq!(tree.validate(), Ok(()));
+                        assert_eq!(tree.len(), set.len());
+                        assert_eq!(tree.is_empty(), set.is_empty());
+                    }
+                }
+
+                #[test]
+                fn invariants_after_deletes() {
+                    for seed in 0..20 {
+                        let mut tree = random_tree(1000, 300, seed);
+                        let mut state = seed + 1;
+                        for _ in 0..600 {
+                            // includes plenty of redundant deletes of absent values
+                            tree.delete(K::from_u64(xorshift(&mut state) % 1000));
+                            assert_eq!(tree.validate(), Ok(()));
+                        }
+                    }
+                }
+            }
+        )*
+    }
+}
+
+#[cfg(test)]
+set_tests!(u32_set: VEBTree<u32>, u64_set: VEBTree<u64>,
+           u32_flat: FlatVEBTree<u32>, u64_flat: FlatVEBTree<u64>);
+
+// The tests are instantiated once for each of the listed key types.
+#[cfg(test)]
+macro_rules! tests {
+    ( $( $name: ident: $t: ty ),* ) => {
+        $(
+            mod $name {
+                use super::*;
+                use std::collections::HashSet;
+
+                type K = $t;
+
+                #[test]
+                fn try_insert() {
+                    let mut tree = VEBTree::<K>::new(16).unwrap();
+                    assert_eq!(tree.try_insert(3), Ok(true));
+                    assert_eq!(tree.try_insert(3), Ok(false));
+                    assert_eq!(tree.try_insert(16).unwrap_err(), Error::ValueOutOfRange);
+                    assert_eq!(tree.try_insert(K::MAX).unwrap_err(), Error::ValueOutOfRange);
+                    assert_eq!(tree.minimum(), Some(3));
+                    assert_eq!(tree.maximum(), Some(3));
+                    assert!(tree.find_next(3).is_none());
+                }
+
+                #[test]
+                fn iter_range() {
+                    let mut tree = VEBTree::<K>::new(256).unwrap();
+                    for &x in &[3, 17, 100, 255] {
+                        tree.insert(x);
+                    }
+                    assert_eq!(tree.iter_range(0, 255).collect::<Vec<_>>(), vec![3, 17, 100, 255]);
+                    assert_eq!(tree.iter_range(3, 17).collect::<Vec<_>>(), vec![3, 17]);
+                    assert_eq!(tree.iter_range(4, 255).collect::<Vec<_>>(), vec![17, 100, 255]);
+                    assert_eq!(tree.iter_range(100, 1000).collect::<Vec<_>>(), vec![100, 255]);
+                    // entirely between two stored values
+                    assert_eq!(tree.iter_range(18, 99).count(), 0);
+                    // inverted
+                    assert_eq!(tree.iter_range(17, 3).count(), 0);
+                    assert_eq!(tree.iter_range(256, 1000).count(), 0);
+
+                    let empty = VEBTree::<K>::new(256).unwrap();
+                    assert_eq!(empty.iter_range(0, 255).count(), 0);
+                }
+
+                #[test]
+                fn pop_drains_random_trees() {
+                    for &universe in &[2, 3, 16, 1000, 65536] {
+                        for seed in 0..10 {
+                            let tree = random_tree::<K>(universe, 200, seed);
+                            let expected = tree.to_sorted_vec();
+
+                            let mut ascending = tree.clone();
+                            let mut drained = vec![];
+                            while let Some(x) = ascending.pop_min() {
+                                assert_eq!(ascending.validate(), Ok(()));
+                                assert_eq!(ascending.minimum(), ascending.iter().next());
+                                drained.push(x);
+                            }
+                            assert_eq!(drained, expected);
+                            assert!(ascending.is_empty());
+
+                            let mut descending = tree.clone();
+                            let mut drained = vec![];
+                            while let Some(x) = descending.pop_max() {
+                                assert_eq!(descending.validate(), Ok(()));
+                                assert_eq!(descending.maximum(), descending.iter().next_back());
+                                drained.push(x);
+                            }
+                            drained.reverse();
+                            assert_eq!(drained, expected);
+                            assert_eq!(descending.pop_max(), None);
+                        }
+                    }
+                }
+
+                #[test]
+                fn union() {
+                    let mut a = VEBTree::<K>::new(256).unwrap();
+                    let mut b = VEBTree::<K>::new(256).unwrap();
+                    for &x in &[3, 17, 100] {
+                        a.insert(x);
+                    }
+                    for &x in &[1, 17, 200] {
+                        b.insert(x);
+                    }
+                    let both = a.union(&b).unwrap();
+                    assert_eq!(both.iter_range(0, 255).collect::<Vec<_>>(),
+                               vec![1, 3, 17, 100, 200]);
+                    assert_eq!(both.minimum(), Some(1));
+                    assert_eq!(both.maximum(), Some(200));
+
+                    let empty = VEBTree::<K>::new(256).unwrap();
+                    let same = a.union(&empty).unwrap();
+                    assert_eq!(same.iter_range(0, 255).collect::<Vec<_>>(), vec![3, 17, 100]);
+                    let same = empty.union(&a).unwrap();
+                    assert_eq!(same.iter_range(0, 255).collect::<Vec<_>>(), vec![3, 17, 100]);
+
+                    assert_eq!(a.union(&VEBTree::<K>::new(16).unwrap()).unwrap_err(),
+                               Error::UniverseMismatch);
+                }
+
+                #[test]
+                fn union_matches_naive() {
+                    for seed in 0..50 {
+                        for &universe in &[16, 256, 65536] {
+                            let a = random_tree::<K>(universe, (seed as usize * 7) % 40, seed);
+                            let b =
+                                random_tree::<K>(universe, (seed as usize * 3) % 40, seed + 1000);
+                            let mut naive = a.clone();
+                            for x in b.iter_range(0, K::from_u64(universe - 1)) {
+                                naive.insert(x);
+                            }
+                            let merged = a.union(&b).unwrap();
+                            assert_eq!(merged.validate(), Ok(()));
+                            let all = K::from_u64(universe - 1);
+                            assert_eq!(merged.iter_range(0, all).collect::<Vec<_>>(),
+                                       naive.iter_range(0, all).collect::<Vec<_>>());
+                            assert_eq!(merged.minimum(), naive.minimum());
+                            assert_eq!(merged.maximum(), naive.maximum());
+                            for x in 0..cmp::min(universe, 300) {
+                                let x = K::from_u64(x);
+                                assert_eq!(merged.has(x), naive.has(x));
+                            }
+                        }
+                    }
+                }
+
+                #[test]
+                fn union_with() {
+                    let cases: &[(&[u64], &[u64])] = &[
+                        // overlapping
+                        (&[3, 17, 100], &[1, 17, 200]),
+                        // disjoint, in the same clusters and in different ones
+                        (&[3, 20], &[4, 21, 250]),
+                        // one side empty
+                        (&[], &[5, 6]),
+                        (&[5, 6], &[]),
+                        (&[], &[]),
+                        // one side's min is the other's max
+                        (&[40, 90], &[10, 40]),
+                    ];
+                    for &(a, b) in cases {
+                        let to_tree = |values: &[u64]| {
+                            let values = values.iter().map(|&x| K::from_u64(x));
+                            VEBTree::<K>::from_iter_with_universe(256, values).unwrap()
+                        };
+                        let mut tree = to_tree(a);
+                        let other = to_tree(b);
+                        tree.union_with(&other).unwrap();
+                        assert_eq!(tree.validate(), Ok(()));
+                        let mut expected = BTreeSet::from(&to_tree(a));
+                        expected.extend(BTreeSet::from(&other));
+                        assert!(tree.iter().eq(expected.iter().cloned()));
+                        assert_eq!(tree.len(), expected.len());
+                        assert_eq!(tree.minimum(), expected.iter().next().cloned());
+                        assert_eq!(tree.maximum(), expected.iter().next_back().cloned());
+                        assert_eq!(tree, to_tree(a).union(&other).unwrap());
+                    }
+
+                    let mut tree = VEBTree::<K>::from_slice(256, &[1]).unwrap();
+                    assert_eq!(tree.union_with(&VEBTree::new(16).unwrap()).unwrap_err(),
+                               Error::UniverseMismatch);
+                    assert_eq!(tree.to_sorted_vec(), vec![1]);
+                }
+
+                #[test]
+                fn append() {
+                    let cases: &[(&[u64], &[u64])] = &[
+                        // disjoint clusters, so every cluster of b is moved over whole
+                        (&[3, 20, 1000], &[300, 301, 5000, 65535]),
+                        // heavily overlapping, in the same clusters
+                        (&[1, 2, 3, 256, 257, 4000], &[2, 3, 4, 256, 4000, 4001]),
+                        // one side empty
+                        (&[5, 6], &[]),
+                        (&[], &[5, 6, 700]),
+                        (&[], &[]),
+                        // one side's min is the other's max
+                        (&[40, 9000], &[10, 40]),
+                    ];
+                    for &(a, b) in cases {
+                        for &sparse in &[false, true] {
+                            let to_tree = |values: &[u64], sparse| {
+                                let mut tree = VEBTree::<K>::with_storage(65536, sparse).unwrap();
+                                for &x in values {
+                                    tree.insert(K::from_u64(x));
+                                }
+                                tree
+                            };
+                            let mut tree = to_tree(a, false);
+                            let mut other = to_tree(b, sparse);
+                            let expected = to_tree(a, false).union(&other).unwrap();
+                            tree.append(&mut other).unwrap();
+                            assert_eq!(tree.validate(), Ok(()));
+                            assert_eq!(tree, expected);
+                            assert_eq!(tree.len(), expected.len());
+                            assert!(other.is_empty());
+                            assert_eq!(other.is_sparse(), sparse);
+                            assert_eq!(other.validate(), Ok(()));
+                        }
+                    }
+
+                    // random, partly overlapping trees, with b shared with a clone
+                    for seed in 0..20 {
+                        let mut tree = random_tree::<K>(1 << 16, 500, seed);
+                        let mut other = random_tree::<K>(1 << 16, 500, seed + 100);
+                        let snapshot = other.clone();
+                        let before = BTreeSet::from(&other);
+                        let expected = tree.union(&other).unwrap();
+                        tree.append(&mut other).unwrap();
+                        assert_eq!(tree.validate(), Ok(()));
+                        assert_eq!(tree, expected);
+                        assert!(other.is_empty());
+                        // moving shared clusters leaves the clone alone
+                        assert!(snapshot.iter().eq(before.iter().cloned()));
+                        assert_eq!(snapshot.validate(), Ok(()));
+                    }
+
+                    let mut tree = VEBTree::<K>::from_slice(256, &[1]).unwrap();
+                    let mut other = VEBTree::<K>::from_slice(16, &[2]).unwrap();
+                    assert_eq!(tree.append(&mut other).unwrap_err(), Error::UniverseMismatch);
+                    assert_eq!((tree.to_sorted_vec(), other.to_sorted_vec()), (vec![1], vec![2]));
+                }
+
+                #[test]
+                fn intersection() {
+                    let mut a = VEBTree::<K>::new(256).unwrap();
+                    let mut b = VEBTree::<K>::new(256).unwrap();
+                    for &x in &[3, 17, 100, 200] {
+                        a.insert(x);
+                    }
+                    for &x in &[1, 17, 200, 201] {
+                        b.insert(x);
+                    }
+                    let both = a.intersection(&b).unwrap();
+                    assert_eq!(both.iter_range(0, 255).collect::<Vec<_>>(), vec![17, 200]);
+                    assert_eq!(both.minimum(), Some(17));
+                    assert_eq!(both.maximum(), Some(200));
+
+                    let mut c = VEBTree::<K>::new(256).unwrap();
+                    c.insert(4);
+                    c.insert(250);
+                    assert!(a.intersection(&c).unwrap().is_empty());
+
+                    let empty = VEBTree::<K>::new(256).unwrap();
+                    assert!(a.intersection(&empty).unwrap().is_empty());
+                    assert!(empty.intersection(&a).unwrap().is_empty());
+
+                    assert_eq!(a.intersection(&VEBTree::<K>::new(16).unwrap()).unwrap_err(),
+                               Error::UniverseMismatch);
+                }
+
+                #[test]
+                fn intersection_corner_cases() {
+                    let a = random_tree::<K>(65536, 500, 4);
+                    let same = a.intersection(&a).unwrap();
+                    assert_eq!(same.validate(), Ok(()));
+                    assert_eq!(same, a);
+                    assert_eq!(same.len(), a.len());
+
+                    let evens = VEBTree::<K>::from_iter_with_universe(
+                        256, (0..128).map(|x| K::from_u64(x * 2))).unwrap();
+                    let odds = VEBTree::<K>::from_iter_with_universe(
+                        256, (0..128).map(|x| K::from_u64(x * 2 + 1))).unwrap();
+                    let none = evens.intersection(&odds).unwrap();
+                    assert!(none.is_empty());
+                    assert_eq!(none.validate(), Ok(()));
+                    assert_eq!(none.memory_footprint(),
+                               VEBTree::<K>::new(256).unwrap().memory_footprint());
+
+                    // the only common value is one tree's minimum and the other's maximum
+                    let low = VEBTree::<K>::from_slice(256, &[3, 50, 100]).unwrap();
+                    let high = VEBTree::<K>::from_slice(256, &[100, 150, 255]).unwrap();
+                    for &(a, b) in &[(&low, &high), (&high, &low)] {
+                        let both = a.intersection(b).unwrap();
+                        assert_eq!(both.validate(), Ok(()));
+                        assert_eq!(both.to_sorted_vec(), vec![100]);
+                        assert_eq!((both.minimum(), both.maximum()), (Some(100), Some(100)));
+                    }
+                }
+
+                #[test]
+                fn intersection_membership() {
+                    for seed in 0..50 {
+                        for &universe in &[16, 256, 65536] {
+                            let a = random_tree::<K>(universe, (seed as usize * 7) % 60, seed);
+                            let b =
+                                random_tree::<K>(universe, (seed as usize * 3) % 60, seed + 1000);
+                            let both = a.intersection(&b).unwrap();
+                            assert_eq!(both.validate(), Ok(()));
+                            let all = K::from_u64(universe - 1);
+                            let expected =
+                                a.iter_range(0, all).filter(|&x| b.has(x)).collect::<Vec<_>>();
+                            assert_eq!(both.iter_range(0, all).collect::<Vec<_>>(), expected);
+                            assert_eq!(both.minimum(), expected.first().cloned());
+                            assert_eq!(both.maximum(), expected.last().cloned());
+                            for x in 0..cmp::min(universe, 300) {
+                                let x = K::from_u64(x);
+                                assert_eq!(both.has(x), a.has(x) && b.has(x));
+                            }
+                        }
+                    }
+                }
+
+                #[test]
+                fn equality() {
+                    let mut a = VEBTree::<K>::new(256).unwrap();
+                    let mut b = VEBTree::<K>::new(256).unwrap();
+                    assert_eq!(a, b);
+                    for &x in &[3, 17, 100, 200] {
+                        a.insert(x);
+                    }
+                    for &x in &[200, 5, 100, 17, 3] {
+                        b.insert(x);
+                    }
+                    assert!(a != b);
+                    b.delete(5);
+                    assert_eq!(a, b);
+                    assert_eq!(a, a.clone());
+
+                    // same contents, different history
+                    let mut c = a.clone();
+                    c.pop_min();
+                    c.insert(3);
+                    assert_eq!(a, c);
+
+                    b.delete(200);
+                    assert!(a != b);
+
+                    let mut d = VEBTree::<K>::new(65536).unwrap();
+                    for &x in &[3, 17, 100, 200] {
+                        d.insert(x);
+                    }
+                    assert!(a != d);
+                }
+
+                #[test]
+                fn equality_across_histories() {
+                    let original = random_tree::<K>(1000, 300, 11);
+                    let values = original.to_sorted_vec();
+
+                    let mut descending = VEBTree::<K>::new(1000).unwrap();
+                    for &x in values.iter().rev() {
+                        descending.insert(x);
+                    }
+                    assert_eq!(original, descending);
+
+                    // pruned clusters from deletes, then the same values put back
+                    let mut churned = original.clone();
+                    for &x in values.iter().step_by(2) {
+                        churned.delete(x);
+                    }
+                    assert!(churned != original);
+                    for &x in values.iter().step_by(2) {
+                        churned.insert(x);
+                    }
+                    assert_eq!(original, churned);
+
+                    // clear keeps empty clusters allocated
+                    let mut reused = random_tree::<K>(1000, 500, 12);
+                    reused.clear();
+                    reused.extend(values.iter().cloned());
+                    assert_eq!(original, reused);
+                    assert_eq!(reused, original);
+
+                    // one differing value in the middle
+                    let mut changed = original.clone();
+                    changed.delete(values[values.len() / 2]);
+                    changed.insert(changed.find_first_absent().unwrap());
+                    assert_eq!(changed.len(), original.len());
+                    assert!(changed != original);
+                }
+
+                // the counters kept with the `stats` feature are atomic, but aren't hashed
+                #[test]
+                #[cfg_attr(feature = "stats", allow(clippy::mutable_key_type))]
+                fn hash_matches_equality() {
+                    let values = random_tree::<K>(1000, 200, 5).to_sorted_vec();
+                    let mut set = HashSet::new();
+                    for seed in 1..10 {
+                        let mut shuffled = values.clone();
+                        let mut state = seed;
+                        for i in (1..shuffled.len()).rev() {
+                            let j = (xorshift(&mut state) % (i as u64 + 1)) as usize;
+                            shuffled.swap(i, j);
+                        }
+                        let mut tree = VEBTree::<K>::new(1000).unwrap();
+                        for &x in &shuffled {
+                            tree.insert(x);
+                        }
+                        set.insert(tree);
+                    }
+                    // delete and reinsert to leave a differently shaped tree
+                    let mut churned = set.iter().next().unwrap().clone();
+                    churned.pop_max();
+                    churned.pop_min();
+                    churned.insert(values[0]);
+                    churned.insert(values[values.len() - 1]);
+                    set.insert(churned);
+                    assert_eq!(set.len(), 1);
+
+                    set.insert(VEBTree::from_slice(1024, &values).unwrap());
+                    assert_eq!(set.len(), 2);
+                }
+
+                #[test]
+                fn from_sorted_slice() {
+                    for &universe in &[2, 3, 16, 1000, 65536] {
+                        for seed in 0..5 {
+                            let expected = random_tree::<K>(universe, 300, seed);
+                            let values = expected.to_sorted_vec();
+                            let tree = VEBTree::from_sorted_slice(universe, &values).unwrap();
+                            assert_eq!(tree.validate(), Ok(()));
+                            assert_eq!(tree, expected);
+                            assert_eq!(tree.len(), expected.len());
+                            assert_eq!(tree.minimum(), expected.minimum());
+                            assert_eq!(tree.maximum(), expected.maximum());
+                            for x in 0..cmp::min(universe, 2000) {
+                                let x = K::from_u64(x);
+                                assert_eq!(tree.has(x), expected.has(x));
+                                assert_eq!(tree.find_next(x), expected.find_next(x));
+                                assert_eq!(tree.find_prev(x), expected.find_prev(x));
+                            }
+
+                            // the result behaves like any other tree afterwards
+                            let mut tree = tree;
+                            let mut expected = expected;
+                            for &x in values.iter().step_by(3) {
+                                assert_eq!(tree.delete(x), expected.delete(x));
+                            }
+                            assert_eq!(tree.validate(), Ok(()));
+                            assert_eq!(tree, expected);
+                        }
+                    }
+
+                    let tree = VEBTree::<K>::from_sorted_slice(256, &[]).unwrap();
+                    assert!(tree.is_empty());
+                    let tree = VEBTree::<K>::from_sorted_slice(256, &[0, 255]).unwrap();
+                    assert_eq!(tree.to_sorted_vec(), vec![0, 255]);
+                    assert_eq!(VEBTree::<K>::from_sorted_slice(256, &[1, 256]),
+                               Err(Error::ValueOutOfRange));
+                    assert_eq!(VEBTree::<K>::from_sorted_slice(256, &[1, 3, 3]),
+                               Err(Error::NotStrictlyIncreasing));
+                    assert_eq!(VEBTree::<K>::from_sorted_slice(256, &[3, 1]),
+                               Err(Error::NotStrictlyIncreasing));
+                    assert_eq!(VEBTree::<K>::from_sorted_slice(1, &[]).unwrap_err(),
+                               Error::UniverseTooSmall);
+                }
+
+                #[test]
+                fn extend() {
+                    let mut tree = VEBTree::<K>::new(256).unwrap();
+                    tree.extend(vec![3, 7, 42]);
+                    tree.extend(vec![7, 1]);
+                    assert_eq!(tree.iter_range(0, 255).collect::<Vec<_>>(), vec![1, 3, 7, 42]);
+                }
+
+                #[test]
+                #[should_panic(expected = "value out of range")]
+                fn extend_out_of_range() {
+                    let mut tree = VEBTree::<K>::new(16).unwrap();
+                    tree.extend(vec![3, 16]);
+                }
+
+                #[test]
+                fn collect() {
+                    let empty: VEBTree<K> = iter::empty().collect();
+                    assert!(empty.is_empty());
+                    assert_eq!(empty.universe(), 2);
+
+                    let one: VEBTree<K> = iter::once(K::from_u64(5)).collect();
+                    assert_eq!(one.to_sorted_vec(), vec![5]);
+                    assert_eq!(one.universe(), 8);
+
+                    let many: VEBTree<K> = (0..10000).step_by(3).map(K::from_u64).collect();
+                    assert_eq!(many.len(), 3334);
+                    assert_eq!(many.universe(), 16384);
+                    assert!(many.has(9999) && !many.has(9998));
+                    assert!(many.iter().eq((0..10000).step_by(3).map(K::from_u64)));
+
+                    let mut tree = VEBTree::<K>::new(256).unwrap();
+                    tree.extend(&[7, 3, 7]);
+                    assert_eq!(tree.to_sorted_vec(), vec![3, 7]);
+                }
+
+                #[test]
+                fn btree_set_round_trip() {
+                    let cases = [(2, 0), (2, 5), (100, 0), (100, 40), (65536, 3000)];
+                    for &(universe, count) in &cases {
+                        for seed in 1..4 {
+                            let mut state = seed;
+                            let set: BTreeSet<K> = (0..count)
+                                .map(|_| K::from_u64(xorshift(&mut state) % universe))
+                                .collect();
+                            let tree = VEBTree::from_btree_set(universe, &set).unwrap();
+                            assert_eq!(tree.validate(), Ok(()));
+                            assert_eq!(BTreeSet::from(&tree), set);
+                            let values: Vec<K> = set.iter().cloned().collect();
+                            let other = VEBTree::try_from((universe, &values[..])).unwrap();
+                            assert_eq!(other, tree);
+                            assert_eq!(tree.into_sorted_vec(), values);
+                        }
+                    }
+
+                    // nothing is built if a value doesn't fit
+                    let set: BTreeSet<K> = [3, 7, 100].iter().cloned().collect();
+                    assert_eq!(VEBTree::from_btree_set(100, &set), Err(Error::ValueOutOfRange));
+                    assert_eq!(VEBTree::<K>::from_btree_set(1, &BTreeSet::new()),
+                               Err(Error::UniverseTooSmall));
+                    assert_eq!(VEBTree::<K>::try_from((100, &[3, 100, 7][..])),
+                               Err(Error::ValueOutOfRange));
+                    assert_eq!(VEBTree::<K>::try_from((101, &[3, 100, 7][..])).unwrap().len(), 3);
+                }
+
+                #[test]
+                fn grow_universe() {
+                    // empty trees, leaves which grow into clustered trees, growth by odd amounts
+                    // and growth which adds a level of clusters
+                    let cases = [(2, 0, 3), (64, 0, 1000), (100, 30, 101), (100, 30, 5000),
+                                 (4096, 200, 4097), (300, 100, 1 << 20)];
+                    for &(universe, count, grown) in &cases {
+                        for &sparse in &[false, true] {
+                            let mut tree = random_tree::<K>(universe, count, universe);
+                            if sparse {
+                                let values = tree.to_sorted_vec();
+                                tree = VEBTree::new_sparse(universe).unwrap();
+                                tree.insert_sorted(&values).unwrap();
+                            }
+                            let before = tree.to_sorted_vec();
+                            tree.grow_universe(grown).unwrap();
+                            assert_eq!(tree.universe(), grown);
+                            assert_eq!(tree.is_sparse(), sparse);
+                            assert_eq!(tree.validate(), Ok(()));
+                            assert_eq!(tree.to_sorted_vec(), before);
+                            for pair in before.windows(2) {
+                                assert!(tree.has(pair[0]));
+                                assert_eq!(tree.find_next(pair[0]), Some(pair[1]));
+                            }
+                            // keys past the old universe can now be inserted
+                            let top = K::from_u64(grown - 1);
+                            assert!(tree.insert(top));
+                            assert_eq!(tree.maximum(), Some(top));
+                            if let Some(&last) = before.last() {
+                                assert_eq!(tree.find_next(last), Some(top));
+                            }
+                            assert_eq!(tree.validate(), Ok(()));
+                        }
+                    }
+
+                    // shrinking only works while every value still fits
+                    let mut tree = VEBTree::<K>::from_slice(1000, &[3, 70, 500]).unwrap();
+                    assert_eq!(tree.grow_universe(500), Err(Error::ValueOutOfRange));
+                    assert_eq!(tree.grow_universe(1), Err(Error::UniverseTooSmall));
+                    assert_eq!(tree.universe(), 1000);
+                    tree.grow_universe(501).unwrap();
+                    assert_eq!(tree.to_sorted_vec(), [3, 70, 500]);
+                    assert_eq!(tree.validate(), Ok(()));
+                    tree.grow_universe(501).unwrap();
+                    assert_eq!(tree.universe(), 501);
+                }
+
+                #[test]
+                fn into_iter() {
+                    let empty = VEBTree::<K>::new(16).unwrap();
+                    assert_eq!((&empty).into_iter().next(), None);
+                    assert_eq!(empty.into_iter().next(), None);
+
+                    let one = VEBTree::<K>::from_slice(16, &[9]).unwrap();
+                    let mut borrowed = vec![];
+                    for x in &one {
+                        borrowed.push(x);
+                    }
+                    assert_eq!(borrowed, vec![9]);
+                    assert_eq!(one.into_iter().collect::<Vec<_>>(), vec![9]);
+
+                    let tree = random_tree::<K>(65536, 500, 3);
+                    let expected = tree.to_sorted_vec();
+                    assert!((&tree).into_iter().eq(expected.iter().cloned()));
+                    let mut owned = tree.into_iter();
+                    assert_eq!(owned.len(), expected.len());
+                    assert_eq!(owned.next_back(), expected.last().cloned());
+                    let rest: Vec<K> = owned.collect();
+                    assert_eq!(rest[..], expected[..expected.len() - 1]);
+                }
+
+                #[test]
+                fn from_iter_with_universe() {
+                    let tree =
+                        VEBTree::<K>::from_iter_with_universe(256, vec![42, 3, 7, 3]).unwrap();
+                    assert_eq!(tree.iter_range(0, 255).collect::<Vec<_>>(), vec![3, 7, 42]);
+                    let tree = VEBTree::<K>::from_iter_with_universe(16, vec![]).unwrap();
+                    assert!(tree.is_empty());
+
+                    assert_eq!(VEBTree::<K>::from_iter_with_universe(1, vec![0]).unwrap_err(),
+                               Error::UniverseTooSmall);
+                    assert_eq!(VEBTree::<K>::from_iter_with_universe(16, vec![3, 16, 4]),
+                               Err(Error::ValueOutOfRange));
+                }
+
+                #[test]
+                fn memory_footprint() {
+                    let mut tree = VEBTree::<K>::new(65536).unwrap();
+                    // nothing is allocated until a value goes into a cluster
+                    assert_eq!(tree.memory_footprint(), 0);
+                    tree.insert(5);
+                    // the first value is only stored in min and max
+                    assert_eq!(tree.memory_footprint(), 0);
+                    tree.insert(1000);
+                    let one_cluster = tree.memory_footprint();
+                    assert!(one_cluster > 0);
+                    tree.insert(60000);
+                    assert!(tree.memory_footprint() > one_cluster);
+                    tree.delete(60000);
+                    assert_eq!(tree.memory_footprint(), one_cluster);
+                    tree.delete(1000);
+                    assert_eq!(tree.memory_footprint(), 0);
+                }
+
+                #[test]
+                fn structure_stats() {
+                    let mut tree = VEBTree::<K>::new(65536).unwrap();
+                    assert_eq!((tree.node_count(), tree.depth()), (1, 1));
+                    assert_eq!(tree.allocated_bytes(), size_of::<VEBTree<K>>());
+                    for round in 0..3 {
+                        let mut counts = vec![tree.node_count()];
+                        // each value lands in a new cluster
+                        for x in (0..8).map(|i| i * 4096 + round) {
+                            tree.insert(x);
+                            counts.push(tree.node_count());
+                        }
+                        assert!(counts.windows(2).all(|w| w[0] <= w[1]));
+                        assert!(counts[8] > counts[1]);
+                        // clusters of 256 values are split into leaves of 16
+                        assert_eq!(tree.depth(), 3);
+                        assert_eq!(tree.allocated_bytes(),
+                                   size_of::<VEBTree<K>>() + tree.memory_footprint());
+                        for x in (0..8).map(|i| i * 4096 + round) {
+                            tree.delete(x);
+                            counts.push(tree.node_count());
+                        }
+                        assert!(counts[8..].windows(2).all(|w| w[0] >= w[1]));
+                        assert_eq!((tree.node_count(), tree.depth()), (1, 1));
+                    }
+
+                    // deeper universes have more levels
+                    let mut big = VEBTree::<K>::new(1 << 32).unwrap();
+                    big.extend((0..300).map(|x| K::from_u64((1 << 32) - 1 - x)));
+                    // clusters of 65536 values, then 256, then leaves of 16
+                    assert_eq!(big.depth(), 4);
+                    assert!(big.node_count() > big.depth());
+                }
+
+                #[test]
+                fn len() {
+                    let mut tree = VEBTree::<K>::new(256).unwrap();
+                    assert_eq!(tree.len(), 0);
+                    tree.insert(3);
+                    tree.insert(3);
+                    tree.insert(200);
+                    tree.insert(17);
+                    assert_eq!(tree.len(), 3);
+                    tree.delete(100);
+                    tree.delete(3);
+                    assert_eq!(tree.len(), 2);
+                    tree.pop_max();
+                    assert_eq!(tree.len(), 1);
+                    tree.clear();
+                    assert_eq!(tree.len(), 0);
+
+                    for seed in 0..20 {
+                        let a = random_tree::<K>(256, 40, seed);
+                        let b = random_tree::<K>(256, 40, seed + 100);
+                        assert_eq!(a.len(), a.iter_range(0, 255).count());
+                        let both = a.union(&b).unwrap();
+                        assert_eq!(both.len(), both.iter_range(0, 255).count());
+                        let both = a.intersection(&b).unwrap();
+                        assert_eq!(both.len(), both.iter_range(0, 255).count());
+                    }
+                }
+
+                #[test]
+                fn count_in_range() {
+                    let mut tree = VEBTree::<K>::new(256).unwrap();
+                    assert_eq!(tree.count_in_range(0, 255), 0);
+                    for &x in &[3, 17, 100, 255] {
+                        tree.insert(x);
+                    }
+                    assert_eq!(tree.count_in_range(0, 255), 4);
+                    assert_eq!(tree.count_in_range(3, 3), 1);
+                    assert_eq!(tree.count_in_range(4, 99), 1);
+                    assert_eq!(tree.count_in_range(18, 99), 0);
+                    assert_eq!(tree.count_in_range(100, 1000), 2);
+                    assert_eq!(tree.count_in_range(17, 3), 0);
+
+                    for seed in 0..50 {
+                        for &universe in &[16, 256, 65536] {
+                            let tree = random_tree::<K>(universe, 50, seed);
+                            let mut state = seed + 1;
+                            for _ in 0..20 {
+                                let lo = K::from_u64(xorshift(&mut state) % universe);
+                                let hi = K::from_u64(xorshift(&mut state) % universe);
+                                assert_eq!(tree.count_in_range(lo, hi),
+                                           tree.iter_range(lo, hi).count());
+                            }
+                        }
+                    }
+                }
+
+                #[test]
+                fn rank() {
+                    let mut tree = VEBTree::<K>::new(256).unwrap();
+                    assert_eq!(tree.rank(100), 0);
+                    for &x in &[3, 17, 100, 255] {
+                        tree.insert(x);
+                    }
+                    assert_eq!(tree.rank(0), 0);
+                    assert_eq!(tree.rank(3), 1);
+                    assert_eq!(tree.rank(99), 2);
+                    assert_eq!(tree.rank(100), 3);
+                    assert_eq!(tree.rank(255), 4);
+
+                    for seed in 0..50 {
+                        for &universe in &[16, 256, 65536] {
+                            let tree = random_tree::<K>(universe, 50, seed);
+                            let sorted = tree.iter_range(0, K::from_u64(universe - 1))
+                                .collect::<Vec<_>>();
+                            let mut state = seed + 1;
+                            for _ in 0..20 {
+                                let x = K::from_u64(xorshift(&mut state) % universe);
+                                let expected = sorted.iter().filter(|&&y| y <= x).count();
+                                assert_eq!(tree.rank(x), expected);
+                            }
+                        }
+                    }
+                }
+
+                #[test]
+                fn select() {
+                    let mut tree = VEBTree::<K>::new(256).unwrap();
+                    assert_eq!(tree.select(0), None);
+                    tree.insert(17);
+                    assert_eq!(tree.select(0), Some(17));
+                    assert_eq!(tree.select(1), None);
+
+                    for seed in 0..50 {
+                        for &universe in &[2, 16, 256, 65536] {
+                            let tree = random_tree::<K>(universe, 50, seed);
+                            let sorted = tree.iter_range(0, K::from_u64(universe - 1))
+                                .collect::<Vec<_>>();
+                            for (k, &x) in sorted.iter().enumerate() {
+                                assert_eq!(tree.select(k), Some(x));
+                                assert_eq!(tree.rank(x), k + 1);
+                            }
+                            assert_eq!(tree.select(0), tree.minimum());
+                            assert_eq!(tree.select(tree.len() - 1), tree.maximum());
+                            assert_eq!(tree.select(tree.len()), None);
+                        }
+                    }
+                }
+
+                // One value in every cluster, so rank and select have to add up every cluster
+                // before the value from the running counts.
+                #[test]
+                fn rank_and_select_one_value_per_cluster() {
+                    let values: Vec<K> = (0..256).map(|i| K::from_u64(i * 256 + i % 7)).collect();
+                    let tree = VEBTree::<K>::from_sorted_slice(65536, &values).unwrap();
+                    for (k, &x) in values.iter().enumerate() {
+                        assert_eq!(tree.select(k), Some(x));
+                        assert_eq!(tree.rank(x), k + 1);
+                        assert_eq!(tree.rank(K::from_u64(x.to_u64() + 1)), k + 1);
+                        assert_eq!(tree.count_below(x), k);
+                    }
+                }
+
+                // Deletes outnumber inserts, so the tree shrinks away while the counts are
+                // updated both ways.
+                #[test]
+                fn select_after_inserts_and_deletes() {
+                    let mut tree = random_tree::<K>(65536, 2000, 11);
+                    let mut sorted = tree.to_sorted_vec();
+                    let mut state = 5;
+                    while sorted.len() > 1 {
+                        if xorshift(&mut state) % 3 == 0 {
+                            let x = K::from_u64(xorshift(&mut state) % 65536);
+                            if let Err(i) = sorted.binary_search(&x) {
+                                assert!(tree.insert(x));
+                                sorted.insert(i, x);
+                            }
+                        } else {
+                            let i = (xorshift(&mut state) % sorted.len() as u64) as usize;
+                            assert!(tree.delete(sorted.remove(i)));
+                        }
+                        let k = (xorshift(&mut state) % sorted.len() as u64) as usize;
+                        assert_eq!(tree.select(k), Some(sorted[k]));
+                        assert_eq!(tree.select(sorted.len()), None);
+                        // jumping ahead from the element before the k-th one
+                        if k > 0 {
+                            assert_eq!(tree.nth_next(sorted[0], k - 1), Some(sorted[k]));
+                        }
+                    }
+                }
+
+                #[test]
+                fn sorted_vec_round_trip() {
+                    let tree = VEBTree::<K>::new(256).unwrap();
+                    assert!(tree.to_sorted_vec().is_empty());
+                    for seed in 0..20 {
+                        let tree = random_tree::<K>(256, 60, seed);
+                        let values = tree.to_sorted_vec();
+                        assert_eq!(values.len(), tree.len());
+                        assert!(values.windows(2).all(|pair| pair[0] < pair[1]));
+                        assert_eq!(VEBTree::from_slice(256, &values).unwrap(), tree);
+                    }
+                    assert_eq!(VEBTree::<K>::from_slice(16, &[1, 2, 16]).unwrap_err(),
+                               Error::ValueOutOfRange);
+                }
+
+                #[test]
+                fn into_btree_set() {
+                    let tree = random_tree::<K>(65536, 100, 7);
+                    let set = BTreeSet::from(&tree);
+                    assert_eq!(set.len(), tree.len());
+                    assert_eq!(set.into_iter().collect::<Vec<_>>(), tree.to_sorted_vec());
+                }
+
+                #[test]
+                fn split_off() {
+                    let mut tree = VEBTree::<K>::new(256).unwrap();
+                    tree.extend(vec![3, 17, 100, 200]);
+                    let upper = tree.split_off(17);
+                    assert_eq!(tree.to_sorted_vec(), vec![3]);
+                    assert_eq!(upper.to_sorted_vec(), vec![17, 100, 200]);
+                    assert_eq!((tree.len(), upper.len()), (1, 3));
+                    assert_eq!(upper.minimum(), Some(17));
+                    assert_eq!(upper.maximum(), Some(200));
+                    assert_eq!(upper.universe(), 256);
+
+                    // everything moves
+                    let mut tree = VEBTree::<K>::from_slice(256, &[3, 17]).unwrap();
+                    let upper = tree.split_off(3);
+                    assert!(tree.is_empty());
+                    assert_eq!(upper.to_sorted_vec(), vec![3, 17]);
+
+                    // nothing moves
+                    let mut tree = VEBTree::<K>::from_slice(256, &[3, 17]).unwrap();
+                    let upper = tree.split_off(18);
+                    assert!(upper.is_empty());
+                    assert_eq!(tree.to_sorted_vec(), vec![3, 17]);
+
+                    for seed in 0..20 {
+                        let original = random_tree::<K>(65536, 100, seed);
+                        let pivot = K::from_u64(seed * 3000);
+                        let mut lower = original.clone();
+                        let upper = lower.split_off(pivot);
+                        assert_eq!(lower.validate(), Ok(()));
+                        assert_eq!(upper.validate(), Ok(()));
+                        assert!(lower.to_sorted_vec().iter().all(|&x| x < pivot));
+                        assert!(upper.to_sorted_vec().iter().all(|&x| x >= pivot));
+                        assert_eq!(lower.len() + upper.len(), original.len());
+                        assert_eq!(lower.union(&upper).unwrap(), original);
+                    }
+                }
+
+                #[test]
+                fn split_off_edges() {
+                    let original = VEBTree::<K>::from_slice(256, &[3, 17, 20, 100, 200]).unwrap();
+                    for &(x, below) in &[(0, 0), (3, 0), (4, 1), (17, 1), (18, 2), (20, 2),
+                                         (100, 3), (200, 4), (201, 5), (255, 5)] {
+                        let mut lower = original.clone();
+                        let upper = lower.split_off(x);
+                        assert_eq!(lower.validate(), Ok(()), "lower split at {}", x);
+                        assert_eq!(upper.validate(), Ok(()), "upper split at {}", x);
+                        let values = original.to_sorted_vec();
+                        assert_eq!(lower.to_sorted_vec(), values[..below]);
+                        assert_eq!(upper.to_sorted_vec(), values[below..]);
+                        assert_eq!(lower.maximum(), values[..below].last().cloned());
+                        assert_eq!(upper.minimum(), values[below..].first().cloned());
+                    }
+
+                    for &universe in &[2, 3, 16, 1000] {
+                        for seed in 0..20 {
+                            let original = random_tree::<K>(universe, 200, seed);
+                            let pivot = K::from_u64(seed * universe / 20);
+                            let mut lower = original.clone();
+                            let upper = lower.split_off(pivot);
+                            assert_eq!(lower.validate(), Ok(()));
+                            assert_eq!(upper.validate(), Ok(()));
+                            assert!(lower.iter().eq(original.iter().filter(|&x| x < pivot)));
+                            assert!(upper.iter().eq(original.iter().filter(|&x| x >= pivot)));
+                        }
+                    }
+                }
+
+                #[test]
+                fn find_prev() {
+                    let tree = random_tree::<K>(1000, 200, 13);
+                    let expected: Vec<K> = tree.iter_range(0, 999).collect();
+                    for x in 0..1000 {
+                        let x = K::from_u64(x);
+                        let prev = expected.iter().cloned().filter(|&v| v < x).last();
+                        assert_eq!(tree.find_prev(x), prev, "find_prev({})", x);
+                    }
+                    assert_eq!(VEBTree::<K>::new(16).unwrap().find_prev(10), None);
+                }
+
+                #[test]
+                fn inclusive_successor_and_predecessor() {
+                    // clusters of 16 values each
+                    let tree = VEBTree::<K>::from_slice(256, &[3, 17, 20, 100, 200]).unwrap();
+                    // at the minimum and maximum
+                    assert_eq!(tree.find_next_geq(3), Some(3));
+                    assert_eq!(tree.find_prev_leq(3), Some(3));
+                    assert_eq!(tree.find_next_geq(200), Some(200));
+                    assert_eq!(tree.find_prev_leq(200), Some(200));
+                    // absent, but in an allocated cluster
+                    assert_eq!(tree.find_next_geq(18), Some(20));
+                    assert_eq!(tree.find_prev_leq(18), Some(17));
+                    assert_eq!(tree.find_next_geq(21), Some(100));
+                    assert_eq!(tree.find_prev_leq(16), Some(3));
+                    // out of range on either side
+                    assert_eq!(tree.find_next_geq(0), Some(3));
+                    assert_eq!(tree.find_prev_leq(2), None);
+                    assert_eq!(tree.find_next_geq(201), None);
+                    assert_eq!(tree.find_prev_leq(1000), Some(200));
+                    assert_eq!(tree.find_next_geq(1000), None);
+
+                    for &universe in &[2, 3, 16, 1000, 65536] {
+                        for seed in 0..10 {
+                            let tree = random_tree::<K>(universe, 40, seed);
+                            for x in (0..cmp::min(universe, 2000)).map(K::from_u64) {
+                                let present = if tree.has(x) { Some(x) } else { None };
+                                assert_eq!(tree.find_next_geq(x), present.or(tree.find_next(x)));
+                                assert_eq!(tree.find_prev_leq(x), present.or(tree.find_prev(x)));
+                            }
+                        }
+                    }
+                }
+
+                #[test]
+                fn runs() {
+                    let runs = |tree: &VEBTree<K>| -> Vec<(u64, u64)> {
+                        tree.runs().map(|run| (run.start().to_u64(), run.end().to_u64())).collect()
+                    };
+                    let tree = VEBTree::<K>::from_slice(100, &[3, 4, 5, 9, 10]).unwrap();
+                    assert_eq!(runs(&tree), [(3, 5), (9, 10)]);
+                    assert_eq!(runs(&VEBTree::<K>::new(100).unwrap()), []);
+
+                    // full trees are a single run, however big
+                    for &universe in &[2, 64, 65, 1000, 1 << 16] {
+                        let mut tree = VEBTree::<K>::new(universe).unwrap();
+                        tree.insert_range(..);
+                        assert_eq!(runs(&tree), [(0, universe - 1)]);
+                    }
+
+                    // runs across cluster boundaries come out whole
+                    let mut tree = VEBTree::<K>::new(1 << 20).unwrap();
+                    tree.insert_range(K::from_u64(1000)..=K::from_u64(300_000));
+                    tree.insert(K::from_u64(300_002));
+                    tree.insert_range(K::from_u64((1 << 20) - 5000)..);
+                    assert_eq!(runs(&tree), [(1000, 300_000), (300_002, 300_002),
+                                             ((1 << 20) - 5000, (1 << 20) - 1)]);
+
+                    // against coalescing the values one at a time
+                    for &(universe, count) in &[(64, 40), (1000, 700), (65536, 50000)] {
+                        let tree = random_tree::<K>(universe, count, universe);
+                        let mut expected: Vec<(u64, u64)> = vec![];
+                        for x in tree.iter().map(K::to_u64) {
+                            match expected.last_mut() {
+                                Some(run) if run.1 + 1 == x => run.1 = x,
+                                _ => expected.push((x, x)),
+                            }
+                        }
+                        assert_eq!(runs(&tree), expected);
+                    }
+                }
+
+                #[test]
+                fn complement() {
+                    // every subset of small universes
+                    for universe in 2..10u64 {
+                        for mask in 0..1u32 << universe {
+                            let values: Vec<K> = (0..universe)
+                                .filter(|&x| mask & (1 << x) != 0)
+                                .map(K::from_u64)
+                                .collect();
+                            let tree = VEBTree::from_slice(universe, &values).unwrap();
+                            let inverse = tree.complement();
+                            assert_eq!(inverse.validate(), Ok(()));
+                            for x in (0..universe).map(K::from_u64) {
+                                assert_ne!(inverse.has(x), tree.has(x));
+                            }
+                        }
+                    }
+
+                    let cases = [(64, 30), (1000, 10), (1000, 990), (65536, 3000), (65536, 0)];
+                    for &(universe, count) in &cases {
+                        let mut tree = random_tree::<K>(universe, count, universe);
+                        tree.insert_range(K::from_u64(universe / 3)..K::from_u64(universe / 2));
+                        for sparse in &[false, true] {
+                            let original = tree.clone_as(*sparse);
+                            let mut inverse = original.clone();
+                            inverse.invert();
+                            assert_eq!(inverse.validate(), Ok(()));
+                            assert_eq!(inverse.is_sparse(), *sparse);
+                            assert_eq!(inverse.len() as u64, universe - original.len() as u64);
+                            let expected: Vec<K> = original.absent().collect();
+                            assert_eq!(inverse.to_sorted_vec(), expected);
+                            inverse.invert();
+                            assert_eq!(inverse.validate(), Ok(()));
+                            assert_eq!(inverse, original);
+                        }
+                    }
+
+                    // empty and full trees swap places
+                    let empty = VEBTree::<K>::new(1 << 16).unwrap();
+                    let full = empty.complement();
+                    assert_eq!(full.len(), 1 << 16);
+                    assert_eq!(full.validate(), Ok(()));
+                    assert!(full.complement().is_empty());
+                }
+
+                #[test]
+                fn absent() {
+                    let empty = VEBTree::<K>::new(50).unwrap();
+                    assert!(empty.absent().eq((0..50).map(K::from_u64)));
+
+                    let mut full = VEBTree::<K>::new(50).unwrap();
+                    full.extend((0..50).map(K::from_u64));
+                    assert_eq!(full.absent().next(), None);
+                    full.delete(0);
+                    full.delete(49);
+                    assert_eq!(full.absent().collect::<Vec<_>>(), vec![0, 49]);
+                    full.delete(20);
+                    assert_eq!(full.absent_in(1..49).collect::<Vec<_>>(), vec![20]);
+                    assert_eq!(full.absent_in(20..=20).collect::<Vec<_>>(), vec![20]);
+                    assert_eq!(full.absent_in(40..1000).collect::<Vec<_>>(), vec![49]);
+                    assert_eq!(full.absent_in(50..).next(), None);
+
+                    let tree = VEBTree::<K>::from_slice(16, &[0, 1, 2, 5, 15]).unwrap();
+                    assert_eq!(tree.absent().collect::<Vec<_>>(),
+                               vec![3, 4, 6, 7, 8, 9, 10, 11, 12, 13, 14]);
+                    assert_eq!(tree.absent_in(..4).collect::<Vec<_>>(), vec![3]);
+
+                    for &universe in &[2, 3, 16, 50, 1000, 4096] {
+                        for seed in 0..10 {
+                            // dense enough to fill whole clusters
+                            let tree = random_tree::<K>(universe, universe as usize * 2, seed);
+                            let expected = (0..universe).map(K::from_u64).filter(|&x| !tree.has(x));
+                            assert!(tree.absent().eq(expected));
+                            let lo = K::from_u64(seed * universe / 20);
+                            let expected = (0..universe).map(K::from_u64)
+                                .filter(|&x| x >= lo && !tree.has(x));
+                            assert!(tree.absent_in(lo..).eq(expected));
+                        }
+                    }
+                }
+
+                #[test]
+                fn id_allocation() {
+                    let mut ids = VEBTree::<K>::new(1000).unwrap();
+                    for expected in 0..1000 {
+                        let id = ids.find_first_absent().unwrap();
+                        assert_eq!(id, K::from_u64(expected));
+                        ids.insert(id);
+                    }
+                    assert_eq!(ids.find_first_absent(), None);
+                    assert_eq!(ids.find_next_absent(500), None);
+
+                    for &freed in &[700, 12, 400, 401, 999] {
+                        ids.delete(freed);
+                    }
+                    assert_eq!(ids.find_next_absent(13), Some(400));
+                    assert_eq!(ids.find_next_absent(402), Some(700));
+                    assert_eq!(ids.find_next_absent(1000), None);
+                    for &expected in &[12, 400, 401, 700, 999] {
+                        let id = ids.find_first_absent().unwrap();
+                        assert_eq!(id, expected);
+                        ids.insert(id);
+                    }
+                    assert_eq!(ids.find_first_absent(), None);
+                    assert_eq!(VEBTree::<K>::new(16).unwrap().find_next_absent(7), Some(7));
+                }
+
+                #[test]
+                fn nearest() {
+                    let tree = VEBTree::<K>::from_slice(256, &[10, 20, 100]).unwrap();
+                    assert_eq!(tree.nearest(20), Some(20));
+                    assert_eq!(tree.nearest(14), Some(10));
+                    assert_eq!(tree.nearest(16), Some(20));
+                    // ties go to the smaller value
+                    assert_eq!(tree.nearest(15), Some(10));
+                    assert_eq!(tree.nearest(60), Some(20));
+                    assert_eq!(tree.nearest(0), Some(10));
+                    assert_eq!(tree.nearest(255), Some(100));
+                    assert_eq!(tree.nearest(1000), Some(100));
+                    assert_eq!(VEBTree::<K>::new(256).unwrap().nearest(5), None);
+                    let one = VEBTree::<K>::from_slice(256, &[77]).unwrap();
+                    assert!((0..256).all(|x| one.nearest(K::from_u64(x)) == Some(77)));
+
+                    for seed in 0..10 {
+                        let tree = random_tree::<K>(1000, 30, seed);
+                        let set = BTreeSet::from(&tree);
+                        for x in (0..1000).map(K::from_u64) {
+                            let expected = set.iter().cloned().min_by_key(|&y| {
+                                (cmp::max(x, y).to_u64() - cmp::min(x, y).to_u64(), y)
+                            });
+                            assert_eq!(tree.nearest(x), expected);
+                        }
+                    }
+                }
+
+                #[test]
+                fn k_closest() {
+                    let tree = VEBTree::<K>::from_slice(256, &[10, 20, 30, 100]).unwrap();
+                    assert_eq!(tree.k_closest(20, 3), vec![20, 10, 30]);
+                    // ties go to the smaller value
+                    assert_eq!(tree.k_closest(25, 2), vec![20, 30]);
+                    assert_eq!(tree.k_closest(0, 2), vec![10, 20]);
+                    assert_eq!(tree.k_closest(255, 2), vec![100, 30]);
+                    assert_eq!(tree.k_closest(1000, 10), vec![100, 30, 20, 10]);
+                    assert!(tree.k_closest(60, 0).is_empty());
+                    assert!(VEBTree::<K>::new(256).unwrap().k_closest(5, 3).is_empty());
+
+                    // against sorting every value by its distance
+                    for seed in 0..10 {
+                        let tree = random_tree::<K>(1000, 30, seed);
+                        let set = BTreeSet::from(&tree);
+                        for x in (0..1010).step_by(7).map(K::from_u64) {
+                            let mut expected: Vec<K> = set.iter().cloned().collect();
+                            expected.sort_by_key(|&y| {
+                                (cmp::max(x, y).to_u64() - cmp::min(x, y).to_u64(), y)
+                            });
+                            for &k in &[1, 5, 29, 30, 31] {
+                                let k = cmp::min(k, expected.len());
+                                assert_eq!(tree.k_closest(x, k), &expected[..k]);
+                            }
+                            assert_eq!(tree.k_closest(x, usize::MAX), expected);
+                        }
+                    }
+                }
+
+                #[test]
+                fn cyclic() {
+                    assert_eq!(VEBTree::<K>::new(64).unwrap().find_next_cyclic(3), None);
+                    assert_eq!(VEBTree::<K>::new(64).unwrap().find_prev_cyclic(3), None);
+                    // a single value comes back around to itself
+                    let tree = VEBTree::<K>::from_slice(1000, &[500]).unwrap();
+                    for &x in &[0, 499, 500, 501, 999, 5000] {
+                        assert_eq!(tree.find_next_cyclic(x), Some(500));
+                        assert_eq!(tree.find_prev_cyclic(x), Some(500));
+                    }
+                    let tree = VEBTree::<K>::from_slice(1000, &[10, 20, 900]).unwrap();
+                    assert_eq!(tree.find_next_cyclic(10), Some(20));
+                    assert_eq!(tree.find_next_cyclic(900), Some(10));
+                    assert_eq!(tree.find_next_cyclic(999), Some(10));
+                    assert_eq!(tree.find_next_cyclic(1 << 12), Some(10));
+                    assert_eq!(tree.find_next_cyclic(0), Some(10));
+                    assert_eq!(tree.find_prev_cyclic(20), Some(10));
+                    assert_eq!(tree.find_prev_cyclic(10), Some(900));
+                    assert_eq!(tree.find_prev_cyclic(0), Some(900));
+                    assert_eq!(tree.find_prev_cyclic(1 << 12), Some(900));
+                    // a full tree, where every value's neighbour is one step away
+                    let all: Vec<K> = (0..256).map(K::from_u64).collect();
+                    let full = VEBTree::<K>::from_sorted_slice(256, &all).unwrap();
+                    for x in 0..256 {
+                        let next = K::from_u64((x + 1) % 256);
+                        let prev = K::from_u64((x + 255) % 256);
+                        assert_eq!(full.find_next_cyclic(K::from_u64(x)), Some(next));
+                        assert_eq!(full.find_prev_cyclic(K::from_u64(x)), Some(prev));
+                    }
+
+                    for seed in 0..10 {
+                        let tree = random_tree::<K>(1000, 20, seed);
+                        let set = BTreeSet::from(&tree);
+                        for x in (0..1010).map(K::from_u64) {
+                            let next = set.range(x..).find(|&&y| y > x).or(set.iter().next());
+                            assert_eq!(tree.find_next_cyclic(x), next.cloned());
+                            let prev = set.range(..x).next_back().or(set.iter().next_back());
+                            assert_eq!(tree.find_prev_cyclic(x), prev.cloned());
+                        }
+                    }
+                }
+
+                #[test]
+                fn iter() {
+                    assert_eq!(VEBTree::<K>::new(256).unwrap().iter().next(), None);
+                    let tree = VEBTree::<K>::from_slice(256, &[42]).unwrap();
+                    assert_eq!(tree.iter().collect::<Vec<_>>(), vec![42]);
+                    // 0 and 1 are each the minimum of their cluster, one level down
+                    let tree = VEBTree::<K>::from_slice(256, &[0, 1, 16, 17, 255]).unwrap();
+                    assert_eq!(tree.iter().collect::<Vec<_>>(), vec![0, 1, 16, 17, 255]);
+                    assert_eq!((&tree).into_iter().count(), 5);
+
+                    for &universe in &[2, 3, 5, 16, 1000, 65536] {
+                        for seed in 0..10 {
+                            let tree = random_tree::<K>(universe, (seed as usize * 13) % 200, seed);
+                            let all = K::from_u64(universe - 1);
+                            let expected: Vec<K> = tree.iter_range(0, all).collect();
+                            let mut iter = tree.iter();
+                            assert_eq!(iter.size_hint(), (expected.len(), Some(expected.len())));
+                            if iter.next().is_some() {
+                                assert_eq!(iter.size_hint().0, expected.len() - 1);
+                            }
+                            assert_eq!(tree.iter().collect::<Vec<_>>(), expected);
+                        }
+                    }
+                }
+
+                #[test]
+                fn iter_from() {
+                    let tree = VEBTree::<K>::from_slice(256, &[0, 1, 16, 17, 255]).unwrap();
+                    assert!(tree.iter_from(0).eq(tree.iter()));
+                    // starting at a stored value includes it
+                    assert_eq!(tree.iter_from(16).collect::<Vec<_>>(), vec![16, 17, 255]);
+                    assert_eq!(tree.iter_from(2).collect::<Vec<_>>(), vec![16, 17, 255]);
+                    assert_eq!(tree.iter_from(255).collect::<Vec<_>>(), vec![255]);
+                    assert_eq!(tree.iter_from(256).next(), None);
+                    assert_eq!(tree.iter_from(K::from_u64(K::MAX_VALUE)).next_back(), None);
+                    assert_eq!(tree.iter_from(17).rev().collect::<Vec<_>>(), vec![255, 17]);
+                    assert_eq!(VEBTree::<K>::new(256).unwrap().iter_from(5).next(), None);
+
+                    // against filtering the whole tree, from both ends
+                    for &universe in &[2, 3, 16, 1000, 65536, 1 << 24] {
+                        for seed in 0..5 {
+                            let tree = random_tree::<K>(universe, (seed as usize * 37) % 300, seed);
+                            let mut state = seed + 1;
+                            for _ in 0..50 {
+                                let x = K::from_u64(xorshift(&mut state) % (universe + 2));
+                                let expected: Vec<K> = tree.iter().filter(|&y| y >= x).collect();
+                                let mut iter = tree.iter_from(x);
+                                assert_eq!(iter.len(), expected.len());
+                                assert_eq!(iter.clone().collect::<Vec<_>>(), expected);
+                                let mut rev: Vec<K> = iter.clone().rev().collect();
+                                rev.reverse();
+                                assert_eq!(rev, expected);
+                                // the two ends meet in the middle
+                                let half = expected.len() / 2;
+                                let mut met: Vec<K> = iter.by_ref().take(half).collect();
+                                let mut back: Vec<K> = iter.rev().collect();
+                                back.reverse();
+                                met.extend(back);
+                                assert_eq!(met, expected);
+                            }
+                        }
+                    }
+                }
+
+                #[test]
+                fn iter_ends_meet() {
+                    let empty = VEBTree::<K>::new(2).unwrap();
+                    assert_eq!(empty.iter().next_back(), None);
+                    for &universe in &[2, 3, 16] {
+                        for &values in &[&[1][..], &[0, 1], &[1, 2], &[0, 1, 2]] {
+                            let values: Vec<K> = values.iter().cloned()
+                                .filter(|&x| x.to_u64() < universe)
+                                .collect();
+                            let tree = VEBTree::<K>::from_slice(universe, &values).unwrap();
+                            let mut rev = values.clone();
+                            rev.reverse();
+                            assert_eq!(tree.iter().rev().collect::<Vec<_>>(), rev);
+
+                            // back first, then front
+                            let mut iter = tree.iter();
+                            assert_eq!(iter.next_back(), values.last().cloned());
+                            let second = if values.len() > 1 { Some(values[0]) } else { None };
+                            assert_eq!(iter.next(), second);
+                            let rest: Vec<K> = iter.collect();
+                            assert_eq!(rest.len(), values.len().saturating_sub(2));
+                        }
+                    }
+                }
+
+                #[test]
+                fn iter_both_ends() {
+                    for &count in &[0, 1, 2, 3, 100, 101] {
+                        let tree = random_tree::<K>(1 << 10, count, 7 + count as u64);
+                        let expected = tree.to_sorted_vec();
+                        let mut iter = tree.iter();
+                        let mut front = vec![];
+                        let mut back = vec![];
+                        loop {
+                            assert_eq!(iter.len(), expected.len() - front.len() - back.len());
+                            match iter.next() {
+                                Some(x) => front.push(x),
+                                None => break,
+                            }
+                            match iter.next_back() {
+                                Some(x) => back.push(x),
+                                None => break,
+                            }
+                        }
+                        assert_eq!(iter.next(), None);
+                        assert_eq!(iter.next_back(), None);
+                        back.reverse();
+                        front.extend(back);
+                        assert_eq!(front, expected);
+                    }
+                }
+
+                #[test]
+                fn retain() {
+                    let mut tree = VEBTree::<K>::new(1024).unwrap();
+                    tree.extend((0..1024).map(K::from_u64));
+                    let mut visited = vec![];
+                    tree.retain(|x| {
+                        visited.push(x);
+                        x % 2 == 0
+                    });
+                    assert_eq!(tree.validate(), Ok(()));
+                    assert_eq!(visited, (0..1024).map(K::from_u64).collect::<Vec<_>>());
+                    for x in 0..1024 {
+                        assert_eq!(tree.has(K::from_u64(x)), x % 2 == 0);
+                    }
+                    assert_eq!(tree.len(), 512);
+                    assert_eq!(tree.minimum(), Some(0));
+                    assert_eq!(tree.maximum(), Some(1022));
+
+                    // no empty clusters are left behind
+                    let evens = VEBTree::<K>::from_iter_with_universe(
+                        1024, (0..512).map(|x| K::from_u64(x * 2))).unwrap();
+                    assert_eq!(tree, evens);
+                    assert_eq!(tree.memory_footprint(), evens.memory_footprint());
+
+                    tree.retain(|_| false);
+                    assert!(tree.is_empty());
+                    let empty = VEBTree::<K>::new(1024).unwrap();
+                    assert_eq!(tree.memory_footprint(), empty.memory_footprint());
+                }
+
+                #[test]
+                fn insert_range() {
+                    let cases = [(2, 1), (64, 20), (1000, 100), (5000, 300), (65536, 500)];
+                    for &(universe, count) in &cases {
+                        let dense = random_tree::<K>(universe, count, universe);
+                        let mut state = universe | 1;
+                        let (min, max) = (dense.minimum().unwrap(), dense.maximum().unwrap());
+                        let (min, max, last) = (min.to_u64(), max.to_u64(), universe - 1);
+                        let mut spans = vec![(0, last), (0, 0), (last, last), (0, min),
+                                             (min, max), (max, last)];
+                        for _ in 0..50 {
+                            let a = xorshift(&mut state) % universe;
+                            // mostly short spans, which are more likely to stay in one cluster
+                            let b = match xorshift(&mut state) % 3 {
+                                0 => xorshift(&mut state) % universe,
+                                _ => cmp::min(a + xorshift(&mut state) % 200, universe - 1),
+                            };
+                            spans.push((cmp::min(a, b), cmp::max(a, b)));
+                        }
+                        for (&(lo, hi), sparse) in spans.iter().zip([false, true].iter().cycle()) {
+                            for empty in &[false, true] {
+                                let mut tree = if *empty {
+                                    VEBTree::with_storage(universe, *sparse).unwrap()
+                                } else {
+                                    dense.clone_as(*sparse)
+                                };
+                                let mut expected = BTreeSet::from(&tree);
+                                let before = expected.len();
+                                expected.extend((lo..=hi).map(K::from_u64));
+                                let (lo, hi) = (K::from_u64(lo), K::from_u64(hi));
+                                assert_eq!(tree.insert_range(lo..=hi), expected.len() - before);
+                                assert_eq!(tree.validate(), Ok(()));
+                                assert_eq!(BTreeSet::from(&tree), expected);
+                                // inserting it again adds nothing
+                                assert_eq!(tree.insert_range(lo..=hi), 0);
+                                assert_eq!(tree.len(), expected.len());
+                            }
+                        }
+                    }
+
+                    // every kind of bound, and empty ranges
+                    let original = VEBTree::<K>::from_slice(300, &[3, 16, 17, 30, 40, 299])
+                        .unwrap();
+                    let check = |added: usize, tree: VEBTree<K>, range: (u64, u64)| {
+                        assert_eq!(tree.validate(), Ok(()));
+                        let mut expected = original.to_sorted_vec();
+                        expected.extend((range.0..range.1).map(K::from_u64));
+                        expected.sort();
+                        expected.dedup();
+                        assert_eq!(tree.to_sorted_vec(), expected);
+                        assert_eq!(added, expected.len() - 6);
+                    };
+                    let mut tree = original.clone();
+                    check(tree.insert_range(10..20), tree, (10, 20));
+                    let mut tree = original.clone();
+                    check(tree.insert_range((Bound::Excluded(16), Bound::Included(30))), tree,
+                          (17, 31));
+                    let mut tree = original.clone();
+                    check(tree.insert_range(..17), tree, (0, 17));
+                    let mut tree = original.clone();
+                    check(tree.insert_range(250..), tree, (250, 300));
+                    let mut tree = original.clone();
+                    check(tree.insert_range(5..5), tree, (0, 0));
+                    let mut tree = original.clone();
+                    check(tree.insert_range(..), tree, (0, 300));
+                }
+
+                #[test]
+                #[should_panic(expected = "value out of range")]
+                fn insert_range_out_of_range() {
+                    VEBTree::<K>::new(300).unwrap().insert_range(290..=300);
+                }
+
+                #[test]
+                fn remove_range() {
+                    let cases = [(2, 2), (64, 40), (1000, 300), (65536, 3000), (1 << 20, 500)];
+                    for &(universe, count) in &cases {
+                        let dense = random_tree::<K>(universe, count, universe);
+                        let model = BTreeSet::from(&dense);
+                        let min = dense.minimum().unwrap().to_u64();
+                        let max = dense.maximum().unwrap().to_u64();
+                        let mut state = universe | 1;
+                        let mut spans = vec![(0, universe - 1), (min, max), (min, min),
+                                             (max, max), (min + 1, max.saturating_sub(1)),
+                                             (0, min),
+                                             (max, universe - 1)];
+                        for _ in 0..50 {
+                            let a = xorshift(&mut state) % universe;
+                            let b = xorshift(&mut state) % universe;
+                            spans.push((cmp::min(a, b), cmp::max(a, b)));
+                        }
+                        for (&(lo, hi), sparse) in spans.iter().zip([false, true].iter().cycle()) {
+                            if lo > hi {
+                                continue;
+                            }
+                            let (lo, hi) = (K::from_u64(lo), K::from_u64(hi));
+                            let mut tree = dense.clone_as(*sparse);
+                            let mut expected = model.clone();
+                            expected.retain(|&x| x < lo || x > hi);
+                            assert_eq!(tree.remove_range(lo..=hi), model.len() - expected.len());
+                            assert_eq!(tree.validate(), Ok(()));
+                            assert_eq!(BTreeSet::from(&tree), expected);
+                            assert_eq!(tree.len(), expected.len());
+                            // removing it again does nothing
+                            assert_eq!(tree.remove_range(lo..=hi), 0);
+                        }
+
+                        // the whole universe leaves nothing allocated
+                        let mut tree = dense.clone();
+                        assert_eq!(tree.remove_range(..), model.len());
+                        assert!(tree.is_empty());
+                        assert_eq!(tree.validate(), Ok(()));
+                        assert_eq!((tree.node_count(), tree.memory_footprint()), (1, 0));
+                    }
+
+                    // every kind of bound, and bounds outside of the universe
+                    let original = VEBTree::<K>::from_slice(300, &[3, 16, 17, 30, 40, 299])
+                        .unwrap();
+                    let check = |removed: usize, tree: VEBTree<K>, rest: &[K]| {
+                        assert_eq!(tree.validate(), Ok(()));
+                        assert_eq!(tree.to_sorted_vec(), rest);
+                        assert_eq!(removed, 6 - rest.len());
+                    };
+                    let mut tree = original.clone();
+                    check(tree.remove_range(16..30), tree, &[3, 30, 40, 299]);
+                    let mut tree = original.clone();
+                    check(tree.remove_range((Bound::Excluded(16), Bound::Included(30))), tree,
+                          &[3, 16, 40, 299]);
+                    let mut tree = original.clone();
+                    check(tree.remove_range(..17), tree, &[17, 30, 40, 299]);
+                    let mut tree = original.clone();
+                    check(tree.remove_range(40..), tree, &[3, 16, 17, 30]);
+                    let mut tree = original.clone();
+                    check(tree.remove_range(299..1000), tree, &[3, 16, 17, 30, 40]);
+                    let mut tree = original.clone();
+                    check(tree.remove_range(300..1000), tree, &[3, 16, 17, 30, 40, 299]);
+                    let mut tree = original.clone();
+                    check(tree.remove_range(4..16), tree, &[3, 16, 17, 30, 40, 299]);
+                    let mut empty = VEBTree::<K>::new(300).unwrap();
+                    assert_eq!(empty.remove_range(..), 0);
+                }
+
+                #[test]
+                fn retain_edges() {
+                    // clusters of 16 values each
+                    let original = VEBTree::<K>::from_slice(256, &[3, 16, 17, 30, 40, 255])
+                        .unwrap();
+                    let check = |keep: &dyn Fn(K) -> bool| {
+                        let mut tree = original.clone();
+                        tree.retain(|x| keep(x));
+                        assert_eq!(tree.validate(), Ok(()));
+                        let expected: Vec<K> = original.iter().filter(|&x| keep(x)).collect();
+                        assert_eq!(tree.to_sorted_vec(), expected);
+                        assert_eq!(tree.len(), expected.len());
+                        assert_eq!(tree.minimum(), expected.first().cloned());
+                        assert_eq!(tree.maximum(), expected.last().cloned());
+                        let fresh = VEBTree::<K>::from_slice(256, &expected).unwrap();
+                        assert_eq!(tree.memory_footprint(), fresh.memory_footprint());
+                    };
+                    check(&|x| x != 3);
+                    check(&|x| x != 255);
+                    check(&|x| !(16..32).contains(&x));
+                    check(&|x| x == 17);
+                    check(&|_| true);
+                    check(&|_| false);
+
+                    let mut same = original.clone();
+                    same.retain(|_| true);
+                    assert_eq!(same, original);
+                    assert_eq!(same.memory_footprint(), original.memory_footprint());
+
+                    for &universe in &[2, 3, 16, 1000] {
+                        for seed in 0..10 {
+                            let original = random_tree::<K>(universe, 100, seed);
+                            let mut tree = original.clone();
+                            tree.retain(|x| x.to_u64() % (seed + 2) != 0);
+                            assert_eq!(tree.validate(), Ok(()));
+                            assert!(tree.iter().eq(original.iter()
+                                .filter(|x| x.to_u64() % (seed + 2) != 0)));
+                        }
+                    }
+                }
+
+                #[test]
+                fn insert_sorted() {
+                    for seed in 0..20 {
+                        // start from a non-empty tree so runs land in existing clusters too
+                        let mut tree = random_tree::<K>(65536, 50, seed + 100);
+                        let mut expected = tree.clone();
+                        let mut values = random_tree::<K>(65536, 500, seed).to_sorted_vec();
+                        if seed % 2 == 0 {
+                            values.insert(0, 0);
+                        }
+                        for &x in &values {
+                            expected.insert(x);
+                        }
+                        tree.insert_sorted(&values).unwrap();
+                        assert_eq!(tree.validate(), Ok(()));
+                        assert_eq!(tree, expected);
+                        assert_eq!(tree.len(), expected.len());
+                        assert_eq!(tree.memory_footprint(), expected.memory_footprint());
+                    }
+
+                    let mut tree = VEBTree::<K>::from_slice(256, &[5]).unwrap();
+                    assert_eq!(tree.insert_sorted(&[1, 2, 256]).unwrap_err(),
+                               Error::ValueOutOfRange);
+                    assert_eq!(tree.to_sorted_vec(), vec![5]);
+                    tree.insert_sorted(&[]).unwrap();
+                    tree.insert_sorted(&[0, 5, 255]).unwrap();
+                    assert_eq!(tree.to_sorted_vec(), vec![0, 5, 255]);
+                }
+
+                #[test]
+                fn shrink_to_fit() {
+                    let mut tree = VEBTree::<K>::new(65536).unwrap();
+                    tree.insert_sorted(&(0..65536).map(K::from_u64).collect::<Vec<_>>()).unwrap();
+                    tree.retain(|x| x < 300 || x % 1000 == 7);
+                    let values = tree.to_sorted_vec();
+                    let before = tree.memory_footprint();
+                    tree.shrink_to_fit();
+                    assert_eq!(tree.validate(), Ok(()));
+                    assert!(tree.memory_footprint() < before);
+                    assert_eq!(tree.to_sorted_vec(), values);
+                    assert_eq!(tree.len(), values.len());
+                    assert_eq!(tree.minimum(), Some(0));
+                    assert_eq!(tree.maximum(), Some(65007));
+                    for x in 0..65536 {
+                        let x = K::from_u64(x);
+                        assert_eq!(tree.has(x), values.binary_search(&x).is_ok());
+                    }
+
+                    // trimmed cluster slots come back when they're needed
+                    tree.delete(65007);
+                    tree.shrink_to_fit();
+                    tree.insert(65535);
+                    assert!(tree.has(65535));
+                    assert_eq!(tree.find_next(64007), Some(65535));
+                    assert_eq!(tree.find_prev(65535), Some(64007));
+                }
+
+                #[test]
+                fn shrink_after_mass_deletion() {
+                    let mut tree = VEBTree::<K>::new(65536).unwrap();
+                    // shrinking a full or an empty tree doesn't change anything
+                    tree.shrink_to_fit();
+                    assert_eq!(tree.memory_footprint(), 0);
+                    tree.extend((0..65536).map(K::from_u64));
+                    let full = tree.clone();
+                    tree.shrink_to_fit();
+                    assert_eq!(tree, full);
+                    assert_eq!(tree.validate(), Ok(()));
+
+                    // delete 99% of the values, one at a time
+                    let (nodes, bytes) = (tree.node_count(), tree.allocated_bytes());
+                    for x in 656..65536 {
+                        assert!(tree.delete(K::from_u64(x)));
+                    }
+                    tree.shrink_to_fit();
+                    assert_eq!(tree.validate(), Ok(()));
+                    assert!(tree.node_count() * 50 < nodes);
+                    assert!(tree.allocated_bytes() * 50 < bytes);
+                    assert_eq!(tree.len(), 656);
+                    assert_eq!(tree.find_next(654), Some(655));
+                    assert_eq!(tree.find_next(655), None);
+                    assert_eq!(tree.find_prev(60000), Some(655));
+
+                    // the tree still works as before once values go back in
+                    for x in (656..65536).step_by(7) {
+                        assert!(tree.insert(K::from_u64(x)));
+                    }
+                    assert_eq!(tree.validate(), Ok(()));
+                    for x in 0..65536 {
+                        let expected = x < 656 || (x - 656) % 7 == 0;
+                        assert_eq!(tree.has(K::from_u64(x)), expected);
+                    }
+                    tree.clear();
+                    tree.shrink_to_fit();
+                    assert_eq!((tree.node_count(), tree.memory_footprint()), (1, 0));
+                }
+
+                #[test]
+                fn difference() {
+                    let a = VEBTree::<K>::from_slice(256, &[1, 3, 17, 100, 200]).unwrap();
+                    let b = VEBTree::<K>::from_slice(256, &[3, 100, 201]).unwrap();
+                    assert_eq!(a.difference(&b).unwrap().to_sorted_vec(), vec![1, 17, 200]);
+                    assert_eq!(b.difference(&a).unwrap().to_sorted_vec(), vec![201]);
+                    assert_eq!(a.symmetric_difference(&b).unwrap().to_sorted_vec(),
+                               vec![1, 17, 200, 201]);
+                    assert!(a.difference(&a).unwrap().is_empty());
+                    assert_eq!(a.difference(&VEBTree::new(512).unwrap()).unwrap_err(),
+                               Error::UniverseMismatch);
+                    assert_eq!(a.symmetric_difference(&VEBTree::new(512).unwrap()).unwrap_err(),
+                               Error::UniverseMismatch);
+
+                    for universe in vec![2, 3, 16, 1000, 65536] {
+                        for seed in 0..20 {
+                            let count = (seed as usize * 7) % 60;
+                            let a = random_tree::<K>(universe, count, seed);
+                            let b = random_tree::<K>(universe, 60 - count, seed + 1000);
+                            let only_a = a.difference(&b).unwrap();
+                            let either = a.symmetric_difference(&b).unwrap();
+                            assert_eq!(only_a.validate(), Ok(()));
+                            assert_eq!(either.validate(), Ok(()));
+                            assert_eq!(either, b.symmetric_difference(&a).unwrap());
+                            assert!(a.difference(&a).unwrap().is_empty());
+                            for x in (0..universe).map(K::from_u64) {
+                                assert_eq!(only_a.has(x), a.has(x) && !b.has(x));
+                                assert_eq!(either.has(x), a.has(x) != b.has(x));
+                            }
+                            assert_eq!(only_a.len(), only_a.iter().count());
+                            assert_eq!(either.len(), either.iter().count());
+                        }
+                    }
+                }
+
+                #[test]
+                fn difference_matches_btree_set() {
+                    for &universe in &[2, 16, 1000, 65536] {
+                        for seed in 0..20 {
+                            let a = random_tree::<K>(universe, 50, seed);
+                            let mut b = random_tree::<K>(universe, 50, seed + 500);
+                            if seed % 3 == 0 {
+                                // other is a superset of self
+                                b.union_with(&a).unwrap();
+                            }
+                            let (sa, sb) = (BTreeSet::from(&a), BTreeSet::from(&b));
+                            let only_a = a.difference(&b).unwrap();
+                            let either = a.symmetric_difference(&b).unwrap();
+                            assert!(only_a.iter().eq(sa.difference(&sb).cloned()));
+                            assert!(either.iter().eq(sa.symmetric_difference(&sb).cloned()));
+                            assert_eq!(only_a.len(), sa.difference(&sb).count());
+                            assert_eq!(either.len(), sa.symmetric_difference(&sb).count());
+                            if seed % 3 == 0 {
+                                assert!(only_a.is_empty());
+                            }
+                        }
+                    }
+                }
+
+                #[test]
+                fn nth_next() {
+                    let tree = VEBTree::<K>::from_slice(256, &[3, 17, 100, 200]).unwrap();
+                    assert_eq!(tree.nth_next(0, 0), Some(3));
+                    assert_eq!(tree.nth_next(3, 0), Some(17));
+                    assert_eq!(tree.nth_next(3, 2), Some(200));
+                    assert_eq!(tree.nth_next(3, 3), None);
+                    assert_eq!(tree.nth_next(255, 0), None);
+                    assert_eq!(tree.nth_next(0, usize::MAX), None);
+                    assert_eq!(VEBTree::<K>::new(256).unwrap().nth_next(0, 0), None);
+
+                    for seed in 0..20 {
+                        let tree = random_tree::<K>(65536, 200, seed);
+                        let mut state = seed + 1;
+                        for _ in 0..20 {
+                            let x = K::from_u64(xorshift(&mut state) % 65536);
+                            let mut expected = tree.find_next(x);
+                            for k in 0..40 {
+                                assert_eq!(tree.nth_next(x, k), expected, "nth_next({}, {})", x, k);
+                                expected = expected.and_then(|y| tree.find_next(y));
+                            }
+                        }
+                    }
+                }
+
+                // One value in every cluster, so a jump crosses a cluster for every value it
+                // passes, and has to count them rather than visit them.
+                #[test]
+                fn nth_next_one_value_per_cluster() {
+                    let values: Vec<K> = (0..256).map(|i| K::from_u64(i * 256 + i % 7)).collect();
+                    let tree = VEBTree::<K>::from_sorted_slice(65536, &values).unwrap();
+                    for (i, &x) in values.iter().enumerate() {
+                        for &k in &[0, 1, 17, 128, 254usize.saturating_sub(i)] {
+                            assert_eq!(tree.nth_next(x, k), values.get(i + 1 + k).cloned());
+                        }
+                        assert_eq!(tree.nth_next(x, 255 - i), None);
+                        // starting between values gives the same jumps
+                        let between = K::from_u64(x.to_u64() + 1);
+                        assert_eq!(tree.nth_next(between, 0), values.get(i + 1).cloned());
+                    }
+                }
+
+                #[test]
+                fn clear_keeps_allocations() {
+                    let mut tree = random_tree::<K>(65536, 300, 1);
+                    let full = tree.memory_footprint();
+                    tree.clear();
+                    assert_eq!(tree.validate(), Ok(()));
+                    assert_eq!(tree.memory_footprint(), full);
+                    assert_eq!(tree.find_next(0), None);
+                    assert_eq!(tree.find_prev(65535), None);
+                    assert_eq!(tree.iter().count(), 0);
+
+                    for seed in 2..12 {
+                        let fresh = random_tree::<K>(65536, 300, seed);
+                        let values = fresh.to_sorted_vec();
+                        if seed % 2 == 0 {
+                            tree.extend(&values);
+                        } else {
+                            tree.insert_sorted(&values).unwrap();
+                        }
+                        assert_eq!(tree.validate(), Ok(()));
+                        assert_eq!(tree, fresh);
+                        assert_eq!(tree.len(), fresh.len());
+                        assert!(tree.iter().eq(fresh.iter()));
+                        assert!(tree.iter().rev().eq(fresh.iter().rev()));
+                        let mut state = seed;
+                        for _ in 0..100 {
+                            let x = K::from_u64(xorshift(&mut state) % 65536);
+                            assert_eq!(tree.has(x), fresh.has(x));
+                            assert_eq!(tree.find_next(x), fresh.find_next(x));
+                            assert_eq!(tree.find_prev(x), fresh.find_prev(x));
+                            assert_eq!(tree.rank(x), fresh.rank(x));
+                        }
+                        assert_eq!(tree.union(&fresh).unwrap(), fresh);
+                        assert!(tree.difference(&fresh).unwrap().is_empty());
+                        tree.clear();
+                        assert!(tree.is_empty());
+                        assert!(values.iter().all(|&x| !tree.has(x)));
+                    }
+
+                    tree.shrink_to_fit();
+                    assert_eq!(tree.validate(), Ok(()));
+                    assert_eq!(tree.memory_footprint(),
+                               VEBTree::<K>::new(65536).unwrap().memory_footprint());
+                }
+
+                #[test]
+                fn range() {
+                    let tree = VEBTree::<K>::from_slice(256, &[3, 17, 100, 200, 255]).unwrap();
+                    let collect = |r: Range<K>| r.collect::<Vec<_>>();
+                    assert_eq!(collect(tree.range(..)), vec![3, 17, 100, 200, 255]);
+                    assert_eq!(collect(tree.range(17..200)), vec![17, 100]);
+                    assert_eq!(collect(tree.range(17..=200)), vec![17, 100, 200]);
+                    assert_eq!(collect(tree.range((Bound::Excluded(17), Bound::Unbounded))),
+                               vec![100, 200, 255]);
+                    assert_eq!(collect(tree.range(..100)), vec![3, 17]);
+                    assert_eq!(tree.range(5..5).next(), None);
+                    assert_eq!(tree.range(18..100).next(), None);
+                    // only the minimum, or only the maximum
+                    assert_eq!(collect(tree.range(..=3)), vec![3]);
+                    assert_eq!(collect(tree.range(201..)), vec![255]);
+                    // outside of the universe
+                    assert_eq!(collect(tree.range(200..1000)), vec![200, 255]);
+                    assert_eq!(tree.range(256..1000).next(), None);
+                    assert_eq!(tree.range(..0).next(), None);
+                    assert_eq!(tree.range(3..=255).rev().collect::<Vec<_>>(),
+                               vec![255, 200, 100, 17, 3]);
+                    let mut both = tree.range(4..=255);
+                    assert_eq!(both.next_back(), Some(255));
+                    assert_eq!(both.next(), Some(17));
+                    assert_eq!(both.next_back(), Some(200));
+                    assert_eq!(both.next_back(), Some(100));
+                    assert_eq!(both.next(), None);
+                    assert_eq!(both.next_back(), None);
+
+                    let tree = random_tree::<K>(65536, 1000, 5);
+                    let mut state = 99;
+                    for _ in 0..50 {
+                        let a = K::from_u64(xorshift(&mut state) % 65536);
+                        let b = K::from_u64(xorshift(&mut state) % 65536);
+                        let (lo, hi) = (cmp::min(a, b), cmp::max(a, b));
+                        assert!(tree.range(lo..=hi).eq(tree.iter_range(lo, hi)));
+                        assert!(tree.range(lo..hi).eq(tree.iter().filter(|&x| x >= lo && x < hi)));
+                    }
+                }
+
+                #[test]
+                #[should_panic(expected = "range start is greater than range end")]
+                fn range_reversed() {
+                    let tree = VEBTree::<K>::new(256).unwrap();
+                    tree.range((Bound::Included(10), Bound::Excluded(5)));
+                }
+
+                #[test]
+                fn count_below_and_range() {
+                    let tree = VEBTree::<K>::from_slice(256, &[0, 3, 17, 100, 255]).unwrap();
+                    assert_eq!(tree.count_below(0), 0);
+                    assert_eq!(tree.count_below(1), 1);
+                    assert_eq!(tree.count_below(17), 2);
+                    assert_eq!(tree.count_below(18), 3);
+                    assert_eq!(tree.count_below(255), 4);
+                    assert_eq!(tree.count_below(1000), 5);
+                    assert_eq!(tree.count_range(3, 100), 2);
+                    assert_eq!(tree.count_range(3, 101), 3);
+                    assert_eq!(tree.count_range(100, 100), 0);
+                    assert_eq!(tree.count_range(200, 100), 0);
+                    assert_eq!(tree.count_range(0, 1000), 5);
+
+                    let mut tree = VEBTree::<K>::new(4096).unwrap();
+                    let mut set = BTreeSet::new();
+                    let mut state = 77;
+                    for _ in 0..2000 {
+                        let x = K::from_u64(xorshift(&mut state) % 4096);
+                        if xorshift(&mut state) % 3 == 0 {
+                            tree.delete(x);
+                            set.remove(&x);
+                        } else {
+                            tree.insert(x);
+                            set.insert(x);
+                        }
+                        let y = K::from_u64(xorshift(&mut state) % 4096);
+                        let z = K::from_u64(xorshift(&mut state) % 4096);
+                        assert_eq!(tree.count_below(y), set.range(..y).count());
+                        assert_eq!(tree.rank(y), set.range(..=y).count());
+                        if y <= z {
+                            assert_eq!(tree.count_range(y, z), set.range(y..z).count());
+                        }
+                    }
+                }
+
+                #[test]
+                fn drain() {
+                    let mut empty = VEBTree::<K>::new(256).unwrap();
+                    assert_eq!(empty.drain().next(), None);
+                    assert!(empty.is_empty());
+
+                    let mut one = VEBTree::<K>::from_slice(256, &[42]).unwrap();
+                    assert_eq!(one.drain().collect::<Vec<_>>(), vec![42]);
+                    assert!(one.is_empty() && !one.has(42));
+
+                    let mut tree = random_tree::<K>(65536, 500, 8);
+                    let full = tree.memory_footprint();
+                    let expected = tree.to_sorted_vec();
+                    {
+                        let drain = tree.drain();
+                        assert_eq!(drain.len(), expected.len());
+                        assert_eq!(drain.collect::<Vec<_>>(), expected);
+                    }
+                    assert!(tree.is_empty());
+                    assert_eq!(tree.validate(), Ok(()));
+                    assert_eq!(tree.memory_footprint(), full);
+
+                    // refill, then stop draining early
+                    tree.extend(&expected);
+                    assert_eq!(tree.to_sorted_vec(), expected);
+                    assert_eq!(tree.drain().take(3).collect::<Vec<_>>(), expected[..3]);
+                    assert!(tree.is_empty());
+                    assert_eq!(tree.find_next(0), None);
+                    assert!(expected.iter().all(|&x| !tree.has(x)));
+                }
+
+                #[test]
+                fn cluster_size() {
+                    assert_eq!(VEBTree::<K>::new(2).unwrap().cluster_size(), 1);
+                    assert_eq!(VEBTree::<K>::new(16).unwrap().cluster_size(), 4);
+                    assert_eq!(VEBTree::<K>::new(50).unwrap().cluster_size(), 8);
+                    assert_eq!(VEBTree::<K>::new(256).unwrap().cluster_size(), 16);
+                    assert_eq!(VEBTree::<K>::new(1000).unwrap().cluster_size(), 32);
+                }
+
+                #[test]
+                fn subset_and_disjoint() {
+                    let empty = VEBTree::<K>::new(256).unwrap();
+                    let a = VEBTree::<K>::from_slice(256, &[3, 17, 100, 200]).unwrap();
+                    let b = VEBTree::<K>::from_slice(256, &[3, 100]).unwrap();
+                    let c = VEBTree::<K>::from_slice(256, &[4, 18, 101]).unwrap();
+                    assert!(empty.is_subset(&a).unwrap() && empty.is_subset(&empty).unwrap());
+                    assert!(a.is_superset(&empty).unwrap());
+                    assert!(empty.is_disjoint(&a).unwrap() && a.is_disjoint(&empty).unwrap());
+                    assert!(empty.is_disjoint(&empty).unwrap());
+                    assert!(!a.is_subset(&empty).unwrap());
+                    assert!(b.is_subset(&a).unwrap() && a.is_superset(&b).unwrap());
+                    assert!(!a.is_subset(&b).unwrap() && !b.is_superset(&a).unwrap());
+                    assert!(a.is_subset(&a).unwrap() && !a.is_disjoint(&a).unwrap());
+                    assert!(a.is_disjoint(&c).unwrap() && c.is_disjoint(&a).unwrap());
+                    assert!(!a.is_disjoint(&b).unwrap());
+                    // other's minimum lives in the same cluster as some of self's values
+                    let d = VEBTree::<K>::from_slice(256, &[17, 18, 20]).unwrap();
+                    let e = VEBTree::<K>::from_slice(256, &[16, 17, 18, 20]).unwrap();
+                    assert!(d.is_subset(&e).unwrap() && !e.is_subset(&d).unwrap());
+
+                    // different universes are an error, as for the other set operations
+                    let wide = VEBTree::<K>::from_slice(1 << 12, &[3, 17, 100, 200, 4000]).unwrap();
+                    assert_eq!(a.is_subset(&wide), Err(Error::UniverseMismatch));
+                    assert_eq!(wide.is_superset(&a), Err(Error::UniverseMismatch));
+                    assert_eq!(c.is_disjoint(&wide), Err(Error::UniverseMismatch));
+                    assert_eq!(empty.is_subset(&wide), Err(Error::UniverseMismatch));
+
+                    for &universe in &[2, 16, 1000] {
+                        for seed in 0..30 {
+                            let a = random_tree::<K>(universe, (seed as usize * 7) % 40, seed);
+                            let b = random_tree::<K>(universe, (seed as usize * 3) % 40, seed + 9);
+                            let both = a.union(&b).unwrap();
+                            let common = a.intersection(&b).unwrap();
+                            let all = a.to_sorted_vec();
+                            assert_eq!(a.is_subset(&b).unwrap(), all.iter().all(|&x| b.has(x)));
+                            assert_eq!(a.is_disjoint(&b).unwrap(), !all.iter().any(|&x| b.has(x)));
+                            assert_eq!(a.is_disjoint(&b).unwrap(), b.is_disjoint(&a).unwrap());
+                            assert!(a.is_subset(&both).unwrap() && b.is_subset(&both).unwrap());
+                            assert!(common.is_subset(&a).unwrap() && common.is_subset(&b).unwrap());
+                            assert!(a.difference(&b).unwrap().is_disjoint(&b).unwrap());
+                        }
+                    }
+                }
+
+                #[test]
+                fn predicates_match_btree_set() {
+                    for &universe in &[2, 16, 1000] {
+                        for seed in 0..30 {
+                            let a = random_tree::<K>(universe, (seed as usize * 5) % 30, seed);
+                            let mut b = random_tree::<K>(universe, 20, seed + 77);
+                            if seed % 2 == 0 {
+                                b.union_with(&a).unwrap();
+                            }
+                            let (sa, sb) = (BTreeSet::from(&a), BTreeSet::from(&b));
+                            assert_eq!(a.is_subset(&b).unwrap(), sa.is_subset(&sb));
+                            assert_eq!(a.is_superset(&b).unwrap(), sa.is_superset(&sb));
+                            assert_eq!(a.is_disjoint(&b).unwrap(), sa.is_disjoint(&sb));
+                            assert!(a.is_subset(&a).unwrap() && a.is_superset(&a).unwrap());
+                        }
+                    }
+                }
+
+                #[test]
+                fn ceiling_and_floor() {
+                    let tree = VEBTree::<K>::from_slice(256, &[3, 17, 100, 200]).unwrap();
+                    // present
+                    assert_eq!(tree.ceiling(17), Some(17));
+                    assert_eq!(tree.floor(17), Some(17));
+                    // between two values
+                    assert_eq!(tree.ceiling(18), Some(100));
+                    assert_eq!(tree.floor(99), Some(17));
+                    // boundaries
+                    assert_eq!(tree.ceiling(tree.minimum().unwrap()), Some(3));
+                    assert_eq!(tree.floor(tree.maximum().unwrap()), Some(200));
+                    assert_eq!(tree.ceiling(0), Some(3));
+                    assert_eq!(tree.floor(2), None);
+                    assert_eq!(tree.ceiling(201), None);
+                    assert_eq!(tree.floor(255), Some(200));
+                    assert_eq!(tree.floor(1000), Some(200));
+                    assert_eq!(tree.ceiling(1000), None);
+
+                    let empty = VEBTree::<K>::new(256).unwrap();
+                    assert_eq!(empty.ceiling(0), None);
+                    assert_eq!(empty.floor(255), None);
+                }
+
+                #[test]
+                fn from_max_value() {
+                    let sizes = [(0, 2), (1, 2), (2, 4), (7, 8), (8, 16), (1000, 1024)];
+                    for &(max, universe) in &sizes {
+                        let values = [K::from_u64(max / 2), K::from_u64(max), 0];
+                        let tree = VEBTree::<K>::from_max_value(&values).unwrap();
+                        assert_eq!(tree.universe(), universe);
+                        assert!(tree.universe().is_power_of_two() && tree.universe() > max);
+                        assert!(values.iter().all(|&x| tree.has(x)));
+                        assert_eq!(tree.maximum(), Some(K::from_u64(max)));
+                    }
+                    assert_eq!(VEBTree::<K>::from_max_value(&[]).unwrap_err(), Error::NoValues);
+                }
+            }
+        )*
+    }
+}
+
+#[cfg(test)]
+tests!(u32_keys: u32, u64_keys: u64);
+
+#[test]
+fn large_universe() {
+    let universe = 1 << 40;
+    let mut tree = VEBTree::<u64>::new(universe).unwrap();
+    let values = [0, (1 << 20) - 1, 1 << 20, universe - (1 << 20) - 1, universe - 2, universe - 1];
+    for &x in values.iter().rev() {
+        assert!(tree.insert(x));
+    }
+    for &x in &values {
+        assert!(tree.has(x));
+    }
+    assert!(!tree.has(1));
+    assert!(!tree.has(universe - 3));
+    assert!(!tree.has(universe));
+    assert_eq!(tree.iter_range(0, universe - 1).collect::<Vec<_>>(), values);
+    assert_eq!(tree.maximum(), Some(universe - 1));
+}
+
+// Values at the top of a universe of at least 2^48 and on either side of cluster boundaries
+// at every level; the tests are built with overflow checks, so any overflow in the index
+// arithmetic panics.
+#[test]
+fn large_universe_boundaries() {
+    for &universe in &[1 << 48, (1 << 50) + 3, 1 << 63, u64::MAX] {
+        let mut tree = VEBTree::<u64>::new_sparse(universe).unwrap();
+        let mut values = BTreeSet::new();
+        for shift in (6..64).step_by(3) {
+            let boundary = 1u64 << shift;
+            if boundary < universe {
+                for &x in &[boundary - 1, boundary, boundary + 1, universe - boundary] {
+                    values.insert(x);
+                }
+            }
+        }
+        values.extend(&[universe - 1, universe - 2, universe - 64, universe - 65]);
+        for &x in &values {
+            assert!(tree.insert(x) && tree.has(x));
+        }
+        assert_eq!(tree.validate(), Ok(()));
+        assert!(tree.iter().eq(values.iter().cloned()));
+        for &x in &values {
+            assert_eq!(tree.find_next(x), values.range(x + 1..).next().cloned());
+            assert_eq!(tree.find_prev(x), values.range(..x).next_back().cloned());
+            assert_eq!(tree.find_next(x - 1), Some(x));
+        }
+        assert_eq!(tree.maximum(), Some(universe - 1));
+        for &x in &values {
+            assert!(tree.delete(x) && !tree.has(x));
+        }
+        assert!(tree.is_empty());
+        assert_eq!(tree.validate(), Ok(()));
+    }
+}
+
+#[test]
+fn debug_and_display() {
+    let empty = VEBTree::<u32>::new(1024).unwrap();
+    assert_eq!(format!("{:?}", empty), "VEBTree { universe: 1024, len: 0, elements: {} }");
+    assert_eq!(format!("{}", empty), "{}");
+
+    let small = VEBTree::<u32>::from_slice(1024, &[900, 3, 512, 17, 200]).unwrap();
+    assert_eq!(format!("{:?}", small),
+               "VEBTree { universe: 1024, len: 5, elements: {3, 17, 200, 512, 900} }");
+    assert_eq!(format!("{}", small), "{3, 17, 200, 512, 900}");
+    assert!(format!("{:#?}", small).contains("sqrt_universe"));
+
+    let big = VEBTree::<u64>::from_iter_with_universe(1 << 20, (0..100).map(|x| x * 10)).unwrap();
+    let first: Vec<String> = (0..32).map(|x| format!("{}", x * 10)).collect();
+    assert_eq!(format!("{:?}", big),
+               format!("VEBTree {{ universe: 1048576, len: 100, elements: {{{}, .. 68 more}} }}",
+                       first.join(", ")));
+    let all: Vec<String> = (0..100).map(|x| format!("{}", x * 10)).collect();
+    assert_eq!(format!("{}", big), format!("{{{}}}", all.join(", ")));
+}
+
+#[test]
+fn lazy_summary() {
+    let mut tree = VEBTree::<u64>::new(u64::MAX).unwrap();
+    assert!(tree.summary.is_none());
+    assert_eq!(tree.memory_footprint(), 0);
+    assert!(!tree.has(12345));
+    assert_eq!(tree.find_next(0), None);
+    assert_eq!(tree.find_prev(u64::MAX - 1), None);
+    assert!(tree.is_subset(&tree.clone()).unwrap());
+
+    tree.insert(1 << 60);
+    assert_eq!(tree.memory_footprint(), 0);
+    assert_eq!(tree.find_next(0), Some(1 << 60));
+    assert_eq!(tree.find_prev(u64::MAX - 1), Some(1 << 60));
+    assert_eq!(tree.find_next(1 << 60), None);
+    assert_eq!(tree.count_in_range(0, u64::MAX - 1), 1);
+    let other = VEBTree::<u64>::from_slice(u64::MAX, &[1 << 60]).unwrap();
+    assert_eq!(tree.intersection(&other).unwrap(), tree);
+    assert!(tree.difference(&other).unwrap().is_empty());
+    assert!(tree.is_subset(&other).unwrap() && !tree.is_disjoint(&other).unwrap());
+    tree.merge_from(&other);
+    assert_eq!(tree.validate(), Ok(()));
+    assert_eq!(tree.memory_footprint(), 0);
+
+    // the summary goes away again once every cluster is empty
+    let mut tree = VEBTree::<u32>::new(1 << 20).unwrap();
+    tree.insert(7);
+    tree.insert(500_000);
+    assert!(tree.summary.is_some());
+    tree.delete(7);
+    assert!(tree.summary.is_none());
+    assert_eq!(tree.validate(), Ok(()));
+}
+
+#[test]
+fn every_small_universe() {
+    for universe in 2..300u64 {
+        let mut tree = VEBTree::<u32>::new(universe).unwrap();
+        // fill from the top, so the partial cluster at the end is used first
+        for x in (0..universe as u32).rev() {
+            assert!(tree.insert(x), "{} in {}", x, universe);
+        }
+        assert_eq!(tree.len() as u64, universe);
+        for x in 0..universe as u32 {
+            assert!(tree.has(x));
+            let next = Some(x + 1).filter(|&next| u64::from(next) < universe);
+            assert_eq!(tree.find_next(x), next);
+            assert_eq!(tree.find_prev(x), x.checked_sub(1));
+        }
+        assert!(!tree.has(universe as u32));
+        assert_eq!(tree.validate(), Ok(()));
+        for x in (0..universe as u32).step_by(2) {
+            assert!(tree.delete(x));
+        }
+        for x in 0..universe as u32 {
+            assert_eq!(tree.has(x), x % 2 == 1);
+        }
+        assert_eq!(tree.validate(), Ok(()));
+    }
+}
+
+// The universe is exclusive: U - 1 is the largest value a tree can hold, and U is out of range
+// everywhere.
+#[test]
+fn universe_boundary() {
+    fn check(mut tree: VEBTree<u64>) {
+        let u = tree.universe();
+        let last = u - 1;
+        assert_eq!(tree.try_insert(u), Err(Error::ValueOutOfRange));
+        assert!(tree.insert(last) && tree.insert(0));
+        assert!(tree.has(last) && !tree.has(u));
+        assert_eq!(tree.maximum(), Some(last));
+        assert_eq!(tree.find_next(0), Some(last));
+        assert_eq!(tree.find_next(last), None);
+        assert_eq!(tree.find_next(u), None);
+        assert_eq!(tree.find_prev(u), Some(last));
+        assert_eq!(tree.find_next_absent(last), None);
+        assert_eq!(tree.iter_range(0, u).collect::<Vec<_>>(), [0, last]);
+        assert!(!tree.delete(u));
+        assert_eq!(tree.validate(), Ok(()));
+        assert!(tree.delete(last) && !tree.has(last));
+        assert_eq!(tree.maximum(), Some(0));
+        assert_eq!(tree.validate(), Ok(()));
+    }
+
+    for &u in &[2, 3, 63, 64, 65, 100, 256, 1000, 4096, 65_537, 1 << 32, (1 << 40) + 7] {
+        check(VEBTree::new(u).unwrap());
+        check(VEBTree::new_sparse(u).unwrap());
+        if u <= 1 << 16 {
+            check(VEBTree::new_prealloc(u).unwrap());
+        }
+    }
+    check(VEBTree::new_sparse(u64::MAX).unwrap());
+
+    assert_eq!(VEBTree::<u64>::new(1).unwrap_err(), Error::UniverseTooSmall);
+    // a u32 holds values up to 2^32 - 1, so that's the largest universe it allows
+    assert!(VEBTree::<u32>::new(1 << 32).is_ok());
+    assert_eq!(VEBTree::<u32>::new((1 << 32) + 1).unwrap_err(), Error::UniverseTooLarge);
+    assert!(VEBTree::<u8>::new(256).unwrap().try_insert(255).is_ok());
+}
+
+// Universes of at most 64 are stored as a single bitmask, so check either side of the cutover
+// between leaves and trees with clusters.
+#[test]
+fn leaf_boundaries() {
+    for &universe in &[2, 63, 64, 65, 4096] {
+        let mut tree = VEBTree::<u32>::new(universe).unwrap();
+        let mut expected = BTreeSet::new();
+        let mut state = universe | 1;
+        for step in 0..2000 {
+            let x = (xorshift(&mut state) % universe) as u32;
+            if step % 3 == 2 {
+                assert_eq!(tree.delete(x), expected.remove(&x));
+            } else {
+                assert_eq!(tree.insert(x), expected.insert(x));
+            }
+            assert_eq!(tree.validate(), Ok(()));
+            assert_eq!(tree.len(), expected.len());
+            assert_eq!(tree.minimum(), expected.iter().next().cloned());
+            assert_eq!(tree.maximum(), expected.iter().next_back().cloned());
+        }
+        assert_eq!(tree.validate(), Ok(()));
+        for x in 0..universe as u32 + 2 {
+            assert_eq!(tree.has(x), expected.contains(&x), "{} in {}", x, universe);
+            assert_eq!(tree.find_next(x), expected.range(x + 1..).next().cloned());
+            assert_eq!(tree.find_prev(x), expected.range(..x).next_back().cloned());
+            assert_eq!(tree.find_next_geq(x), expected.range(x..).next().cloned());
+            assert_eq!(tree.find_prev_leq(x), expected.range(..=x).next_back().cloned());
+            assert_eq!(tree.rank(x), expected.range(..=x).count());
+            assert_eq!(tree.select(x as usize), expected.iter().nth(x as usize).cloned());
+            assert_eq!(tree.find_next_absent(x),
+                       (x..universe as u32).find(|x| !expected.contains(x)));
+        }
+        assert!(tree.iter().eq(expected.iter().cloned()));
+        assert!(tree.iter().rev().eq(expected.iter().rev().cloned()));
+
+        let evens = VEBTree::from_iter_with_universe(universe, (0..universe as u32).step_by(2))
+            .unwrap();
+        let both: Vec<_> = expected.iter().cloned().filter(|x| x % 2 == 0).collect();
+        assert_eq!(tree.intersection(&evens).unwrap().to_sorted_vec(), both);
+        assert_eq!(tree.union(&evens).unwrap().len(),
+                   expected.len() + evens.len() - both.len());
+        let mut upper = tree.clone();
+        let lower = upper.split_off(universe as u32 / 2);
+        assert_eq!(lower.validate(), Ok(()));
+        assert_eq!(upper.validate(), Ok(()));
+        upper.retain(|x| x % 3 == 0);
+        assert!(upper.iter().all(|x| x % 3 == 0));
+        assert_eq!(upper.validate(), Ok(()));
+
+        for x in 0..universe as u32 {
+            tree.insert(x);
+        }
+        assert_eq!(tree.len() as u64, universe);
+        assert_eq!(tree.find_first_absent(), None);
+        tree.clear();
+        assert!(tree.is_empty());
+        assert_eq!(tree.validate(), Ok(()));
+    }
+
+    // leaves own no memory, even when they're full
+    let full = |universe| VEBTree::<u32>::from_iter_with_universe(universe, 0..universe as u32);
+    assert_eq!(full(64).unwrap().memory_footprint(), 0);
+    assert!(full(65).unwrap().memory_footprint() > 0);
+    // 64 slots for clusters of 64 values each, with a count of the values in each, and 65
+    // nodes counting the summary, each allocated along with its reference counts
+    assert_eq!(full(4096).unwrap().memory_footprint(),
+               64 * (size_of::<Option<Arc<VEBTree<u32>>>>() + size_of::<usize>()) +
+               65 * (size_of::<VEBTree<u32>>() + 2 * size_of::<usize>()));
+}
+
+// Adds the address of every node in the tree to `seen`. Nodes already seen are shared, so
+// everything below them has been seen too.
+#[cfg(test)]
+fn collect_nodes<T: VebInt>(tree: &VEBTree<T>, seen: &mut BTreeSet<*const VEBTree<T>>) {
+    if !seen.insert(tree) {
+        return;
+    }
+    for (_, subtree) in tree.children.iter() {
+        collect_nodes(subtree, seen);
+    }
+    if let Some(ref summary) = tree.summary {
+        collect_nodes(summary, seen);
+    }
+}
+
+#[test]
+fn clones_share_structure() {
+    let original = random_tree::<u32>(1 << 20, 5000, 7);
+    let model = BTreeSet::from(&original);
+    let mut a = original.clone();
+    let mut b = original.clone();
+    let mut a_model = model.clone();
+    let mut b_model = model.clone();
+    let mut state = 11;
+    for _ in 0..500 {
+        let x = (xorshift(&mut state) % (1 << 20)) as u32;
+        assert_eq!(a.insert(x), a_model.insert(x));
+        let y = (xorshift(&mut state) % (1 << 20)) as u32;
+        assert_eq!(b.delete(y), b_model.remove(&y));
+        let z = *model.iter().nth(xorshift(&mut state) as usize % model.len()).unwrap();
+        assert_eq!(b.delete(z), b_model.remove(&z));
+    }
+    for (tree, model) in &[(&original, &model), (&a, &a_model), (&b, &b_model)] {
+        assert_eq!(tree.validate(), Ok(()));
+        assert_eq!(&BTreeSet::from(*tree), *model);
+    }
+
+    // clearing or shrinking one clone leaves the others alone
+    let mut c = a.clone();
+    c.clear();
+    c.shrink_to_fit();
+    assert_eq!(c.memory_footprint(), 0);
+    let mut d = b.clone();
+    d.retain(|x| x % 2 == 0);
+    d.shrink_to_fit();
+    assert_eq!(BTreeSet::from(&a), a_model);
+    assert_eq!(BTreeSet::from(&b), b_model);
+    assert_eq!(BTreeSet::from(&d), b_model.iter().cloned().filter(|x| x % 2 == 0).collect());
+    assert_eq!(a.validate(), Ok(()));
+    assert_eq!(b.validate(), Ok(()));
+
+    // a change to one value only copies the nodes on its path
+    let mut e = original.clone();
+    e.insert(12345);
+    let mut seen = BTreeSet::new();
+    collect_nodes(&original, &mut seen);
+    let before = seen.len();
+    collect_nodes(&e, &mut seen);
+    assert!(seen.len() - before <= 2 * original.depth(), "{} new nodes", seen.len() - before);
+}
+
+// Copies between trees of very different occupancy, each one checked against a plain clone
+// and then modified, which mustn't change the tree it was copied from.
+#[test]
+fn clone_from_matches_clone() {
+    let universe = 1 << 16;
+    let full: Vec<u32> = (0..20_000).collect();
+    let sources = [
+        VEBTree::<u32>::new(universe).unwrap(),
+        VEBTree::from_slice(universe, &[12345]).unwrap(),
+        random_tree::<u32>(universe, 50, 1),
+        random_tree::<u32>(universe, 5000, 2),
+        VEBTree::from_sorted_slice(universe, &full).unwrap(),
+        VEBTree::from_slice(universe, &[0, 1, 2, 60_000, 65_535]).unwrap(),
+    ];
+    let mut state = 3;
+    for &sparse in &[false, true] {
+        let sources: Vec<VEBTree<u32>> = sources.iter().map(|tree| tree.clone_as(sparse)).collect();
+        let models: Vec<BTreeSet<u32>> = sources.iter().map(BTreeSet::from).collect();
+        let mut scratch = sources[0].clone();
+        for round in 0..60 {
+            let i = (xorshift(&mut state) % sources.len() as u64) as usize;
+            let source = &sources[i];
+            scratch.clone_from(source);
+            assert_eq!(scratch, *source);
+            assert_eq!(scratch, source.clone());
+            assert_eq!(scratch.validate(), Ok(()));
+            assert_eq!(scratch.minimum(), source.minimum());
+            assert_eq!(scratch.maximum(), source.maximum());
+            assert!(scratch.iter().eq(source.iter()));
+            for x in (0..universe as u32).step_by(997) {
+                assert_eq!(scratch.has(x), source.has(x));
+                assert_eq!(scratch.find_next(x), source.find_next(x));
+            }
+            // every other round leaves the copy alone, so the next copy lands on nodes which
+            // are still shared
+            if round % 2 == 1 {
+                continue;
+            }
+            for _ in 0..200 {
+                let x = (xorshift(&mut state) % universe) as u32;
+                if xorshift(&mut state) % 2 == 1 {
+                    scratch.insert(x);
+                } else {
+                    scratch.delete(x);
+                }
+            }
+            if let Some(min) = scratch.minimum() {
+                scratch.remove_range(min..min.saturating_add(3000));
+            }
+            assert_eq!(BTreeSet::from(source), models[i]);
+            assert_eq!(source.validate(), Ok(()));
+        }
+    }
+
+    // a different universe or kind of storage is just a clone
+    let mut scratch = VEBTree::<u32>::new(100).unwrap();
+    scratch.clone_from(&sources[3]);
+    assert_eq!(scratch, sources[3]);
+    let mut scratch = sources[3].clone_as(true);
+    scratch.clone_from(&sources[4]);
+    assert!(!scratch.is_sparse());
+    assert_eq!(scratch, sources[4]);
+    let mut scratch = VEBTree::<u32>::new_prealloc(universe).unwrap();
+    scratch.clone_from(&sources[2]);
+    assert!(!scratch.is_preallocated());
+    assert_eq!(scratch, sources[2]);
+}
+
+#[test]
+fn many_snapshots_stay_small() {
+    let mut tree = random_tree::<u32>(1 << 20, 20000, 3);
+    let nodes = tree.node_count();
+    let mut snapshots = Vec::new();
+    let mut state = 5;
+    for i in 0..2000 {
+        let x = (xorshift(&mut state) % (1 << 20)) as u32;
+        if i % 3 == 0 {
+            tree.delete(tree.find_next(x).unwrap_or(x));
+        } else {
+            tree.insert(x);
+        }
+        snapshots.push(tree.clone());
+    }
+    // copying every snapshot in full would need 2000 times as many nodes
+    let mut seen = BTreeSet::new();
+    for snapshot in &snapshots {
+        collect_nodes(snapshot, &mut seen);
+    }
+    assert!(seen.len() < nodes + 2000 * 2 * tree.depth(), "{} nodes", seen.len());
+    assert_eq!(snapshots.last().unwrap(), &tree);
+    assert_eq!(snapshots[0].validate(), Ok(()));
+}
+
+// validate should point at the node which has been broken.
+#[test]
+fn validate_reports_corruption() {
+    let tree = VEBTree::<u32>::from_slice(65536, &[1, 300, 301, 5000]).unwrap();
+    assert_eq!(tree.validate(), Ok(()));
+
+    let mut broken = tree.clone();
+    broken.children.get_mut(1).unwrap().len = 5;
+    assert_eq!(broken.validate(),
+               Err("root.clusters[1]: len is 5 but 2 values are stored".into()));
+
+    let mut broken = tree.clone();
+    Arc::make_mut(broken.summary.as_mut().unwrap()).delete(1);
+    assert_eq!(broken.validate(), Err("root: cluster 1 is missing from the summary".into()));
+
+    // the minimum must not also be stored in a cluster
+    let mut broken = VEBTree::<u32>::from_slice(65536, &[300, 301]).unwrap();
+    broken.children.get_mut(1).unwrap().insert(44);
+    broken.len += 1;
+    assert_eq!(broken.validate(),
+               Err("root: cluster 1 holds 300, which isn't above the minimum 300".into()));
+
+    let mut broken = VEBTree::<u32>::from_slice(64, &[3, 9]).unwrap();
+    broken.max = 8;
+    assert_eq!(broken.validate(),
+               Err("root: min and max are 3 and 8 but the stored values range from 3 to 9"
+                   .into()));
+}
+
+#[test]
+fn cluster_split_round_trip() {
+    // a universe of 2 has no clusters
+    for &universe in &[3, 1000, 1 << 32, 1 << 40, (1 << 45) + 3, u64::MAX] {
+        let tree = VEBTree::<u64>::new(universe).unwrap();
+        let clusters = cluster_count(universe);
+        let mut state = universe | 1;
+        let boundaries = [0, 1, tree.sqrt_universe - 1, tree.sqrt_universe, universe - 1];
+        let random = (0..1000).map(|_| xorshift(&mut state) % universe);
+        for x in boundaries.iter().cloned().filter(|&x| x < universe).chain(random) {
+            let (high, low) = (tree.high(x), tree.low(x));
+            assert!(high < clusters && low < tree.sqrt_universe);
+            assert_eq!(tree.index(high, low), x);
+        }
+    }
+
+    // without allocating clusters, only the minimum and maximum can be stored
+    let universe = (1 << 45) + 3;
+    let mut tree = VEBTree::<u64>::new(universe).unwrap();
+    tree.insert(universe - 1);
+    tree.insert(1 << 23);
+    assert!(tree.has(universe - 1) && tree.has(1 << 23));
+    assert!(!tree.has((1 << 23) - 1) && !tree.has(universe - 2));
+    assert_eq!(tree.find_next(0), Some(1 << 23));
+    assert_eq!(tree.find_next(1 << 23), Some(universe - 1));
+    assert_eq!(tree.find_prev(universe - 1), Some(1 << 23));
+}
+
+// Values reconstructed from a cluster index and an offset used to be computed with a
+// multiplication which could overflow for large universes.
+#[test]
+fn index_reconstruction() {
+    fn check<T: VebInt>(universe: u64) {
+        let mut tree = VEBTree::<T>::new(universe).unwrap();
+        let (first, low, high) = (T::from_u64(3), T::from_u64(universe / 2 + 5),
+                                  T::from_u64(universe - 2));
+        tree.insert(first);
+        tree.insert(high);
+        tree.insert(low);
+        assert_eq!(tree.find_next(first), Some(low));
+        assert_eq!(tree.find_next(low), Some(high));
+        assert_eq!(tree.find_prev(high), Some(low));
+        assert_eq!(tree.find_prev(low), Some(first));
+        assert_eq!(tree.to_sorted_vec(), vec![first, low, high]);
+        tree.delete(high);
+        assert_eq!(tree.maximum(), Some(low));
+    }
+    check::<u64>(1 << 40);
+    // the largest universe a u32 can index
+    check::<u32>(1 << 32);
+}
+
+#[test]
+fn lazy_children() {
+    let universe = 1 << 32;
+    let tree = VEBTree::<u64>::new(universe).unwrap();
+    // allocating every top-level cluster slot up front would take this much
+    let eager = (1 << 16) * size_of::<Option<VEBTree<u64>>>();
+    assert!(tree.memory_footprint() * 100 < eager);
+}
+
+#[test]
+fn sparse_memory() {
+    let universe = 1 << 40;
+    let mut sparse = VEBTree::<u64>::new_sparse(universe).unwrap();
+    let mut dense = VEBTree::<u64>::new(universe).unwrap();
+    assert!(sparse.is_sparse() && !dense.is_sparse());
+    assert_eq!(sparse.memory_footprint(), 0);
+    for &x in &[5, 1 << 20, 1 << 39, universe - 1] {
+        sparse.insert(x);
+        dense.insert(x);
+    }
+    assert_eq!(sparse.validate(), Ok(()));
+    assert_eq!(sparse, dense);
+    assert!(sparse.memory_footprint() < 16 * 1024);
+    assert!(sparse.memory_footprint() * 100 < dense.memory_footprint());
+    // everything created from a sparse tree is sparse too
+    assert!(sparse.split_off(1 << 30).is_sparse());
+    assert!(sparse.intersection(&dense).unwrap().is_sparse());
+    let mut empty = VEBTree::<u64>::new_sparse(universe).unwrap();
+    empty.union_with(&dense).unwrap();
+    assert!(empty.is_sparse() && empty == dense);
+    assert_eq!(empty.validate(), Ok(()));
+}
+
+// Runs the same operations on a sparse tree, a dense tree and a BTreeSet.
+#[test]
+fn sparse_matches_dense() {
+    for &universe in &[2, 3, 16, 1000, 1 << 16, 1 << 24] {
+        for seed in 1..4 {
+            let mut sparse = VEBTree::<u32>::new_sparse(universe).unwrap();
+            let mut dense = VEBTree::<u32>::new(universe).unwrap();
+            let mut set = BTreeSet::new();
+            let mut state = (seed * universe) | 1;
+            let mut random = || (xorshift(&mut state) % universe) as u32;
+            for step in 0..3000 {
+                let x = random();
+                match step % 7 {
+                    0..=3 => {
+                        assert_eq!(sparse.insert(x), set.insert(x));
+                        dense.insert(x);
+                    }
+                    4 | 5 => {
+                        assert_eq!(sparse.delete(x), set.remove(&x));
+                        dense.delete(x);
+                    }
+                    _ => {
+                        let y = random();
+                        assert_eq!(sparse.find_next(y), dense.find_next(y));
+                        assert_eq!(sparse.find_prev(y), dense.find_prev(y));
+                        assert_eq!(sparse.rank(y), dense.rank(y));
+                        assert_eq!(sparse.has(y), set.contains(&y));
+                    }
+                }
+                assert_eq!(sparse.len(), set.len());
+                assert_eq!(sparse.minimum(), set.iter().next().cloned());
+                assert_eq!(sparse.maximum(), set.iter().next_back().cloned());
+            }
+            assert_eq!(sparse.validate(), Ok(()));
+            assert!(sparse.iter().eq(set.iter().cloned()));
+            assert!(sparse.iter().rev().eq(set.iter().rev().cloned()));
+
+            let other = random_tree::<u32>(universe, 500, seed + 10);
+            let mut sparse_other = VEBTree::<u32>::new_sparse(universe).unwrap();
+            sparse_other.extend(other.iter());
+            assert_eq!(sparse.union(&sparse_other).unwrap(), dense.union(&other).unwrap());
+            assert_eq!(sparse.intersection(&other).unwrap(),
+                       dense.intersection(&other).unwrap());
+            assert_eq!(sparse.difference(&sparse_other).unwrap(),
+                       dense.difference(&other).unwrap());
+            assert_eq!(sparse.is_subset(&other).unwrap(), dense.is_subset(&other).unwrap());
+
+            let pivot = random();
+            let upper = sparse.split_off(pivot);
+            assert_eq!(upper, dense.split_off(pivot));
+            assert_eq!(sparse.validate(), Ok(()));
+            assert_eq!(upper.validate(), Ok(()));
+            sparse.retain(|x| x % 3 != 0);
+            dense.retain(|x| x % 3 != 0);
+            assert_eq!(sparse, dense);
+            sparse.shrink_to_fit();
+            assert_eq!(sparse.validate(), Ok(()));
+            assert_eq!(sparse, dense);
+            sparse.clear();
+            sparse.extend(upper.iter());
+            assert_eq!(sparse.validate(), Ok(()));
+            assert_eq!(sparse, upper);
+        }
+    }
+}
+
+// The loops in has, find_next and find_prev against the recursive versions they replaced.
+#[test]
+fn iterative_matches_recursive() {
+    fn check<T: VebInt>(tree: &VEBTree<T>, x: u64) {
+        let x = T::from_u64(x);
+        assert_eq!(tree.has(x), tree.has_recursive(x), "has({:?})", x);
+        assert_eq!(tree.find_next(x), tree.find_next_recursive(x), "find_next({:?})", x);
+        assert_eq!(tree.find_prev(x), tree.find_prev_recursive(x), "find_prev({:?})", x);
+    }
+
+    for &universe in &[2, 3, 64, 65, 1000, 1 << 16, 1 << 24, 1 << 32] {
+        for &sparse in &[false, true] {
+            let mut tree = VEBTree::<u64>::with_storage(universe, sparse).unwrap();
+            let mut state = universe | 1;
+            for step in 0..4000 {
+                // values a little past the universe too
+                let x = xorshift(&mut state) % (universe + universe / 8 + 2);
+                if x < universe {
+                    if step % 3 == 2 {
+                        tree.delete(x);
+                    } else {
+                        tree.insert(x);
+                    }
+                }
+                check(&tree, xorshift(&mut state) % (universe + 2));
+                if let Some(y) = tree.find_next(x) {
+                    check(&tree, y);
+                    check(&tree, y - 1);
+                    check(&tree, y + 1);
+                }
+            }
+            check(&tree, u64::MAX);
+            check(&tree, 0);
+            let narrow = VEBTree::<u32>::from_iter_with_universe(
+                cmp::min(universe, 1 << 32), tree.iter().map(|x| x as u32)).unwrap();
+            for x in tree.iter() {
+                check(&narrow, x);
+            }
+        }
+    }
+}
+
+// Runs the same operations on a preallocated tree, a default one and a BTreeSet.
+#[test]
+fn prealloc_matches_default() {
+    for &universe in &[2, 3, 100, 1000, 1 << 16] {
+        for seed in 1..4 {
+            let mut prealloc = VEBTree::<u32>::new_prealloc(universe).unwrap();
+            let mut tree = VEBTree::<u32>::new(universe).unwrap();
+            let mut set = BTreeSet::new();
+            assert!(prealloc.is_preallocated() && !tree.is_preallocated());
+            let mut state = (seed * universe) | 1;
+            let mut random = || (xorshift(&mut state) % universe) as u32;
+            for step in 0..3000 {
+                let x = random();
+                match step % 9 {
+                    0..=3 => {
+                        assert_eq!(prealloc.insert(x), set.insert(x));
+                        tree.insert(x);
+                    }
+                    4 | 5 => {
+                        assert_eq!(prealloc.delete(x), set.remove(&x));
+                        tree.delete(x);
+                    }
+                    6 => {
+                        assert_eq!(prealloc.pop_min(), tree.pop_min());
+                        set = tree.iter().collect();
+                    }
+                    7 => {
+                        let y = cmp::min(x as u64 + universe / 50, universe - 1) as u32;
+                        assert_eq!(prealloc.remove_range(x..=y), tree.remove_range(x..=y));
+                        set = tree.iter().collect();
+                    }
+                    _ => {
+                        let y = random();
+                        assert_eq!(prealloc.find_next(y), tree.find_next(y));
+                        assert_eq!(prealloc.find_prev(y), tree.find_prev(y));
+                        assert_eq!(prealloc.has(y), set.contains(&y));
+                    }
+                }
+                assert_eq!(prealloc.len(), set.len());
+                assert_eq!(prealloc.minimum(), set.iter().next().cloned());
+                assert_eq!(prealloc.maximum(), set.iter().next_back().cloned());
+            }
+            assert_eq!(prealloc.validate(), Ok(()));
+            assert!(prealloc.iter().eq(set.iter().cloned()));
+            assert!(prealloc.iter().rev().eq(set.iter().rev().cloned()));
+
+            let other = random_tree::<u32>(universe, 500, seed + 10);
+            assert_eq!(prealloc.union(&other).unwrap(), tree.union(&other).unwrap());
+            assert_eq!(prealloc.intersection(&other).unwrap(),
+                       tree.intersection(&other).unwrap());
+            assert_eq!(prealloc.difference(&other).unwrap(), tree.difference(&other).unwrap());
+            let mut merged = prealloc.clone();
+            merged.union_with(&other).unwrap();
+            assert_eq!(merged.validate(), Ok(()));
+            assert_eq!(merged, tree.union(&other).unwrap());
+
+            prealloc.retain(|x| x % 3 != 0);
+            tree.retain(|x| x % 3 != 0);
+            assert_eq!(prealloc.validate(), Ok(()));
+            assert_eq!(prealloc, tree);
+            let pivot = random();
+            let upper = prealloc.split_off(pivot);
+            assert_eq!(upper, tree.split_off(pivot));
+            assert_eq!(prealloc.validate(), Ok(()));
+            assert_eq!(upper.validate(), Ok(()));
+            prealloc.clear();
+            prealloc.extend(upper.iter());
+            assert_eq!(prealloc.validate(), Ok(()));
+            assert_eq!(prealloc, upper);
+        }
+    }
+}
+
+// Inserting and deleting never adds or removes a node from a preallocated tree.
+#[test]
+fn prealloc_keeps_nodes() {
+    let mut tree = VEBTree::<u32>::new_prealloc(1 << 16).unwrap();
+    let nodes = tree.node_count();
+    let bytes = tree.memory_footprint();
+    // every cluster at every level, and their summaries
+    assert!(nodes > (1 << 16) / 64, "{} nodes", nodes);
+    let mut state = 5;
+    for round in 0..20 {
+        for _ in 0..500 {
+            tree.insert((xorshift(&mut state) % (1 << 16)) as u32);
+        }
+        while tree.len() > round * 10 {
+            let x = (xorshift(&mut state) % (1 << 16)) as u32;
+            let next = tree.find_next(x).or(tree.minimum()).unwrap();
+            tree.delete(next);
+        }
+        tree.remove_range(1000..30_000);
+        tree.pop_max();
+        assert_eq!(tree.node_count(), nodes);
+        assert_eq!(tree.memory_footprint(), bytes);
+    }
+    assert_eq!(tree.validate(), Ok(()));
+    tree.clear();
+    tree.shrink_to_fit();
+    assert_eq!((tree.node_count(), tree.memory_footprint()), (nodes, bytes));
+
+    // growing the universe keeps the tree preallocated
+    tree.insert(70);
+    tree.grow_universe(1 << 18).unwrap();
+    assert!(tree.is_preallocated());
+    assert_eq!(tree.node_count(), VEBTree::<u32>::new_prealloc(1 << 18).unwrap().node_count());
+    assert_eq!(tree.to_sorted_vec(), [70]);
+    assert_eq!(tree.validate(), Ok(()));
+}
+
+// Clearing a tree which shares its nodes with a clone leaves the clone alone, and leaves a
+// tree that can be filled again.
+#[test]
+fn clear_shared() {
+    let trees = [
+        VEBTree::<u32>::new(1000).unwrap(),
+        VEBTree::<u32>::new_sparse(1000).unwrap(),
+        VEBTree::<u32>::new_prealloc(1000).unwrap(),
+    ];
+    for tree in &trees {
+        let nodes = tree.node_count();
+        // everything shared, and then only the root's summary shared
+        for &partly in &[false, true] {
+            let mut tree = tree.clone();
+            tree.extend(&[5, 700, 701]);
+            let copy = tree.clone();
+            if partly {
+                // copies cluster 21 without touching the summary
+                tree.insert(702);
+            }
+            tree.clear();
+            assert_eq!(tree.validate(), Ok(()));
+            tree.insert(562);
+            assert_eq!(tree.validate(), Ok(()));
+            tree.extend(&[3, 999]);
+            assert_eq!(tree.validate(), Ok(()));
+            assert_eq!(tree.to_sorted_vec(), [3, 562, 999]);
+            assert_eq!(copy.to_sorted_vec(), [5, 700, 701]);
+            if tree.is_preallocated() {
+                assert_eq!(tree.node_count(), nodes);
+            }
+        }
+    }
+}
+
+#[test]
+fn from_max_value_at_type_limit() {
+    let tree = VEBTree::<u8>::from_max_value(&[255, 3]).unwrap();
+    assert_eq!(tree.universe(), 256);
+    assert!(tree.has(255));
+    assert_eq!(VEBTree::<u64>::from_max_value(&[u64::MAX]).unwrap_err(), Error::UniverseTooLarge);
+}
+
+// Runs random operations against a tree and a BTreeSet.
+#[cfg(test)]
+fn matches_btree_set<K: VebInt>(universe: u64) {
+    let mut tree = VEBTree::<K>::new(universe).unwrap();
+    let mut set = BTreeSet::new();
+    let mut state = universe | 1;
+    for _ in 0..2000 {
+        let x = K::from_u64(xorshift(&mut state) % universe);
+        if xorshift(&mut state) % 3 == 2 {
+            assert_eq!(tree.delete(x), set.remove(&x));
+        } else {
+            assert_eq!(tree.insert(x), set.insert(x));
+        }
+        // walking every cluster slot of a big universe each time would be too slow
+        if universe <= 1 << 16 {
+            assert_eq!(tree.validate(), Ok(()));
+        }
+        let y = K::from_u64(xorshift(&mut state) % universe);
+        assert_eq!(tree.has(y), set.contains(&y));
+        assert_eq!(tree.find_next(y), set.range((Bound::Excluded(y), Bound::Unbounded))
+                   .next().cloned());
+        assert_eq!(tree.minimum(), set.iter().next().cloned());
+        assert_eq!(tree.maximum(), set.iter().next_back().cloned());
+    }
+    assert_eq!(tree.validate(), Ok(()));
+    assert!(tree.iter().eq(set.iter().cloned()));
+}
+
+#[test]
+fn every_key_width() {
+    matches_btree_set::<u8>(256);
+    matches_btree_set::<u8>(100);
+    matches_btree_set::<u16>(65536);
+    matches_btree_set::<u32>(1000);
+    matches_btree_set::<u64>(1 << 40);
+    matches_btree_set::<usize>(1 << 20);
+}
+
+#[test]
+#[should_panic(expected = "value out of range")]
+fn insert_out_of_range() {
+    let mut tree = VEBTree::<u32>::new(1000).unwrap();
+    tree.insert(1000);
+}
+
+#[test]
+fn narrow_keys() {
+    assert_eq!(VEBTree::<u8>::new(257).unwrap_err(), Error::UniverseTooLarge);
+    let mut tree = VEBTree::<u8>::new(256).unwrap();
+    tree.insert(255);
+    tree.insert(0);
+    assert!(tree.has(255));
+    assert_eq!(tree.find_next(0), Some(255));
+    assert_eq!(tree.pop_max(), Some(255));
+    assert_eq!(tree.maximum(), Some(0));
+}
+
+#[test]
+fn out_of_range_queries() {
+    for &universe in &[2u64, 3, 16, 1000] {
+        let empty = VEBTree::<u32>::new(universe).unwrap();
+        let mut tree = VEBTree::<u32>::from_slice(universe, &[0, 1]).unwrap();
+        for &x in &[0, 1, universe - 1, universe, universe + 1, 5000, u64::from(u32::MAX)] {
+            let x = x as u32;
+            assert!(!empty.has(x));
+            assert_eq!(empty.find_next(x), None);
+            assert_eq!(empty.find_prev(x), None);
+            if u64::from(x) < universe {
+                continue;
+            }
+            assert!(!tree.has(x));
+            assert_eq!(tree.find_next(x), None);
+            assert_eq!(tree.find_prev(x), Some(1));
+            assert!(!tree.delete(x));
+            assert_eq!(tree.try_insert(x).unwrap_err(), Error::ValueOutOfRange);
+            assert_eq!(tree.rank(x), 2);
+            assert_eq!(tree.count_below(x), 2);
+            assert_eq!(tree.count_in_range(0, x), 2);
+            assert_eq!(tree.count_in_range(x, x), 0);
+            assert_eq!(tree.find_next_geq(x), None);
+            assert_eq!(tree.find_prev_leq(x), Some(1));
+            assert_eq!(tree.nearest(x), Some(1));
+            assert_eq!(tree.nth_next(x, 0), None);
+            assert_eq!(tree.iter_range(0, x).count(), 2);
+            assert_eq!(tree.range(x..).next(), None);
+            assert_eq!(tree.find_next_absent(x), None);
+            assert_eq!(tree.len(), 2);
+            assert_eq!(tree.validate(), Ok(()));
+        }
+    }
 }
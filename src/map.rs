@@ -0,0 +1,305 @@
+//! A van Emde Boas tree which associates a value with each key.
+
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+
+use {Error, VEBTree, VebInt};
+
+// The largest universe whose values are kept in a vector indexed by key.
+const DENSE_UNIVERSE: u64 = 1 << 16;
+
+/// A map from integer keys in the range [0, U) to values, supporting the same fast successor
+/// queries as `VEBTree`.
+/// For universes of up to 2^16 keys, values are stored in a vector indexed by key, which grows
+/// to fit the largest key inserted, so finding a key's value takes constant time. For larger
+/// universes, and for maps made by `new_sparse`, they're stored in a `BTreeMap`, which uses
+/// memory in proportion to the number of entries however large the keys are, and finding a
+/// value takes O(log(N)) time, where N is the number of entries.
+#[derive(Debug, Clone)]
+pub struct VEBMap<T: VebInt, V> {
+    keys: VEBTree<T>,
+    values: Values<V>,
+}
+
+#[derive(Debug, Clone)]
+enum Values<V> {
+    Dense(Vec<Option<V>>),
+    Sparse(BTreeMap<u64, V>),
+}
+
+impl<T: VebInt, V> VEBMap<T, V> {
+    /// Generates a new, empty map. Will return an error under the same conditions as
+    /// `VEBTree::new`.
+    pub fn new(max_elem: u64) -> Result<Self, Error> {
+        let values = if max_elem <= DENSE_UNIVERSE {
+            Values::Dense(Vec::new())
+        } else {
+            Values::Sparse(BTreeMap::new())
+        };
+        Ok(VEBMap {
+            keys: VEBTree::new(max_elem)?,
+            values,
+        })
+    }
+
+    /// Generates a new, empty map whose keys only store the clusters they use, as
+    /// `VEBTree::new_sparse` does, and whose values are always kept in a `BTreeMap`. This
+    /// suits a few keys spread over a huge universe. Will return an error under the same
+    /// conditions as `new`.
+    pub fn new_sparse(max_elem: u64) -> Result<Self, Error> {
+        Ok(VEBMap {
+            keys: VEBTree::new_sparse(max_elem)?,
+            values: Values::Sparse(BTreeMap::new()),
+        })
+    }
+
+    /// Returns the set of keys in the map.
+    pub fn keys(&self) -> &VEBTree<T> {
+        &self.keys
+    }
+
+    /// Returns the size of the universe, the argument to the constructor. Keys must be in the
+    /// range [0, U), so the largest key the map can store is one less than this.
+    /// Takes constant time.
+    pub fn universe(&self) -> u64 {
+        self.keys.universe()
+    }
+
+    /// Returns the number of entries in the map.
+    /// Takes constant time.
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    /// Returns true if the map is empty.
+    /// Takes constant time.
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    /// Returns true if the map contains a value for the key.
+    /// Takes constant time, or O(log(N)) if the values are kept in a `BTreeMap`.
+    pub fn contains_key(&self, key: T) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// Returns a reference to the value stored for the key.
+    /// Takes constant time, or O(log(N)) if the values are kept in a `BTreeMap`.
+    pub fn get(&self, key: T) -> Option<&V> {
+        match self.values {
+            Values::Dense(ref values) => values.get(key.to_u64() as usize).and_then(Option::as_ref),
+            Values::Sparse(ref values) => values.get(&key.to_u64()),
+        }
+    }
+
+    /// Returns a mutable reference to the value stored for the key.
+    /// Takes constant time, or O(log(N)) if the values are kept in a `BTreeMap`.
+    pub fn get_mut(&mut self, key: T) -> Option<&mut V> {
+        match self.values {
+            Values::Dense(ref mut values) => {
+                values.get_mut(key.to_u64() as usize).and_then(Option::as_mut)
+            }
+            Values::Sparse(ref mut values) => values.get_mut(&key.to_u64()),
+        }
+    }
+
+    /// Returns the lowest key in the map, or None if it's empty.
+    /// Takes constant time.
+    pub fn minimum(&self) -> Option<T> {
+        self.keys.minimum()
+    }
+
+    /// Returns the highest key in the map, or None if it's empty.
+    /// Takes constant time.
+    pub fn maximum(&self) -> Option<T> {
+        self.keys.maximum()
+    }
+
+    /// Finds the next highest key in the map, or None if it doesn't exist.
+    /// Takes O(log(log(U))) time, where U is the argument to the constructor.
+    pub fn find_next(&self, key: T) -> Option<T> {
+        self.keys.find_next(key)
+    }
+
+    /// Returns the entry with the lowest key in the map, or None if it's empty.
+    /// Takes constant time.
+    pub fn min_entry(&self) -> Option<(T, &V)> {
+        self.minimum().map(|key| (key, self.get(key).unwrap()))
+    }
+
+    /// Returns the entry with the highest key in the map, or None if it's empty.
+    /// Takes constant time.
+    pub fn max_entry(&self) -> Option<(T, &V)> {
+        self.maximum().map(|key| (key, self.get(key).unwrap()))
+    }
+
+    /// Finds the entry with the next highest key in the map, or None if it doesn't exist.
+    /// Takes O(log(log(U))) time, where U is the argument to the constructor.
+    pub fn next_entry(&self, key: T) -> Option<(T, &V)> {
+        self.find_next(key).map(|next| (next, self.get(next).unwrap()))
+    }
+
+    /// Associates a value with a key, returning the value previously stored for it. Panics if
+    /// the key is outside of the range [0, U).
+    /// Takes O(log(U)) time, where U is the argument to the constructor, plus O(log(N)) if
+    /// the values are kept in a `BTreeMap`.
+    pub fn insert(&mut self, key: T, value: V) -> Option<V> {
+        self.keys.try_insert(key).expect("key out of range");
+        match self.values {
+            Values::Dense(ref mut values) => {
+                let idx = key.to_u64() as usize;
+                if idx >= values.len() {
+                    values.resize_with(idx + 1, || None);
+                }
+                values[idx].replace(value)
+            }
+            Values::Sparse(ref mut values) => values.insert(key.to_u64(), value),
+        }
+    }
+
+    /// Removes a key from the map, returning the value which was stored for it.
+    /// Takes O(log(U)) time, where U is the argument to the constructor, plus O(log(N)) if
+    /// the values are kept in a `BTreeMap`.
+    pub fn remove(&mut self, key: T) -> Option<V> {
+        if !self.keys.delete(key) {
+            return None;
+        }
+        match self.values {
+            Values::Dense(ref mut values) => values[key.to_u64() as usize].take(),
+            Values::Sparse(ref mut values) => values.remove(&key.to_u64()),
+        }
+    }
+
+    /// Removes every entry from the map.
+    /// Takes time proportional to the number of clusters the keys have allocated, plus the time
+    /// to drop the values: the N entries of the `BTreeMap`, or the vector of values, which has
+    /// an entry for every key up to the largest one inserted, so up to 2^16 of them.
+    pub fn clear(&mut self) {
+        self.keys.clear();
+        match self.values {
+            Values::Dense(ref mut values) => values.clear(),
+            Values::Sparse(ref mut values) => values.clear(),
+        }
+    }
+}
+
+#[test]
+fn insert_and_get() {
+    let mut map = VEBMap::<u32, &str>::new(256).unwrap();
+    assert!(map.get(3).is_none());
+    assert_eq!(map.insert(3, "three"), None);
+    assert_eq!(map.insert(200, "two hundred"), None);
+    assert_eq!(map.get(3), Some(&"three"));
+    assert_eq!(map.get(200), Some(&"two hundred"));
+    assert!(map.get(4).is_none());
+    assert!(map.get(255).is_none());
+    assert_eq!(map.len(), 2);
+}
+
+#[test]
+fn overwrite() {
+    let mut map = VEBMap::<u32, u32>::new(256).unwrap();
+    assert_eq!(map.insert(7, 1), None);
+    assert_eq!(map.insert(7, 2), Some(1));
+    assert_eq!(map.get(7), Some(&2));
+    assert_eq!(map.len(), 1);
+}
+
+#[test]
+fn remove() {
+    let mut map = VEBMap::<u32, u32>::new(256).unwrap();
+    map.insert(7, 1);
+    map.insert(9, 2);
+    assert_eq!(map.remove(7), Some(1));
+    assert_eq!(map.remove(7), None);
+    assert_eq!(map.remove(100), None);
+    assert!(!map.contains_key(7));
+    assert_eq!(map.minimum(), Some(9));
+    assert_eq!(map.insert(7, 3), None);
+    assert_eq!(map.get(7), Some(&3));
+}
+
+#[test]
+fn find_next_after_mutation() {
+    let mut map = VEBMap::<u32, u32>::new(256).unwrap();
+    map.insert(3, 30);
+    map.insert(100, 1000);
+    map.insert(200, 2000);
+    *map.get_mut(100).unwrap() += 1;
+    assert_eq!(map.find_next(3), Some(100));
+    assert_eq!(map.get(map.find_next(3).unwrap()), Some(&1001));
+    assert_eq!(map.find_next(100), Some(200));
+    assert_eq!(map.find_next(200), None);
+}
+
+#[test]
+#[should_panic(expected = "key out of range")]
+fn insert_out_of_range() {
+    let mut map = VEBMap::<u32, u32>::new(16).unwrap();
+    map.insert(16, 0);
+}
+
+#[test]
+fn entries() {
+    let mut map = VEBMap::<u32, &str>::new(256).unwrap();
+    assert_eq!(map.min_entry(), None);
+    assert_eq!(map.max_entry(), None);
+    assert_eq!(map.next_entry(0), None);
+    map.insert(3, "three");
+    map.insert(100, "hundred");
+    map.insert(200, "two hundred");
+    assert_eq!(map.min_entry(), Some((3, &"three")));
+    assert_eq!(map.max_entry(), Some((200, &"two hundred")));
+    assert_eq!(map.next_entry(0), Some((3, &"three")));
+    assert_eq!(map.next_entry(3), Some((100, &"hundred")));
+    assert_eq!(map.next_entry(150), Some((200, &"two hundred")));
+    assert_eq!(map.next_entry(200), None);
+
+    assert_eq!(map.remove(3), Some("three"));
+    assert_eq!(map.min_entry(), Some((100, &"hundred")));
+    assert_eq!(map.next_entry(0), Some((100, &"hundred")));
+    assert_eq!(map.insert(100, "one hundred"), Some("hundred"));
+    assert_eq!(map.min_entry(), Some((100, &"one hundred")));
+}
+
+#[test]
+fn drops_values() {
+    use alloc::rc::Rc;
+
+    let value = Rc::new(());
+    let mut map = VEBMap::<u32, Rc<()>>::new(256).unwrap();
+    map.insert(1, value.clone());
+    map.insert(2, value.clone());
+    map.insert(3, value.clone());
+    assert_eq!(Rc::strong_count(&value), 4);
+    drop(map.remove(1));
+    assert_eq!(Rc::strong_count(&value), 3);
+    map.insert(2, value.clone());
+    assert_eq!(Rc::strong_count(&value), 3);
+    drop(map);
+    assert_eq!(Rc::strong_count(&value), 1);
+}
+
+// Keys near the top of a huge universe don't make the map allocate in proportion to them.
+#[test]
+fn large_keys() {
+    let mut map = VEBMap::<u64, &str>::new_sparse(u64::MAX).unwrap();
+    assert_eq!(map.insert(u64::MAX - 1, "top"), None);
+    assert_eq!(map.insert(5, "bottom"), None);
+    assert_eq!(map.insert(u64::MAX - 1, "very top"), Some("top"));
+    assert_eq!(map.get(u64::MAX - 1), Some(&"very top"));
+    assert_eq!(map.next_entry(5), Some((u64::MAX - 1, &"very top")));
+    assert_eq!(map.remove(u64::MAX - 1), Some("very top"));
+    assert_eq!(map.max_entry(), Some((5, &"bottom")));
+
+    // past 2^16 keys, even a dense map keeps its values in a BTreeMap
+    let mut map = VEBMap::<u64, u32>::new(1 << 40).unwrap();
+    map.insert((1 << 40) - 1, 1);
+    assert!(matches!(map.values, Values::Sparse(ref values) if values.len() == 1));
+    assert_eq!(map.get((1 << 40) - 1), Some(&1));
+    let mut small = VEBMap::<u64, u32>::new(1 << 16).unwrap();
+    small.insert(65535, 1);
+    assert!(matches!(small.values, Values::Dense(ref values) if values.len() == 65536));
+    small.clear();
+    assert!(small.is_empty() && small.get(65535).is_none());
+}
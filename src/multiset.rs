@@ -0,0 +1,235 @@
+//! A van Emde Boas tree which can hold each value more than once.
+
+use {Error, VEBMap, VEBTree, VebInt};
+
+/// A multiset of integers in the range [0, U), supporting the same fast successor queries as
+/// `VEBTree` over its distinct values.
+/// The distinct values are kept in a `VEBMap` along with how many times each one was
+/// inserted, so a value only leaves the tree once every copy of it has been deleted. For
+/// universes larger than 2^16, the map keeps the counts in a `BTreeMap`, so looking up a
+/// count takes O(log(N)) time rather than the constant time given below, where N is the
+/// number of distinct values.
+#[derive(Debug, Clone)]
+pub struct VEBMultiSet<T: VebInt> {
+    counts: VEBMap<T, usize>,
+    // the total number of copies of every value
+    len: usize,
+}
+
+impl<T: VebInt> VEBMultiSet<T> {
+    /// Generates a new, empty multiset. Will return an error under the same conditions as
+    /// `VEBTree::new`.
+    pub fn new(max_elem: u64) -> Result<Self, Error> {
+        Ok(VEBMultiSet {
+            counts: VEBMap::new(max_elem)?,
+            len: 0,
+        })
+    }
+
+    /// Returns the set of distinct values in the multiset.
+    pub fn distinct(&self) -> &VEBTree<T> {
+        self.counts.keys()
+    }
+
+    /// Returns the size of the universe, the argument to the constructor. The largest value
+    /// the multiset can store is one less than this.
+    /// Takes constant time.
+    pub fn universe(&self) -> u64 {
+        self.counts.universe()
+    }
+
+    /// Returns the number of values in the multiset, counting every copy.
+    /// Takes constant time.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns the number of distinct values in the multiset.
+    /// Takes constant time.
+    pub fn distinct_len(&self) -> usize {
+        self.counts.len()
+    }
+
+    /// Returns true if the multiset is empty.
+    /// Takes constant time.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the number of copies of a value in the multiset.
+    /// Takes constant time.
+    pub fn count(&self, x: T) -> usize {
+        self.counts.get(x).cloned().unwrap_or(0)
+    }
+
+    /// Returns true if the multiset contains at least one copy of a value.
+    /// Takes constant time.
+    pub fn has(&self, x: T) -> bool {
+        self.counts.contains_key(x)
+    }
+
+    /// Returns the lowest value in the multiset, or None if it's empty.
+    /// Takes constant time.
+    pub fn minimum(&self) -> Option<T> {
+        self.counts.minimum()
+    }
+
+    /// Returns the highest value in the multiset, or None if it's empty.
+    /// Takes constant time.
+    pub fn maximum(&self) -> Option<T> {
+        self.counts.maximum()
+    }
+
+    /// Finds the lowest value in the multiset which is greater than x, or None if there isn't
+    /// one.
+    /// Takes O(log(log(U))) time, where U is the argument to the constructor.
+    pub fn find_next(&self, x: T) -> Option<T> {
+        self.counts.find_next(x)
+    }
+
+    /// Finds the highest value in the multiset which is less than x, or None if there isn't
+    /// one.
+    /// Takes O(log(log(U))) time, where U is the argument to the constructor.
+    pub fn find_prev(&self, x: T) -> Option<T> {
+        self.distinct().find_prev(x)
+    }
+
+    /// Adds a copy of a value, returning how many copies there are now. Panics if the value
+    /// is outside of the range [0, U).
+    /// Takes O(log(U)) time if the value wasn't already present, and constant time otherwise.
+    pub fn insert(&mut self, x: T) -> usize {
+        let count = match self.counts.get_mut(x) {
+            Some(count) => {
+                *count += 1;
+                *count
+            }
+            None => {
+                self.counts.insert(x, 1);
+                1
+            }
+        };
+        self.len += 1;
+        count
+    }
+
+    /// Removes one copy of a value, returning true if there was one. The value only stops
+    /// being found by `has` and the successor queries once its last copy is removed.
+    /// Takes O(log(U)) time if that was the last copy, and constant time otherwise.
+    pub fn delete(&mut self, x: T) -> bool {
+        let last = match self.counts.get_mut(x) {
+            Some(count) => {
+                *count -= 1;
+                *count == 0
+            }
+            None => return false,
+        };
+        if last {
+            self.counts.remove(x);
+        }
+        self.len -= 1;
+        true
+    }
+
+    /// Removes every copy of a value, returning how many there were.
+    /// Takes O(log(U)) time, where U is the argument to the constructor.
+    pub fn remove_all(&mut self, x: T) -> usize {
+        let count = self.counts.remove(x).unwrap_or(0);
+        self.len -= count;
+        count
+    }
+
+    /// Removes and returns one copy of the lowest value, or None if the multiset is empty.
+    /// Takes O(log(U)) time, where U is the argument to the constructor.
+    pub fn pop_min(&mut self) -> Option<T> {
+        let min = self.minimum()?;
+        self.delete(min);
+        Some(min)
+    }
+
+    /// Removes every value from the multiset.
+    /// Takes O(sqrt(U)) time, where U is the argument to the constructor.
+    pub fn clear(&mut self) {
+        self.counts.clear();
+        self.len = 0;
+    }
+}
+
+#[test]
+fn duplicates() {
+    let mut set = VEBMultiSet::<u32>::new(256).unwrap();
+    assert_eq!(set.insert(3), 1);
+    assert_eq!(set.insert(10), 1);
+    assert_eq!(set.insert(10), 2);
+    assert_eq!(set.insert(10), 3);
+    assert_eq!((set.len(), set.distinct_len()), (4, 2));
+    assert_eq!(set.count(10), 3);
+
+    assert!(set.delete(10));
+    assert!(set.delete(10));
+    assert!(set.has(10));
+    assert_eq!(set.count(10), 1);
+    assert_eq!(set.find_next(3), Some(10));
+    assert_eq!(set.maximum(), Some(10));
+
+    assert!(set.delete(10));
+    assert!(!set.has(10));
+    assert_eq!(set.count(10), 0);
+    assert_eq!(set.find_next(3), None);
+    assert_eq!(set.find_prev(200), Some(3));
+    assert_eq!(set.maximum(), Some(3));
+    assert!(!set.delete(10));
+    assert_eq!((set.len(), set.distinct_len()), (1, 1));
+    assert_eq!(set.distinct().validate(), Ok(()));
+}
+
+#[test]
+fn remove_all_and_pop_min() {
+    let mut set = VEBMultiSet::<u32>::new(256).unwrap();
+    for &x in &[5, 7, 5, 200, 7, 5] {
+        set.insert(x);
+    }
+    assert_eq!(set.remove_all(7), 2);
+    assert_eq!(set.remove_all(7), 0);
+    assert_eq!(set.len(), 4);
+    assert_eq!(set.pop_min(), Some(5));
+    assert_eq!(set.pop_min(), Some(5));
+    assert_eq!(set.pop_min(), Some(5));
+    assert_eq!(set.pop_min(), Some(200));
+    assert_eq!(set.pop_min(), None);
+    assert!(set.is_empty());
+    set.insert(9);
+    set.clear();
+    assert!(set.is_empty() && !set.has(9));
+}
+
+// Random inserts and deletes, against a count for every value.
+#[test]
+fn matches_counts() {
+    let mut set = VEBMultiSet::<u32>::new(1000).unwrap();
+    let mut counts = vec![0usize; 1000];
+    let mut state = 17;
+    for _ in 0..5000 {
+        let x = (::xorshift(&mut state) % 1000) as u32;
+        if ::xorshift(&mut state) % 3 == 2 {
+            assert_eq!(set.delete(x), counts[x as usize] > 0);
+            counts[x as usize] = counts[x as usize].saturating_sub(1);
+        } else {
+            counts[x as usize] += 1;
+            assert_eq!(set.insert(x), counts[x as usize]);
+        }
+    }
+    assert_eq!(set.len(), counts.iter().sum::<usize>());
+    for x in 0..1000 {
+        assert_eq!(set.count(x), counts[x as usize]);
+        assert_eq!(set.has(x), counts[x as usize] > 0);
+        let next = (x + 1..1000).find(|&y| counts[y as usize] > 0);
+        assert_eq!(set.find_next(x), next);
+    }
+    assert_eq!(set.distinct().validate(), Ok(()));
+}
+
+#[test]
+#[should_panic(expected = "key out of range")]
+fn insert_out_of_range() {
+    VEBMultiSet::<u32>::new(16).unwrap().insert(16);
+}
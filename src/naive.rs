@@ -0,0 +1,121 @@
+//! A straightforward ordered set with the same interface as `VEBTree`, for use as a baseline.
+
+use alloc::collections::BTreeSet;
+#[cfg(test)]
+use alloc::boxed::Box;
+#[cfg(test)]
+use alloc::vec::Vec;
+use core::ops::Bound;
+
+use {Error, SuccessorSet, VebInt};
+#[cfg(test)]
+use {FlatVEBTree, VEBTree};
+
+/// An ordered set of values of type `T` in the range [0, U), backed by a `BTreeSet`.
+/// It has the same behaviour as `VEBTree` but none of the cleverness, so it's useful as an
+/// oracle in tests and as a baseline in benchmarks. Every operation takes O(log(N)) time, where
+/// N is the number of values stored.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct NaiveSet<T: VebInt> {
+    values: BTreeSet<T>,
+    universe: u64,
+}
+
+impl<T: VebInt> NaiveSet<T> {
+    /// Generates a new, empty set. Will return an error under the same conditions as
+    /// `VEBTree::new`.
+    pub fn new(max_elem: u64) -> Result<Self, Error> {
+        if max_elem <= 1 {
+            Err(Error::UniverseTooSmall)
+        } else if max_elem - 1 > T::MAX_VALUE {
+            Err(Error::UniverseTooLarge)
+        } else {
+            Ok(NaiveSet {
+                values: BTreeSet::new(),
+                universe: max_elem,
+            })
+        }
+    }
+}
+
+impl<T: VebInt> SuccessorSet<T> for NaiveSet<T> {
+    fn insert(&mut self, x: T) -> bool {
+        assert!(x.to_u64() < self.universe, "value out of range");
+        self.values.insert(x)
+    }
+
+    fn delete(&mut self, x: T) -> bool {
+        self.values.remove(&x)
+    }
+
+    fn has(&self, x: T) -> bool {
+        self.values.contains(&x)
+    }
+
+    fn minimum(&self) -> Option<T> {
+        self.values.iter().next().cloned()
+    }
+
+    fn maximum(&self) -> Option<T> {
+        self.values.iter().next_back().cloned()
+    }
+
+    fn find_next(&self, x: T) -> Option<T> {
+        self.values.range((Bound::Excluded(x), Bound::Unbounded)).next().cloned()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    fn universe(&self) -> u64 {
+        self.universe
+    }
+}
+
+// Applies random operations to a set, recording every observable result.
+#[cfg(test)]
+fn trace(set: &mut dyn SuccessorSet<u32>, seed: u64) -> Vec<u64> {
+    let universe = set.universe();
+    let mut state = seed;
+    let mut results = vec![universe];
+    for _ in 0..2000 {
+        let x = (::xorshift(&mut state) % universe) as u32;
+        let changed = match ::xorshift(&mut state) % 3 {
+            0 | 1 => set.insert(x),
+            _ => set.delete(x),
+        };
+        let y = (::xorshift(&mut state) % universe) as u32;
+        results.push(changed as u64);
+        results.push(set.is_empty() as u64);
+        results.push(set.has(y) as u64);
+        results.extend(set.minimum().map(u64::from));
+        results.extend(set.maximum().map(u64::from));
+        results.push(set.find_next(y).map_or(universe, u64::from));
+    }
+    results
+}
+
+#[test]
+fn implementations_agree() {
+    for &universe in &[2, 3, 16, 1000, 1 << 16] {
+        for seed in 1..5 {
+            let mut sets: Vec<Box<dyn SuccessorSet<u32>>> = vec![
+                Box::new(NaiveSet::new(universe).unwrap()),
+                Box::new(VEBTree::new(universe).unwrap()),
+                Box::new(FlatVEBTree::new(universe).unwrap()),
+            ];
+            let expected = trace(&mut *sets[0], seed);
+            for set in &mut sets[1..] {
+                assert_eq!(trace(&mut **set, seed), expected);
+            }
+        }
+    }
+}
+
+#[test]
+fn new_errors() {
+    assert_eq!(NaiveSet::<u32>::new(1).unwrap_err(), Error::UniverseTooSmall);
+    assert_eq!(NaiveSet::<u8>::new(257).unwrap_err(), Error::UniverseTooLarge);
+    assert_eq!(NaiveSet::<u8>::new(256).unwrap().universe(), 256);
+}
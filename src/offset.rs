@@ -0,0 +1,755 @@
+//! A van Emde Boas tree over an arbitrary range of signed keys.
+
+#[cfg(test)]
+use alloc::collections::BTreeSet;
+#[cfg(test)]
+use core::ops::Bound::{Excluded, Unbounded};
+use alloc::vec::Vec;
+use core::cmp;
+
+use snapshot::{read_varint, write_varint};
+use {Cursor, CursorMut, Error, Iter, VEBTree};
+
+/// A set of integers in the range [lo, hi), which may be negative, supporting the same fast
+/// successor queries as `VEBTree`.
+/// Values are shifted down by `lo` and stored in a `VEBTree<u64>` with a universe of
+/// `hi - lo`, so no memory is spent on the values below `lo`. Every method takes and returns
+/// values in the original range, and values outside of it are treated just like values
+/// outside of a `VEBTree`'s universe. Apart from that, the methods behave, and take the same
+/// time, as the `VEBTree` methods of the same names.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct OffsetVEBTree {
+    tree: VEBTree<u64>,
+    lo: i64,
+}
+
+impl OffsetVEBTree {
+    /// Generates a new, empty tree holding values in the range [lo, hi). Will return an error
+    /// if the range holds fewer than two values.
+    pub fn new_with_range(lo: i64, hi: i64) -> Result<Self, Error> {
+        Ok(OffsetVEBTree {
+            tree: VEBTree::new(universe(lo, hi)?)?,
+            lo,
+        })
+    }
+
+    /// Generates a new, empty tree holding values in the range [lo, hi), which only stores
+    /// the clusters it uses, as `VEBTree::new_sparse` does. Will return an error under the
+    /// same conditions as `new_with_range`.
+    pub fn new_sparse_with_range(lo: i64, hi: i64) -> Result<Self, Error> {
+        Ok(OffsetVEBTree {
+            tree: VEBTree::new_sparse(universe(lo, hi)?)?,
+            lo,
+        })
+    }
+
+    /// Returns the underlying tree, which holds every value shifted down by `lo`.
+    pub fn inner(&self) -> &VEBTree<u64> {
+        &self.tree
+    }
+
+    /// Returns the range of values the tree can hold, as (lo, hi); hi itself is out of range.
+    /// Takes constant time.
+    pub fn range(&self) -> (i64, i64) {
+        (self.lo, self.lo.wrapping_add(self.tree.universe() as i64))
+    }
+
+    /// Returns the number of values the tree can hold, hi - lo.
+    /// Takes constant time.
+    pub fn universe(&self) -> u64 {
+        self.tree.universe()
+    }
+
+    /// Returns the number of values stored in the tree.
+    /// Takes constant time.
+    pub fn len(&self) -> usize {
+        self.tree.len()
+    }
+
+    /// Returns true if the tree is empty.
+    /// Takes constant time.
+    pub fn is_empty(&self) -> bool {
+        self.tree.is_empty()
+    }
+
+    // The position of x in the underlying tree, or None if it's outside of [lo, hi).
+    fn offset(&self, x: i64) -> Option<u64> {
+        if x < self.lo {
+            None
+        } else {
+            Some(x.wrapping_sub(self.lo) as u64).filter(|&x| x < self.tree.universe())
+        }
+    }
+
+    // The position of x, which is at least lo, in the underlying tree, or of the top of the
+    // range if x is past it.
+    fn clamp(&self, x: i64) -> u64 {
+        cmp::min(x.wrapping_sub(self.lo) as u64, self.tree.universe() - 1)
+    }
+
+    fn value(&self, x: u64) -> i64 {
+        self.lo.wrapping_add(x as i64)
+    }
+
+    fn with_tree(&self, tree: VEBTree<u64>) -> OffsetVEBTree {
+        OffsetVEBTree { tree, lo: self.lo }
+    }
+
+    /// Returns true if the tree contains the value.
+    /// Takes O(log(log(U))) time, where U is hi - lo.
+    pub fn has(&self, x: i64) -> bool {
+        self.offset(x).is_some_and(|x| self.tree.has(x))
+    }
+
+    /// Inserts a value, returning true if it was not already present. Panics if the value is
+    /// outside of the range [lo, hi).
+    /// Takes O(log(U)) time, where U is hi - lo.
+    pub fn insert(&mut self, x: i64) -> bool {
+        let x = self.offset(x).expect("value out of range");
+        self.tree.insert(x)
+    }
+
+    /// Inserts a value, returning an error and leaving the tree untouched if the value is
+    /// outside of the range [lo, hi). Otherwise, returns true if it was not already present.
+    /// Takes O(log(U)) time, where U is hi - lo.
+    pub fn try_insert(&mut self, x: i64) -> Result<bool, Error> {
+        let x = self.offset(x).ok_or(Error::ValueOutOfRange)?;
+        Ok(self.tree.insert(x))
+    }
+
+    /// Removes a value, returning true if it was present.
+    /// Takes O(log(U)) time, where U is hi - lo.
+    pub fn delete(&mut self, x: i64) -> bool {
+        self.offset(x).is_some_and(|x| self.tree.delete(x))
+    }
+
+    /// Removes every value from the tree.
+    /// Takes time proportional to the number of clusters which are allocated.
+    pub fn clear(&mut self) {
+        self.tree.clear();
+    }
+
+    /// Returns the lowest value in the tree, or None if it's empty.
+    /// Takes constant time.
+    pub fn minimum(&self) -> Option<i64> {
+        self.tree.minimum().map(|x| self.value(x))
+    }
+
+    /// Returns the highest value in the tree, or None if it's empty.
+    /// Takes constant time.
+    pub fn maximum(&self) -> Option<i64> {
+        self.tree.maximum().map(|x| self.value(x))
+    }
+
+    /// Finds the lowest value in the tree which is greater than x, or None if there isn't
+    /// one.
+    /// Takes O(log(log(U))) time, where U is hi - lo.
+    pub fn find_next(&self, x: i64) -> Option<i64> {
+        let next = if x < self.lo {
+            self.tree.minimum()
+        } else {
+            self.offset(x).and_then(|x| self.tree.find_next(x))
+        };
+        next.map(|x| self.value(x))
+    }
+
+    /// Finds the highest value in the tree which is less than x, or None if there isn't one.
+    /// Takes O(log(log(U))) time, where U is hi - lo.
+    pub fn find_prev(&self, x: i64) -> Option<i64> {
+        let prev = if x < self.lo {
+            None
+        } else {
+            // anything past the end of the range just finds the maximum
+            self.tree.find_prev(x.wrapping_sub(self.lo) as u64)
+        };
+        prev.map(|x| self.value(x))
+    }
+
+    /// Finds the next highest value in the tree, wrapping around to the minimum if there's
+    /// nothing above x, as `VEBTree::find_next_cyclic` does. Returns None only if the tree is
+    /// empty.
+    /// Takes O(log(log(U))) time, where U is hi - lo.
+    pub fn find_next_cyclic(&self, x: i64) -> Option<i64> {
+        self.find_next(x).or_else(|| self.minimum())
+    }
+
+    /// Finds the next lowest value in the tree, wrapping around to the maximum if there's
+    /// nothing below x, as `VEBTree::find_prev_cyclic` does. Returns None only if the tree is
+    /// empty.
+    /// Takes O(log(log(U))) time, where U is hi - lo.
+    pub fn find_prev_cyclic(&self, x: i64) -> Option<i64> {
+        self.find_prev(x).or_else(|| self.maximum())
+    }
+
+    /// Returns an iterator over every value in the tree, in ascending order. Like
+    /// `VEBTree::iter`, the iterator is double-ended.
+    pub fn iter<'a>(&'a self) -> OffsetIter<'a> {
+        OffsetIter {
+            inner: self.tree.iter(),
+            lo: self.lo,
+        }
+    }
+
+    /// Returns a cursor pointing at the smallest value in the tree which is greater than or
+    /// equal to x, or at the ghost position if there isn't one.
+    /// Takes O(log(log(U))) time, where U is hi - lo.
+    pub fn cursor_at<'a>(&'a self, x: i64) -> OffsetCursor<'a> {
+        let mut inner = self.tree.cursor_at(if x < self.lo { 0 } else { self.clamp(x) });
+        // the top of the range is below x, so even a value there is too small
+        if x >= self.lo && self.offset(x).is_none() && inner.current().is_some() {
+            inner.move_next();
+        }
+        OffsetCursor { inner, lo: self.lo }
+    }
+
+    /// Returns a cursor which can remove values, pointing at the smallest value in the tree
+    /// which is greater than or equal to x, or at the ghost position if there isn't one.
+    /// Takes O(log(log(U))) time, where U is hi - lo.
+    pub fn cursor_at_mut<'a>(&'a mut self, x: i64) -> OffsetCursorMut<'a> {
+        let past_end = x >= self.lo && self.offset(x).is_none();
+        let start = if x < self.lo { 0 } else { self.clamp(x) };
+        let mut inner = self.tree.cursor_at_mut(start);
+        if past_end && inner.current().is_some() {
+            inner.move_next();
+        }
+        OffsetCursorMut { inner, lo: self.lo }
+    }
+
+    // ================
+    // order statistics
+    // ================
+
+    /// Returns the number of values stored in the tree which are in the range [a, b].
+    /// Takes the same time as `VEBTree::count_in_range`.
+    pub fn count_in_range(&self, a: i64, b: i64) -> usize {
+        match self.offset(a) {
+            _ if b < a || b < self.lo => 0,
+            Some(a) => self.tree.count_in_range(a, self.clamp(b)),
+            None if a < self.lo => self.tree.count_in_range(0, self.clamp(b)),
+            None => 0,
+        }
+    }
+
+    /// Returns the number of values stored in the tree which are less than or equal to `x`.
+    /// Takes the same time as `VEBTree::rank`.
+    pub fn rank(&self, x: i64) -> usize {
+        if x < self.lo {
+            0
+        } else {
+            self.tree.rank(self.clamp(x))
+        }
+    }
+
+    /// Returns the k-th smallest value stored in the tree, counting from 0, or None if there
+    /// are k or fewer values.
+    /// Takes the same time as `VEBTree::select`.
+    pub fn select(&self, k: usize) -> Option<i64> {
+        self.tree.select(k).map(|x| self.value(x))
+    }
+
+    /// Returns the value k places after the next highest value above x, so `nth_next(x, 0)`
+    /// is the same as `find_next(x)`. Returns None if k or fewer values are greater than x.
+    /// Takes the same time as `VEBTree::nth_next`.
+    pub fn nth_next(&self, x: i64, k: usize) -> Option<i64> {
+        let next = if x < self.lo {
+            self.tree.select(k)
+        } else {
+            self.tree.nth_next(self.clamp(x), k)
+        };
+        next.map(|x| self.value(x))
+    }
+
+    // ==============
+    // set operations
+    // ==============
+
+    // Trees with the same range have underlying trees with the same universe.
+    fn same_range(&self, other: &OffsetVEBTree) -> Result<(), Error> {
+        if self.range() == other.range() {
+            Ok(())
+        } else {
+            Err(Error::UniverseMismatch)
+        }
+    }
+
+    /// Returns a new tree containing every value in either this tree or `other`. Will return
+    /// an error if the two trees have different ranges.
+    pub fn union(&self, other: &OffsetVEBTree) -> Result<OffsetVEBTree, Error> {
+        self.same_range(other)?;
+        self.tree.union(&other.tree).map(|tree| self.with_tree(tree))
+    }
+
+    /// Adds every value in `other` to this tree. Will return an error, leaving this tree
+    /// unchanged, if the two trees have different ranges.
+    pub fn union_with(&mut self, other: &OffsetVEBTree) -> Result<(), Error> {
+        self.same_range(other)?;
+        self.tree.union_with(&other.tree)
+    }
+
+    /// Returns a new tree containing every value in both this tree and `other`. Will return
+    /// an error if the two trees have different ranges.
+    pub fn intersection(&self, other: &OffsetVEBTree) -> Result<OffsetVEBTree, Error> {
+        self.same_range(other)?;
+        self.tree.intersection(&other.tree).map(|tree| self.with_tree(tree))
+    }
+
+    /// Returns a new tree containing every value in this tree which isn't in `other`. Will
+    /// return an error if the two trees have different ranges.
+    pub fn difference(&self, other: &OffsetVEBTree) -> Result<OffsetVEBTree, Error> {
+        self.same_range(other)?;
+        self.tree.difference(&other.tree).map(|tree| self.with_tree(tree))
+    }
+
+    /// Returns a new tree containing every value in exactly one of this tree and `other`.
+    /// Will return an error if the two trees have different ranges.
+    pub fn symmetric_difference(&self, other: &OffsetVEBTree) -> Result<OffsetVEBTree, Error> {
+        self.same_range(other)?;
+        self.tree.symmetric_difference(&other.tree).map(|tree| self.with_tree(tree))
+    }
+
+    /// Returns true if every value in this tree is also in `other`. Will return an error if
+    /// the two trees have different ranges.
+    pub fn is_subset(&self, other: &OffsetVEBTree) -> Result<bool, Error> {
+        self.same_range(other)?;
+        self.tree.is_subset(&other.tree)
+    }
+
+    /// Returns true if every value in `other` is also in this tree. Will return an error if
+    /// the two trees have different ranges.
+    pub fn is_superset(&self, other: &OffsetVEBTree) -> Result<bool, Error> {
+        other.is_subset(self)
+    }
+
+    /// Returns true if no value is in both this tree and `other`. Will return an error if the
+    /// two trees have different ranges.
+    pub fn is_disjoint(&self, other: &OffsetVEBTree) -> Result<bool, Error> {
+        self.same_range(other)?;
+        self.tree.is_disjoint(&other.tree)
+    }
+
+    // =========
+    // snapshots
+    // =========
+
+    /// Encodes the tree as a compact snapshot, which can be read back with `from_bytes`: lo,
+    /// zigzag-encoded as a varint, followed by the snapshot `VEBTree::to_bytes` writes for
+    /// the underlying tree.
+    /// Takes the same time as `VEBTree::to_bytes`.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![];
+        write_varint(&mut bytes, ((self.lo << 1) ^ (self.lo >> 63)) as u64);
+        bytes.extend(self.tree.to_bytes());
+        bytes
+    }
+
+    /// Decodes a snapshot written by `to_bytes`. Will return an error under the same
+    /// conditions as `VEBTree::from_bytes`, or if the range it describes ends past
+    /// `i64::MAX`. Chooses the kind of storage as `VEBTree::from_bytes` does.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        let mut rest = bytes;
+        let zigzag = read_varint(&mut rest)?;
+        let lo = (zigzag >> 1) as i64 ^ -((zigzag & 1) as i64);
+        let tree = VEBTree::from_bytes(rest)?;
+        if tree.universe() > i64::MAX.wrapping_sub(lo) as u64 {
+            return Err(Error::UniverseTooLarge);
+        }
+        Ok(OffsetVEBTree { tree, lo })
+    }
+}
+
+// Builds a tree from strictly increasing values which were read from outside the program,
+// choosing the kind of storage as `VEBTree::from_decoded` does.
+#[cfg(feature = "serde")]
+pub fn from_decoded(lo: i64, hi: i64, values: &[i64]) -> Result<OffsetVEBTree, Error> {
+    let universe = universe(lo, hi)?;
+    if values.iter().any(|&x| x < lo) {
+        return Err(Error::ValueOutOfRange);
+    }
+    let values: Vec<u64> = values.iter().map(|&x| x.wrapping_sub(lo) as u64).collect();
+    Ok(OffsetVEBTree {
+        tree: VEBTree::from_decoded(universe, &values)?,
+        lo,
+    })
+}
+
+impl<'a> IntoIterator for &'a OffsetVEBTree {
+    type Item = i64;
+    type IntoIter = OffsetIter<'a>;
+
+    fn into_iter(self) -> OffsetIter<'a> {
+        self.iter()
+    }
+}
+
+// The size of [lo, hi), which always fits in a u64.
+fn universe(lo: i64, hi: i64) -> Result<u64, Error> {
+    if hi <= lo {
+        Err(Error::UniverseTooSmall)
+    } else {
+        Ok(hi.wrapping_sub(lo) as u64)
+    }
+}
+
+/// An iterator over the values in an `OffsetVEBTree`, created by `OffsetVEBTree::iter`.
+#[derive(Debug, Clone)]
+pub struct OffsetIter<'a> {
+    inner: Iter<'a, u64>,
+    lo: i64,
+}
+
+impl<'a> Iterator for OffsetIter<'a> {
+    type Item = i64;
+
+    fn next(&mut self) -> Option<i64> {
+        let lo = self.lo;
+        self.inner.next().map(|x| lo.wrapping_add(x as i64))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<'a> DoubleEndedIterator for OffsetIter<'a> {
+    fn next_back(&mut self) -> Option<i64> {
+        let lo = self.lo;
+        self.inner.next_back().map(|x| lo.wrapping_add(x as i64))
+    }
+}
+
+impl<'a> ExactSizeIterator for OffsetIter<'a> {}
+
+/// A read-only cursor over an `OffsetVEBTree`, created by `OffsetVEBTree::cursor_at`. It moves
+/// just like a `Cursor` over the underlying tree.
+#[derive(Debug, Clone)]
+pub struct OffsetCursor<'a> {
+    inner: Cursor<'a, u64>,
+    lo: i64,
+}
+
+impl<'a> OffsetCursor<'a> {
+    /// Returns the value the cursor points at, or None at the ghost position.
+    pub fn current(&self) -> Option<i64> {
+        let lo = self.lo;
+        self.inner.current().map(|x| lo.wrapping_add(x as i64))
+    }
+
+    /// Moves to the next value, or from the maximum to the ghost position, or from the ghost
+    /// position to the minimum.
+    pub fn move_next(&mut self) {
+        self.inner.move_next();
+    }
+
+    /// Moves to the previous value, or from the minimum to the ghost position, or from the
+    /// ghost position to the maximum.
+    pub fn move_prev(&mut self) {
+        self.inner.move_prev();
+    }
+}
+
+/// A cursor over an `OffsetVEBTree` which can remove values, created by
+/// `OffsetVEBTree::cursor_at_mut`. It moves just like a `CursorMut` over the underlying tree.
+#[derive(Debug)]
+pub struct OffsetCursorMut<'a> {
+    inner: CursorMut<'a, u64>,
+    lo: i64,
+}
+
+impl<'a> OffsetCursorMut<'a> {
+    /// Returns the value the cursor points at, or None at the ghost position.
+    pub fn current(&self) -> Option<i64> {
+        let lo = self.lo;
+        self.inner.current().map(|x| lo.wrapping_add(x as i64))
+    }
+
+    /// Moves to the next value, or from the maximum to the ghost position, or from the ghost
+    /// position to the minimum.
+    /// Takes O(log(log(U))) time, where U is hi - lo.
+    pub fn move_next(&mut self) {
+        self.inner.move_next();
+    }
+
+    /// Moves to the previous value, or from the minimum to the ghost position, or from the
+    /// ghost position to the maximum.
+    /// Takes O(log(log(U))) time, where U is hi - lo.
+    pub fn move_prev(&mut self) {
+        self.inner.move_prev();
+    }
+
+    /// Removes the value the cursor points at and moves to the next one, returning the value
+    /// which was removed. Does nothing and returns None at the ghost position.
+    /// Takes O(log(U)) time, where U is hi - lo.
+    pub fn remove_current(&mut self) -> Option<i64> {
+        let lo = self.lo;
+        self.inner.remove_current().map(|x| lo.wrapping_add(x as i64))
+    }
+}
+
+#[test]
+fn negative_range() {
+    let mut tree = OffsetVEBTree::new_with_range(-1000, -10).unwrap();
+    assert_eq!(tree.range(), (-1000, -10));
+    assert_eq!(tree.universe(), 990);
+    for &x in &[-1000, -500, -11, -999] {
+        assert!(tree.insert(x));
+    }
+    assert!(!tree.insert(-500));
+    assert_eq!(tree.try_insert(-10), Err(Error::ValueOutOfRange));
+    assert_eq!(tree.try_insert(-1001), Err(Error::ValueOutOfRange));
+    assert_eq!(tree.try_insert(5), Err(Error::ValueOutOfRange));
+    assert!(tree.has(-1000) && tree.has(-11) && !tree.has(-10) && !tree.has(0));
+    assert_eq!((tree.minimum(), tree.maximum()), (Some(-1000), Some(-11)));
+    assert_eq!(tree.iter().collect::<Vec<_>>(), [-1000, -999, -500, -11]);
+    assert_eq!(tree.iter().rev().collect::<Vec<_>>(), [-11, -500, -999, -1000]);
+    assert_eq!(tree.find_next(-999), Some(-500));
+    assert_eq!(tree.find_next(i64::MIN), Some(-1000));
+    assert_eq!(tree.find_next(-11), None);
+    assert_eq!(tree.find_prev(-999), Some(-1000));
+    assert_eq!(tree.find_prev(i64::MAX), Some(-11));
+    assert_eq!(tree.find_prev(-1000), None);
+    assert!(tree.delete(-1000) && !tree.delete(-1000) && !tree.delete(7));
+    assert_eq!(tree.minimum(), Some(-999));
+    assert_eq!(tree.inner().validate(), Ok(()));
+}
+
+#[test]
+fn cyclic() {
+    let mut tree = OffsetVEBTree::new_with_range(-100, 100).unwrap();
+    assert_eq!((tree.find_next_cyclic(0), tree.find_prev_cyclic(0)), (None, None));
+    tree.insert(-5);
+    assert_eq!((tree.find_next_cyclic(-5), tree.find_prev_cyclic(-5)), (Some(-5), Some(-5)));
+    tree.insert(50);
+    assert_eq!(tree.find_next_cyclic(-5), Some(50));
+    assert_eq!(tree.find_next_cyclic(50), Some(-5));
+    assert_eq!(tree.find_next_cyclic(i64::MAX), Some(-5));
+    assert_eq!(tree.find_next_cyclic(i64::MIN), Some(-5));
+    assert_eq!(tree.find_prev_cyclic(-5), Some(50));
+    assert_eq!(tree.find_prev_cyclic(i64::MIN), Some(50));
+    assert_eq!(tree.find_prev_cyclic(i64::MAX), Some(50));
+}
+
+#[test]
+#[should_panic(expected = "value out of range")]
+fn insert_out_of_range() {
+    OffsetVEBTree::new_with_range(1_000_000, 2_000_000).unwrap().insert(999_999);
+}
+
+#[test]
+fn bad_ranges() {
+    assert_eq!(OffsetVEBTree::new_with_range(5, 5).unwrap_err(), Error::UniverseTooSmall);
+    assert_eq!(OffsetVEBTree::new_with_range(5, 6).unwrap_err(), Error::UniverseTooSmall);
+    assert_eq!(OffsetVEBTree::new_with_range(6, 5).unwrap_err(), Error::UniverseTooSmall);
+    // the widest range there is
+    let mut tree = OffsetVEBTree::new_sparse_with_range(i64::MIN, i64::MAX).unwrap();
+    assert_eq!(tree.range(), (i64::MIN, i64::MAX));
+    for &x in &[i64::MIN, -1, 0, 1, i64::MAX - 1] {
+        tree.insert(x);
+    }
+    assert!(!tree.has(i64::MAX));
+    assert_eq!(tree.iter().collect::<Vec<_>>(), [i64::MIN, -1, 0, 1, i64::MAX - 1]);
+    assert_eq!(tree.find_next(-1), Some(0));
+    assert_eq!(tree.find_prev(i64::MAX), Some(i64::MAX - 1));
+}
+
+// Random operations on a range straddling zero, against a BTreeSet.
+#[test]
+fn matches_btree_set() {
+    for &(lo, hi) in &[(-5000, 5000), (-3, 2), (1_000_000, 1_065_536), (-70_000, -3)] {
+        let mut tree = OffsetVEBTree::new_with_range(lo, hi).unwrap();
+        let mut set = BTreeSet::new();
+        let mut state = (hi - lo) as u64 | 1;
+        let width = (hi - lo) as u64 + 20;
+        let mut random = || lo - 10 + (::xorshift(&mut state) % width) as i64;
+        for step in 0..3000 {
+            let x = random();
+            if step % 3 == 2 {
+                assert_eq!(tree.delete(x), set.remove(&x));
+            } else if x >= lo && x < hi {
+                assert_eq!(tree.insert(x), set.insert(x));
+            } else {
+                assert_eq!(tree.try_insert(x), Err(Error::ValueOutOfRange));
+            }
+            let y = random();
+            assert_eq!(tree.has(y), set.contains(&y));
+            assert_eq!(tree.find_next(y), set.range(y + 1..).next().cloned());
+            assert_eq!(tree.find_prev(y), set.range(..y).next_back().cloned());
+        }
+        assert_eq!(tree.len(), set.len());
+        assert_eq!(tree.minimum(), set.iter().next().cloned());
+        assert_eq!(tree.maximum(), set.iter().next_back().cloned());
+        assert!(tree.iter().eq(set.iter().cloned()));
+        assert!((&tree).into_iter().rev().eq(set.iter().rev().cloned()));
+        tree.clear();
+        assert!(tree.is_empty());
+    }
+}
+
+// The order statistics against a BTreeSet, with queries on both sides of the range.
+#[test]
+fn order_statistics() {
+    for &(lo, hi) in &[(-5000, 5000), (-70_000, -3), (i64::MIN, i64::MIN + 1000)] {
+        let mut tree = OffsetVEBTree::new_with_range(lo, hi).unwrap();
+        let mut set = BTreeSet::new();
+        let width = (hi as i128 - lo as i128) as u64;
+        let mut state = width | 1;
+        for _ in 0..300 {
+            let x = lo + (::xorshift(&mut state) % width) as i64;
+            tree.insert(x);
+            set.insert(x);
+        }
+        let sorted: Vec<i64> = set.iter().cloned().collect();
+        for (k, &x) in sorted.iter().enumerate() {
+            assert_eq!(tree.select(k), Some(x));
+            assert_eq!(tree.rank(x), k + 1);
+        }
+        assert_eq!(tree.select(sorted.len()), None);
+        let mut queries = vec![i64::MIN, i64::MAX, lo, hi - 1, hi, lo.saturating_sub(1)];
+        queries.extend((0..100).map(|_| lo + (::xorshift(&mut state) % width) as i64));
+        for &x in &queries {
+            assert_eq!(tree.rank(x), set.range(..=x).count());
+            for &k in &[0, 1, 7, 250] {
+                let expected = set.range((Excluded(x), Unbounded)).nth(k).cloned();
+                assert_eq!(tree.nth_next(x, k), expected);
+            }
+            for &y in &queries {
+                let expected = if x <= y { set.range(x..=y).count() } else { 0 };
+                assert_eq!(tree.count_in_range(x, y), expected);
+            }
+        }
+    }
+}
+
+#[test]
+fn set_operations() {
+    let a = OffsetVEBTree::from_values(-100, 100, &[-100, -50, 0, 99]);
+    let b = OffsetVEBTree::from_values(-100, 100, &[-50, 1, 99]);
+    let values = |tree: OffsetVEBTree| tree.iter().collect::<Vec<_>>();
+    assert_eq!(values(a.union(&b).unwrap()), [-100, -50, 0, 1, 99]);
+    assert_eq!(values(a.intersection(&b).unwrap()), [-50, 99]);
+    assert_eq!(values(a.difference(&b).unwrap()), [-100, 0]);
+    assert_eq!(values(a.symmetric_difference(&b).unwrap()), [-100, 0, 1]);
+    assert_eq!(a.union(&b).unwrap().range(), (-100, 100));
+    let mut c = a.clone();
+    c.union_with(&b).unwrap();
+    assert_eq!(c, a.union(&b).unwrap());
+    assert!(a.intersection(&b).unwrap().is_subset(&a).unwrap());
+    assert!(c.is_superset(&b).unwrap() && !a.is_superset(&b).unwrap());
+    assert!(a.difference(&b).unwrap().is_disjoint(&b).unwrap());
+    assert!(!a.is_disjoint(&b).unwrap());
+
+    // the same universe starting somewhere else is a different range
+    let shifted = OffsetVEBTree::from_values(-99, 101, &[0]);
+    assert_eq!(a.union(&shifted).unwrap_err(), Error::UniverseMismatch);
+    assert_eq!(c.union_with(&shifted).unwrap_err(), Error::UniverseMismatch);
+    assert_eq!(a.intersection(&shifted).unwrap_err(), Error::UniverseMismatch);
+    assert_eq!(a.difference(&shifted).unwrap_err(), Error::UniverseMismatch);
+    assert_eq!(a.symmetric_difference(&shifted).unwrap_err(), Error::UniverseMismatch);
+    assert_eq!(a.is_subset(&shifted), Err(Error::UniverseMismatch));
+    assert_eq!(a.is_superset(&shifted), Err(Error::UniverseMismatch));
+    assert_eq!(a.is_disjoint(&shifted), Err(Error::UniverseMismatch));
+}
+
+#[test]
+fn cursors() {
+    let mut tree = OffsetVEBTree::from_values(-1000, -10, &[-1000, -500, -11]);
+    let at = |tree: &OffsetVEBTree, x| tree.cursor_at(x).current();
+    assert_eq!(at(&tree, i64::MIN), Some(-1000));
+    assert_eq!(at(&tree, -999), Some(-500));
+    assert_eq!(at(&tree, -11), Some(-11));
+    assert_eq!(at(&tree, -10), None);
+    assert_eq!(at(&tree, i64::MAX), None);
+    let mut cursor = tree.cursor_at(-600);
+    cursor.move_next();
+    assert_eq!(cursor.current(), Some(-11));
+    cursor.move_next();
+    assert_eq!(cursor.current(), None);
+    cursor.move_prev();
+    assert_eq!(cursor.current(), Some(-11));
+    // the ghost position past the end moves back to the maximum
+    let mut cursor = tree.cursor_at(0);
+    cursor.move_prev();
+    assert_eq!(cursor.current(), Some(-11));
+
+    let mut cursor = tree.cursor_at_mut(i64::MAX);
+    assert_eq!(cursor.current(), None);
+    cursor.move_next();
+    assert_eq!(cursor.remove_current(), Some(-1000));
+    assert_eq!(cursor.current(), Some(-500));
+    cursor.move_prev();
+    assert_eq!(cursor.current(), None);
+    let mut cursor = tree.cursor_at_mut(-11);
+    assert_eq!(cursor.remove_current(), Some(-11));
+    assert_eq!(cursor.current(), None);
+    assert_eq!(tree.iter().collect::<Vec<_>>(), [-500]);
+}
+
+#[test]
+fn snapshots() {
+    let mut widest = OffsetVEBTree::new_sparse_with_range(i64::MIN, i64::MAX).unwrap();
+    for &x in &[i64::MIN, 0, i64::MAX - 1] {
+        widest.insert(x);
+    }
+    let full: Vec<i64> = (1_000_000..1_000_100).collect();
+    let trees = [
+        OffsetVEBTree::from_values(-1000, -10, &[-1000, -500, -11]),
+        OffsetVEBTree::from_values(1_000_000, 1_000_100, &full),
+        widest,
+        OffsetVEBTree::new_with_range(0, 2).unwrap(),
+    ];
+    for tree in &trees {
+        let back = OffsetVEBTree::from_bytes(&tree.to_bytes()).unwrap();
+        assert_eq!(&back, tree);
+        assert_eq!(back.range(), tree.range());
+        assert_eq!(back.inner().validate(), Ok(()));
+    }
+    // lo is zigzag-encoded, so -1 is 1, followed by an empty bitmap with a universe of 2
+    assert_eq!(trees[3].to_bytes(), [0, 0, 2, 0]);
+    assert_eq!(OffsetVEBTree::new_with_range(-1, 1).unwrap().to_bytes(), [1, 0, 2, 0]);
+    assert_eq!(OffsetVEBTree::new_with_range(1, 3).unwrap().to_bytes(), [2, 0, 2, 0]);
+    // a range which would end past i64::MAX
+    let mut bytes = vec![];
+    write_varint(&mut bytes, (i64::MAX - 5) as u64 * 2);
+    bytes.extend(VEBTree::<u64>::new(10).unwrap().to_bytes());
+    assert_eq!(OffsetVEBTree::from_bytes(&bytes), Err(Error::UniverseTooLarge));
+    assert_eq!(OffsetVEBTree::from_bytes(&[]), Err(Error::TruncatedSnapshot));
+    assert_eq!(OffsetVEBTree::from_bytes(&[0]), Err(Error::TruncatedSnapshot));
+}
+
+// Equal trees hash the same however they were built, and trees holding the same values over
+// different ranges are different.
+#[test]
+#[cfg_attr(feature = "stats", allow(clippy::mutable_key_type))]
+fn hash_matches_equality() {
+    use std::collections::HashSet;
+
+    let values = [-700, -3, 0, 12, 900];
+    let mut set = HashSet::new();
+    set.insert(OffsetVEBTree::from_values(-1000, 1000, &values));
+    let mut reversed = OffsetVEBTree::new_with_range(-1000, 1000).unwrap();
+    for &x in values.iter().rev() {
+        reversed.insert(x);
+    }
+    reversed.insert(5);
+    reversed.delete(5);
+    set.insert(reversed);
+    assert_eq!(set.len(), 1);
+
+    // the same offsets into the tree, shifted by moving lo, and the same values with a wider
+    // range above them
+    let shifted = values.iter().map(|&x| x + 1).collect::<Vec<_>>();
+    set.insert(OffsetVEBTree::from_values(-999, 1001, &shifted));
+    set.insert(OffsetVEBTree::from_values(-1000, 1024, &values));
+    assert_eq!(set.len(), 3);
+    assert!(set.contains(&OffsetVEBTree::from_values(-999, 1001, &shifted)));
+}
+
+#[cfg(test)]
+impl OffsetVEBTree {
+    fn from_values(lo: i64, hi: i64, values: &[i64]) -> Self {
+        let mut tree = OffsetVEBTree::new_with_range(lo, hi).unwrap();
+        for &x in values {
+            tree.insert(x);
+        }
+        tree
+    }
+}
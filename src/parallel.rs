@@ -0,0 +1,165 @@
+//! Rayon support, enabled by the `rayon` feature.
+//!
+//! Every cluster covers its own range of values, so a tree is split up by handing its
+//! clusters to different threads, with the node's minimum (which isn't stored in any cluster)
+//! as a piece of its own. A piece which is still too big gets split again the same way, so the
+//! work stays balanced even when most of the values are in a few clusters.
+
+use alloc::vec::Vec;
+
+use rayon::iter::plumbing::{bridge_unindexed, Folder, UnindexedConsumer, UnindexedProducer};
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+
+use {VEBTree, VebInt};
+
+// Nodes holding fewer values than this are walked by a single thread.
+const MIN_SPLIT_LEN: usize = 1024;
+
+/// A parallel iterator over the values in a `VEBTree`, created by `VEBTree::par_iter`. The
+/// values are produced in no particular order.
+#[derive(Debug, Clone)]
+pub struct ParIter<'a, T: VebInt + 'a> {
+    tree: &'a VEBTree<T>,
+}
+
+impl<T: VebInt + Send + Sync> VEBTree<T> {
+    /// Returns a parallel iterator over every value in the tree, which yields the same values
+    /// as `iter`, in no particular order.
+    pub fn par_iter<'a>(&'a self) -> ParIter<'a, T> {
+        ParIter { tree: self }
+    }
+}
+
+impl<'a, T: VebInt + Send + Sync> IntoParallelIterator for &'a VEBTree<T> {
+    type Item = T;
+    type Iter = ParIter<'a, T>;
+
+    fn into_par_iter(self) -> ParIter<'a, T> {
+        self.par_iter()
+    }
+}
+
+impl<'a, T: VebInt + Send + Sync> ParallelIterator for ParIter<'a, T> {
+    type Item = T;
+
+    fn drive_unindexed<C: UnindexedConsumer<T>>(self, consumer: C) -> C::Result {
+        let pieces = if self.tree.is_empty() { vec![] } else { vec![Piece::Node(self.tree, 0)] };
+        bridge_unindexed(Producer { pieces }, consumer)
+    }
+}
+
+// Part of a tree: a node along with the value its range starts at, or a single value.
+enum Piece<'a, T: VebInt + 'a> {
+    Node(&'a VEBTree<T>, u64),
+    Value(u64),
+}
+
+struct Producer<'a, T: VebInt + 'a> {
+    pieces: Vec<Piece<'a, T>>,
+}
+
+impl<'a, T: VebInt + Send + Sync> UnindexedProducer for Producer<'a, T> {
+    type Item = T;
+
+    fn split(mut self) -> (Self, Option<Self>) {
+        if self.pieces.len() == 1 {
+            // a single big node is replaced by its minimum and its clusters
+            if let Piece::Node(node, base) = self.pieces[0] {
+                if node.len >= MIN_SPLIT_LEN && node.children.bits().is_none() {
+                    self.pieces.clear();
+                    self.pieces.push(Piece::Value(base + node.min.to_u64()));
+                    self.pieces.extend(node.children
+                        .iter()
+                        .filter(|&(_, subtree)| !subtree.is_empty())
+                        .map(|(idx, subtree)| {
+                            Piece::Node(subtree, base + idx * node.sqrt_universe)
+                        }));
+                }
+            }
+        }
+        if self.pieces.len() < 2 {
+            return (self, None);
+        }
+        let rest = self.pieces.split_off(self.pieces.len() / 2);
+        (self, Some(Producer { pieces: rest }))
+    }
+
+    fn fold_with<F: Folder<T>>(self, mut folder: F) -> F {
+        for piece in self.pieces {
+            match piece {
+                Piece::Node(node, base) => {
+                    folder = folder.consume_iter(node.iter()
+                        .map(|x| T::from_u64(base + x.to_u64())));
+                }
+                Piece::Value(x) => folder = folder.consume(T::from_u64(x)),
+            }
+            if folder.full() {
+                break;
+            }
+        }
+        folder
+    }
+}
+
+#[cfg(test)]
+fn check<T: VebInt + Send + Sync>(tree: &VEBTree<T>) {
+    let mut values: Vec<T> = tree.par_iter().collect();
+    values.sort();
+    assert_eq!(values, tree.to_sorted_vec());
+    assert_eq!(tree.into_par_iter().count(), tree.len());
+}
+
+#[test]
+fn matches_iter() {
+    use alloc::collections::BTreeSet;
+
+    let mut state = 1u64;
+    let mut random = |count, universe| {
+        (0..count).map(|_| (::xorshift(&mut state) % universe) as u32).collect::<BTreeSet<u32>>()
+    };
+    let cases = [
+        (2, random(0, 2)),
+        (64, random(40, 64)),
+        (1 << 20, random(100_000, 1 << 20)),
+        (1 << 32, random(50_000, 1 << 32)),
+        // every value in one cluster, and most of them in one cluster
+        (1 << 20, (4096..5120).collect()),
+        (1 << 20, (0..30_000).chain((1 << 19..(1 << 19) + 8).step_by(3)).collect()),
+    ];
+    for &(universe, ref values) in &cases {
+        let values: Vec<u32> = values.iter().cloned().collect();
+        check(&VEBTree::from_sorted_slice(universe, &values).unwrap());
+        let mut sparse = VEBTree::new_sparse(universe).unwrap();
+        sparse.insert_sorted(&values).unwrap();
+        check(&sparse);
+    }
+}
+
+// A tree whose values are all in one cluster still gets split, further down.
+#[test]
+fn splits_inside_clusters() {
+    fn split_fully<T: VebInt + Send + Sync>(producer: Producer<T>) -> usize {
+        match producer.split() {
+            (left, Some(right)) => split_fully(left) + split_fully(right),
+            (_, None) => 1,
+        }
+    }
+
+    let values: Vec<u32> = (1 << 20..(1 << 20) + (1 << 16)).collect();
+    let tree = VEBTree::from_sorted_slice(1 << 32, &values).unwrap();
+    assert_eq!(tree.children.iter().count(), 1);
+    let pieces = split_fully(Producer { pieces: vec![Piece::Node(&tree, 0)] });
+    assert!(pieces > 64, "{} pieces", pieces);
+    check(&tree);
+}
+
+#[test]
+fn short_circuits() {
+    let values = (0..1 << 24).step_by(7);
+    let tree = VEBTree::<u64>::from_iter_with_universe(1 << 24, values).unwrap();
+    let found = tree.par_iter().find_any(|&x| x > 1 << 23 && x % 5 == 0).unwrap();
+    assert!(tree.has(found) && found > 1 << 23 && found % 5 == 0);
+    assert_eq!(tree.par_iter().max(), tree.maximum());
+    let evens = tree.par_iter().filter(|x| x % 2 == 0).count();
+    assert_eq!(evens, tree.iter().filter(|x| x % 2 == 0).count());
+}
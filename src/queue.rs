@@ -0,0 +1,174 @@
+//! A monotone priority queue built on a van Emde Boas tree.
+
+#[cfg(test)]
+use alloc::collections::BinaryHeap;
+#[cfg(test)]
+use alloc::vec::Vec;
+#[cfg(test)]
+use core::cmp::Reverse;
+
+use {Error, VEBTree, VebInt};
+
+/// A min-priority queue of distinct keys in the range [0, U), where peeking takes constant
+/// time, and pushing, popping and removing an arbitrary key all take O(log(U)) time.
+/// Each key is queued at most once: pushing a key which is already queued does nothing and
+/// returns false. To queue the same priority for several items, fold the item into the key,
+/// as in `priority * N + item`. Decreasing a key is done by removing the old one and pushing
+/// the new one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VEBQueue<T: VebInt> {
+    tree: VEBTree<T>,
+}
+
+impl<T: VebInt> VEBQueue<T> {
+    /// Generates a new, empty queue. Will return an error under the same conditions as
+    /// `VEBTree::new`.
+    pub fn new(max_elem: u64) -> Result<Self, Error> {
+        Ok(VEBQueue { tree: VEBTree::new(max_elem)? })
+    }
+
+    /// Generates a new, empty queue which only stores the clusters it uses, as
+    /// `VEBTree::new_sparse` does. Will return an error under the same conditions as `new`.
+    pub fn new_sparse(max_elem: u64) -> Result<Self, Error> {
+        Ok(VEBQueue { tree: VEBTree::new_sparse(max_elem)? })
+    }
+
+    /// Returns the set of queued keys.
+    pub fn keys(&self) -> &VEBTree<T> {
+        &self.tree
+    }
+
+    /// Returns the size of the universe, the argument to the constructor. The largest key the
+    /// queue can hold is one less than this.
+    /// Takes constant time.
+    pub fn universe(&self) -> u64 {
+        self.tree.universe()
+    }
+
+    /// Returns the number of queued keys.
+    /// Takes constant time.
+    pub fn len(&self) -> usize {
+        self.tree.len()
+    }
+
+    /// Returns true if the queue is empty.
+    /// Takes constant time.
+    pub fn is_empty(&self) -> bool {
+        self.tree.is_empty()
+    }
+
+    /// Returns true if the key is queued.
+    /// Takes O(log(log(U))) time, where U is the argument to the constructor.
+    pub fn contains(&self, x: T) -> bool {
+        self.tree.has(x)
+    }
+
+    /// Queues a key, returning false and leaving the queue untouched if it was already
+    /// queued. Panics if the key is outside of the range [0, U).
+    /// Takes O(log(U)) time, where U is the argument to the constructor.
+    pub fn push(&mut self, x: T) -> bool {
+        self.tree.insert(x)
+    }
+
+    /// Returns the lowest queued key without removing it, or None if the queue is empty.
+    /// Takes constant time.
+    pub fn peek(&self) -> Option<T> {
+        self.tree.minimum()
+    }
+
+    /// Removes and returns the lowest queued key, or None if the queue is empty.
+    /// Takes O(log(U)) time, where U is the argument to the constructor.
+    pub fn pop(&mut self) -> Option<T> {
+        self.tree.pop_min()
+    }
+
+    /// Removes a key from anywhere in the queue, returning true if it was queued.
+    /// Takes O(log(U)) time, where U is the argument to the constructor.
+    pub fn remove(&mut self, x: T) -> bool {
+        self.tree.delete(x)
+    }
+
+    /// Removes every key from the queue.
+    /// Takes time proportional to the number of clusters which are allocated.
+    pub fn clear(&mut self) {
+        self.tree.clear();
+    }
+}
+
+#[test]
+fn push_pop_peek() {
+    let mut queue = VEBQueue::<u32>::new(100).unwrap();
+    assert_eq!((queue.peek(), queue.pop()), (None, None));
+    assert!(queue.push(40) && queue.push(7) && queue.push(99));
+    assert!(!queue.push(7));
+    assert_eq!(queue.len(), 3);
+    assert_eq!(queue.peek(), Some(7));
+    assert!(queue.contains(40) && !queue.contains(41));
+    assert!(queue.remove(40) && !queue.remove(40));
+    assert_eq!(queue.pop(), Some(7));
+    assert_eq!(queue.pop(), Some(99));
+    assert_eq!(queue.pop(), None);
+    assert!(queue.is_empty());
+    queue.push(3);
+    queue.clear();
+    assert!(queue.is_empty() && queue.keys().validate() == Ok(()));
+}
+
+#[test]
+#[should_panic(expected = "value out of range")]
+fn push_out_of_range() {
+    VEBQueue::<u32>::new(16).unwrap().push(16);
+}
+
+// Distances from node 0 in a random directed graph, with the queue as Dijkstra's frontier
+// (using remove + push to decrease keys), against a lazy-deletion BinaryHeap.
+#[test]
+fn dijkstra() {
+    const NODES: u64 = 200;
+    const MAX_WEIGHT: u64 = 50;
+    let mut state = 7;
+    let mut edges = vec![Vec::new(); NODES as usize];
+    for _ in 0..1500 {
+        let from = ::xorshift(&mut state) % NODES;
+        let to = ::xorshift(&mut state) % NODES;
+        let weight = 1 + ::xorshift(&mut state) % MAX_WEIGHT;
+        edges[from as usize].push((to as usize, weight));
+    }
+
+    // keys are distance * NODES + node, so nodes at the same distance don't collide
+    let mut queue = VEBQueue::<u32>::new(NODES * NODES * MAX_WEIGHT).unwrap();
+    let mut dist = vec![None; NODES as usize];
+    dist[0] = Some(0);
+    queue.push(0);
+    while let Some(key) = queue.pop() {
+        let (d, node) = (u64::from(key) / NODES, (u64::from(key) % NODES) as usize);
+        for &(to, weight) in &edges[node] {
+            let new = d + weight;
+            if dist[to].is_some_and(|old| old <= new) {
+                continue;
+            }
+            if let Some(old) = dist[to] {
+                assert!(queue.remove((old * NODES + to as u64) as u32));
+            }
+            assert!(queue.push((new * NODES + to as u64) as u32));
+            dist[to] = Some(new);
+        }
+    }
+
+    let mut expected = vec![None; NODES as usize];
+    let mut heap = BinaryHeap::new();
+    heap.push(Reverse((0, 0)));
+    while let Some(Reverse((d, node))) = heap.pop() {
+        if expected[node].is_some() {
+            continue;
+        }
+        expected[node] = Some(d);
+        for &(to, weight) in &edges[node] {
+            if expected[to].is_none() {
+                heap.push(Reverse((d + weight, to)));
+            }
+        }
+    }
+    assert_eq!(dist, expected);
+    assert!(dist.iter().filter(|d| d.is_some()).count() > (NODES / 2) as usize);
+}
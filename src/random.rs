@@ -0,0 +1,111 @@
+//! Random sampling, enabled by the `rand` feature.
+
+#[cfg(test)]
+use alloc::vec::Vec;
+
+use rand::Rng;
+
+use {VEBTree, VebInt};
+
+impl<T: VebInt> VEBTree<T> {
+    /// Returns a value picked uniformly at random from the tree, or None if it's empty.
+    ///
+    /// Every node knows how many values it holds, so this picks one random index and then
+    /// goes down the tree, taking a node's minimum (which isn't stored in any cluster) or one
+    /// of its clusters with probability proportional to how many values they hold, without
+    /// collecting the values first. The cluster is found from the node's running counts, as
+    /// `select` does, so this takes O(log(U)) time, where U is the argument to the constructor.
+    pub fn choose<R: Rng + ?Sized>(&self, rng: &mut R) -> Option<T> {
+        if self.is_empty() {
+            None
+        } else {
+            self.select(rng.gen_range(0..self.len))
+        }
+    }
+}
+
+// An rng which is reproducible without any of rand's optional features.
+#[cfg(test)]
+struct XorShift(u64);
+
+#[cfg(test)]
+impl rand::RngCore for XorShift {
+    fn next_u32(&mut self) -> u32 {
+        (::xorshift(&mut self.0) >> 32) as u32
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        ::xorshift(&mut self.0)
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        for chunk in dest.chunks_mut(8) {
+            let bytes = self.next_u64().to_le_bytes();
+            chunk.copy_from_slice(&bytes[..chunk.len()]);
+        }
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+#[test]
+fn empty_and_single() {
+    let mut rng = XorShift(1);
+    let mut tree = VEBTree::<u32>::new(1 << 20).unwrap();
+    assert_eq!(tree.choose(&mut rng), None);
+    tree.insert(777);
+    for _ in 0..10 {
+        assert_eq!(tree.choose(&mut rng), Some(777));
+    }
+    // one value in each of two clusters, so each cluster's count is 1
+    tree.insert(1 << 19);
+    tree.delete(777);
+    tree.insert(5);
+    for _ in 0..100 {
+        let x = tree.choose(&mut rng).unwrap();
+        assert!(x == 5 || x == 1 << 19);
+    }
+}
+
+// Every value should be drawn about equally often, including the minimum of each node and
+// values alone in their cluster.
+#[test]
+fn uniform() {
+    const DRAWS: usize = 100_000;
+    let values = [0u32, 1, 63, 64, 1000, 1001, 4095, 30_000, 65_535];
+    for &sparse in &[false, true] {
+        let mut tree = if sparse {
+            VEBTree::new_sparse(1 << 16)
+        } else {
+            VEBTree::new(1 << 16)
+        }.unwrap();
+        for &x in &values {
+            tree.insert(x);
+        }
+        let mut rng = XorShift(0x9e37_79b9_7f4a_7c15);
+        let mut counts = vec![0usize; values.len()];
+        for _ in 0..DRAWS {
+            let x = tree.choose(&mut rng).unwrap();
+            counts[values.iter().position(|&y| y == x).unwrap()] += 1;
+        }
+        // the 99.9th percentile of chi-squared with 8 degrees of freedom is 26.1
+        let expected = DRAWS as f64 / values.len() as f64;
+        let chi_squared: f64 = counts.iter()
+            .map(|&count| (count as f64 - expected) * (count as f64 - expected) / expected)
+            .sum();
+        assert!(chi_squared < 26.1, "{:?}", counts);
+    }
+}
+
+#[test]
+fn matches_select() {
+    let tree = ::random_tree::<u64>(1 << 40, 3000, 7);
+    let values: Vec<u64> = tree.iter().collect();
+    let mut rng = XorShift(3);
+    for _ in 0..1000 {
+        assert!(values.binary_search(&tree.choose(&mut rng).unwrap()).is_ok());
+    }
+}
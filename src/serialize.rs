@@ -0,0 +1,169 @@
+//! Serde support, enabled by the `serde` feature.
+//!
+//! A tree is serialized as its universe and the sorted list of values it stores, rather than
+//! its internal layout, which is mostly empty space. An `OffsetVEBTree` is serialized the same
+//! way, with its range in place of the universe.
+
+#[cfg(test)]
+use alloc::string::ToString;
+use alloc::vec::Vec;
+
+use serde::de::Error;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use offset;
+use {OffsetVEBTree, VEBTree, VebInt};
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename = "VEBTree")]
+struct Repr<T> {
+    universe: u64,
+    values: Vec<T>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename = "OffsetVEBTree")]
+struct OffsetRepr {
+    lo: i64,
+    hi: i64,
+    values: Vec<i64>,
+}
+
+impl<T: VebInt + Serialize> Serialize for VEBTree<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        Repr {
+            universe: self.universe(),
+            values: self.to_sorted_vec(),
+        }
+        .serialize(serializer)
+    }
+}
+
+/// Fails if the universe isn't one `VEBTree::new` accepts, if any value is outside of it, or
+/// if the values aren't strictly increasing.
+/// As with `VEBTree::from_bytes`, the tree is made sparse unless it holds at least one value
+/// for every 64 in the universe, so the memory used is in proportion to the size of the input.
+impl<'de, T: VebInt + Deserialize<'de>> Deserialize<'de> for VEBTree<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let repr = Repr::<T>::deserialize(deserializer)?;
+        VEBTree::from_decoded(repr.universe, &repr.values).map_err(D::Error::custom)
+    }
+}
+
+impl Serialize for OffsetVEBTree {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let (lo, hi) = self.range();
+        OffsetRepr {
+            lo,
+            hi,
+            values: self.iter().collect(),
+        }
+        .serialize(serializer)
+    }
+}
+
+/// Fails if the range holds fewer than two values, if any value is outside of it, or if the
+/// values aren't strictly increasing. Chooses the kind of storage as the `VEBTree` impl does.
+impl<'de> Deserialize<'de> for OffsetVEBTree {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let repr = OffsetRepr::deserialize(deserializer)?;
+        offset::from_decoded(repr.lo, repr.hi, &repr.values).map_err(D::Error::custom)
+    }
+}
+
+#[test]
+fn round_trip() {
+    let tree = VEBTree::<u32>::from_slice(1000, &[3, 17, 100, 999]).unwrap();
+    let json = ::serde_json::to_string(&tree).unwrap();
+    assert_eq!(json, r#"{"universe":1000,"values":[3,17,100,999]}"#);
+    let back: VEBTree<u32> = ::serde_json::from_str(&json).unwrap();
+    assert_eq!(back, tree);
+    assert_eq!(back.universe(), 1000);
+
+    let empty = VEBTree::<u64>::new(1 << 40).unwrap();
+    let json = ::serde_json::to_string(&empty).unwrap();
+    assert_eq!(::serde_json::from_str::<VEBTree<u64>>(&json).unwrap(), empty);
+}
+
+// A short document which declares a huge universe doesn't allocate in proportion to it, and
+// only a tree with a value for every 64 in the universe gets dense storage.
+#[test]
+fn storage() {
+    let json = r#"{"universe":4611686018427387904,"values":[0,1,4294967297]}"#;
+    let tree = ::serde_json::from_str::<VEBTree<u64>>(json).unwrap();
+    assert!(tree.is_sparse());
+    assert_eq!(tree.validate(), Ok(()));
+    assert_eq!(tree.to_sorted_vec(), [0, 1, (1 << 32) + 1]);
+
+    let full = VEBTree::<u32>::from_iter_with_universe(1000, (0..1000).step_by(60)).unwrap();
+    let back: VEBTree<u32> =
+        ::serde_json::from_str(&::serde_json::to_string(&full).unwrap()).unwrap();
+    assert!(!back.is_sparse() && back == full);
+    let sparse = VEBTree::<u32>::from_iter_with_universe(1000, (0..1000).step_by(100)).unwrap();
+    let back: VEBTree<u32> =
+        ::serde_json::from_str(&::serde_json::to_string(&sparse).unwrap()).unwrap();
+    assert!(back.is_sparse() && back == sparse);
+}
+
+#[test]
+fn malformed() {
+    let err = ::serde_json::from_str::<VEBTree<u32>>(r#"{"universe":16,"values":[3,16]}"#);
+    assert!(err.unwrap_err().to_string().contains("value out of range"));
+    let err = ::serde_json::from_str::<VEBTree<u32>>(r#"{"universe":1,"values":[]}"#);
+    assert!(err.unwrap_err().to_string().contains("universe size must be > 1"));
+    let err = ::serde_json::from_str::<VEBTree<u8>>(r#"{"universe":1000,"values":[]}"#);
+    assert!(err.unwrap_err().to_string().contains("universe too big"));
+    assert!(::serde_json::from_str::<VEBTree<u32>>(r#"{"universe":16}"#).is_err());
+    let err = ::serde_json::from_str::<VEBTree<u32>>(r#"{"universe":16,"values":[5,3]}"#);
+    assert!(err.unwrap_err().to_string().contains("values not strictly increasing"));
+    let err = ::serde_json::from_str::<VEBTree<u32>>(r#"{"universe":16,"values":[3,3]}"#);
+    assert!(err.unwrap_err().to_string().contains("values not strictly increasing"));
+}
+
+#[test]
+fn round_trip_bincode() {
+    let trees = [
+        VEBTree::<u32>::new(1000).unwrap(),
+        VEBTree::<u32>::from_slice(1000, &[0, 3, 17, 100, 999]).unwrap(),
+        VEBTree::<u32>::from_slice(2, &[0, 1]).unwrap(),
+        VEBTree::<u32>::from_slice(1 << 32, &[0, u32::MAX]).unwrap(),
+    ];
+    for tree in &trees {
+        let bytes = ::bincode::serialize(tree).unwrap();
+        let back: VEBTree<u32> = ::bincode::deserialize(&bytes).unwrap();
+        assert_eq!(&back, tree);
+        assert_eq!(back.universe(), tree.universe());
+
+        let json = ::serde_json::to_string(tree).unwrap();
+        assert_eq!(&::serde_json::from_str::<VEBTree<u32>>(&json).unwrap(), tree);
+    }
+    let truncated = &::bincode::serialize(&trees[1]).unwrap()[..20];
+    assert!(::bincode::deserialize::<VEBTree<u32>>(truncated).is_err());
+}
+
+#[test]
+fn offset_round_trip() {
+    let mut tree = OffsetVEBTree::new_with_range(-100, 100).unwrap();
+    for &x in &[-100, -3, 0, 99] {
+        tree.insert(x);
+    }
+    let json = ::serde_json::to_string(&tree).unwrap();
+    assert_eq!(json, r#"{"lo":-100,"hi":100,"values":[-100,-3,0,99]}"#);
+    let back: OffsetVEBTree = ::serde_json::from_str(&json).unwrap();
+    assert_eq!(back, tree);
+    assert_eq!(back.range(), (-100, 100));
+    let bytes = ::bincode::serialize(&tree).unwrap();
+    assert_eq!(::bincode::deserialize::<OffsetVEBTree>(&bytes).unwrap(), tree);
+
+    // the widest range, with few values, is read back without a dense allocation
+    let json = r#"{"lo":-9223372036854775808,"hi":9223372036854775807,"values":[-5,5]}"#;
+    let wide: OffsetVEBTree = ::serde_json::from_str(json).unwrap();
+    assert!(wide.inner().is_sparse());
+    assert_eq!(wide.iter().collect::<Vec<_>>(), [-5, 5]);
+
+    let parse = |json| ::serde_json::from_str::<OffsetVEBTree>(json).unwrap_err().to_string();
+    assert!(parse(r#"{"lo":5,"hi":5,"values":[]}"#).contains("universe size must be > 1"));
+    assert!(parse(r#"{"lo":-5,"hi":5,"values":[-6]}"#).contains("value out of range"));
+    assert!(parse(r#"{"lo":-5,"hi":5,"values":[5]}"#).contains("value out of range"));
+    assert!(parse(r#"{"lo":-5,"hi":5,"values":[1,-1]}"#).contains("not strictly increasing"));
+}
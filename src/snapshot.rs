@@ -0,0 +1,210 @@
+//! A compact binary snapshot format with no dependencies.
+//!
+//! A snapshot starts with a one-byte format tag and the universe as a varint (unsigned LEB128),
+//! followed by one of two encodings of the values, whichever is smaller:
+//!
+//! * `0`, a bitmap: ceil(U / 8) bytes, where bit `x % 8` of byte `x / 8` is set if `x` is in
+//!   the tree. Bits at or beyond the universe must be zero.
+//! * `1`, a delta list: the number of values as a varint, then the lowest value as a varint,
+//!   then the difference between each value and the one before it as a varint.
+//!
+//! The format is stable: snapshots written by any 0.x release can be read by every later
+//! one, and new encodings will only ever be added under new tags.
+
+use alloc::vec::Vec;
+
+use {Error, VEBTree, VebInt};
+
+const BITMAP: u8 = 0;
+const DELTAS: u8 = 1;
+
+pub fn write_varint(out: &mut Vec<u8>, mut x: u64) {
+    while x >= 0x80 {
+        out.push(x as u8 | 0x80);
+        x >>= 7;
+    }
+    out.push(x as u8);
+}
+
+pub fn read_varint(bytes: &mut &[u8]) -> Result<u64, Error> {
+    let mut x = 0u64;
+    for shift in (0..64).step_by(7) {
+        let (&byte, rest) = bytes.split_first().ok_or(Error::TruncatedSnapshot)?;
+        *bytes = rest;
+        let bits = u64::from(byte & 0x7f);
+        if bits << shift >> shift != bits {
+            return Err(Error::VarintTooLong);
+        }
+        x |= bits << shift;
+        if byte & 0x80 == 0 {
+            return Ok(x);
+        }
+    }
+    Err(Error::VarintTooLong)
+}
+
+impl<T: VebInt> VEBTree<T> {
+    /// Encodes the tree as a compact snapshot, which can be read back with `from_bytes`. The
+    /// values are stored as a bitmap or as a list of gaps between values, whichever is smaller;
+    /// the module documentation describes the format, which is stable across releases.
+    /// Takes O(N) time, where N is the number of values, or O(U) for a bitmap.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut deltas = vec![DELTAS];
+        write_varint(&mut deltas, self.universe);
+        write_varint(&mut deltas, self.len as u64);
+        let mut prev = 0;
+        for x in self.iter() {
+            write_varint(&mut deltas, x.to_u64() - prev);
+            prev = x.to_u64();
+        }
+        let bitmap_len = self.universe.div_ceil(8);
+        if deltas.len() as u64 <= bitmap_len {
+            return deltas;
+        }
+        let mut bitmap = vec![BITMAP];
+        write_varint(&mut bitmap, self.universe);
+        let start = bitmap.len();
+        bitmap.resize(start + bitmap_len as usize, 0);
+        for x in self.iter() {
+            let x = x.to_u64();
+            bitmap[start + (x / 8) as usize] |= 1 << (x % 8);
+        }
+        bitmap
+    }
+
+    /// Decodes a snapshot written by `to_bytes`. Will return an error if the buffer is
+    /// truncated or has bytes left over, if the format tag is unknown, if the universe isn't
+    /// one `new` accepts, or if any value is outside of the universe.
+    /// Snapshots don't record how the tree stored its clusters, so the tree is made sparse,
+    /// as by `new_sparse`, unless it holds at least one value for every 64 in the universe.
+    /// This keeps the memory used in proportion to the size of the snapshot, whatever
+    /// universe it declares.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        let (&tag, mut rest) = bytes.split_first().ok_or(Error::TruncatedSnapshot)?;
+        let universe = read_varint(&mut rest)?;
+        // reject a bad universe before reading any further
+        VEBTree::<T>::new(universe)?;
+        let mut values = Vec::new();
+        match tag {
+            BITMAP => {
+                let bitmap_len = universe.div_ceil(8);
+                if (rest.len() as u64) < bitmap_len {
+                    return Err(Error::TruncatedSnapshot);
+                }
+                let (bitmap, tail) = rest.split_at(bitmap_len as usize);
+                rest = tail;
+                for (i, &byte) in bitmap.iter().enumerate() {
+                    for bit in (0..8).filter(|bit| byte & 1 << bit != 0) {
+                        let x = i as u64 * 8 + bit;
+                        if x >= universe {
+                            return Err(Error::ValueOutOfRange);
+                        }
+                        values.push(T::from_u64(x));
+                    }
+                }
+            }
+            DELTAS => {
+                let count = read_varint(&mut rest)?;
+                // every value takes at least a byte, so a count larger than that is bogus
+                if count > rest.len() as u64 {
+                    return Err(Error::TruncatedSnapshot);
+                }
+                values.reserve(count as usize);
+                let mut prev = None;
+                for _ in 0..count {
+                    let delta = read_varint(&mut rest)?;
+                    let x = match prev {
+                        None => delta,
+                        Some(_) if delta == 0 => return Err(Error::NotStrictlyIncreasing),
+                        Some(prev) => u64::checked_add(prev, delta).ok_or(Error::ValueOutOfRange)?,
+                    };
+                    if x >= universe {
+                        return Err(Error::ValueOutOfRange);
+                    }
+                    values.push(T::from_u64(x));
+                    prev = Some(x);
+                }
+            }
+            _ => return Err(Error::UnknownSnapshotFormat),
+        }
+        if !rest.is_empty() {
+            return Err(Error::TrailingBytes);
+        }
+        VEBTree::from_decoded(universe, &values)
+    }
+}
+
+#[test]
+fn round_trip() {
+    let mut full = VEBTree::<u32>::new(100).unwrap();
+    full.extend(0..100);
+    let trees = [
+        VEBTree::<u32>::new(2).unwrap(),
+        VEBTree::<u32>::new(1000).unwrap(),
+        VEBTree::<u32>::from_slice(1000, &(0..1000).filter(|x| x % 3 != 0).collect::<Vec<_>>())
+            .unwrap(),
+        VEBTree::<u32>::from_slice(1 << 32, &[0, 5, 1 << 20, u32::MAX]).unwrap(),
+        VEBTree::<u32>::from_slice(13, &[0, 12]).unwrap(),
+        full,
+    ];
+    for tree in &trees {
+        let bytes = tree.to_bytes();
+        let back = VEBTree::<u32>::from_bytes(&bytes).unwrap();
+        assert_eq!(&back, tree);
+        assert_eq!(back.validate(), Ok(()));
+        // dense storage only if there's a value for every 64 in the universe
+        assert_eq!(back.is_sparse(), (tree.len() as u64) < tree.universe() / 64);
+    }
+    let sparse = VEBTree::<u32>::new_sparse(1 << 20).unwrap();
+    assert!(VEBTree::<u32>::from_bytes(&sparse.to_bytes()).unwrap().is_sparse());
+    // dense trees use the bitmap and sparse ones the delta list
+    assert_eq!(trees[2].to_bytes()[0], BITMAP);
+    assert_eq!(trees[2].to_bytes().len(), 1 + 2 + 125);
+    assert_eq!(trees[3].to_bytes()[0], DELTAS);
+    assert_eq!(trees[5].to_bytes()[0], BITMAP);
+    assert_eq!(trees[1].to_bytes(), vec![DELTAS, 0xe8, 0x07, 0]);
+}
+
+#[test]
+fn malformed() {
+    let sparse = VEBTree::<u32>::from_slice(1000, &[3, 17, 999]).unwrap().to_bytes();
+    let dense = VEBTree::<u32>::from_slice(20, &[3, 17, 19]).unwrap().to_bytes();
+    assert_eq!(dense[0], BITMAP);
+    for bytes in &[&sparse, &dense] {
+        for end in 0..bytes.len() {
+            assert_eq!(VEBTree::<u32>::from_bytes(&bytes[..end]), Err(Error::TruncatedSnapshot));
+        }
+        let mut extra = bytes.to_vec();
+        extra.push(0);
+        assert_eq!(VEBTree::<u32>::from_bytes(&extra), Err(Error::TrailingBytes));
+    }
+    assert_eq!(VEBTree::<u32>::from_bytes(&[7, 16]), Err(Error::UnknownSnapshotFormat));
+    // bit 20 is past the universe of 20
+    let mut bytes = dense.clone();
+    bytes[2 + 2] |= 1 << 4;
+    assert_eq!(VEBTree::<u32>::from_bytes(&bytes), Err(Error::ValueOutOfRange));
+    assert_eq!(VEBTree::<u32>::from_bytes(&[DELTAS, 16, 1, 16]), Err(Error::ValueOutOfRange));
+    assert_eq!(VEBTree::<u32>::from_bytes(&[DELTAS, 16, 2, 3, 0]),
+               Err(Error::NotStrictlyIncreasing));
+    assert_eq!(VEBTree::<u32>::from_bytes(&[DELTAS, 1, 0]), Err(Error::UniverseTooSmall));
+    assert_eq!(VEBTree::<u8>::from_bytes(&[DELTAS, 0x81, 0x02, 0]), Err(Error::UniverseTooLarge));
+    assert_eq!(VEBTree::<u32>::from_bytes(&[DELTAS, 16, 0xff, 0x7f]),
+               Err(Error::TruncatedSnapshot));
+    assert_eq!(VEBTree::<u32>::from_bytes(&[DELTAS, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+                                            0xff, 0xff, 0x7f, 0]),
+               Err(Error::VarintTooLong));
+}
+
+// A short snapshot which declares a huge universe doesn't allocate in proportion to it.
+#[test]
+fn huge_universe() {
+    let mut bytes = vec![DELTAS];
+    write_varint(&mut bytes, 1 << 62);
+    bytes.extend_from_slice(&[3, 0, 1, 0x80, 0x80, 0x80, 0x80, 0x10]);
+    assert_eq!(bytes.len(), 1 + 9 + 8);
+    let tree = VEBTree::<u64>::from_bytes(&bytes).unwrap();
+    assert!(tree.is_sparse());
+    assert_eq!(tree.validate(), Ok(()));
+    assert_eq!(tree.to_sorted_vec(), [0, 1, (1 << 32) + 1]);
+    assert_eq!(tree.to_bytes(), bytes);
+}
@@ -0,0 +1,190 @@
+//! Operation counters for tuning, enabled by the `stats` feature.
+//!
+//! An operation collects the work it does in a `Probe` on its way down the tree, and adds it
+//! to the counters of the tree it was called on once it's done, so only that tree's counters
+//! change, not those of the clusters it went through. Without the feature, `Probe` has no
+//! fields, the tree has no counters and recording does nothing, so all of it compiles away.
+
+#[cfg(feature = "stats")]
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+#[cfg(feature = "stats")]
+use {VEBTree, VebInt};
+
+macro_rules! stats {
+    ( $( $(#[$doc: meta])* $field: ident, )* ) => {
+        /// Counts of the operations a tree has performed, and of the work they did, since it
+        /// was created or `reset_stats` was last called. Every call counts, including those
+        /// other methods of the tree make on its behalf. The counts are kept in `usize`s, so on
+        /// targets with 32-bit pointers they wrap around after 2^32.
+        #[cfg(feature = "stats")]
+        #[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+        pub struct Stats {
+            $( $(#[$doc])* pub $field: u64, )*
+        }
+
+        // The counters kept in every node. They're atomic so that queries can count through
+        // a shared reference without making the tree any less thread-safe, and pointer-sized
+        // since not every target has 64-bit atomics.
+        #[cfg(feature = "stats")]
+        #[derive(Debug, Default)]
+        pub struct Counters {
+            $( $field: AtomicUsize, )*
+        }
+
+        #[cfg(feature = "stats")]
+        impl Counters {
+            fn load(&self) -> Stats {
+                Stats { $( $field: self.$field.load(Ordering::Relaxed) as u64, )* }
+            }
+
+            fn store(&self, stats: &Stats) {
+                $( self.$field.store(stats.$field as usize, Ordering::Relaxed); )*
+            }
+
+            fn add(&self, stats: &Stats) {
+                $( self.$field.fetch_add(stats.$field as usize, Ordering::Relaxed); )*
+            }
+        }
+    }
+}
+
+stats! {
+    /// Calls to `insert`, whether or not the value was new.
+    inserts,
+    /// Calls to `delete`, whether or not the value was present.
+    deletes,
+    /// Calls to `has` which found the value.
+    has_hits,
+    /// Calls to `has` which didn't find the value.
+    has_misses,
+    /// Calls to `find_next`.
+    find_next_calls,
+    /// Nodes visited by the operations above on the way down to a value's cluster, not
+    /// counting the summaries.
+    levels,
+    /// Clusters allocated by `insert`.
+    allocations,
+    /// Clusters emptied by `delete`, and so removed from their summary.
+    prunes,
+}
+
+#[cfg(feature = "stats")]
+impl Clone for Counters {
+    fn clone(&self) -> Self {
+        let counters = Counters::default();
+        counters.store(&self.load());
+        counters
+    }
+}
+
+// The operations which are counted.
+#[cfg_attr(not(feature = "stats"), allow(dead_code))]
+pub enum Op {
+    Insert,
+    Delete,
+    Has(bool),
+    FindNext,
+}
+
+// The work done by one operation so far.
+#[derive(Default)]
+pub struct Probe {
+    #[cfg(feature = "stats")]
+    levels: u64,
+    #[cfg(feature = "stats")]
+    allocations: u64,
+    #[cfg(feature = "stats")]
+    prunes: u64,
+}
+
+impl Probe {
+    #[inline]
+    pub fn level(&mut self) {
+        #[cfg(feature = "stats")]
+        {
+            self.levels += 1;
+        }
+    }
+
+    #[cfg(feature = "stats")]
+    pub fn allocation(&mut self) {
+        self.allocations += 1;
+    }
+
+    #[inline]
+    pub fn prune(&mut self) {
+        #[cfg(feature = "stats")]
+        {
+            self.prunes += 1;
+        }
+    }
+}
+
+// Adds a finished operation to a tree's counters.
+#[cfg(feature = "stats")]
+pub fn record(counters: &Counters, op: Op, probe: Probe) {
+    let mut stats = Stats {
+        levels: probe.levels,
+        allocations: probe.allocations,
+        prunes: probe.prunes,
+        ..Stats::default()
+    };
+    match op {
+        Op::Insert => stats.inserts = 1,
+        Op::Delete => stats.deletes = 1,
+        Op::Has(true) => stats.has_hits = 1,
+        Op::Has(false) => stats.has_misses = 1,
+        Op::FindNext => stats.find_next_calls = 1,
+    }
+    counters.add(&stats);
+}
+
+#[cfg(feature = "stats")]
+impl<T: VebInt> VEBTree<T> {
+    /// Returns the counts of the operations the tree has performed and the work they did.
+    /// Only available with the `stats` feature.
+    pub fn stats(&self) -> Stats {
+        self.counters.load()
+    }
+
+    /// Sets every count returned by `stats` back to zero. Only available with the `stats`
+    /// feature.
+    pub fn reset_stats(&self) {
+        self.counters.store(&Stats::default());
+    }
+}
+
+// The exact counts for a fixed sequence of operations, so that a change to the work an
+// operation does shows up here.
+#[cfg(feature = "stats")]
+#[test]
+fn scripted() {
+    // clusters of 64 values, which are leaves
+    let mut tree = VEBTree::<u32>::new(4096).unwrap();
+    assert_eq!(tree.stats(), Stats::default());
+    // into the empty root, into a new cluster, into that cluster's leaf, and a duplicate
+    for &x in &[5, 100, 101, 5] {
+        tree.insert(x);
+    }
+    assert_eq!(tree.stats(), Stats { inserts: 4, levels: 5, allocations: 1, ..Stats::default() });
+    // a leaf, a missing cluster and the root's minimum
+    assert!(tree.has(100) && !tree.has(3000) && tree.has(5));
+    assert_eq!(tree.find_next(5), Some(100));
+    assert_eq!(tree.find_next(100), Some(101));
+    let stats = tree.stats();
+    assert_eq!((stats.has_hits, stats.has_misses, stats.find_next_calls), (2, 1, 2));
+    assert_eq!(stats.levels, 5 + 4 + 3);
+
+    tree.reset_stats();
+    // finding 100 takes two levels and 101, the maximum, one; each is then deleted from the
+    // leaf, emptying it the second time. The last doesn't get past the root.
+    assert!(tree.delete(100) && tree.delete(101) && !tree.delete(7));
+    assert_eq!(tree.stats(), Stats { deletes: 3, levels: 8, prunes: 1, ..Stats::default() });
+    // only the tree an operation was called on counts it
+    let clone = tree.clone();
+    assert_eq!(clone.stats(), tree.stats());
+    clone.has(5);
+    assert_eq!(clone.stats().has_hits, 1);
+    assert_eq!(tree.stats().has_hits, 0);
+}
@@ -0,0 +1,60 @@
+// Checks that `clone_from` reuses the nodes a scratch tree already has, by counting every call
+// to the allocator while the scratch tree is reset from a baseline and modified, over and over.
+
+extern crate veb_rs;
+
+mod common;
+
+use veb_rs::VEBTree;
+
+// Runs `rounds` of resetting the scratch tree with `reset` and then inserting the same values
+// into it, returning the number of allocator calls in each round.
+fn rounds<F: FnMut(&mut VEBTree<u32>, &VEBTree<u32>)>(mut reset: F) -> Vec<usize> {
+    let mut state = 1u32;
+    let values: Vec<u32> = (0..20_000)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state % (1 << 20)
+        })
+        .collect();
+    let baseline = VEBTree::<u32>::from_iter_with_universe(1 << 20, values.iter().cloned())
+        .unwrap();
+    // values next to stored ones, in leaves of 32 values which hold at least two, so every
+    // insert lands in a leaf the baseline already has (a node's minimum isn't in any leaf)
+    let changes: Vec<u32> = baseline.iter()
+        .step_by(50)
+        .filter(|&x| {
+            let leaf = x & !31;
+            x % 32 != 31 && !baseline.has(x + 1) && baseline.count_range(leaf, leaf + 32) >= 2
+        })
+        .map(|x| x + 1)
+        .collect();
+    assert!(changes.len() > 100);
+    let mut scratch = baseline.clone();
+    (0..10)
+        .map(|_| {
+            let calls = common::count_calls(|| {
+                reset(&mut scratch, &baseline);
+                for &x in &changes {
+                    scratch.insert(x);
+                }
+            });
+            assert_eq!(scratch.len(), baseline.len() + changes.len());
+            calls
+        })
+        .collect()
+}
+
+#[test]
+fn clone_from_reuses_nodes() {
+    // the first round copies the nodes on each changed path; after that they're reused
+    let calls = rounds(|scratch, baseline| scratch.clone_from(baseline));
+    assert!(calls[0] > 0);
+    assert!(calls[1..].iter().all(|&calls| calls == 0), "{:?}", calls);
+
+    // a fresh clone shares every node with the baseline, so each round copies them again
+    let calls = rounds(|scratch, baseline| *scratch = baseline.clone());
+    assert!(calls.iter().all(|&calls| calls > 100), "{:?}", calls);
+}
@@ -0,0 +1,49 @@
+// A global allocator which counts calls to it, for tests checking that some piece of code
+// doesn't allocate. Only calls made from a thread inside `count_calls` are counted, so the
+// test harness's own threads (and other tests running alongside) can't throw the count off.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+struct Counting;
+
+static CALLS: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    static COUNTING: Cell<bool> = const { Cell::new(false) };
+}
+
+impl Counting {
+    fn count(&self) {
+        // `try_with` since the allocator is still called while thread-locals are torn down
+        if COUNTING.try_with(Cell::get).unwrap_or(false) {
+            CALLS.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+}
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        self.count();
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.count();
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: Counting = Counting;
+
+// Runs `f` on this thread, returning the number of times it allocated or freed memory.
+pub fn count_calls<F: FnOnce()>(f: F) -> usize {
+    COUNTING.with(|counting| counting.set(true));
+    let before = CALLS.load(Ordering::SeqCst);
+    f();
+    let calls = CALLS.load(Ordering::SeqCst) - before;
+    COUNTING.with(|counting| counting.set(false));
+    calls
+}
@@ -0,0 +1,24 @@
+// Exercises the crate from a `no_std` crate, using nothing outside of `core` and `alloc`.
+// Run with `cargo test --no-default-features`.
+
+#![no_std]
+
+extern crate alloc;
+extern crate veb_rs;
+
+use alloc::vec::Vec;
+use veb_rs::VEBTree;
+
+#[test]
+fn smoke() {
+    let mut tree = VEBTree::<u32>::new(256).unwrap();
+    assert!(tree.insert(42));
+    assert!(tree.insert(7));
+    assert!(!tree.insert(42));
+    assert!(tree.has(7));
+    assert!(!tree.has(8));
+    assert_eq!(tree.find_next(7), Some(42));
+    assert_eq!(tree.iter_range(0, 255).collect::<Vec<_>>(), [7, 42]);
+    assert!(tree.delete(7));
+    assert_eq!(tree.minimum(), Some(42));
+}
@@ -0,0 +1,42 @@
+// Checks that a tree made by `new_prealloc` doesn't touch the allocator while values are
+// inserted and deleted, by counting every call to it.
+
+extern crate veb_rs;
+
+mod common;
+
+use veb_rs::VEBTree;
+
+#[test]
+fn no_allocation() {
+    let mut tree = VEBTree::<u32>::new_prealloc(1 << 16).unwrap();
+    let mut state = 1u32;
+    let mut random = || {
+        state ^= state << 13;
+        state ^= state >> 17;
+        state ^= state << 5;
+        state % (1 << 16)
+    };
+    let calls = common::count_calls(|| {
+        for _ in 0..20 {
+            for _ in 0..1000 {
+                tree.insert(random());
+            }
+            for _ in 0..1500 {
+                let x = random();
+                tree.delete(x);
+                if let Some(next) = tree.find_next(x) {
+                    tree.delete(next);
+                }
+            }
+            tree.pop_min();
+            tree.pop_max();
+            let lo = random();
+            tree.remove_range(lo..lo.saturating_add(2000));
+            tree.retain(|x| x % 7 != 0);
+        }
+        tree.clear();
+        tree.insert(5);
+    });
+    assert_eq!(calls, 0);
+}